@@ -0,0 +1,82 @@
+//! Compares `tabula invoices list` on a large ledger without the cache, with a cache that
+//! has to be built first, and with a cache that is up to date.
+
+use std::{fs, path::Path, process::Command};
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+
+const YEARS: i32 = 10;
+const INVOICES_PER_YEAR: u32 = 1000;
+
+/// Writes a ledger that includes a file per year, each with invoices and their payments.
+fn write_ledger(dir: &Path) {
+    let mut main = String::from("option \"operating_currency\" \"EUR\"\n\n");
+    for year in 2014..2014 + YEARS {
+        let mut content = String::new();
+        for i in 1..=INVOICES_PER_YEAR {
+            let day = format!("{}-{:02}-{:02}", year, i % 12 + 1, i % 28 + 1);
+            content.push_str(&format!(
+                "{day} ! \"Invoice {year}-{i:04}\"\n\
+                 \tinvoice_number: \"{year}-{i:04}\"\n\
+                 \tcustomer: \"Customer {customer}\"\n\
+                 \tAssets:AccountsReceivable\t{amount} EUR\n\
+                 \tIncome:Work\t-{amount} EUR\n\n\
+                 {day} * \"Payment of {year}-{i:04}\"\n\
+                 \tpaid_invoice: \"{year}-{i:04}\"\n\
+                 \tAssets:Bank\t{amount} EUR\n\
+                 \tAssets:AccountsReceivable\t-{amount} EUR\n\n",
+                customer = i % 40,
+                amount = 100 + i,
+            ));
+        }
+        fs::write(dir.join(format!("{}.beancount", year)), content).unwrap();
+        main.push_str(&format!("include \"{}.beancount\"\n", year));
+    }
+    fs::write(dir.join("ledger.beancount"), main).unwrap();
+    fs::write(
+        dir.join("cached.toml"),
+        "[cache]\nenabled = true\npath = \"cache.sqlite\"\n",
+    )
+    .unwrap();
+    fs::write(dir.join("uncached.toml"), "").unwrap();
+}
+
+fn list(dir: &Path, config: &str) {
+    let output = Command::new(env!("CARGO_BIN_EXE_tabula"))
+        .arg("--config")
+        .arg(dir.join(config))
+        .arg("--ledger")
+        .arg(dir.join("ledger.beancount"))
+        .args(["invoices", "list"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+}
+
+fn invoices_list(c: &mut Criterion) {
+    let dir = std::env::temp_dir().join("tabula-bench-invoices-list");
+    fs::create_dir_all(&dir).unwrap();
+    write_ledger(&dir);
+    let cache = dir.join("cache.sqlite");
+
+    let mut group = c.benchmark_group("invoices list");
+    group.sample_size(10);
+    group.bench_function("uncached", |b| b.iter(|| list(&dir, "uncached.toml")));
+    group.bench_function("cold cache", |b| {
+        b.iter_batched(
+            || {
+                let _ = fs::remove_file(&cache);
+            },
+            |_| list(&dir, "cached.toml"),
+            BatchSize::PerIteration,
+        )
+    });
+    list(&dir, "cached.toml");
+    group.bench_function("warm cache", |b| b.iter(|| list(&dir, "cached.toml")));
+    group.finish();
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+criterion_group!(benches, invoices_list);
+criterion_main!(benches);
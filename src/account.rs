@@ -1,4 +1,9 @@
+//! Accounts as an event-sourced aggregate. The ledger is the event store: its `open` and
+//! `close` directives are the events of each account, and accepted commands are appended to
+//! it as new directives.
+
 pub mod aggregate;
+pub mod chart;
 pub mod command;
 pub mod errors;
 pub mod event;
@@ -0,0 +1,307 @@
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use cqrs_es::Aggregate;
+use serde::{Deserialize, Serialize};
+
+use crate::domain::{
+    account::{is_valid_name, Account, Booking},
+    invoice::Date,
+};
+
+use super::{
+    command::AccountCommand, errors::AccountError, event::AccountEvent, services::AccountServices,
+};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccountStatus {
+    /// Not opened yet
+    #[default]
+    New,
+    Open,
+    Closed,
+}
+
+/// An account as the sum of its events.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AccountAggregate {
+    pub status: AccountStatus,
+    pub name: String,
+    pub opened_on: Option<NaiveDate>,
+    pub closed_on: Option<NaiveDate>,
+    pub currencies: Vec<String>,
+    pub booking: Option<Booking>,
+    pub metadata: Vec<(String, String)>,
+}
+
+/// Whether `currency` is a valid beancount commodity, such as `EUR` or `VWRL.AS`.
+fn is_valid_currency(currency: &str) -> bool {
+    currency.starts_with(|c: char| c.is_ascii_uppercase())
+        && currency.ends_with(|c: char| c.is_ascii_uppercase() || c.is_ascii_digit())
+        && currency
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || "'._-".contains(c))
+}
+
+impl AccountAggregate {
+    /// The account as it is declared in the ledger, once it has been opened.
+    pub fn account(&self) -> Option<Account> {
+        Some(Account {
+            name: self.name.clone(),
+            opened_on: Date(self.opened_on?),
+            closed_on: self.closed_on.map(Date),
+            currencies: self.currencies.clone(),
+            booking: self.booking,
+        })
+    }
+}
+
+/// Replays the `open` and `close` directives of an account in the ledger.
+impl From<&Account> for AccountAggregate {
+    fn from(account: &Account) -> Self {
+        let mut aggregate = Self::default();
+        aggregate.apply(AccountEvent::AccountOpened {
+            account: account.name.clone(),
+            date: account.opened_on.0,
+            currencies: account.currencies.clone(),
+            booking: account.booking,
+            metadata: vec![],
+        });
+        if let Some(closed_on) = &account.closed_on {
+            aggregate.apply(AccountEvent::AccountClosed { date: closed_on.0 });
+        }
+        aggregate
+    }
+}
+
+#[async_trait]
+impl Aggregate for AccountAggregate {
+    type Command = AccountCommand;
+    type Event = AccountEvent;
+    type Error = AccountError;
+    type Services = AccountServices;
+
+    fn aggregate_type() -> String {
+        "Account".to_string()
+    }
+
+    async fn handle(
+        &self,
+        command: Self::Command,
+        services: &Self::Services,
+    ) -> Result<Vec<Self::Event>, Self::Error> {
+        match command {
+            AccountCommand::Open {
+                account,
+                date,
+                currencies,
+                booking,
+                metadata,
+            } => {
+                match (self.status, self.closed_on) {
+                    (AccountStatus::New, _) => {}
+                    (AccountStatus::Closed, Some(closed_on)) => {
+                        return Err(format!(
+                            "Account {} was closed on {} and cannot be opened again",
+                            self.name, closed_on
+                        )
+                        .into())
+                    }
+                    _ => return Err(format!("Account {} is already open", self.name).into()),
+                }
+                if !is_valid_name(&account) {
+                    return Err(format!(
+                        "{} is not an account name, such as Assets:Bank or Income:Work",
+                        account
+                    )
+                    .into());
+                }
+                if let Some(currency) = currencies.iter().find(|c| !is_valid_currency(c)) {
+                    return Err(format!("{} is not a currency, such as EUR", currency).into());
+                }
+                Ok(vec![AccountEvent::AccountOpened {
+                    account,
+                    date,
+                    currencies,
+                    booking,
+                    metadata,
+                }])
+            }
+            AccountCommand::Close { date } => {
+                let opened_on = match (self.status, self.opened_on, self.closed_on) {
+                    (AccountStatus::Open, Some(opened_on), _) => opened_on,
+                    (AccountStatus::Closed, _, Some(closed_on)) => {
+                        return Err(format!(
+                            "Account {} was already closed on {}",
+                            self.name, closed_on
+                        )
+                        .into())
+                    }
+                    _ => return Err("The account has not been opened".into()),
+                };
+                if date < opened_on {
+                    return Err(format!(
+                        "Account {} cannot be closed before it was opened on {}",
+                        self.name, opened_on
+                    )
+                    .into());
+                }
+                let balance: Vec<String> = services
+                    .balances
+                    .get(&self.name)
+                    .into_iter()
+                    .flatten()
+                    .filter(|money| !money.amount.is_zero())
+                    .map(|money| money.to_string())
+                    .collect();
+                if !balance.is_empty() {
+                    return Err(format!(
+                        "Account {} still has a balance of {}",
+                        self.name,
+                        balance.join(", ")
+                    )
+                    .into());
+                }
+                Ok(vec![AccountEvent::AccountClosed { date }])
+            }
+        }
+    }
+
+    fn apply(&mut self, event: Self::Event) {
+        match event {
+            AccountEvent::AccountOpened {
+                account,
+                date,
+                currencies,
+                booking,
+                metadata,
+            } => {
+                self.status = AccountStatus::Open;
+                self.name = account;
+                self.opened_on = Some(date);
+                self.currencies = currencies;
+                self.booking = booking;
+                self.metadata = metadata;
+            }
+            AccountEvent::AccountClosed { date } => {
+                self.status = AccountStatus::Closed;
+                self.closed_on = Some(date);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::domain::money::Money;
+
+    use super::*;
+
+    fn day(d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2023, 6, d).unwrap()
+    }
+
+    fn open(account: &str, currencies: &[&str]) -> AccountCommand {
+        AccountCommand::Open {
+            account: account.to_string(),
+            date: day(1),
+            currencies: currencies.iter().map(|c| c.to_string()).collect(),
+            booking: None,
+            metadata: vec![],
+        }
+    }
+
+    fn opened() -> AccountAggregate {
+        let mut account = AccountAggregate::default();
+        account.apply(AccountEvent::AccountOpened {
+            account: "Assets:Bank".to_string(),
+            date: day(1),
+            currencies: vec!["EUR".to_string()],
+            booking: Some(Booking::Strict),
+            metadata: vec![],
+        });
+        account
+    }
+
+    #[tokio::test]
+    async fn test_open_checks_names_and_currencies() {
+        let services = AccountServices::default();
+        let new = AccountAggregate::default();
+
+        let lowercase = new.handle(open("Assets:bank", &[]), &services).await;
+        let currency = new.handle(open("Assets:Bank", &["eur"]), &services).await;
+        let again = opened()
+            .handle(open("Assets:Bank", &["EUR"]), &services)
+            .await;
+
+        assert_eq!(
+            Err(AccountError::from(
+                "Assets:bank is not an account name, such as Assets:Bank or Income:Work"
+            )),
+            lowercase
+        );
+        assert_eq!(
+            Err(AccountError::from("eur is not a currency, such as EUR")),
+            currency
+        );
+        assert_eq!(
+            Err(AccountError::from("Account Assets:Bank is already open")),
+            again
+        );
+    }
+
+    #[tokio::test]
+    async fn test_accounts_close_once_without_a_balance() {
+        let mut services = AccountServices::default();
+        services.balances.insert(
+            "Assets:Bank".to_string(),
+            vec![Money::new(250.into(), "EUR")],
+        );
+        let mut account = opened();
+
+        let with_balance = account
+            .handle(AccountCommand::Close { date: day(30) }, &services)
+            .await;
+        services.balances.clear();
+        let early = account
+            .handle(
+                AccountCommand::Close {
+                    date: NaiveDate::from_ymd_opt(2023, 5, 31).unwrap(),
+                },
+                &services,
+            )
+            .await;
+        for event in account
+            .handle(AccountCommand::Close { date: day(30) }, &services)
+            .await
+            .unwrap()
+        {
+            account.apply(event);
+        }
+        let twice = account
+            .handle(AccountCommand::Close { date: day(30) }, &services)
+            .await;
+
+        assert_eq!(
+            Err(AccountError::from(
+                "Account Assets:Bank still has a balance of 250 EUR"
+            )),
+            with_balance
+        );
+        assert_eq!(
+            Err(AccountError::from(
+                "Account Assets:Bank cannot be closed before it was opened on 2023-06-01"
+            )),
+            early
+        );
+        assert_eq!(AccountStatus::Closed, account.status);
+        assert_eq!(
+            Err(AccountError::from(
+                "Account Assets:Bank was already closed on 2023-06-30"
+            )),
+            twice
+        );
+    }
+}
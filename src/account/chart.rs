@@ -0,0 +1,92 @@
+//! A standard chart of accounts for a small Dutch business, such as a sole proprietorship
+//! (eenmanszaak) or a partnership. The accounts are grouped like the Referentie
+//! Grootboekschema (RGS), whose main group code each account carries in its `rgs` metadata,
+//! so the ledger maps onto the schema that Dutch accountants and banks report in.
+
+use super::command::AccountCommand;
+use chrono::NaiveDate;
+
+/// An account of the chart: its name, RGS main group and Dutch description.
+pub struct ChartAccount {
+    pub name: &'static str,
+    pub rgs: &'static str,
+    pub description: &'static str,
+}
+
+const fn account(name: &'static str, rgs: &'static str, description: &'static str) -> ChartAccount {
+    ChartAccount {
+        name,
+        rgs,
+        description,
+    }
+}
+
+pub const DUTCH_SMALL_BUSINESS: &[ChartAccount] = &[
+    account("Assets:FixedAssets:Equipment", "BMva", "Inventaris"),
+    account("Assets:FixedAssets:Vehicles", "BMva", "Vervoermiddelen"),
+    account(
+        "Assets:FixedAssets:Depreciation",
+        "BMva",
+        "Cumulatieve afschrijvingen",
+    ),
+    account("Assets:AccountsReceivable", "BVor", "Debiteuren"),
+    account("Assets:VAT:Input", "BVor", "Te vorderen omzetbelasting"),
+    account("Assets:Prepaid", "BVor", "Vooruitbetaalde kosten"),
+    account("Assets:Bank", "BLim", "Bankrekening"),
+    account("Assets:Savings", "BLim", "Spaarrekening"),
+    account("Assets:Cash", "BLim", "Kas"),
+    account("Equity:Capital", "BEiv", "Ondernemingsvermogen"),
+    account("Equity:Withdrawals", "BEiv", "Privéopnamen"),
+    account("Equity:Deposits", "BEiv", "Privéstortingen"),
+    account("Equity:OpeningBalances", "BEiv", "Beginbalans"),
+    account("Liabilities:Loans", "BLas", "Langlopende leningen"),
+    account("Liabilities:AccountsPayable", "BSch", "Crediteuren"),
+    account(
+        "Liabilities:VAT:Output",
+        "BSch",
+        "Af te dragen omzetbelasting",
+    ),
+    account("Liabilities:IncomeTax", "BSch", "Inkomstenbelasting"),
+    account("Income:Work", "WOmz", "Omzet diensten"),
+    account("Income:Sales", "WOmz", "Omzet goederen"),
+    account("Income:Interest", "WFbe", "Rentebaten"),
+    account("Expenses:CostOfSales", "WKpr", "Inkoopwaarde van de omzet"),
+    account("Expenses:Housing", "WBed", "Huisvestingskosten"),
+    account("Expenses:Car", "WBed", "Autokosten"),
+    account("Expenses:Travel", "WBed", "Reiskosten"),
+    account("Expenses:Office", "WBed", "Kantoorkosten"),
+    account("Expenses:Software", "WBed", "Software en abonnementen"),
+    account("Expenses:Telecom", "WBed", "Telefoon en internet"),
+    account("Expenses:Marketing", "WBed", "Verkoopkosten"),
+    account("Expenses:Insurance", "WBed", "Verzekeringen"),
+    account("Expenses:Education", "WBed", "Opleidingskosten"),
+    account(
+        "Expenses:Accounting",
+        "WBed",
+        "Administratie- en advieskosten",
+    ),
+    account("Expenses:Depreciation", "WAfs", "Afschrijvingen"),
+    account("Expenses:BankFees", "WFbe", "Bankkosten"),
+    account("Expenses:Interest", "WFbe", "Rentelasten"),
+];
+
+/// The commands that open every account of the chart on `date`, holding `currency`.
+pub fn open_commands(
+    chart: &[ChartAccount],
+    date: NaiveDate,
+    currency: &str,
+) -> Vec<AccountCommand> {
+    chart
+        .iter()
+        .map(|account| AccountCommand::Open {
+            account: account.name.to_string(),
+            date,
+            currencies: vec![currency.to_string()],
+            booking: None,
+            metadata: vec![
+                ("rgs".to_string(), account.rgs.to_string()),
+                ("description".to_string(), account.description.to_string()),
+            ],
+        })
+        .collect()
+}
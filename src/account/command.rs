@@ -0,0 +1,20 @@
+use chrono::NaiveDate;
+use serde::Deserialize;
+
+use crate::domain::account::Booking;
+
+/// A change to an account, addressed by its name as the aggregate id.
+#[derive(Debug, Deserialize)]
+pub enum AccountCommand {
+    Open {
+        account: String,
+        date: NaiveDate,
+        /// The only currencies the account may hold. Any currency when empty
+        currencies: Vec<String>,
+        booking: Option<Booking>,
+        /// Metadata for the `open` directive, such as the RGS code of the account
+        metadata: Vec<(String, String)>,
+    },
+    /// Closes an open account, which must have no balance left
+    Close { date: NaiveDate },
+}
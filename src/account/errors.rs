@@ -0,0 +1,26 @@
+use core::fmt;
+use std::error::Error;
+
+/// A command that the rules of accounts do not allow, such as closing an account twice.
+#[derive(Debug, PartialEq)]
+pub struct AccountError(String);
+
+impl Error for AccountError {}
+
+impl fmt::Display for AccountError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<&str> for AccountError {
+    fn from(message: &str) -> Self {
+        Self(message.to_string())
+    }
+}
+
+impl From<String> for AccountError {
+    fn from(message: String) -> Self {
+        Self(message)
+    }
+}
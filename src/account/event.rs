@@ -0,0 +1,33 @@
+use chrono::NaiveDate;
+use cqrs_es::DomainEvent;
+use serde::{Deserialize, Serialize};
+
+use crate::domain::account::Booking;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum AccountEvent {
+    AccountOpened {
+        account: String,
+        date: NaiveDate,
+        currencies: Vec<String>,
+        booking: Option<Booking>,
+        metadata: Vec<(String, String)>,
+    },
+    AccountClosed {
+        date: NaiveDate,
+    },
+}
+
+impl DomainEvent for AccountEvent {
+    fn event_type(&self) -> String {
+        let event_type = match self {
+            AccountEvent::AccountOpened { .. } => "AccountOpened",
+            AccountEvent::AccountClosed { .. } => "AccountClosed",
+        };
+        event_type.to_string()
+    }
+
+    fn event_version(&self) -> String {
+        "1.0".to_string()
+    }
+}
@@ -0,0 +1,10 @@
+use std::collections::HashMap;
+
+use crate::domain::money::Money;
+
+/// What the account aggregate needs to know from outside the account itself.
+#[derive(Default)]
+pub struct AccountServices {
+    /// The balance, per currency, of accounts on the day they are closed
+    pub balances: HashMap<String, Vec<Money>>,
+}
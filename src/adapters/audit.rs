@@ -0,0 +1,322 @@
+//! A record of every change made to the ledger through tabula, for the bookkeeping. Each
+//! line of the audit log is a JSON object, and lines are only ever added.
+
+use std::{
+    collections::HashMap,
+    env,
+    error::Error,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use chrono::{Local, SecondsFormat};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::{
+    config::AuditConfig,
+    ledger_storage::{FileLedgerStorage, LedgerStorage},
+    logger,
+};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub timestamp: String,
+    pub user: String,
+    /// Such as `invoices send` or `POST /invoices`
+    pub command: String,
+    pub arguments: Vec<String>,
+    /// The invoices that were created or changed, or got a reminder
+    pub invoices: Vec<String>,
+    /// The hashes of the ledger files, unless they could not be read
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub ledger_sha256_before: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub ledger_sha256_after: Option<String>,
+    /// Why the command failed. A failed command may still have changed the ledger
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub error: Option<String>,
+    /// Why the ledger could not be read before or after the command, in which case the
+    /// invoices it changed are not known
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub snapshot_error: Option<String>,
+}
+
+/// What the audit log compares before and after a command.
+struct LedgerSnapshot {
+    sha256: String,
+    /// Each invoice as JSON, by number, in ledger order
+    invoices: Vec<(String, String)>,
+    reminders: Vec<String>,
+}
+
+impl LedgerSnapshot {
+    /// A ledger that does not exist yet, such as one a command is about to create, is empty.
+    fn take(ledger: &Path) -> Result<Self, Box<dyn Error>> {
+        if !ledger.exists() {
+            return Ok(Self {
+                sha256: hex::encode(Sha256::new().finalize()),
+                invoices: vec![],
+                reminders: vec![],
+            });
+        }
+        let storage = FileLedgerStorage::open(ledger)?;
+
+        let mut hasher = Sha256::new();
+        for file in storage.files() {
+            hasher.update(fs::read(file)?);
+        }
+
+        let invoices = storage
+            .find_invoices()?
+            .invoices
+            .iter()
+            .map(|invoice| Ok((invoice.number.0.clone(), serde_json::to_string(invoice)?)))
+            .collect::<Result<_, serde_json::Error>>()?;
+        let reminders = storage
+            .find_reminders()?
+            .iter()
+            .map(serde_json::to_string)
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            sha256: hex::encode(hasher.finalize()),
+            invoices,
+            reminders,
+        })
+    }
+
+    /// The numbers of the invoices that differ in `after`, in ledger order. A new credit
+    /// note also affects the invoice it credits.
+    fn affected_invoices(&self, after: &LedgerSnapshot) -> Vec<String> {
+        let before: HashMap<&str, &str> = self
+            .invoices
+            .iter()
+            .map(|(number, json)| (number.as_str(), json.as_str()))
+            .collect();
+
+        let mut affected = vec![];
+        for (number, json) in &after.invoices {
+            if before.get(number.as_str()) == Some(&json.as_str()) {
+                continue;
+            }
+            affected.push(number.clone());
+            if let Ok(invoice) = serde_json::from_str::<serde_json::Value>(json) {
+                if let Some(credited) = invoice["credits_invoice"].as_str() {
+                    affected.push(credited.to_string());
+                }
+            }
+        }
+        for reminder in &after.reminders {
+            if self.reminders.contains(reminder) {
+                continue;
+            }
+            if let Ok(reminder) = serde_json::from_str::<serde_json::Value>(reminder) {
+                if let Some(number) = reminder["invoice_number"].as_str() {
+                    affected.push(number.to_string());
+                }
+            }
+        }
+
+        let mut seen = vec![];
+        affected.retain(|number| {
+            let new = !seen.contains(number);
+            seen.push(number.clone());
+            new
+        });
+        affected
+    }
+}
+
+/// Wraps the commands that change a ledger, recording each in the audit log next to it.
+pub struct AuditLog {
+    path: PathBuf,
+    ledger: PathBuf,
+    enabled: bool,
+}
+
+impl AuditLog {
+    pub fn new(config: &AuditConfig, ledger: &Path) -> Self {
+        let base = ledger.parent().unwrap_or_else(|| Path::new(""));
+        Self {
+            path: base.join(&config.path),
+            ledger: ledger.to_path_buf(),
+            enabled: config.enabled,
+        }
+    }
+
+    /// Runs `run` and records what it changed, also when it fails. When the ledger cannot be
+    /// read before or after, the command still runs and is recorded, with the reason in
+    /// `snapshot_error`.
+    pub fn record<T>(
+        &self,
+        command: &str,
+        arguments: Vec<String>,
+        run: impl FnOnce() -> Result<T, Box<dyn Error>>,
+    ) -> Result<T, Box<dyn Error>> {
+        if !self.enabled {
+            return run();
+        }
+
+        let before = LedgerSnapshot::take(&self.ledger);
+        let result = run();
+        let after = LedgerSnapshot::take(&self.ledger);
+
+        let snapshot_error = [("before", &before), ("after", &after)]
+            .into_iter()
+            .filter_map(|(when, snapshot)| {
+                let err = snapshot.as_ref().err()?;
+                Some(format!(
+                    "Could not read the ledger {} the command: {}",
+                    when, err
+                ))
+            })
+            .collect::<Vec<String>>();
+        let invoices = match (&before, &after) {
+            (Ok(before), Ok(after)) => before.affected_invoices(after),
+            _ => vec![],
+        };
+
+        let entry = AuditEntry {
+            timestamp: Local::now().to_rfc3339_opts(SecondsFormat::Secs, false),
+            user: user(),
+            command: command.to_string(),
+            arguments,
+            invoices,
+            ledger_sha256_before: before.ok().map(|snapshot| snapshot.sha256),
+            ledger_sha256_after: after.ok().map(|snapshot| snapshot.sha256),
+            error: result.as_ref().err().map(|err| err.to_string()),
+            snapshot_error: (!snapshot_error.is_empty()).then(|| snapshot_error.join("; ")),
+        };
+        self.append(&entry)?;
+
+        if entry.ledger_sha256_before != entry.ledger_sha256_after {
+            logger::info(&format!(
+                "{} changed {} (invoices: {})",
+                command,
+                self.ledger.display(),
+                entry.invoices.join(", ")
+            ));
+        }
+
+        result
+    }
+
+    fn append(&self, entry: &AuditEntry) -> Result<(), Box<dyn Error>> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| {
+                format!(
+                    "Could not open the audit log {}: {}",
+                    self.path.display(),
+                    e
+                )
+            })?;
+        writeln!(file, "{}", serde_json::to_string(entry)?)?;
+        Ok(())
+    }
+}
+
+/// The user running tabula, as far as the environment tells.
+fn user() -> String {
+    env::var("USER")
+        .or_else(|_| env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        adapters::cli::entries::payment_entry,
+        domain::{invoice::InvoiceNumber, money::Money, payment::Payment},
+    };
+
+    use super::*;
+
+    const LEDGER: &str = r#"
+2023-06-01 * "Invoice #1"
+	invoice_number: "2023-001"
+	Assets:AccountsReceivable:Acme	100 EUR
+	Income:Work	-100 EUR
+"#;
+
+    #[test]
+    fn test_records_changed_invoices_and_hashes() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let ledger = dir.path().join("main.beancount");
+        fs::write(&ledger, LEDGER).unwrap();
+        let audit = AuditLog::new(&AuditConfig::default(), &ledger);
+
+        audit
+            .record("invoices pay", vec!["2023-001".to_string()], || {
+                let payment = Payment {
+                    date: "2023-06-10".into(),
+                    amount: Money::new(100.into(), "EUR"),
+                };
+                FileLedgerStorage::open(&ledger)?.append(&payment_entry(
+                    &InvoiceNumber("2023-001".to_string()),
+                    &payment,
+                    "Assets:Bank",
+                ))
+            })
+            .unwrap();
+        let failed = audit.record("invoices send", vec![], || -> Result<(), _> {
+            Err("no mail server".into())
+        });
+
+        let log = fs::read_to_string(dir.path().join("audit.jsonl")).unwrap();
+        let entries: Vec<AuditEntry> = log
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert!(failed.is_err());
+        assert_eq!(2, entries.len());
+        assert_eq!("invoices pay", entries[0].command);
+        assert_eq!(vec!["2023-001".to_string()], entries[0].invoices);
+        assert_eq!(
+            Some(hex::encode(Sha256::digest(LEDGER))),
+            entries[0].ledger_sha256_before
+        );
+        assert_ne!(
+            entries[0].ledger_sha256_before,
+            entries[0].ledger_sha256_after
+        );
+        assert_eq!(
+            entries[0].ledger_sha256_after,
+            entries[1].ledger_sha256_before
+        );
+        assert!(entries[1].invoices.is_empty());
+        assert_eq!(Some("no mail server".to_string()), entries[1].error);
+    }
+
+    #[test]
+    fn test_runs_and_records_commands_on_a_ledger_that_cannot_be_read() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let ledger = dir.path().join("main.beancount");
+        fs::write(&ledger, format!("{}\n2023-07-0", LEDGER)).unwrap();
+        let audit = AuditLog::new(&AuditConfig::default(), &ledger);
+
+        let result = audit.record("invoices pay", vec![], || {
+            fs::write(&ledger, LEDGER)?;
+            Ok(42)
+        });
+
+        let log = fs::read_to_string(dir.path().join("audit.jsonl")).unwrap();
+        let entry: AuditEntry = serde_json::from_str(&log).unwrap();
+        assert_eq!(42, result.unwrap());
+        assert_eq!(None, entry.ledger_sha256_before);
+        assert_eq!(
+            Some(hex::encode(Sha256::digest(LEDGER))),
+            entry.ledger_sha256_after
+        );
+        assert!(entry
+            .snapshot_error
+            .unwrap()
+            .starts_with("Could not read the ledger before the command"));
+    }
+}
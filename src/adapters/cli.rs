@@ -1,7 +1,34 @@
 use crate::{
-    adapters::InputAdapter,
-    commands::{BuildInvoiceCommand, Command, FindInvoiceCommand, ListInvoicesCommand},
-    domain::invoice::{Date, Invoice, InvoiceList, InvoiceNumber},
+    adapters::{
+        audit::AuditLog,
+        config::Config,
+        document_storage::document_storage,
+        http::HttpAdapter,
+        logger,
+        notification::{announced, notifier},
+        InputAdapter,
+    },
+    commands::{
+        ArchiveInvoiceCommand, BuildInvoiceCommand, Command, CustomerStatsCommand, FacturXCommand,
+        FindInvoiceCommand, ForecastCommand, ImportBillCommand, ImportInvoicesCommand,
+        InterestCommand, IssueInvoiceCommand, ListInvoicesCommand, RemindCommand, RevenueCommand,
+        SendInvoiceCommand,
+    },
+    daemon::Daemon,
+    domain::{
+        invoice::{Date, Invoice, InvoiceList, InvoiceNumber},
+        query::{InvoiceQuery, PaymentStatus, SortKey},
+    },
+    error::TabulaError,
+    services::{
+        bills::BillImportOptions,
+        dunning::DunningOptions,
+        e_invoice::{e_invoice, validate, EInvoiceError, EInvoiceOptions},
+        forecast::ForecastOptions,
+        interest::{InterestOptions, RateTable},
+        payment_behaviour::PaymentBehaviourOptions,
+        revenue::{GroupBy, RevenueOptions},
+    },
 };
 use beancount_core::{
     metadata::{Meta, MetaValue},
@@ -9,62 +36,470 @@ use beancount_core::{
 };
 use beancount_render::render;
 use core::fmt;
-use prettytable::{Cell, Row, Table};
 
-use std::{borrow::Cow, fmt::Display};
+use std::{
+    borrow::Cow,
+    fmt::Display,
+    path::{Path, PathBuf},
+};
 use std::{error::Error, io::Read};
 
-use self::arguments::OutputFormat;
+use self::arguments::{
+    Cli, ForecastArgs, ImportFormat, InterestArgs, ListArgs, ListSort, ListStatus, OutputFormat,
+    RemindArgs, ReportActions, RevenueArgs, RevenueGroup, StatsArgs,
+};
+use self::entries::invoice_entry;
+use self::invoicing::{action_name, run_lifecycle};
+pub use self::tabular::CsvOptions;
+use self::tabular::{csv, read_csv, Tabular};
 
-use super::ledger_storage::StdinLedgerStorage;
+use super::ledger_storage::{cache, FileLedgerStorage, LedgerStorage, StdinLedgerStorage};
 
+mod accounts;
 pub mod arguments;
+pub mod bills;
+mod check;
+pub mod document;
+pub mod entries;
+pub mod factur_x;
+mod import;
+mod interest;
+mod invoicing;
+mod reminders;
+mod reports;
+mod tabular;
+mod ubl;
+mod xml;
 
 #[derive(Default)]
 pub struct CliAdapter {
     response: String,
+    exit_code: u8,
 }
 
 impl CliAdapter {
     pub fn set_response(&mut self, response: String) {
         self.response = response;
     }
+
+    /// The exit code of a command that ran, which is not 0 when its output reports failure.
+    pub fn exit_code(&self) -> u8 {
+        self.exit_code
+    }
 }
 
-fn ledger_storage_with_stdin() -> StdinLedgerStorage {
+fn ledger_storage_with_stdin() -> Result<StdinLedgerStorage, TabulaError> {
     let mut ledger = String::new();
-    std::io::stdin().read_to_string(&mut ledger).unwrap();
-    StdinLedgerStorage::new(ledger)
+    std::io::stdin()
+        .read_to_string(&mut ledger)
+        .map_err(|source| TabulaError::Io {
+            path: PathBuf::from("<stdin>"),
+            source,
+        })?;
+    Ok(StdinLedgerStorage::new(ledger))
+}
+
+/// Reads the ledger from the file passed with `--ledger`, or from stdin when there is none.
+fn ledger_storage(ledger: &Option<PathBuf>) -> Result<Box<dyn LedgerStorage>, Box<dyn Error>> {
+    match ledger {
+        Some(path) => Ok(Box::new(FileLedgerStorage::open(path)?)),
+        None => Ok(Box::new(ledger_storage_with_stdin()?)),
+    }
+}
+
+/// The ledger file passed with `--ledger`, for commands that cannot work on stdin.
+pub(crate) fn ledger_file<'a>(
+    ledger: &'a Option<PathBuf>,
+    action: &str,
+) -> Result<&'a PathBuf, TabulaError> {
+    ledger.as_ref().ok_or_else(|| {
+        TabulaError::Config(format!(
+            "{} needs the ledger as a file, pass it with --ledger",
+            action
+        ))
+    })
+}
+
+/// Runs a command that changes the ledger, recording it in the audit log and announcing it to
+/// the webhooks when the ledger is a file. A ledger read from stdin is never changed.
+fn audited(
+    ledger: &Option<PathBuf>,
+    config: &Config,
+    command: &str,
+    run: impl FnOnce() -> Result<Box<dyn Output>, Box<dyn Error>>,
+) -> Result<Box<dyn Output>, Box<dyn Error>> {
+    match ledger {
+        Some(ledger) => announced(config, ledger, || {
+            AuditLog::new(&config.audit, ledger).record(
+                command,
+                std::env::args().skip(1).collect(),
+                run,
+            )
+        }),
+        None => run(),
+    }
 }
 
 fn ledger_storage_without_stdin() -> StdinLedgerStorage {
     StdinLedgerStorage::new("".to_string())
 }
 
+fn forecast_options(args: ForecastArgs, config: &Config) -> ForecastOptions {
+    let defaults = ForecastOptions::default();
+    ForecastOptions {
+        start: args.start.unwrap_or(defaults.start),
+        weeks: args.weeks,
+        currency: args.currency.unwrap_or(config.currency.clone()),
+        threshold: args.threshold.or(config.forecast.threshold),
+        bank_accounts: config.forecast.bank_accounts.clone(),
+        recurring: config.forecast.recurring.clone(),
+        payment_terms_days: config.payment_terms_days,
+        adjust_for_lateness: args.adjust_for_lateness,
+        filter: args.filter,
+    }
+}
+
+fn revenue_options(args: RevenueArgs, config: &Config) -> RevenueOptions {
+    let defaults = RevenueOptions::default();
+    RevenueOptions {
+        group_by: match args.group_by {
+            RevenueGroup::Customer => GroupBy::Customer,
+            RevenueGroup::Month => GroupBy::Month,
+            RevenueGroup::Quarter => GroupBy::Quarter,
+            RevenueGroup::Account => GroupBy::Account,
+        },
+        year: args.year.unwrap_or(defaults.year),
+        currency: args.currency.unwrap_or(config.currency.clone()),
+        top: args.top,
+        sparkline: args.sparkline,
+        payment_terms_days: config.payment_terms_days,
+        filter: args.filter,
+    }
+}
+
+/// Which invoices `invoices list` and `GET /invoices` show.
+pub fn invoice_query(args: ListArgs, config: &Config) -> InvoiceQuery {
+    InvoiceQuery {
+        from: args.from,
+        to: args.to,
+        customer: args.customer,
+        status: args.status.map(|status| match status {
+            ListStatus::Open => PaymentStatus::Open,
+            ListStatus::Overdue => PaymentStatus::Overdue,
+            ListStatus::Paid => PaymentStatus::Paid,
+        }),
+        min_amount: args.min_amount,
+        max_amount: args.max_amount,
+        currency: args.currency,
+        tags: args.tag,
+        search: args.search,
+        filter: args.filter,
+        sort: args.sort.map(|sort| match sort {
+            ListSort::Date => SortKey::Date,
+            ListSort::Number => SortKey::Number,
+            ListSort::Due => SortKey::Due,
+            ListSort::Amount => SortKey::Amount,
+        }),
+        reverse: args.reverse,
+        limit: args.limit,
+        payment_terms_days: config.payment_terms_days,
+        ..InvoiceQuery::default()
+    }
+}
+
+fn read_file(path: &Path) -> Result<String, TabulaError> {
+    std::fs::read_to_string(path).map_err(|source| TabulaError::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+pub(crate) fn dunning_options(args: &RemindArgs, config: &Config) -> DunningOptions {
+    let defaults = DunningOptions::default();
+    DunningOptions {
+        date: args.date.unwrap_or(defaults.date),
+        first_after_days: config.reminders.first_after_days,
+        second_after_days: config.reminders.second_after_days,
+        final_after_days: config.reminders.final_after_days,
+        payment_terms_days: config.payment_terms_days,
+        filter: args.filter.clone(),
+    }
+}
+
+fn interest_options(
+    args: &InterestArgs,
+    config: &Config,
+) -> Result<InterestOptions, Box<dyn Error>> {
+    let defaults = InterestOptions::default();
+    let rates = match &config.interest.rates_file {
+        Some(path) => RateTable::from_toml(&std::fs::read_to_string(path)?)?,
+        None => defaults.rates,
+    };
+    Ok(InterestOptions {
+        date: args.date.unwrap_or(defaults.date),
+        payment_terms_days: config.payment_terms_days,
+        rates,
+    })
+}
+
+fn e_invoice_options(config: &Config) -> EInvoiceOptions {
+    EInvoiceOptions {
+        seller: config.seller.party.clone(),
+        iban: config.seller.iban.clone(),
+        bic: config.seller.bic.clone(),
+        customers: config.customers.clone(),
+        vat_rate: config.e_invoice.vat_rate,
+        unit_code: config.e_invoice.unit_code.clone(),
+        tax: config.e_invoice.tax.clone(),
+        payment_terms_days: config.payment_terms_days,
+    }
+}
+
+fn bill_import_options(config: &Config) -> BillImportOptions {
+    BillImportOptions {
+        accounts: config.bills.accounts.clone(),
+        default_account: config.bills.default_account.clone(),
+        vat_account: config.bills.vat_account.clone(),
+    }
+}
+
+fn csv_options(args: &Cli, config: &Config) -> CsvOptions {
+    CsvOptions {
+        delimiter: args.csv_delimiter.unwrap_or(config.csv.delimiter),
+        decimal_separator: args
+            .decimal_separator
+            .unwrap_or(config.csv.decimal_separator),
+        expand_lines: false,
+    }
+}
+
+fn payment_behaviour_options(args: StatsArgs, config: &Config) -> PaymentBehaviourOptions {
+    let defaults = PaymentBehaviourOptions::default();
+    PaymentBehaviourOptions {
+        as_of: args.as_of.unwrap_or(defaults.as_of),
+        window_days: args.window_days,
+        currency: args.currency.unwrap_or(config.currency.clone()),
+        payment_terms_days: config.payment_terms_days,
+        filter: args.filter,
+    }
+}
+
+/// Runs a report on any ledger storage. Shared by the command line, the HTTP API and the
+/// daemon, so the options of a report mean the same everywhere.
+pub(crate) fn run_report<S: LedgerStorage>(
+    ledger_storage: S,
+    report: ReportActions,
+    config: &Config,
+) -> Result<Box<dyn Output>, Box<dyn Error>> {
+    match report {
+        ReportActions::Forecast(args) => ForecastCommand::new(ledger_storage)
+            .with_options(forecast_options(args, config))
+            .execute(),
+        ReportActions::Revenue(args) => RevenueCommand::new(ledger_storage)
+            .with_options(revenue_options(args, config))
+            .execute(),
+    }
+}
+
 impl InputAdapter for CliAdapter {
     fn run(&mut self) -> Result<(), Box<dyn Error>> {
         let global_args = arguments::parse();
+        logger::init(
+            global_args
+                .log_level
+                .adjust(global_args.verbose, global_args.quiet),
+            global_args.log_format,
+        );
+        let config = Config::load(global_args.config.as_deref())?;
+        if let Some(path) = &global_args.config {
+            logger::debug(&format!("Read the configuration from {}", path.display()));
+        }
+        cache::init(&config.cache);
+        let mut csv_options = csv_options(&global_args, &config);
 
         let command_res = match arguments::parse().command {
             arguments::Namespace::Invoices(invoices_args) => match invoices_args.command {
                 arguments::InvoiceActions::Build => {
                     BuildInvoiceCommand::new(ledger_storage_without_stdin()).execute()?
                 }
-                arguments::InvoiceActions::List => {
-                    ListInvoicesCommand::new(ledger_storage_with_stdin()).execute()?
+                arguments::InvoiceActions::List(args) => {
+                    csv_options.expand_lines = args.expand_lines;
+                    ListInvoicesCommand::new(ledger_storage(&global_args.ledger)?)
+                        .with_query(invoice_query(args, &config))
+                        .execute()?
                 }
                 arguments::InvoiceActions::Convert(args) => {
-                    FindInvoiceCommand::new(ledger_storage_with_stdin())
+                    FindInvoiceCommand::new(ledger_storage(&global_args.ledger)?)
+                        .with_invoice_number(args.invoice_number)
+                        .execute()?
+                }
+                arguments::InvoiceActions::Remind(args) if args.dry_run => {
+                    RemindCommand::new(ledger_storage(&global_args.ledger)?)
+                        .with_options(dunning_options(&args, &config))
+                        .with_dry_run(true)
+                        .execute()?
+                }
+                arguments::InvoiceActions::Remind(args) => {
+                    audited(&global_args.ledger, &config, "invoices remind", || {
+                        RemindCommand::new(ledger_storage(&global_args.ledger)?)
+                            .with_options(dunning_options(&args, &config))
+                            .execute()
+                    })?
+                }
+                arguments::InvoiceActions::Interest(args) => {
+                    InterestCommand::new(ledger_storage(&global_args.ledger)?)
+                        .with_options(interest_options(&args, &config)?)
+                        .with_invoice_number(args.invoice_number)
+                        .execute()?
+                }
+                arguments::InvoiceActions::Send(args) => {
+                    let ledger = ledger_file(&global_args.ledger, "Sending")?;
+                    audited(&global_args.ledger, &config, "invoices send", || {
+                        SendInvoiceCommand::new(FileLedgerStorage::open(ledger)?)
+                            .with_invoice_number(args.invoice_number)
+                            .with_notifier(notifier(&config.notification)?)
+                            .with_document_storage(document_storage(&config.documents, ledger)?)
+                            .with_addresses(config.notification.from.clone(), args.to)
+                            .execute()
+                    })?
+                }
+                arguments::InvoiceActions::Archive(args) => {
+                    let ledger = ledger_file(&global_args.ledger, "Archiving")?;
+                    audited(&global_args.ledger, &config, "invoices archive", || {
+                        ArchiveInvoiceCommand::new(FileLedgerStorage::open(ledger)?)
+                            .with_invoice_number(args.invoice_number)
+                            .with_document_storage(document_storage(&config.documents, ledger)?)
+                            .execute()
+                    })?
+                }
+                arguments::InvoiceActions::Issue(args) => {
+                    let ledger = ledger_file(&global_args.ledger, "Issuing")?;
+                    audited(&global_args.ledger, &config, "invoices issue", || {
+                        IssueInvoiceCommand::new(FileLedgerStorage::open(ledger)?)
+                            .with_invoice_number(args.invoice_number)
+                            .with_date(args.date)
+                            .with_document_storage(document_storage(&config.documents, ledger)?)
+                            .execute()
+                    })?
+                }
+                arguments::InvoiceActions::FacturX(args) => {
+                    FacturXCommand::new(ledger_storage(&global_args.ledger)?)
                         .with_invoice_number(args.invoice_number)
+                        .with_options(e_invoice_options(&config))
+                        .with_profile(args.profile)
+                        .with_output(args.output)
                         .execute()?
                 }
+                arguments::InvoiceActions::Import(args) => {
+                    let ledger = ledger_file(&global_args.ledger, "Importing invoices")?;
+                    let content = read_file(&args.file)?;
+                    let invoices = match args.format {
+                        ImportFormat::Csv => import::invoices_from_csv(
+                            &content,
+                            &csv_options,
+                            &config.csv.columns,
+                            &config.currency,
+                        )?,
+                    };
+                    let command = ImportInvoicesCommand::new(FileLedgerStorage::open(ledger)?)
+                        .with_invoices(invoices)
+                        .with_payment_terms_days(config.payment_terms_days)
+                        .with_dry_run(args.dry_run);
+                    if args.dry_run {
+                        command.execute()?
+                    } else {
+                        audited(&global_args.ledger, &config, "invoices import", || {
+                            command.execute()
+                        })?
+                    }
+                }
+            },
+            arguments::Namespace::Reports(reports_args) => run_report(
+                ledger_storage(&global_args.ledger)?,
+                reports_args.command,
+                &config,
+            )?,
+            arguments::Namespace::Serve(args) => {
+                let ledger = ledger_file(&global_args.ledger, "Serving")?.clone();
+                let mut http = HttpAdapter::new(args.bind, ledger, config);
+                http.run()?;
+                self.set_response(http.get_response());
+                return Ok(());
+            }
+            arguments::Namespace::Daemon => {
+                let ledger = ledger_file(&global_args.ledger, "The daemon")?.clone();
+                Daemon::new(ledger, config).run()?;
+                return Ok(());
+            }
+            arguments::Namespace::Lifecycle(lifecycle_args) => {
+                let ledger = ledger_file(&global_args.ledger, "The lifecycle")?;
+                match lifecycle_args.command {
+                    action @ arguments::LifecycleActions::History(_) => {
+                        run_lifecycle(action, ledger, &config)?
+                    }
+                    action => audited(&global_args.ledger, &config, action_name(&action), || {
+                        run_lifecycle(action, ledger, &config)
+                    })?,
+                }
+            }
+            arguments::Namespace::Accounts(accounts_args) => match accounts_args.command {
+                action @ (arguments::AccountActions::List | arguments::AccountActions::Tree) => {
+                    accounts::run_accounts(action, &global_args.ledger, &config)?
+                }
+                action => audited(
+                    &global_args.ledger,
+                    &config,
+                    accounts::action_name(&action),
+                    || accounts::run_accounts(action, &global_args.ledger, &config),
+                )?,
             },
+            arguments::Namespace::Bills(bills_args) => match bills_args.command {
+                arguments::BillActions::Import(args) => {
+                    let ledger = ledger_file(&global_args.ledger, "Importing bills")?;
+                    let xml = read_file(&args.file)?;
+                    let command = ImportBillCommand::new(FileLedgerStorage::open(ledger)?)
+                        .with_xml(xml)
+                        .with_options(bill_import_options(&config))
+                        .with_dry_run(args.dry_run);
+                    if args.dry_run {
+                        command.execute()?
+                    } else {
+                        audited(&global_args.ledger, &config, "bills import", || {
+                            command
+                                .with_document_storage(document_storage(&config.documents, ledger)?)
+                                .execute()
+                        })?
+                    }
+                }
+            },
+            arguments::Namespace::Customers(customers_args) => match customers_args.command {
+                arguments::CustomerActions::Stats(args) => {
+                    CustomerStatsCommand::new(ledger_storage(&global_args.ledger)?)
+                        .with_options(payment_behaviour_options(args, &config))
+                        .execute()?
+                }
+            },
+            arguments::Namespace::Check => check::run_check(&global_args.ledger, &config)?,
         };
+        self.exit_code = command_res.exit_code();
 
         let output = match &global_args.format {
             OutputFormat::Json => command_res.as_json(),
             OutputFormat::Txt => command_res.as_txt(),
-            OutputFormat::Beancount => command_res.as_beancount(),
+            OutputFormat::Beancount => {
+                let beancount = command_res.as_beancount();
+                // Only a ledger file can be read again, stdin has been read by the command
+                if let Some(ledger) = &global_args.ledger {
+                    accounts::check_postings(
+                        &beancount,
+                        &FileLedgerStorage::open(ledger)?.find_accounts()?,
+                    )?;
+                }
+                beancount
+            }
+            OutputFormat::Csv => command_res.as_csv(&csv_options),
+            OutputFormat::Markdown => command_res.as_markdown(),
+            OutputFormat::Ubl => command_res.as_ubl(&e_invoice_options(&config))?,
         };
 
         self.set_response(output.to_string());
@@ -80,8 +515,76 @@ pub trait Output {
     fn as_json(&self) -> String;
     fn as_txt(&self) -> String;
     fn as_beancount(&self) -> String;
+    fn as_csv(&self, options: &CsvOptions) -> String;
+
+    /// GitHub-flavoured Markdown, for issue trackers and emails. Outputs without tables of
+    /// their own show their text in a code block.
+    fn as_markdown(&self) -> String {
+        format!("```\n{}\n```\n", self.as_txt().trim_end())
+    }
+
+    /// An electronic invoice, which only the outputs that are a single invoice have.
+    fn as_ubl(&self, _options: &EInvoiceOptions) -> Result<String, Box<dyn Error>> {
+        Err("Only a single invoice can be written as UBL, such as with invoices convert".into())
+    }
+
+    /// Outputs that report problems, such as those of `check`, are written and then exit
+    /// with a code other than 0.
+    fn exit_code(&self) -> u8 {
+        0
+    }
+}
+
+fn invoice_csv_row(invoice: &Invoice) -> Vec<String> {
+    vec![
+        invoice.number.to_string(),
+        invoice.date.to_string(),
+        invoice
+            .due_date
+            .as_ref()
+            .map(|d| d.to_string())
+            .unwrap_or_default(),
+        invoice.customer.clone().unwrap_or_default(),
+        invoice.narration.clone(),
+        invoice.total.clone(),
+    ]
+}
+
+/// The rows of an invoice with its line items, or with its total as a single line.
+fn invoice_line_csv_rows(invoice: &Invoice) -> Vec<Vec<String>> {
+    let mut invoice_row = invoice_csv_row(invoice);
+    invoice_row.pop();
+    document::lines(invoice)
+        .into_iter()
+        .map(|line| {
+            let mut row = invoice_row.clone();
+            row.extend([line.description, line.quantity, line.unit_price, line.total]);
+            row
+        })
+        .collect()
 }
 
+const INVOICE_CSV_HEADER: [&str; 6] = [
+    "Number",
+    "Date",
+    "Due date",
+    "Customer",
+    "Narration",
+    "Total",
+];
+
+const INVOICE_LINE_CSV_HEADER: [&str; 9] = [
+    "Number",
+    "Date",
+    "Due date",
+    "Customer",
+    "Narration",
+    "Description",
+    "Quantity",
+    "Unit price",
+    "Amount",
+];
+
 impl Output for Invoice {
     fn as_json(&self) -> String {
         serde_json::to_string_pretty(&self).unwrap()
@@ -145,6 +648,26 @@ impl Output for Invoice {
 
         String::from_utf8(w).unwrap()
     }
+
+    fn as_csv(&self, options: &CsvOptions) -> String {
+        InvoiceList {
+            invoices: vec![self.clone()],
+        }
+        .as_csv(options)
+    }
+
+    fn as_markdown(&self) -> String {
+        invoice_tabular(self).markdown()
+    }
+
+    fn as_ubl(&self, options: &EInvoiceOptions) -> Result<String, Box<dyn Error>> {
+        let e_invoice = e_invoice(self, options)?;
+        let violations = validate(&e_invoice);
+        if !violations.is_empty() {
+            return Err(EInvoiceError::Invalid(self.number.clone(), violations).into());
+        }
+        Ok(ubl::invoice_ubl(&e_invoice))
+    }
 }
 
 impl Output for InvoiceList {
@@ -153,115 +676,105 @@ impl Output for InvoiceList {
     }
 
     fn as_txt(&self) -> String {
-        let mut table = Table::new();
-        table.add_row(Row::new(vec![
-            Cell::new("Number"),
-            Cell::new("Date"),
-            Cell::new("Narration"),
-            Cell::new("Due date"),
-        ]));
-        for invoice in &self.invoices {
-            let due_date = invoice
-                .due_date
-                .clone()
-                .map(|d| d.to_string())
-                .unwrap_or("".to_string());
-
-            table.add_row(Row::new(vec![
-                Cell::new(&invoice.number.to_string()),
-                Cell::new(&invoice.date.to_string()),
-                Cell::new(&invoice.narration),
-                Cell::new(&due_date),
-            ]));
-        }
-        table.to_string()
+        invoice_list_tabular(self).txt()
     }
 
     fn as_beancount(&self) -> String {
-        unimplemented!()
+        self.invoices
+            .iter()
+            .map(invoice_entry)
+            .collect::<Vec<String>>()
+            .join("\n")
     }
-}
 
-impl Display for Invoice {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let owned_date = self.date.clone();
-        let owned_due_date = if let Some(due_date) = self.due_date.clone() {
-            due_date.to_string()
+    fn as_csv(&self, options: &CsvOptions) -> String {
+        let mut tabular = if options.expand_lines {
+            let mut tabular = Tabular::new(&INVOICE_LINE_CSV_HEADER);
+            tabular.set_amounts(&["Quantity", "Unit price", "Amount"]);
+            tabular
         } else {
-            "".to_string()
+            let mut tabular = Tabular::new(&INVOICE_CSV_HEADER);
+            tabular.set_amounts(&["Total"]);
+            tabular
         };
-        let owned_number = self.number.0.clone();
-
-        let mut renderer = KeyValueRenderer::new();
-        renderer.add_field("Invoice", &owned_number);
-        renderer.add_field("Date issued", &owned_date);
-        renderer.add_field("Due date", &owned_due_date);
-        renderer.add_field("Income:Work", &self.total);
-        let meta = renderer.to_string();
-
-        // If there are line-items, render them to a table with prettytable
-        if !self.line_items.is_empty() {
-            let mut table = Table::new();
-            table.add_row(Row::new(vec![
-                Cell::new("Name"),
-                Cell::new("Qty"),
-                Cell::new("Unit price"),
-                Cell::new("Amount"),
-            ]));
-            for line_item in &self.line_items {
-                table.add_row(Row::new(vec![
-                    Cell::new(&line_item.description),
-                    Cell::new(&line_item.quantity),
-                    Cell::new(&line_item.unit_price),
-                    Cell::new(&line_item.total),
-                ]));
+        for invoice in &self.invoices {
+            if options.expand_lines {
+                invoice_line_csv_rows(invoice)
+                    .into_iter()
+                    .for_each(|row| tabular.add_row(row));
+            } else {
+                tabular.add_row(invoice_csv_row(invoice));
             }
-            write!(f, "{}\n{}\n{}", meta, table, self.narration)?;
-            Ok(())
-        } else {
-            write!(f, "{}\n{}", meta, self.narration)?;
-            Ok(())
         }
+        tabular.csv(options)
     }
-}
 
-impl Display for InvoiceNumber {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.0)
+    fn as_markdown(&self) -> String {
+        invoice_list_tabular(self).markdown()
     }
 }
 
-impl fmt::Display for Date {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.0.to_string())
+/// The invoice number and dates, the total, the line items and the narration.
+fn invoice_tabular(invoice: &Invoice) -> Tabular {
+    let mut tabular = Tabular::new(&["Name", "Qty", "Unit price", "Amount"]);
+    tabular.add_field("Invoice", &invoice.number);
+    tabular.add_field("Date issued", &invoice.date);
+    tabular.add_field(
+        "Due date",
+        invoice
+            .due_date
+            .as_ref()
+            .map(|d| d.to_string())
+            .unwrap_or_default(),
+    );
+    tabular.add_field("Income:Work", &invoice.total);
+    if let Some(sent_on) = &invoice.sent_on {
+        tabular.add_field("Sent on", sent_on);
     }
+    for line_item in &invoice.line_items {
+        tabular.add_row(vec![
+            line_item.description.clone(),
+            line_item.quantity.clone(),
+            line_item.unit_price.clone(),
+            line_item.total.clone(),
+        ]);
+    }
+    tabular.set_footer(&invoice.narration);
+    tabular
 }
 
-struct KeyValueRenderer<'a> {
-    fields: Vec<(&'static str, &'a dyn fmt::Display)>,
-}
-
-impl<'a> KeyValueRenderer<'a> {
-    fn new() -> Self {
-        KeyValueRenderer { fields: Vec::new() }
+fn invoice_list_tabular(list: &InvoiceList) -> Tabular {
+    let mut tabular = Tabular::new(&["Number", "Date", "Narration", "Due date"]);
+    for invoice in &list.invoices {
+        tabular.add_row(vec![
+            invoice.number.to_string(),
+            invoice.date.to_string(),
+            invoice.narration.clone(),
+            invoice
+                .due_date
+                .as_ref()
+                .map(|d| d.to_string())
+                .unwrap_or_default(),
+        ]);
     }
+    tabular
+}
 
-    fn add_field(&mut self, key: &'static str, value: &'a dyn fmt::Display) {
-        self.fields.push((key, value));
+impl Display for Invoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&invoice_tabular(self).txt())
     }
+}
 
-    fn render(&self) -> String {
-        let mut output = String::new();
-        for (key, value) in &self.fields {
-            output.push_str(&format!("{}: {}\n", key, value));
-        }
-        output
+impl Display for InvoiceNumber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
     }
 }
 
-impl<'a> Display for KeyValueRenderer<'a> {
+impl fmt::Display for Date {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.write_str(&self.render())
+        write!(f, "{}", self.0.to_string())
     }
 }
 
@@ -284,6 +797,7 @@ mod tests {
             number: InvoiceNumber("2023-002".to_string()),
             total: "1337 USD".to_string(),
             line_items: vec![],
+            ..Invoice::default()
         };
 
         let actual = serde_json::from_str::<serde_json::Value>(&invoice.as_json()).unwrap();
@@ -295,7 +809,8 @@ mod tests {
                 "narration": "Invoice #2",
                 "number": "2023-002",
                 "total": "1337 USD",
-                "line_items": []
+                "line_items": [],
+                "state": "draft"
             }
         );
 
@@ -311,6 +826,7 @@ mod tests {
             number: InvoiceNumber("2023-002".to_string()),
             total: "1337 USD".to_string(),
             line_items: vec![],
+            ..Invoice::default()
         };
 
         let actual = invoice.as_txt();
@@ -333,6 +849,7 @@ Invoice #2"#;
             number: InvoiceNumber("2023-002".to_string()),
             total: "1337 USD".to_string(),
             line_items: vec![],
+            ..Invoice::default()
         };
 
         invoice.line_items.push(LineItem {
@@ -340,6 +857,8 @@ Invoice #2"#;
             quantity: "65".to_string(),
             unit_price: "65".to_string(),
             total: "1337 USD".to_string(),
+            unit: None,
+            account: None,
         });
 
         let expected = r#"Invoice: 2023-002
@@ -357,4 +876,128 @@ Invoice #2"#;
 
         assert_eq!(expected, invoice.as_txt());
     }
+
+    #[test]
+    fn test_as_markdown_has_line_item_table() {
+        let mut invoice = Invoice {
+            date: "2023-06-02".into(),
+            due_date: Some("2023-07-02".into()),
+            narration: "Invoice #2".to_string(),
+            number: InvoiceNumber("2023-002".to_string()),
+            total: "1337 USD".to_string(),
+            ..Invoice::default()
+        };
+        invoice.line_items.push(LineItem {
+            description: "Uren".to_string(),
+            quantity: "65".to_string(),
+            unit_price: "65".to_string(),
+            total: "1337 USD".to_string(),
+            unit: None,
+            account: None,
+        });
+
+        let expected = r#"- **Invoice:** 2023-002
+- **Date issued:** 2023-06-02
+- **Due date:** 2023-07-02
+- **Income:Work:** 1337 USD
+
+| Name | Qty | Unit price | Amount   |
+| ---- | --- | ---------- | -------- |
+| Uren | 65  | 65         | 1337 USD |
+
+Invoice #2
+"#;
+        assert_eq!(expected, invoice.as_markdown());
+    }
+
+    #[test]
+    fn test_as_csv_for_dutch_spreadsheets() {
+        let mut invoice = Invoice {
+            date: "2023-06-02".into(),
+            due_date: None,
+            narration: "Hosting, June".to_string(),
+            number: InvoiceNumber("2023-002".to_string()),
+            total: "1250.50 EUR".to_string(),
+            customer: Some("Acme B.V.".to_string()),
+            ..Invoice::default()
+        };
+        let options = CsvOptions {
+            delimiter: ';',
+            decimal_separator: ',',
+            expand_lines: false,
+        };
+
+        assert_eq!(
+            "Number;Date;Due date;Customer;Narration;Total\n\
+             2023-002;2023-06-02;;Acme B.V.;Hosting, June;1250,50 EUR\n",
+            invoice.as_csv(&options)
+        );
+
+        invoice.line_items.push(LineItem {
+            description: "Uren; 10.5".to_string(),
+            quantity: "10.5".to_string(),
+            unit_price: "119.10".to_string(),
+            total: "1250.50 EUR".to_string(),
+            unit: None,
+            account: None,
+        });
+        let expanded = invoice.as_csv(&CsvOptions {
+            expand_lines: true,
+            ..options
+        });
+
+        assert_eq!(
+            "2023-002;2023-06-02;;Acme B.V.;Hosting, June;\"Uren; 10.5\";10,5;119,10;1250,50 EUR",
+            expanded.lines().nth(1).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_read_csv_reads_what_csv_writes() {
+        let rows = vec![
+            vec!["Narration".to_string(), "Total".to_string()],
+            vec!["Said \"hi\"; twice\nor so".to_string(), "".to_string()],
+        ];
+        let options = CsvOptions {
+            delimiter: ';',
+            ..CsvOptions::default()
+        };
+
+        assert_eq!(rows, read_csv(&csv(&rows, &[], &options), ';').unwrap());
+    }
+
+    #[test]
+    fn test_invoice_list_as_beancount() {
+        let list = InvoiceList {
+            invoices: vec![
+                Invoice {
+                    date: "2023-06-02".into(),
+                    number: InvoiceNumber("2023-002".to_string()),
+                    narration: "Invoice #2".to_string(),
+                    total: "100 EUR".to_string(),
+                    ..Invoice::default()
+                },
+                Invoice {
+                    date: "2023-06-03".into(),
+                    number: InvoiceNumber("2023-003".to_string()),
+                    narration: "Invoice #3".to_string(),
+                    total: "200 EUR".to_string(),
+                    ..Invoice::default()
+                },
+            ],
+        };
+
+        assert_eq!(
+            "2023-06-02 ! \"Invoice #2\"\n\
+             \tinvoice_number: \"2023-002\"\n\
+             \tAssets:AccountsReceivable\t100 EUR\n\
+             \tIncome:Work\t-100 EUR\n\
+             \n\
+             2023-06-03 ! \"Invoice #3\"\n\
+             \tinvoice_number: \"2023-003\"\n\
+             \tAssets:AccountsReceivable\t200 EUR\n\
+             \tIncome:Work\t-200 EUR\n",
+            list.as_beancount()
+        );
+    }
 }
@@ -0,0 +1,434 @@
+//! `tabula accounts`: the account aggregate on the command line.
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use beancount_core::Directive;
+use chrono::{Local, NaiveDate};
+use cqrs_es::Aggregate;
+use serde::Serialize;
+use tokio::runtime::Runtime;
+
+use crate::{
+    account::{
+        aggregate::AccountAggregate,
+        chart::{open_commands, DUTCH_SMALL_BUSINESS},
+        command::AccountCommand,
+        event::AccountEvent,
+        services::AccountServices,
+    },
+    adapters::{
+        cli::entries::{close_entry, open_entry},
+        config::Config,
+        ledger_storage::{account_name, FileLedgerStorage, LedgerStorage},
+        logger,
+    },
+    domain::{account::Account, invoice::Date},
+};
+
+use super::{
+    arguments::AccountActions, ledger_file, ledger_storage, tabular::Tabular, CsvOptions, Output,
+};
+
+#[derive(Serialize)]
+pub struct AccountList {
+    pub accounts: Vec<Account>,
+}
+
+impl Output for AccountList {
+    fn as_json(&self) -> String {
+        serde_json::to_string_pretty(&self).unwrap()
+    }
+
+    fn as_txt(&self) -> String {
+        self.tabular().txt()
+    }
+
+    fn as_beancount(&self) -> String {
+        let mut entries: String = self
+            .accounts
+            .iter()
+            .map(|account| open_entry(account, &[]))
+            .collect();
+        for account in &self.accounts {
+            if let Some(closed_on) = &account.closed_on {
+                entries.push_str(&close_entry(&account.name, closed_on));
+            }
+        }
+        entries
+    }
+
+    fn as_csv(&self, options: &CsvOptions) -> String {
+        self.tabular().csv(options)
+    }
+
+    fn as_markdown(&self) -> String {
+        self.tabular().markdown()
+    }
+}
+
+impl AccountList {
+    fn tabular(&self) -> Tabular {
+        let mut tabular = Tabular::new(&["Account", "Opened", "Closed", "Currencies", "Booking"]);
+        for account in &self.accounts {
+            tabular.add_row(vec![
+                account.name.clone(),
+                account.opened_on.to_string(),
+                account
+                    .closed_on
+                    .as_ref()
+                    .map(|date| date.to_string())
+                    .unwrap_or_default(),
+                account.currencies.join(","),
+                account
+                    .booking
+                    .map(|booking| booking.to_string())
+                    .unwrap_or_default(),
+            ]);
+        }
+        tabular
+    }
+}
+
+/// An account and the accounts below it. Parents that are not declared themselves, such as
+/// the root `Assets`, are in the tree to hold their children.
+#[derive(Serialize)]
+pub struct AccountNode {
+    pub name: String,
+    pub account: String,
+    pub declared: bool,
+    pub closed_on: Option<Date>,
+    pub children: Vec<AccountNode>,
+}
+
+impl AccountNode {
+    fn render(&self, prefix: &str, last: bool, output: &mut String) {
+        let (branch, indent) = if last {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+        output.push_str(&format!("{}{}{}", prefix, branch, self.name));
+        if let Some(closed_on) = &self.closed_on {
+            output.push_str(&format!(" (closed on {})", closed_on));
+        }
+        output.push('\n');
+        let prefix = format!("{}{}", prefix, indent);
+        for (i, child) in self.children.iter().enumerate() {
+            child.render(&prefix, i == self.children.len() - 1, output);
+        }
+    }
+}
+
+pub struct AccountTree {
+    pub accounts: AccountList,
+    pub roots: Vec<AccountNode>,
+}
+
+impl AccountTree {
+    pub fn new(mut accounts: Vec<Account>) -> Self {
+        accounts.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let mut roots: Vec<AccountNode> = vec![];
+        for account in &accounts {
+            let mut nodes = &mut roots;
+            let mut path = vec![];
+            for part in account.name.split(':') {
+                path.push(part);
+                let at = match nodes.iter().position(|node| node.name == part) {
+                    Some(at) => at,
+                    None => {
+                        nodes.push(AccountNode {
+                            name: part.to_string(),
+                            account: path.join(":"),
+                            declared: false,
+                            closed_on: None,
+                            children: vec![],
+                        });
+                        nodes.len() - 1
+                    }
+                };
+                if path.len() == account.name.split(':').count() {
+                    nodes[at].declared = true;
+                    nodes[at].closed_on = account.closed_on.clone();
+                }
+                nodes = &mut nodes[at].children;
+            }
+        }
+
+        Self {
+            accounts: AccountList { accounts },
+            roots,
+        }
+    }
+}
+
+impl Output for AccountTree {
+    fn as_json(&self) -> String {
+        serde_json::to_string_pretty(&self.roots).unwrap()
+    }
+
+    fn as_txt(&self) -> String {
+        let mut output = String::new();
+        for root in &self.roots {
+            output.push_str(&root.name);
+            output.push('\n');
+            for (i, child) in root.children.iter().enumerate() {
+                child.render("", i == root.children.len() - 1, &mut output);
+            }
+        }
+        output
+    }
+
+    fn as_beancount(&self) -> String {
+        self.accounts.as_beancount()
+    }
+
+    fn as_csv(&self, options: &CsvOptions) -> String {
+        self.accounts.as_csv(options)
+    }
+}
+
+/// Checks that every posting in `beancount` is on an account that is open on the day of its
+/// transaction. Ledgers that do not declare any accounts are not checked.
+pub fn check_postings(beancount: &str, accounts: &[Account]) -> Result<(), Box<dyn Error>> {
+    if accounts.is_empty() {
+        return Ok(());
+    }
+
+    for directive in beancount_parser::parse(beancount)?.directives {
+        let Directive::Transaction(tx) = directive else {
+            continue;
+        };
+        let date = Date::try_from(tx.date.clone()).map_err(|err| err.for_key("date"))?;
+        for posting in &tx.postings {
+            let name = account_name(&posting.account);
+            if !accounts
+                .iter()
+                .any(|account| account.name == name && account.is_open_on(&date))
+            {
+                return Err(format!(
+                    "{} is not open on {}, open it with `tabula accounts open`",
+                    name, date
+                )
+                .into());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The current state of every account in the ledger, by name.
+fn aggregates(accounts: &[Account]) -> HashMap<String, AccountAggregate> {
+    accounts
+        .iter()
+        .map(|account| (account.name.clone(), AccountAggregate::from(account)))
+        .collect()
+}
+
+/// Runs a command on an account and renders the resulting events as ledger entries.
+fn execute(
+    runtime: &Runtime,
+    account: &mut AccountAggregate,
+    command: AccountCommand,
+    services: &AccountServices,
+) -> Result<String, Box<dyn Error>> {
+    let mut entries = String::new();
+    for event in runtime.block_on(account.handle(command, services))? {
+        account.apply(event.clone());
+        match event {
+            AccountEvent::AccountOpened { metadata, .. } => {
+                let opened = account.account().ok_or("The account has not been opened")?;
+                entries.push_str(&open_entry(&opened, &metadata));
+            }
+            AccountEvent::AccountClosed { date } => {
+                entries.push_str(&close_entry(&account.name, &Date(date)));
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// Creates a ledger that only sets the operating currency, unless it exists.
+fn create_ledger(ledger: &Path, currency: &str) -> Result<(), Box<dyn Error>> {
+    if !ledger.exists() {
+        fs::write(
+            ledger,
+            format!("option \"operating_currency\" \"{}\"\n", currency),
+        )?;
+        logger::info(&format!("Created the ledger {}", ledger.display()));
+    }
+    Ok(())
+}
+
+/// The action as it is recorded in the audit log, such as `accounts open`.
+pub fn action_name(action: &AccountActions) -> &'static str {
+    match action {
+        AccountActions::Open(_) => "accounts open",
+        AccountActions::Close(_) => "accounts close",
+        AccountActions::List => "accounts list",
+        AccountActions::Tree => "accounts tree",
+        AccountActions::Bootstrap(_) => "accounts bootstrap",
+    }
+}
+
+/// Runs an account action. Listing works on any ledger, changes need a ledger file.
+pub fn run_accounts(
+    action: AccountActions,
+    ledger: &Option<PathBuf>,
+    config: &Config,
+) -> Result<Box<dyn Output>, Box<dyn Error>> {
+    let today = Local::now().date_naive();
+    let date = |date: Option<NaiveDate>| date.unwrap_or(today);
+    let path = || ledger_file(ledger, "Changing accounts");
+    let runtime = tokio::runtime::Builder::new_current_thread().build()?;
+
+    match action {
+        AccountActions::List => Ok(Box::new(AccountList {
+            accounts: ledger_storage(ledger)?.find_accounts()?,
+        })),
+        AccountActions::Tree => Ok(Box::new(AccountTree::new(
+            ledger_storage(ledger)?.find_accounts()?,
+        ))),
+        AccountActions::Open(args) => {
+            let storage = FileLedgerStorage::open(path()?)?;
+            let mut account = aggregates(&storage.find_accounts()?)
+                .remove(&args.account)
+                .unwrap_or_default();
+            let command = AccountCommand::Open {
+                account: args.account,
+                date: date(args.date),
+                currencies: args.currencies,
+                booking: args.booking,
+                metadata: vec![],
+            };
+            storage.append(&execute(
+                &runtime,
+                &mut account,
+                command,
+                &AccountServices::default(),
+            )?)?;
+            Ok(Box::new(AccountList {
+                accounts: account.account().into_iter().collect(),
+            }))
+        }
+        AccountActions::Close(args) => {
+            let storage = FileLedgerStorage::open(path()?)?;
+            let date = date(args.date);
+            let mut account = aggregates(&storage.find_accounts()?)
+                .remove(&args.account)
+                .unwrap_or_default();
+            let mut services = AccountServices::default();
+            services.balances.insert(
+                args.account.clone(),
+                storage.balance(&args.account, &Date(date))?,
+            );
+            storage.append(&execute(
+                &runtime,
+                &mut account,
+                AccountCommand::Close { date },
+                &services,
+            )?)?;
+            Ok(Box::new(AccountList {
+                accounts: account.account().into_iter().collect(),
+            }))
+        }
+        AccountActions::Bootstrap(args) => {
+            let currency = args.currency.unwrap_or(config.currency.clone());
+            create_ledger(path()?, &currency)?;
+            let storage = FileLedgerStorage::open(path()?)?;
+            let mut existing = aggregates(&storage.find_accounts()?);
+
+            let mut entries = String::new();
+            let mut opened = vec![];
+            for command in open_commands(DUTCH_SMALL_BUSINESS, date(args.date), &currency) {
+                let AccountCommand::Open { account: name, .. } = &command else {
+                    continue;
+                };
+                if existing.contains_key(name) {
+                    logger::debug(&format!("{} is already in the ledger", name));
+                    continue;
+                }
+                let mut account = AccountAggregate::default();
+                entries.push_str(&execute(
+                    &runtime,
+                    &mut account,
+                    command,
+                    &AccountServices::default(),
+                )?);
+                opened.extend(account.account());
+                existing.insert(account.name.clone(), account);
+            }
+            if !entries.is_empty() {
+                storage.append(&entries)?;
+            }
+            logger::info(&format!("Opened {} accounts", opened.len()));
+
+            Ok(Box::new(AccountList { accounts: opened }))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn account(name: &str, opened_on: &str, closed_on: Option<&str>) -> Account {
+        Account {
+            name: name.to_string(),
+            opened_on: opened_on.into(),
+            closed_on: closed_on.map(Date::from),
+            currencies: vec![],
+            booking: None,
+        }
+    }
+
+    #[test]
+    fn test_postings_must_be_on_open_accounts() {
+        let accounts = vec![
+            account("Assets:AccountsReceivable", "2023-01-01", None),
+            account("Income:Work", "2023-01-01", Some("2023-06-01")),
+        ];
+        let entry = |date: &str| {
+            format!(
+                "{} ! \"Invoice #1\"\n\tAssets:AccountsReceivable\t100 EUR\n\tIncome:Work\t-100 EUR\n",
+                date
+            )
+        };
+
+        assert!(check_postings(&entry("2023-05-31"), &accounts).is_ok());
+        assert_eq!(
+            "Income:Work is not open on 2023-06-01, open it with `tabula accounts open`",
+            check_postings(&entry("2023-06-01"), &accounts)
+                .unwrap_err()
+                .to_string()
+        );
+        assert!(check_postings(&entry("2023-06-01"), &[]).is_ok());
+    }
+
+    #[test]
+    fn test_tree_nests_accounts_under_their_parents() {
+        let tree = AccountTree::new(vec![
+            account("Income:Work", "2023-01-01", None),
+            account("Assets:Bank:Savings", "2023-01-01", Some("2023-06-01")),
+            account("Assets:Bank", "2023-01-01", None),
+            account("Assets:AccountsReceivable", "2023-01-01", None),
+        ]);
+
+        let expected = "Assets
+├── AccountsReceivable
+└── Bank
+    └── Savings (closed on 2023-06-01)
+Income
+└── Work
+";
+        assert_eq!(expected, tree.as_txt());
+        assert!(!tree.roots[0].declared);
+    }
+}
@@ -1,4 +1,13 @@
-use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
+use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum};
+use rust_decimal::Decimal;
+
+use crate::{
+    adapters::logger::{Level, LogFormat},
+    domain::{account::Booking, e_invoice::FacturXProfile, expression::Expression, money::Money},
+};
 
 #[derive(Debug, Parser)]
 #[command(name = "tabula")]
@@ -10,6 +19,43 @@ pub struct Cli {
     /// The format to convert to
     #[arg(long, default_value = "txt")]
     pub format: OutputFormat,
+
+    /// The delimiter between CSV fields, such as ; for Dutch spreadsheets. Defaults to the
+    /// configured one
+    #[arg(long, global = true)]
+    pub csv_delimiter: Option<char>,
+
+    /// The decimal separator of numbers in CSV. Defaults to the configured one
+    #[arg(long, global = true)]
+    pub decimal_separator: Option<char>,
+
+    /// Path to a TOML configuration file
+    #[arg(long, env = "TABULA_CONFIG", global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Path to the ledger. Read from stdin when omitted, which cannot record changes
+    #[arg(long, env = "TABULA_LEDGER", global = true)]
+    pub ledger: Option<PathBuf>,
+
+    /// The least important messages to log on stderr
+    #[arg(long, env = "TABULA_LOG", default_value = "info", global = true)]
+    pub log_level: Level,
+
+    #[arg(
+        long,
+        env = "TABULA_LOG_FORMAT",
+        default_value = "human",
+        global = true
+    )]
+    pub log_format: LogFormat,
+
+    /// Log more, once for each -v
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    pub verbose: u8,
+
+    /// Log less, once for each -q
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    pub quiet: u8,
 }
 
 impl Cli {}
@@ -17,6 +63,22 @@ impl Cli {}
 #[derive(Debug, Subcommand)]
 pub enum Namespace {
     Invoices(InvoicesArgs),
+    Reports(ReportsArgs),
+    Customers(CustomersArgs),
+    /// Serves the invoice commands and reports as a JSON API over HTTP. Needs --ledger
+    Serve(ServeArgs),
+    /// Watches the ledger and runs the scheduled jobs from the configuration. Needs --ledger
+    Daemon,
+    /// Changes invoices through their event-sourced lifecycle, which refuses changes its
+    /// rules do not allow, such as paying a voided invoice. Needs --ledger
+    Lifecycle(LifecycleArgs),
+    /// Opens, closes and lists the accounts declared in the ledger
+    Accounts(AccountsArgs),
+    /// Books bills received from suppliers. Needs --ledger
+    Bills(BillsArgs),
+    /// Checks that the invoices in the ledger follow the conventions tabula reads them by,
+    /// such as unique numbers and line items that add up. Exits with 5 on errors
+    Check,
 }
 
 #[derive(Debug, Args)]
@@ -27,12 +89,355 @@ pub struct InvoicesArgs {
 
 #[derive(Debug, Subcommand)]
 pub enum InvoiceActions {
-    List,
+    List(ListArgs),
     /// Converts to --format of an invoice in a ledger
     Convert(ConvertArgs),
 
     /// Builds a template invoice JSON file
     Build,
+
+    /// Writes reminders for overdue invoices and records them in the ledger
+    Remind(RemindArgs),
+
+    /// Calculates statutory interest and collection costs for an overdue invoice
+    Interest(InterestArgs),
+
+    /// Emails an invoice to the customer and records that it was sent. Needs --ledger
+    Send(SendArgs),
+
+    /// Stores the PDF of an invoice in the document archive and records its hash. Needs --ledger
+    Archive(ConvertArgs),
+
+    /// Issues a draft invoice: numbers it, archives its PDF and marks it `*`. Needs --ledger
+    Issue(IssueInvoiceArgs),
+
+    /// Writes an invoice as a Factur-X PDF, with its CII XML embedded
+    FacturX(FacturXArgs),
+
+    /// Books invoices from a file, such as a CSV exported from a spreadsheet. Needs --ledger
+    Import(ImportInvoicesArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct ListArgs {
+    /// Writes a CSV row per line item, rather than per invoice
+    #[arg(long)]
+    pub expand_lines: bool,
+
+    /// Only invoices dated on or after this day
+    #[arg(long)]
+    pub from: Option<NaiveDate>,
+
+    /// Only invoices dated on or before this day
+    #[arg(long)]
+    pub to: Option<NaiveDate>,
+
+    #[arg(long)]
+    pub customer: Option<String>,
+
+    #[arg(long)]
+    pub status: Option<ListStatus>,
+
+    /// Only invoices with a total of at least this amount
+    #[arg(long)]
+    pub min_amount: Option<Decimal>,
+
+    /// Only invoices with a total of at most this amount
+    #[arg(long)]
+    pub max_amount: Option<Decimal>,
+
+    #[arg(long)]
+    pub currency: Option<String>,
+
+    /// Only invoices with this tag, without the #. Repeat to require several
+    #[arg(long)]
+    pub tag: Vec<String>,
+
+    /// Only invoices with this text in the narration or a line item, ignoring case
+    #[arg(long)]
+    pub search: Option<String>,
+
+    /// Sorts the invoices, which are in ledger order otherwise
+    #[arg(long)]
+    pub sort: Option<ListSort>,
+
+    #[arg(long)]
+    pub reverse: bool,
+
+    /// Shows at most this many invoices
+    #[arg(long)]
+    pub limit: Option<usize>,
+
+    /// Only invoices for which the expression holds, such as
+    /// "status = 'open' and due < today() - 30 and customer ~ 'Acme'"
+    #[arg(long = "where", value_name = "EXPRESSION")]
+    pub filter: Option<Expression>,
+}
+
+#[derive(Debug, ValueEnum, Clone)]
+pub enum ListStatus {
+    Open,
+    /// Open after the due date
+    Overdue,
+    Paid,
+}
+
+#[derive(Debug, ValueEnum, Clone)]
+pub enum ListSort {
+    Date,
+    Number,
+    Due,
+    Amount,
+}
+
+#[derive(Debug, Args)]
+pub struct ImportInvoicesArgs {
+    /// The file with an invoice per row
+    pub file: PathBuf,
+
+    /// The format of the file
+    #[arg(long, default_value = "csv")]
+    pub format: ImportFormat,
+
+    /// Only show the invoices that would be booked, without recording them
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, ValueEnum, Clone)]
+pub enum ImportFormat {
+    /// With the columns configured in [csv.columns]
+    Csv,
+}
+
+#[derive(Debug, Args)]
+pub struct LifecycleArgs {
+    #[command(subcommand)]
+    pub command: LifecycleActions,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum LifecycleActions {
+    /// Starts a draft, which is kept in the event store until it is issued
+    Draft(DraftArgs),
+    /// Books a draft in the ledger
+    Issue(IssueArgs),
+    /// Records that an invoice was sent
+    Send(MarkSentArgs),
+    /// Registers a payment, up to the outstanding amount
+    Pay(PayArgs),
+    /// Cancels an issued invoice with a credit note for its full total
+    Credit(CreditArgs),
+    /// Discards a draft. Issued invoices are credited instead
+    Void(VoidArgs),
+    /// Shows the events of an invoice
+    History(ConvertArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct DraftArgs {
+    #[arg(long)]
+    pub invoice_number: String,
+
+    /// The invoice date. Defaults to today
+    #[arg(long)]
+    pub date: Option<NaiveDate>,
+
+    #[arg(long)]
+    pub customer: Option<String>,
+
+    #[arg(long)]
+    pub billing_email: Option<String>,
+
+    #[arg(long, default_value = "")]
+    pub narration: String,
+
+    /// The total, such as "1000 EUR"
+    #[arg(long)]
+    pub total: Money,
+
+    /// The income account the total is booked on
+    #[arg(long, default_value = "Income:Work")]
+    pub account: String,
+}
+
+#[derive(Debug, Args)]
+pub struct IssueArgs {
+    #[arg(long)]
+    pub invoice_number: String,
+
+    /// The day the invoice is issued. Defaults to today
+    #[arg(long)]
+    pub date: Option<NaiveDate>,
+
+    /// Defaults to the day it is issued plus the payment terms
+    #[arg(long)]
+    pub due_date: Option<NaiveDate>,
+}
+
+#[derive(Debug, Args)]
+pub struct MarkSentArgs {
+    #[arg(long)]
+    pub invoice_number: String,
+
+    /// The address the invoice was sent to
+    #[arg(long)]
+    pub to: String,
+
+    /// Defaults to today
+    #[arg(long)]
+    pub date: Option<NaiveDate>,
+}
+
+#[derive(Debug, Args)]
+pub struct PayArgs {
+    #[arg(long)]
+    pub invoice_number: String,
+
+    /// The amount received, such as "1000 EUR"
+    #[arg(long)]
+    pub amount: Money,
+
+    /// The bank account the payment was received on
+    #[arg(long, default_value = "Assets:Bank")]
+    pub account: String,
+
+    /// Defaults to today
+    #[arg(long)]
+    pub date: Option<NaiveDate>,
+}
+
+#[derive(Debug, Args)]
+pub struct CreditArgs {
+    #[arg(long)]
+    pub invoice_number: String,
+
+    /// The number of the credit note
+    #[arg(long)]
+    pub credit_note_number: String,
+
+    /// Defaults to today
+    #[arg(long)]
+    pub date: Option<NaiveDate>,
+}
+
+#[derive(Debug, Args)]
+pub struct VoidArgs {
+    #[arg(long)]
+    pub invoice_number: String,
+
+    /// Why the draft is discarded
+    #[arg(long)]
+    pub reason: String,
+}
+
+#[derive(Debug, Args)]
+pub struct AccountsArgs {
+    #[command(subcommand)]
+    pub command: AccountActions,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AccountActions {
+    /// Opens an account, optionally for some currencies only. Needs --ledger
+    Open(OpenAccountArgs),
+    /// Closes an account that has no balance left. Needs --ledger
+    Close(CloseAccountArgs),
+    List,
+    /// Shows the accounts by their place in the hierarchy
+    Tree,
+    /// Opens a standard chart of accounts for a Dutch small business, grouped like the
+    /// Referentie Grootboekschema (RGS). Creates the ledger when it does not exist. Needs
+    /// --ledger
+    Bootstrap(BootstrapArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct OpenAccountArgs {
+    /// The account, such as Assets:Bank
+    #[arg(long)]
+    pub account: String,
+
+    /// Defaults to today
+    #[arg(long)]
+    pub date: Option<NaiveDate>,
+
+    /// A currency the account may hold, once for each currency. Any currency when omitted
+    #[arg(long = "currency")]
+    pub currencies: Vec<String>,
+
+    /// How lots are matched when they are reduced: strict, none, average, fifo or lifo
+    #[arg(long)]
+    pub booking: Option<Booking>,
+}
+
+#[derive(Debug, Args)]
+pub struct CloseAccountArgs {
+    #[arg(long)]
+    pub account: String,
+
+    /// Defaults to today
+    #[arg(long)]
+    pub date: Option<NaiveDate>,
+}
+
+#[derive(Debug, Args)]
+pub struct BootstrapArgs {
+    /// The day the accounts are opened. Defaults to today
+    #[arg(long)]
+    pub date: Option<NaiveDate>,
+
+    /// The currency of the accounts. Defaults to the configured currency
+    #[arg(long)]
+    pub currency: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct ReportsArgs {
+    #[command(subcommand)]
+    pub command: ReportActions,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ReportActions {
+    /// Projects the bank balance week by week from open invoices, bills and recurring costs
+    Forecast(ForecastArgs),
+    /// Sums invoiced amounts per customer, month, quarter or income account
+    Revenue(RevenueArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct CustomersArgs {
+    #[command(subcommand)]
+    pub command: CustomerActions,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum CustomerActions {
+    /// Shows how quickly each customer pays, and the Days Sales Outstanding
+    Stats(StatsArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct BillsArgs {
+    #[command(subcommand)]
+    pub command: BillActions,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum BillActions {
+    /// Books a UBL or CII e-invoice from a supplier, and keeps the XML in the document archive
+    Import(ImportBillArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct ImportBillArgs {
+    /// The XML file of the e-invoice
+    pub file: PathBuf,
+
+    /// Only show how the bill would be booked, without recording it
+    #[arg(long)]
+    pub dry_run: bool,
 }
 
 #[derive(Debug, ValueEnum, Clone)]
@@ -40,6 +445,11 @@ pub enum OutputFormat {
     Json,
     Txt,
     Beancount,
+    Csv,
+    /// GitHub-flavoured Markdown, such as for an issue tracker
+    Markdown,
+    /// A UBL e-invoice following Peppol BIS Billing 3.0, for a single invoice
+    Ubl,
 }
 
 #[derive(Debug, Args)]
@@ -49,6 +459,185 @@ pub struct ConvertArgs {
     pub invoice_number: String,
 }
 
+#[derive(Debug, Args)]
+pub struct IssueInvoiceArgs {
+    /// The number of the draft, such as TBD, which then gets the next number of its year
+    #[arg(long)]
+    pub invoice_number: String,
+
+    /// The day the invoice is issued. Defaults to today
+    #[arg(long)]
+    pub date: Option<NaiveDate>,
+}
+
+#[derive(Debug, Args)]
+pub struct ForecastArgs {
+    /// The number of weeks to project
+    #[arg(long, default_value_t = 13)]
+    pub weeks: u32,
+
+    /// The first day of the forecast. Defaults to today
+    #[arg(long)]
+    pub start: Option<NaiveDate>,
+
+    /// Only forecast amounts in this currency. Defaults to the configured currency
+    #[arg(long)]
+    pub currency: Option<String>,
+
+    /// Warn when the projected balance drops below this amount
+    #[arg(long)]
+    pub threshold: Option<Decimal>,
+
+    /// Expect customers to pay as late as they did on average in the past
+    #[arg(long)]
+    pub adjust_for_lateness: bool,
+
+    /// Only expect payment of the invoices for which the expression holds, such as
+    /// "customer != 'Acme'"
+    #[arg(long = "where", value_name = "EXPRESSION")]
+    pub filter: Option<Expression>,
+}
+
+#[derive(Debug, ValueEnum, Clone)]
+pub enum RevenueGroup {
+    Customer,
+    Month,
+    Quarter,
+    Account,
+}
+
+#[derive(Debug, Args)]
+pub struct RevenueArgs {
+    /// What to sum the revenue by
+    #[arg(long, default_value = "customer")]
+    pub group_by: RevenueGroup,
+
+    /// The year to report on, compared to the year before. Defaults to this year
+    #[arg(long)]
+    pub year: Option<i32>,
+
+    /// Only count amounts in this currency. Defaults to the configured currency
+    #[arg(long)]
+    pub currency: Option<String>,
+
+    /// Only show this many groups with the most revenue, and sum the rest as "Other"
+    #[arg(long)]
+    pub top: Option<usize>,
+
+    /// Show the revenue per month as a sparkline in txt output
+    #[arg(long)]
+    pub sparkline: bool,
+
+    /// Only count the invoices for which the expression holds, such as "'hosting' in tags"
+    #[arg(long = "where", value_name = "EXPRESSION")]
+    pub filter: Option<Expression>,
+}
+
+#[derive(Debug, Args)]
+pub struct StatsArgs {
+    /// The day to compute the statistics on. Defaults to today
+    #[arg(long)]
+    pub as_of: Option<NaiveDate>,
+
+    /// The number of days of sales the Days Sales Outstanding is computed over
+    #[arg(long, default_value_t = 90)]
+    pub window_days: i64,
+
+    /// Only count amounts in this currency. Defaults to the configured currency
+    #[arg(long)]
+    pub currency: Option<String>,
+
+    /// Only count the invoices for which the expression holds, such as "total >= 1000"
+    #[arg(long = "where", value_name = "EXPRESSION")]
+    pub filter: Option<Expression>,
+}
+
+#[derive(Debug, Args)]
+pub struct RemindArgs {
+    /// Only list the reminders that would be sent, without recording them
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// The date of the reminders. Defaults to today
+    #[arg(long)]
+    pub date: Option<NaiveDate>,
+
+    /// Only remind of the invoices for which the expression holds, such as "customer ~ 'Acme'"
+    #[arg(long = "where", value_name = "EXPRESSION")]
+    pub filter: Option<Expression>,
+}
+
+#[derive(Debug, Args)]
+pub struct InterestArgs {
+    /// The number of the overdue invoice
+    #[arg(long)]
+    pub invoice_number: String,
+
+    /// Calculate the interest up to and including this date. Defaults to today
+    #[arg(long)]
+    pub date: Option<NaiveDate>,
+}
+
+#[derive(Debug, Args)]
+pub struct SendArgs {
+    /// The number of the invoice to send
+    #[arg(long)]
+    pub invoice_number: String,
+
+    /// Send to this address instead of the billing_email of the invoice
+    #[arg(long)]
+    pub to: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct FacturXArgs {
+    /// The number of the invoice to write
+    #[arg(long)]
+    pub invoice_number: String,
+
+    /// How much of the invoice the XML describes: minimum, basic or en16931
+    #[arg(long, default_value = "en16931")]
+    pub profile: FacturXProfile,
+
+    /// The PDF file to write
+    #[arg(short, long)]
+    pub output: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct ServeArgs {
+    /// The address and port to listen on
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    pub bind: String,
+}
+
+#[derive(Debug, Parser)]
+struct ReportCommand {
+    #[command(subcommand)]
+    report: ReportActions,
+}
+
+/// Parses the options of a single report, as they would follow `tabula reports` on the
+/// command line, e.g. `("revenue", ["--year", "2023"])`.
+pub fn parse_report(report: &str, args: Vec<String>) -> Result<ReportActions, clap::Error> {
+    let args = ["reports".to_string(), report.to_string()]
+        .into_iter()
+        .chain(args);
+    ReportCommand::try_parse_from(args).map(|command| command.report)
+}
+
+#[derive(Debug, Parser)]
+struct ListCommand {
+    #[command(flatten)]
+    list: ListArgs,
+}
+
+/// Parses the options of `tabula invoices list`, e.g. `["--status", "open"]`.
+pub fn parse_list(args: Vec<String>) -> Result<ListArgs, clap::Error> {
+    let args = ["list".to_string()].into_iter().chain(args);
+    ListCommand::try_parse_from(args).map(|command| command.list)
+}
+
 pub fn parse() -> Cli {
     Cli::parse()
 }
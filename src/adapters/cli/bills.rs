@@ -0,0 +1,71 @@
+//! Bills received from suppliers as electronic invoices.
+
+use std::error::Error;
+
+use crate::domain::{bill::BillEntry, e_invoice::EInvoice};
+
+use super::{
+    entries::bill_entry, factur_x::read_cii, tabular::Tabular, ubl::read_ubl, xml::XmlDocument,
+    CsvOptions, Output,
+};
+
+/// Reads an electronic invoice as UBL or CII, whichever its root element is.
+pub fn read_e_invoice(xml: &str) -> Result<EInvoice, Box<dyn Error>> {
+    let document = XmlDocument::read(xml)?;
+    let root = document.root();
+    match root.name() {
+        "Invoice" | "CreditNote" => read_ubl(root),
+        "CrossIndustryInvoice" => read_cii(root),
+        name => Err(format!(
+            "Expected a UBL Invoice or CreditNote, or a CII CrossIndustryInvoice, got <{}>",
+            name
+        )
+        .into()),
+    }
+}
+
+/// The bill, with a row for each posting it is booked as.
+fn bill_tabular(entry: &BillEntry) -> Tabular {
+    let mut tabular = Tabular::new(&["Account", "Amount"]);
+    tabular.add_field("Bill", &entry.bill.number);
+    tabular.add_field("Supplier", entry.bill.supplier.clone().unwrap_or_default());
+    tabular.add_field("Date", &entry.bill.date);
+    tabular.add_field(
+        "Due date",
+        entry
+            .bill
+            .due_date
+            .as_ref()
+            .map(|d| d.to_string())
+            .unwrap_or_default(),
+    );
+    tabular.add_field("Total", &entry.bill.total);
+    tabular.add_field("Document", entry.document.clone().unwrap_or_default());
+    for posting in &entry.postings {
+        tabular.add_row(vec![posting.account.clone(), posting.amount.to_string()]);
+    }
+    tabular.set_amounts(&["Amount"]);
+    tabular
+}
+
+impl Output for BillEntry {
+    fn as_json(&self) -> String {
+        serde_json::to_string_pretty(&self).unwrap()
+    }
+
+    fn as_txt(&self) -> String {
+        bill_tabular(self).txt()
+    }
+
+    fn as_beancount(&self) -> String {
+        bill_entry(self)
+    }
+
+    fn as_csv(&self, options: &CsvOptions) -> String {
+        bill_tabular(self).csv(options)
+    }
+
+    fn as_markdown(&self) -> String {
+        bill_tabular(self).markdown()
+    }
+}
@@ -0,0 +1,185 @@
+//! `tabula check`: the conventions of the ledger, as diagnostics in the style of rustc.
+
+use std::{
+    error::Error,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    adapters::{
+        config::{Config, DocumentsBackend},
+        ledger_storage::check::{
+            check, ledger_files, CheckOptions, Diagnostic, LedgerFile, Severity,
+        },
+    },
+    error::TabulaError,
+};
+
+use super::{csv, e_invoice_options, reports::as_comment, CsvOptions, Output};
+
+pub struct CheckReport {
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl CheckReport {
+    fn count(&self, severity: Severity) -> usize {
+        self.diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.severity == severity)
+            .count()
+    }
+
+    /// Such as `2 errors and 1 warning`.
+    fn summary(&self) -> String {
+        let plural = |count: usize, word: &str| match count {
+            1 => format!("1 {}", word),
+            count => format!("{} {}s", count, word),
+        };
+        match (self.count(Severity::Error), self.count(Severity::Warning)) {
+            (0, 0) => "The ledger follows the conventions".to_string(),
+            (errors, 0) => plural(errors, "error"),
+            (0, warnings) => plural(warnings, "warning"),
+            (errors, warnings) => format!(
+                "{} and {}",
+                plural(errors, "error"),
+                plural(warnings, "warning")
+            ),
+        }
+    }
+}
+
+impl Output for CheckReport {
+    fn as_json(&self) -> String {
+        serde_json::to_string_pretty(&self.diagnostics).unwrap()
+    }
+
+    fn as_txt(&self) -> String {
+        let mut txt = String::new();
+        for diagnostic in &self.diagnostics {
+            txt.push_str(&format!(
+                "{}[{}]: {}\n{}\n\n",
+                diagnostic.severity, diagnostic.code, diagnostic.message, diagnostic.location
+            ));
+        }
+        txt.push_str(&self.summary());
+        txt
+    }
+
+    fn as_beancount(&self) -> String {
+        as_comment(&self.as_txt())
+    }
+
+    fn as_csv(&self, options: &CsvOptions) -> String {
+        let mut rows = vec![["Severity", "Code", "File", "Line", "Message"]
+            .map(String::from)
+            .to_vec()];
+        for diagnostic in &self.diagnostics {
+            rows.push(vec![
+                diagnostic.severity.to_string(),
+                diagnostic.code.to_string(),
+                diagnostic
+                    .location
+                    .file
+                    .as_ref()
+                    .map(|file| file.display().to_string())
+                    .unwrap_or_default(),
+                diagnostic
+                    .location
+                    .line
+                    .map(|line| line.to_string())
+                    .unwrap_or_default(),
+                diagnostic.message.clone(),
+            ]);
+        }
+        csv(&rows, &[], options)
+    }
+
+    /// Fails like a validation error when there are errors, warnings alone pass.
+    fn exit_code(&self) -> u8 {
+        match self.count(Severity::Error) {
+            0 => 0,
+            _ => TabulaError::Validation(self.summary()).exit_code(),
+        }
+    }
+}
+
+/// Checks the ledger file and the files it includes, or the ledger on stdin. Documents are
+/// looked for next to the ledger, as the filesystem document storage keeps them.
+pub fn run_check(
+    ledger: &Option<PathBuf>,
+    config: &Config,
+) -> Result<Box<dyn Output>, Box<dyn Error>> {
+    let (files, base) = match ledger {
+        Some(path) => (
+            ledger_files(path)?,
+            path.parent().unwrap_or_else(|| Path::new("")),
+        ),
+        None => {
+            let mut content = String::new();
+            std::io::stdin().read_to_string(&mut content)?;
+            (
+                vec![LedgerFile {
+                    path: None,
+                    content,
+                }],
+                Path::new(""),
+            )
+        }
+    };
+    let options = CheckOptions {
+        e_invoice: e_invoice_options(config),
+        documents: match config.documents.backend {
+            DocumentsBackend::Filesystem => Some(base.to_path_buf()),
+            DocumentsBackend::S3 => None,
+        },
+    };
+
+    Ok(Box::new(CheckReport {
+        diagnostics: check(&files, &options)?,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::error::Location;
+
+    use super::*;
+
+    #[test]
+    fn test_diagnostics_as_txt_end_with_a_summary() {
+        let report = CheckReport {
+            diagnostics: vec![Diagnostic {
+                severity: Severity::Warning,
+                code: "numbering",
+                message: "Invoice number INV-2 does not follow the numbering of 2023, such as \
+                          2023-001"
+                    .to_string(),
+                location: Location {
+                    file: Some(PathBuf::from("books.beancount")),
+                    line: Some(3),
+                    snippet: vec![
+                        (2, "2023-06-02 ! \"Invoice #2\"".to_string()),
+                        (3, "  invoice_number: \"INV-2\"".to_string()),
+                    ],
+                },
+            }],
+        };
+
+        assert_eq!(
+            "warning[numbering]: Invoice number INV-2 does not follow the numbering of 2023, such \
+             as 2023-001
+  --> books.beancount:3
+  |
+2 | 2023-06-02 ! \"Invoice #2\"
+3 |   invoice_number: \"INV-2\"
+  |
+
+1 warning",
+            report.as_txt()
+        );
+        assert_eq!(0, report.exit_code());
+    }
+}
@@ -0,0 +1,163 @@
+//! Invoices rendered as documents for customers: an HTML page and a PDF.
+
+use std::error::Error;
+
+use printpdf::{IndirectFontRef, Mm, PdfDocument, PdfLayerReference};
+
+use crate::domain::invoice::{Invoice, LineItem};
+
+/// The dates and parties at the top of the document.
+fn details(invoice: &Invoice) -> Vec<(&'static str, String)> {
+    let mut details = vec![("Date issued", invoice.date.to_string())];
+    if let Some(due_date) = &invoice.due_date {
+        details.push(("Due date", due_date.to_string()));
+    }
+    if let Some(customer) = &invoice.customer {
+        details.push(("Customer", customer.clone()));
+    }
+    details
+}
+
+/// The line items, or the narration and total as a single line when there are none.
+pub fn lines(invoice: &Invoice) -> Vec<LineItem> {
+    if !invoice.line_items.is_empty() {
+        return invoice.line_items.clone();
+    }
+    vec![LineItem {
+        description: invoice.narration.clone(),
+        quantity: "1".to_string(),
+        unit_price: invoice.total.clone(),
+        total: invoice.total.clone(),
+        unit: None,
+        account: None,
+    }]
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+pub fn invoice_html(invoice: &Invoice) -> String {
+    let title = format!("Invoice {}", escape(&invoice.number.0));
+
+    let mut html = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str(&format!("<title>{}</title>\n</head>\n<body>\n", title));
+    html.push_str(&format!("<h1>{}</h1>\n<table>\n", title));
+    for (key, value) in details(invoice) {
+        html.push_str(&format!(
+            "<tr><th>{}</th><td>{}</td></tr>\n",
+            key,
+            escape(&value)
+        ));
+    }
+    html.push_str("</table>\n<table>\n");
+    html.push_str("<tr><th>Description</th><th>Qty</th><th>Unit price</th><th>Amount</th></tr>\n");
+    for line in lines(invoice) {
+        html.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            escape(&line.description),
+            escape(&line.quantity),
+            escape(&line.unit_price),
+            escape(&line.total)
+        ));
+    }
+    html.push_str(&format!(
+        "<tr><th colspan=\"3\">Total</th><td>{}</td></tr>\n</table>\n",
+        escape(&invoice.total)
+    ));
+    html.push_str(&format!(
+        "<p>{}</p>\n</body>\n</html>\n",
+        escape(&invoice.narration)
+    ));
+    html
+}
+
+/// DejaVu Sans, embedded in every PDF so that it looks the same everywhere and can be archived
+/// as PDF/A, which allows no fonts that are left to the reader.
+const FONT: &[u8] = include_bytes!("../../../assets/fonts/DejaVuSans.ttf");
+const BOLD_FONT: &[u8] = include_bytes!("../../../assets/fonts/DejaVuSans-Bold.ttf");
+
+/// Writes text on an A4 page from the top down, one row at a time.
+struct PdfWriter {
+    layer: PdfLayerReference,
+    font: IndirectFontRef,
+    bold: IndirectFontRef,
+    y: f32,
+}
+
+impl PdfWriter {
+    const COLUMNS: [f32; 4] = [20.0, 110.0, 130.0, 165.0];
+
+    fn row(&mut self, cells: &[&str], bold: bool) {
+        let font = if bold { &self.bold } else { &self.font };
+        for (cell, x) in cells.iter().zip(Self::COLUMNS) {
+            self.layer.use_text(*cell, 10.0, Mm(x), Mm(self.y), font);
+        }
+        self.y -= 6.0;
+    }
+}
+
+pub fn invoice_pdf(invoice: &Invoice) -> Result<Vec<u8>, Box<dyn Error>> {
+    let title = format!("Invoice {}", invoice.number);
+    let (document, page, layer) = PdfDocument::new(&title, Mm(210.0), Mm(297.0), "Invoice");
+    let mut writer = PdfWriter {
+        layer: document.get_page(page).get_layer(layer),
+        font: document.add_external_font(FONT)?,
+        bold: document.add_external_font(BOLD_FONT)?,
+        y: 270.0,
+    };
+
+    writer
+        .layer
+        .use_text(&title, 18.0, Mm(20.0), Mm(writer.y), &writer.bold);
+    writer.y -= 15.0;
+    for (key, value) in details(invoice) {
+        writer.row(&[key, &value], false);
+    }
+
+    writer.y -= 10.0;
+    writer.row(&["Description", "Qty", "Unit price", "Amount"], true);
+    for line in lines(invoice) {
+        writer.row(
+            &[
+                &line.description,
+                &line.quantity,
+                &line.unit_price,
+                &line.total,
+            ],
+            false,
+        );
+    }
+    writer.row(&["Total", "", "", &invoice.total], true);
+
+    writer.y -= 10.0;
+    writer.row(&[&invoice.narration], false);
+
+    // printpdf leaves the embedded fonts uncompressed, and they are most of the file
+    let mut pdf = lopdf::Document::load_mem(&document.save_to_bytes()?)?;
+    pdf.compress();
+    let mut bytes = Vec::new();
+    pdf.save_to(&mut bytes)?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_html_escapes_text() {
+        let invoice = Invoice {
+            narration: "Design & <build>".to_string(),
+            ..Invoice::default()
+        };
+
+        let html = invoice_html(&invoice);
+
+        assert!(html.contains("<td>Design &amp; &lt;build&gt;</td>"));
+        assert!(!html.contains("<build>"));
+    }
+}
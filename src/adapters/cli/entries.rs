@@ -0,0 +1,174 @@
+//! Ledger entries that tabula adds to the ledger. These are rendered by hand rather than with
+//! beancount_render, which writes text metadata without quotes.
+
+use crate::{
+    domain::{
+        account::{is_valid_name, Account},
+        bill::BillEntry,
+        invoice::{Date, Invoice, InvoiceNumber, InvoiceState},
+        money::Money,
+        payment::Payment,
+    },
+    error::TabulaError,
+};
+
+/// Text as a beancount string, with backslashes and quotes escaped. Text that comes from
+/// outside tabula is checked with `check_text` first.
+pub(crate) fn quoted(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Refuses text that cannot be written on a line of the ledger, such as a line break that
+/// would start an entry of its own.
+pub(crate) fn check_text(field: &str, text: &str) -> Result<(), TabulaError> {
+    if text.chars().any(char::is_control) {
+        let message = format!(
+            "The {} cannot contain line breaks or other control characters",
+            field
+        );
+        return Err(TabulaError::Validation(message));
+    }
+    Ok(())
+}
+
+/// Refuses names that are not beancount accounts, which would break the posting they are on.
+pub(crate) fn check_account(account: &str) -> Result<(), TabulaError> {
+    if !is_valid_name(account) {
+        let message = format!(
+            "{:?} is not a beancount account, such as Income:Work",
+            account
+        );
+        return Err(TabulaError::Validation(message));
+    }
+    Ok(())
+}
+
+fn negative(money: &Money) -> Money {
+    Money::new(-money.amount, &money.currency)
+}
+
+pub fn invoice_entry(invoice: &Invoice) -> String {
+    let flag = match invoice.state {
+        InvoiceState::Draft => "!",
+        InvoiceState::Issued => "*",
+    };
+    let mut entry = format!("{} {} {}", invoice.date, flag, quoted(&invoice.narration));
+    for tag in &invoice.tags {
+        entry.push_str(&format!(" #{}", tag));
+    }
+    entry.push('\n');
+    entry.push_str(&format!(
+        "\tinvoice_number: {}\n",
+        quoted(&invoice.number.0)
+    ));
+    if let Some(customer) = &invoice.customer {
+        entry.push_str(&format!("\tcustomer: {}\n", quoted(customer)));
+    }
+    if let Some(billing_email) = &invoice.billing_email {
+        entry.push_str(&format!("\tbilling_email: {}\n", quoted(billing_email)));
+    }
+    if let Some(credited) = &invoice.credits_invoice {
+        entry.push_str(&format!("\tcredits_invoice: {}\n", quoted(&credited.0)));
+    }
+    if let Some(due_date) = &invoice.due_date {
+        entry.push_str(&format!("\tdue: {}\n", due_date));
+    }
+    if let Some(issued_on) = &invoice.issued_on {
+        entry.push_str(&format!("\tissued: {}\n", issued_on));
+    }
+    entry.push_str(&format!("\tAssets:AccountsReceivable\t{}\n", invoice.total));
+    for line in invoice.revenue_lines() {
+        entry.push_str(&format!("\t{}\t{}\n", line.account, negative(&line.amount)));
+    }
+    entry
+}
+
+pub fn bill_entry(entry: &BillEntry) -> String {
+    let bill = &entry.bill;
+    let mut text = format!("{} ! {}\n", bill.date, quoted(&entry.narration));
+    text.push_str(&format!("\tbill_number: {}\n", quoted(&bill.number)));
+    if let Some(supplier) = &bill.supplier {
+        text.push_str(&format!("\tsupplier: {}\n", quoted(supplier)));
+    }
+    if let Some(due_date) = &bill.due_date {
+        text.push_str(&format!("\tdue: {}\n", due_date));
+    }
+    if let Some(document) = &entry.document {
+        text.push_str(&format!("\tdocument: {}\n", quoted(document)));
+    }
+    if let Some(sha256) = &entry.document_sha256 {
+        text.push_str(&format!("\tdocument_sha256: {}\n", quoted(sha256)));
+    }
+    for posting in &entry.postings {
+        text.push_str(&format!("\t{}\t{}\n", posting.account, posting.amount));
+    }
+    text.push_str(&format!(
+        "\t{}\t{}\n",
+        entry.payable_account,
+        negative(&bill.total)
+    ));
+    text
+}
+
+pub fn payment_entry(number: &InvoiceNumber, payment: &Payment, account: &str) -> String {
+    let mut entry = format!(
+        "{} * {}\n",
+        payment.date,
+        quoted(&format!("Payment of invoice {}", number))
+    );
+    entry.push_str(&format!("\tpaid_invoice: {}\n", quoted(&number.0)));
+    entry.push_str(&format!("\t{}\t{}\n", account, payment.amount));
+    entry.push_str(&format!(
+        "\tAssets:AccountsReceivable\t{}\n",
+        negative(&payment.amount)
+    ));
+    entry
+}
+
+pub fn sent_entry(number: &InvoiceNumber, date: &Date, to: &str) -> String {
+    format!(
+        "{} custom \"sent\" {} {}\n",
+        date,
+        quoted(&number.0),
+        quoted(to)
+    )
+}
+
+/// An `open` directive, with the metadata of the account quoted as text.
+pub fn open_entry(account: &Account, metadata: &[(String, String)]) -> String {
+    let mut entry = format!("{} open {}", account.opened_on, account.name);
+    if !account.currencies.is_empty() {
+        entry.push_str(&format!(" {}", account.currencies.join(",")));
+    }
+    if let Some(booking) = &account.booking {
+        entry.push_str(&format!(" {}", quoted(booking.as_str())));
+    }
+    entry.push('\n');
+    for (key, value) in metadata {
+        entry.push_str(&format!("\t{}: {}\n", key, quoted(value)));
+    }
+    entry
+}
+
+pub fn close_entry(account: &str, date: &Date) -> String {
+    format!("{} close {}\n", date, account)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_cannot_break_out_of_its_string() {
+        assert_eq!(r#""C:\\Acme \"BV\"""#, quoted(r#"C:\Acme "BV""#));
+        assert!(check_text("customer", "Acme B.V.").is_ok());
+        assert_eq!(
+            "The customer cannot contain line breaks or other control characters",
+            check_text("customer", "Acme\n2023-06-01 * \"Injected\"")
+                .unwrap_err()
+                .to_string()
+        );
+        assert!(check_account("Income:Work").is_ok());
+        assert!(check_account("Income:Work\t100 EUR").is_err());
+    }
+}
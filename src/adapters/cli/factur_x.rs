@@ -0,0 +1,827 @@
+//! Factur-X (ZUGFeRD) invoices: a PDF/A-3 for people, with the invoice as Cross Industry
+//! Invoice (CII) XML embedded for software.
+
+use std::error::Error;
+
+use chrono::NaiveDate;
+use lopdf::{dictionary, Document, Object, Stream};
+use rust_decimal::Decimal;
+
+use crate::domain::{
+    e_invoice::{
+        EInvoice, EInvoiceLine, Endpoint, FacturXProfile, Party, PaymentMeans, TaxCategory,
+        TaxSubtotal,
+    },
+    invoice::Invoice,
+};
+
+use super::{
+    document::invoice_pdf,
+    xml::{XmlElement, XmlWriter},
+};
+
+/// The name Factur-X readers look for among the attachments.
+pub const FILE_NAME: &str = "factur-x.xml";
+
+/// The sRGB colour profile of the output intent, which PDF/A asks for to say what its colours
+/// mean.
+const SRGB: &[u8] = include_bytes!("../../../assets/sRGB.icc");
+
+/// What the document info and the XMP metadata name as the program that made the PDF.
+const PRODUCER: &str = "tabula";
+
+fn date(w: &mut XmlWriter, tag: &str, date: NaiveDate) {
+    w.open(tag);
+    w.attributed(
+        "udt:DateTimeString",
+        "format",
+        "102",
+        &date.format("%Y%m%d").to_string(),
+    );
+    w.close(tag);
+}
+
+fn amount(w: &mut XmlWriter, tag: &str, amount: Decimal) {
+    w.element(tag, &format!("{:.2}", amount));
+}
+
+fn party(w: &mut XmlWriter, tag: &str, party: &Party, profile: FacturXProfile) {
+    w.open(tag);
+    w.element("ram:Name", party.name.as_deref().unwrap_or_default());
+    if let Some(company_id) = &party.company_id {
+        w.open("ram:SpecifiedLegalOrganization");
+        w.element("ram:ID", company_id);
+        w.close("ram:SpecifiedLegalOrganization");
+    }
+    // MINIMUM only asks for the country of the seller, and nothing of the address of the buyer
+    let seller = tag == "ram:SellerTradeParty";
+    if seller || profile != FacturXProfile::Minimum {
+        w.open("ram:PostalTradeAddress");
+        if profile != FacturXProfile::Minimum {
+            w.optional("ram:PostcodeCode", &party.postal_code);
+            w.optional("ram:LineOne", &party.street);
+            w.optional("ram:CityName", &party.city);
+        }
+        w.element(
+            "ram:CountryID",
+            party.country.as_deref().unwrap_or_default(),
+        );
+        w.close("ram:PostalTradeAddress");
+    }
+    if let (Some(endpoint), false) = (&party.endpoint, profile == FacturXProfile::Minimum) {
+        w.open("ram:URIUniversalCommunication");
+        w.attributed("ram:URIID", "schemeID", &endpoint.scheme, &endpoint.id);
+        w.close("ram:URIUniversalCommunication");
+    }
+    if let (Some(vat_id), true) = (&party.vat_id, seller || profile != FacturXProfile::Minimum) {
+        w.open("ram:SpecifiedTaxRegistration");
+        w.attributed("ram:ID", "schemeID", "VA", vat_id);
+        w.close("ram:SpecifiedTaxRegistration");
+    }
+    w.close(tag);
+}
+
+fn tax_rate(w: &mut XmlWriter, category: TaxCategory, rate: Decimal) {
+    // Like UBL, CII leaves the rate out for supplies outside the scope of VAT
+    if category != TaxCategory::OutsideScope {
+        w.element("ram:RateApplicablePercent", &rate.normalize().to_string());
+    }
+}
+
+fn line_items(w: &mut XmlWriter, invoice: &EInvoice) {
+    for line in &invoice.lines {
+        w.open("ram:IncludedSupplyChainTradeLineItem");
+        w.open("ram:AssociatedDocumentLineDocument");
+        w.element("ram:LineID", &line.id);
+        w.close("ram:AssociatedDocumentLineDocument");
+        w.open("ram:SpecifiedTradeProduct");
+        w.element("ram:Name", &line.name);
+        w.close("ram:SpecifiedTradeProduct");
+        w.open("ram:SpecifiedLineTradeAgreement");
+        w.open("ram:NetPriceProductTradePrice");
+        w.element("ram:ChargeAmount", &line.price.normalize().to_string());
+        w.close("ram:NetPriceProductTradePrice");
+        w.close("ram:SpecifiedLineTradeAgreement");
+        w.open("ram:SpecifiedLineTradeDelivery");
+        w.attributed(
+            "ram:BilledQuantity",
+            "unitCode",
+            &line.unit_code,
+            &line.quantity.normalize().to_string(),
+        );
+        w.close("ram:SpecifiedLineTradeDelivery");
+        w.open("ram:SpecifiedLineTradeSettlement");
+        w.open("ram:ApplicableTradeTax");
+        w.element("ram:TypeCode", "VAT");
+        w.element("ram:CategoryCode", line.category.code());
+        tax_rate(w, line.category, line.rate);
+        w.close("ram:ApplicableTradeTax");
+        w.open("ram:SpecifiedTradeSettlementLineMonetarySummation");
+        amount(w, "ram:LineTotalAmount", line.net_amount);
+        w.close("ram:SpecifiedTradeSettlementLineMonetarySummation");
+        w.close("ram:SpecifiedLineTradeSettlement");
+        w.close("ram:IncludedSupplyChainTradeLineItem");
+    }
+}
+
+fn settlement(w: &mut XmlWriter, invoice: &EInvoice, profile: FacturXProfile) {
+    let minimum = profile == FacturXProfile::Minimum;
+    w.open("ram:ApplicableHeaderTradeSettlement");
+    if !minimum {
+        w.element("ram:PaymentReference", &invoice.payment.reference);
+    }
+    w.element("ram:InvoiceCurrencyCode", &invoice.currency);
+    if !minimum {
+        // 58 is a SEPA credit transfer
+        w.open("ram:SpecifiedTradeSettlementPaymentMeans");
+        w.element("ram:TypeCode", "58");
+        if let Some(iban) = &invoice.payment.iban {
+            w.open("ram:PayeePartyCreditorFinancialAccount");
+            w.element("ram:IBANID", &iban.replace(' ', ""));
+            w.close("ram:PayeePartyCreditorFinancialAccount");
+        }
+        if let (Some(bic), FacturXProfile::En16931) = (&invoice.payment.bic, profile) {
+            w.open("ram:PayeeSpecifiedCreditorFinancialInstitution");
+            w.element("ram:BICID", bic);
+            w.close("ram:PayeeSpecifiedCreditorFinancialInstitution");
+        }
+        w.close("ram:SpecifiedTradeSettlementPaymentMeans");
+
+        for tax in &invoice.taxes {
+            w.open("ram:ApplicableTradeTax");
+            amount(w, "ram:CalculatedAmount", tax.tax);
+            w.element("ram:TypeCode", "VAT");
+            w.optional("ram:ExemptionReason", &tax.exemption_reason);
+            amount(w, "ram:BasisAmount", tax.taxable);
+            w.element("ram:CategoryCode", tax.category.code());
+            tax_rate(w, tax.category, tax.rate);
+            w.close("ram:ApplicableTradeTax");
+        }
+
+        if invoice.payment_terms.is_some() || invoice.due_date.is_some() {
+            w.open("ram:SpecifiedTradePaymentTerms");
+            w.optional("ram:Description", &invoice.payment_terms);
+            if let Some(due_date) = invoice.due_date {
+                date(w, "ram:DueDateDateTime", due_date);
+            }
+            w.close("ram:SpecifiedTradePaymentTerms");
+        }
+    }
+
+    w.open("ram:SpecifiedTradeSettlementHeaderMonetarySummation");
+    if !minimum {
+        amount(w, "ram:LineTotalAmount", invoice.line_total);
+    }
+    amount(w, "ram:TaxBasisTotalAmount", invoice.tax_exclusive);
+    w.attributed(
+        "ram:TaxTotalAmount",
+        "currencyID",
+        &invoice.currency,
+        &format!("{:.2}", invoice.tax_total),
+    );
+    amount(w, "ram:GrandTotalAmount", invoice.tax_inclusive);
+    amount(w, "ram:DuePayableAmount", invoice.payable);
+    w.close("ram:SpecifiedTradeSettlementHeaderMonetarySummation");
+
+    if let (Some(reference), false) = (&invoice.billing_reference, minimum) {
+        w.open("ram:InvoiceReferencedDocument");
+        w.element("ram:IssuerAssignedID", reference);
+        w.close("ram:InvoiceReferencedDocument");
+    }
+    w.close("ram:ApplicableHeaderTradeSettlement");
+}
+
+/// Renders an invoice or credit note as a CII CrossIndustryInvoice, with as much as
+/// `profile` describes.
+pub fn invoice_cii(invoice: &EInvoice, profile: FacturXProfile) -> String {
+    let mut w = XmlWriter::new(
+        "rsm:CrossIndustryInvoice",
+        &[
+            (
+                "rsm",
+                "urn:un:unece:uncefact:data:standard:CrossIndustryInvoice:100",
+            ),
+            (
+                "qdt",
+                "urn:un:unece:uncefact:data:standard:QualifiedDataType:100",
+            ),
+            (
+                "ram",
+                "urn:un:unece:uncefact:data:standard:ReusableAggregateBusinessInformationEntity:100",
+            ),
+            (
+                "udt",
+                "urn:un:unece:uncefact:data:standard:UnqualifiedDataType:100",
+            ),
+        ],
+    );
+
+    w.open("rsm:ExchangedDocumentContext");
+    w.open("ram:GuidelineSpecifiedDocumentContextParameter");
+    w.element("ram:ID", profile.guideline());
+    w.close("ram:GuidelineSpecifiedDocumentContextParameter");
+    w.close("rsm:ExchangedDocumentContext");
+
+    w.open("rsm:ExchangedDocument");
+    w.element("ram:ID", &invoice.number);
+    w.element("ram:TypeCode", invoice.type_code());
+    date(&mut w, "ram:IssueDateTime", invoice.issue_date);
+    if let (Some(note), false) = (&invoice.note, profile == FacturXProfile::Minimum) {
+        w.open("ram:IncludedNote");
+        w.element("ram:Content", note);
+        w.close("ram:IncludedNote");
+    }
+    w.close("rsm:ExchangedDocument");
+
+    w.open("rsm:SupplyChainTradeTransaction");
+    if profile != FacturXProfile::Minimum {
+        line_items(&mut w, invoice);
+    }
+    w.open("ram:ApplicableHeaderTradeAgreement");
+    w.optional("ram:BuyerReference", &invoice.buyer_reference);
+    party(&mut w, "ram:SellerTradeParty", &invoice.seller, profile);
+    party(&mut w, "ram:BuyerTradeParty", &invoice.buyer, profile);
+    w.close("ram:ApplicableHeaderTradeAgreement");
+    w.open("ram:ApplicableHeaderTradeDelivery");
+    w.close("ram:ApplicableHeaderTradeDelivery");
+    settlement(&mut w, invoice, profile);
+    w.close("rsm:SupplyChainTradeTransaction");
+
+    w.finish()
+}
+
+/// The XMP metadata that marks the PDF as PDF/A-3B and says where its invoice XML is, with
+/// the extension schema PDF/A asks for the Factur-X properties. PDF/A wants the title, dates
+/// and producer to be the same as in the document info.
+fn xmp(title: &str, date: NaiveDate, profile: FacturXProfile) -> String {
+    format!(
+        r#"<?xpacket begin="" id="W5M0MpCehiHzreSzNTczkc9d"?>
+<x:xmpmeta xmlns:x="adobe:ns:meta/">
+  <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+    <rdf:Description rdf:about="" xmlns:pdfaid="http://www.aiim.org/pdfa/ns/id/">
+      <pdfaid:part>3</pdfaid:part>
+      <pdfaid:conformance>B</pdfaid:conformance>
+    </rdf:Description>
+    <rdf:Description rdf:about="" xmlns:dc="http://purl.org/dc/elements/1.1/">
+      <dc:title><rdf:Alt><rdf:li xml:lang="x-default">{title}</rdf:li></rdf:Alt></dc:title>
+    </rdf:Description>
+    <rdf:Description rdf:about="" xmlns:xmp="http://ns.adobe.com/xap/1.0/">
+      <xmp:CreateDate>{date}</xmp:CreateDate>
+      <xmp:ModifyDate>{date}</xmp:ModifyDate>
+    </rdf:Description>
+    <rdf:Description rdf:about="" xmlns:pdf="http://ns.adobe.com/pdf/1.3/">
+      <pdf:Producer>{PRODUCER}</pdf:Producer>
+    </rdf:Description>
+    <rdf:Description rdf:about="" xmlns:pdfaExtension="http://www.aiim.org/pdfa/ns/extension/" xmlns:pdfaSchema="http://www.aiim.org/pdfa/ns/schema#" xmlns:pdfaProperty="http://www.aiim.org/pdfa/ns/property#">
+      <pdfaExtension:schemas>
+        <rdf:Bag>
+          <rdf:li rdf:parseType="Resource">
+            <pdfaSchema:schema>Factur-X PDFA Extension Schema</pdfaSchema:schema>
+            <pdfaSchema:namespaceURI>urn:factur-x:pdfa:CrossIndustryDocument:invoice:1p0#</pdfaSchema:namespaceURI>
+            <pdfaSchema:prefix>fx</pdfaSchema:prefix>
+            <pdfaSchema:property>
+              <rdf:Seq>
+                <rdf:li rdf:parseType="Resource">
+                  <pdfaProperty:name>DocumentFileName</pdfaProperty:name>
+                  <pdfaProperty:valueType>Text</pdfaProperty:valueType>
+                  <pdfaProperty:category>external</pdfaProperty:category>
+                  <pdfaProperty:description>The name of the embedded XML document</pdfaProperty:description>
+                </rdf:li>
+                <rdf:li rdf:parseType="Resource">
+                  <pdfaProperty:name>DocumentType</pdfaProperty:name>
+                  <pdfaProperty:valueType>Text</pdfaProperty:valueType>
+                  <pdfaProperty:category>external</pdfaProperty:category>
+                  <pdfaProperty:description>The type of the hybrid document in capital letters, e.g. INVOICE or ORDER</pdfaProperty:description>
+                </rdf:li>
+                <rdf:li rdf:parseType="Resource">
+                  <pdfaProperty:name>Version</pdfaProperty:name>
+                  <pdfaProperty:valueType>Text</pdfaProperty:valueType>
+                  <pdfaProperty:category>external</pdfaProperty:category>
+                  <pdfaProperty:description>The actual version of the standard applying to the embedded XML document</pdfaProperty:description>
+                </rdf:li>
+                <rdf:li rdf:parseType="Resource">
+                  <pdfaProperty:name>ConformanceLevel</pdfaProperty:name>
+                  <pdfaProperty:valueType>Text</pdfaProperty:valueType>
+                  <pdfaProperty:category>external</pdfaProperty:category>
+                  <pdfaProperty:description>The conformance level of the embedded XML document</pdfaProperty:description>
+                </rdf:li>
+              </rdf:Seq>
+            </pdfaSchema:property>
+          </rdf:li>
+        </rdf:Bag>
+      </pdfaExtension:schemas>
+    </rdf:Description>
+    <rdf:Description rdf:about="" xmlns:fx="urn:factur-x:pdfa:CrossIndustryDocument:invoice:1p0#">
+      <fx:DocumentType>INVOICE</fx:DocumentType>
+      <fx:DocumentFileName>{FILE_NAME}</fx:DocumentFileName>
+      <fx:Version>1.0</fx:Version>
+      <fx:ConformanceLevel>{level}</fx:ConformanceLevel>
+    </rdf:Description>
+  </rdf:RDF>
+</x:xmpmeta>
+<?xpacket end="w"?>"#,
+        title = title.replace('&', "&amp;").replace('<', "&lt;"),
+        date = date.format("%Y-%m-%dT00:00:00Z"),
+        level = profile.conformance_level(),
+    )
+}
+
+/// Renders the same PDF as `invoice_pdf`, with the CII of `e_invoice` attached as
+/// factur-x.xml and the metadata of a Factur-X invoice at `profile`, as a PDF/A-3B.
+pub fn factur_x_pdf(
+    invoice: &Invoice,
+    e_invoice: &EInvoice,
+    profile: FacturXProfile,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let xml = invoice_cii(e_invoice, profile);
+    let mut document = Document::load_mem(&invoice_pdf(invoice)?)?;
+    // The XML describes the invoice as it was issued, so it dates from the issue date
+    let modified =
+        Object::string_literal(e_invoice.issue_date.format("D:%Y%m%d000000Z").to_string());
+
+    let file = document.add_object(Stream::new(
+        dictionary! {
+            "Type" => "EmbeddedFile",
+            "Subtype" => "text/xml",
+            "Params" => dictionary! {
+                "Size" => xml.len() as i64,
+                "ModDate" => modified.clone(),
+            },
+        },
+        xml.into_bytes(),
+    ));
+    // MINIMUM is not enough to pay the invoice by, so it is only data for the bookkeeping
+    let relationship = match profile {
+        FacturXProfile::Minimum => "Data",
+        _ => "Alternative",
+    };
+    let file_spec = document.add_object(dictionary! {
+        "Type" => "Filespec",
+        "F" => Object::string_literal(FILE_NAME),
+        "UF" => Object::string_literal(FILE_NAME),
+        "Desc" => Object::string_literal("Factur-X invoice"),
+        "AFRelationship" => relationship,
+        "EF" => dictionary! { "F" => file, "UF" => file },
+    });
+    let title = format!("Invoice {}", e_invoice.number);
+    let mut metadata = Stream::new(
+        dictionary! { "Type" => "Metadata", "Subtype" => "XML" },
+        xmp(&title, e_invoice.issue_date, profile).into_bytes(),
+    );
+    metadata.allows_compression = false;
+    let metadata = document.add_object(metadata);
+    let info = document.add_object(dictionary! {
+        "Title" => Object::string_literal(title),
+        "Producer" => Object::string_literal(PRODUCER),
+        "CreationDate" => modified.clone(),
+        "ModDate" => modified,
+    });
+    document.trailer.set("Info", info);
+    let profile = document.add_object(Stream::new(dictionary! { "N" => 3 }, SRGB.to_vec()));
+
+    // printpdf writes the glyph ids of the embedded fonts as their CIDs, which PDF/A asks to be
+    // said outright
+    for object in document.objects.values_mut() {
+        let Ok(font) = object.as_dict_mut() else {
+            continue;
+        };
+        if let Ok(Object::Array(descendants)) = font.get_mut(b"DescendantFonts") {
+            for descendant in descendants.iter_mut().filter_map(|d| d.as_dict_mut().ok()) {
+                descendant.set("CIDToGIDMap", "Identity");
+            }
+        }
+    }
+
+    let catalog = document.catalog_mut()?;
+    catalog.set(
+        "OutputIntents",
+        vec![Object::Dictionary(dictionary! {
+            "Type" => "OutputIntent",
+            "S" => "GTS_PDFA1",
+            "OutputConditionIdentifier" => Object::string_literal("sRGB IEC61966-2.1"),
+            "Info" => Object::string_literal("sRGB IEC61966-2.1"),
+            "DestOutputProfile" => profile,
+        })],
+    );
+    // The layer printpdf puts the text on must have a name in PDF/A
+    if let Ok(config) = catalog
+        .get_mut(b"OCProperties")
+        .and_then(Object::as_dict_mut)
+        .and_then(|properties| properties.get_mut(b"D"))
+        .and_then(Object::as_dict_mut)
+    {
+        config.set("Name", Object::string_literal("Invoice"));
+    }
+    catalog.set(
+        "Names",
+        dictionary! {
+            "EmbeddedFiles" => dictionary! {
+                "Names" => vec![Object::string_literal(FILE_NAME), file_spec.into()],
+            },
+        },
+    );
+    catalog.set("AF", vec![Object::from(file_spec)]);
+    catalog.set("Metadata", metadata);
+
+    // lopdf writes no comment of binary characters after the header, which PDF/A asks for so
+    // that the file is not taken for text. Each of these characters is two bytes above 127.
+    document.version = "1.7\n%\u{b5}\u{ed}\u{ae}\u{fb}".to_string();
+    document.prune_objects();
+    document.compress();
+    let mut pdf = Vec::new();
+    document.save_to(&mut pdf)?;
+    Ok(pdf)
+}
+
+fn read_date(element: XmlElement, path: &str) -> Result<Option<NaiveDate>, Box<dyn Error>> {
+    element
+        .text(&format!("{}/DateTimeString", path))
+        .map(|text| {
+            NaiveDate::parse_from_str(&text, "%Y%m%d").map_err(|_| {
+                format!(
+                    "Expected a date like 20230601 at {}, got \"{}\"",
+                    path, text
+                )
+                .into()
+            })
+        })
+        .transpose()
+}
+
+fn read_amount(element: XmlElement, path: &str) -> Result<Decimal, Box<dyn Error>> {
+    Ok(element.parse(path)?.unwrap_or_default())
+}
+
+fn read_party(party: Option<XmlElement>) -> Party {
+    let Some(party) = party else {
+        return Party::default();
+    };
+    let vat_id = party
+        .children_named("SpecifiedTaxRegistration")
+        .filter_map(|registration| registration.child("ID"))
+        .find(|id| id.attribute("schemeID") == Some("VA"))
+        .map(|id| id.value());
+    let endpoint = party
+        .find("URIUniversalCommunication/URIID")
+        .map(|uri| Endpoint {
+            scheme: uri.attribute("schemeID").unwrap_or_default().to_string(),
+            id: uri.value(),
+        });
+    Party {
+        name: party.text("Name"),
+        vat_id,
+        company_id: party.text("SpecifiedLegalOrganization/ID"),
+        endpoint,
+        street: party.text("PostalTradeAddress/LineOne"),
+        city: party.text("PostalTradeAddress/CityName"),
+        postal_code: party.text("PostalTradeAddress/PostcodeCode"),
+        country: party.text("PostalTradeAddress/CountryID"),
+        reference: None,
+    }
+}
+
+fn read_tax(tax: Option<XmlElement>) -> Result<(TaxCategory, Decimal), Box<dyn Error>> {
+    match tax {
+        Some(tax) => Ok((
+            tax.parse("CategoryCode")?.unwrap_or(TaxCategory::Standard),
+            read_amount(tax, "RateApplicablePercent")?,
+        )),
+        None => Ok((TaxCategory::Standard, Decimal::ZERO)),
+    }
+}
+
+/// Reads a CII CrossIndustryInvoice, such as the XML of a Factur-X or ZUGFeRD PDF, at any
+/// profile.
+pub fn read_cii(root: XmlElement) -> Result<EInvoice, Box<dyn Error>> {
+    let document = root
+        .child("ExchangedDocument")
+        .ok_or("The CrossIndustryInvoice has no ExchangedDocument")?;
+    let transaction = root
+        .child("SupplyChainTradeTransaction")
+        .ok_or("The CrossIndustryInvoice has no SupplyChainTradeTransaction")?;
+    let agreement = transaction.child("ApplicableHeaderTradeAgreement");
+    let settlement = transaction
+        .child("ApplicableHeaderTradeSettlement")
+        .ok_or("The CrossIndustryInvoice has no ApplicableHeaderTradeSettlement")?;
+    let summation = settlement
+        .child("SpecifiedTradeSettlementHeaderMonetarySummation")
+        .ok_or("The CrossIndustryInvoice has no SpecifiedTradeSettlementHeaderMonetarySummation")?;
+
+    let mut lines = vec![];
+    for line in transaction.children_named("IncludedSupplyChainTradeLineItem") {
+        let line_settlement = line.child("SpecifiedLineTradeSettlement");
+        let (category, rate) =
+            read_tax(line_settlement.and_then(|s| s.child("ApplicableTradeTax")))?;
+        lines.push(EInvoiceLine {
+            id: line.required("AssociatedDocumentLineDocument/LineID")?,
+            name: line.text("SpecifiedTradeProduct/Name").unwrap_or_default(),
+            quantity: read_amount(line, "SpecifiedLineTradeDelivery/BilledQuantity")?,
+            unit_code: line
+                .find("SpecifiedLineTradeDelivery/BilledQuantity")
+                .and_then(|quantity| quantity.attribute("unitCode"))
+                .unwrap_or_default()
+                .to_string(),
+            price: read_amount(
+                line,
+                "SpecifiedLineTradeAgreement/NetPriceProductTradePrice/ChargeAmount",
+            )?,
+            net_amount: match line_settlement {
+                Some(s) => read_amount(
+                    s,
+                    "SpecifiedTradeSettlementLineMonetarySummation/LineTotalAmount",
+                )?,
+                None => Decimal::ZERO,
+            },
+            category,
+            rate,
+        });
+    }
+
+    let mut taxes = vec![];
+    for tax in settlement.children_named("ApplicableTradeTax") {
+        let (category, rate) = read_tax(Some(tax))?;
+        taxes.push(TaxSubtotal {
+            category,
+            rate,
+            taxable: read_amount(tax, "BasisAmount")?,
+            tax: read_amount(tax, "CalculatedAmount")?,
+            exemption_reason: tax.text("ExemptionReason"),
+        });
+    }
+
+    let means = settlement.child("SpecifiedTradeSettlementPaymentMeans");
+    let terms = settlement.child("SpecifiedTradePaymentTerms");
+    let tax_exclusive = read_amount(summation, "TaxBasisTotalAmount")?;
+    Ok(EInvoice {
+        number: document.required("ID")?,
+        issue_date: read_date(document, "IssueDateTime")?
+            .ok_or("The ExchangedDocument has no IssueDateTime")?,
+        due_date: match terms {
+            Some(terms) => read_date(terms, "DueDateDateTime")?,
+            None => None,
+        },
+        credit_note: document.text("TypeCode").as_deref() == Some("381"),
+        billing_reference: settlement.text("InvoiceReferencedDocument/IssuerAssignedID"),
+        note: document.text("IncludedNote/Content"),
+        currency: settlement.required("InvoiceCurrencyCode")?,
+        buyer_reference: agreement.and_then(|a| a.text("BuyerReference")),
+        seller: read_party(agreement.and_then(|a| a.child("SellerTradeParty"))),
+        buyer: read_party(agreement.and_then(|a| a.child("BuyerTradeParty"))),
+        payment: PaymentMeans {
+            iban: means.and_then(|m| m.text("PayeePartyCreditorFinancialAccount/IBANID")),
+            bic: means.and_then(|m| m.text("PayeeSpecifiedCreditorFinancialInstitution/BICID")),
+            reference: settlement.text("PaymentReference").unwrap_or_default(),
+        },
+        payment_terms: terms.and_then(|t| t.text("Description")),
+        lines,
+        taxes,
+        // MINIMUM has no lines to total
+        line_total: summation.parse("LineTotalAmount")?.unwrap_or(tax_exclusive),
+        tax_exclusive,
+        tax_total: read_amount(summation, "TaxTotalAmount")?,
+        tax_inclusive: read_amount(summation, "GrandTotalAmount")?,
+        payable: read_amount(summation, "DuePayableAmount")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::services::e_invoice::{e_invoice, EInvoiceOptions};
+
+    use lopdf::Dictionary;
+
+    use crate::adapters::cli::xml::XmlDocument;
+
+    use super::*;
+
+    fn options() -> EInvoiceOptions {
+        EInvoiceOptions {
+            iban: Some("NL91 ABNA 0417 1643 00".to_string()),
+            customers: HashMap::new(),
+            ..EInvoiceOptions::default()
+        }
+    }
+
+    #[test]
+    fn test_minimum_leaves_out_lines_and_vat_breakdown() {
+        let invoice = Invoice::issued("2023-001", "2023-06-01", "1210 EUR")
+            .with_customer("Acme")
+            .with_revenue("Income:Work", "-1000 EUR");
+        let e_invoice = e_invoice(&invoice, &options()).unwrap();
+
+        let minimum = invoice_cii(&e_invoice, FacturXProfile::Minimum);
+        let basic = invoice_cii(&e_invoice, FacturXProfile::Basic);
+
+        assert!(minimum.contains("<ram:ID>urn:factur-x.eu:1p0:minimum</ram:ID>"));
+        assert!(!minimum.contains("ram:IncludedSupplyChainTradeLineItem"));
+        assert!(!minimum.contains("ram:IBANID"));
+        assert!(minimum.contains("<ram:DuePayableAmount>1210.00</ram:DuePayableAmount>"));
+        assert!(basic.contains("<ram:LineTotalAmount>1000.00</ram:LineTotalAmount>"));
+        assert!(basic.contains("<ram:IBANID>NL91ABNA0417164300</ram:IBANID>"));
+        assert!(basic.contains("<udt:DateTimeString format=\"102\">20230601</udt:DateTimeString>"));
+    }
+
+    fn content(stream: &Stream) -> Vec<u8> {
+        stream
+            .decompressed_content()
+            .unwrap_or_else(|_| stream.content.clone())
+    }
+
+    /// Breaks of the PDF/A-3B rules that are up to the writer of the PDF, as the clauses of
+    /// ISO 19005-3 they break.
+    fn pdf_a_violations(pdf: &[u8]) -> Vec<String> {
+        let mut violations = vec![];
+        let document = Document::load_mem(pdf).unwrap();
+        let object = |object| document.dereference(object).unwrap().1;
+
+        let comment = pdf.split(|b| *b == b'\n').nth(1).unwrap_or_default();
+        if comment.len() < 5 || comment[0] != b'%' || comment[1..5].iter().any(|b| *b < 128) {
+            violations.push("6.1.2 No comment of binary characters after the header".to_string());
+        }
+        if document
+            .trailer
+            .get(b"ID")
+            .and_then(Object::as_array)
+            .map(Vec::len)
+            .ok()
+            != Some(2)
+        {
+            violations.push("6.1.3 No file identifier in the trailer".to_string());
+        }
+        if document.trailer.has(b"Encrypt") {
+            violations.push("6.1.3 Encrypted".to_string());
+        }
+
+        let catalog = document.catalog().unwrap();
+        let intent = catalog
+            .get(b"OutputIntents")
+            .and_then(Object::as_array)
+            .ok()
+            .and_then(|intents| intents.first())
+            .map(|intent| object(intent).as_dict().unwrap());
+        match intent {
+            Some(intent) => {
+                if intent.get(b"S").and_then(Object::as_name).ok() != Some(b"GTS_PDFA1") {
+                    violations.push("6.2.3 The output intent is not GTS_PDFA1".to_string());
+                }
+                let profile = object(intent.get(b"DestOutputProfile").unwrap())
+                    .as_stream()
+                    .unwrap();
+                let icc = content(profile);
+                let size = u32::from_be_bytes(icc[0..4].try_into().unwrap()) as usize;
+                if size != icc.len() || &icc[36..40] != b"acsp" || &icc[16..20] != b"RGB " {
+                    violations.push("6.2.3 The output profile is no RGB ICC profile".to_string());
+                }
+                if profile.dict.get(b"N").and_then(Object::as_i64).ok() != Some(3) {
+                    violations.push("6.2.3 The output profile has no 3 components".to_string());
+                }
+            }
+            None => violations.push("6.2.3 No output intent".to_string()),
+        }
+
+        let metadata = object(catalog.get(b"Metadata").unwrap())
+            .as_stream()
+            .unwrap();
+        if metadata.dict.has(b"Filter") {
+            violations.push("6.6.2.1 The metadata is compressed".to_string());
+        }
+        let xmp = String::from_utf8(content(metadata)).unwrap();
+        let xmp = XmlDocument::read(&xmp).unwrap();
+        let descriptions: Vec<XmlElement> = xmp
+            .root()
+            .find("RDF")
+            .unwrap()
+            .children_named("Description")
+            .collect();
+        let property = |name: &str| descriptions.iter().find_map(|d| d.text(name));
+        if (property("part"), property("conformance"))
+            != (Some("3".to_string()), Some("B".to_string()))
+        {
+            violations.push("6.6.4 The metadata does not identify PDF/A-3B".to_string());
+        }
+        let info = object(document.trailer.get(b"Info").unwrap())
+            .as_dict()
+            .unwrap();
+        for (key, property) in [
+            ("Title", property("title/Alt/li")),
+            ("Producer", property("Producer")),
+        ] {
+            let value = info.get(key.as_bytes()).and_then(Object::as_str).ok();
+            if value.map(|v| String::from_utf8_lossy(v).to_string()) != property {
+                violations.push(format!("6.6.3 The {} is not the same in the metadata", key));
+            }
+        }
+
+        for font in document.objects.values().filter_map(|o| o.as_dict().ok()) {
+            if !font.type_is(b"Font") {
+                continue;
+            }
+            let descendants: Vec<&Dictionary> = match font.get(b"DescendantFonts") {
+                Ok(descendants) => object(descendants)
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|descendant| object(descendant).as_dict().unwrap())
+                    .collect(),
+                Err(_) => vec![font],
+            };
+            for descendant in descendants {
+                let embedded = descendant
+                    .get(b"FontDescriptor")
+                    .map(|d| object(d).as_dict().unwrap())
+                    .is_ok_and(|d| d.has(b"FontFile2") || d.has(b"FontFile3"));
+                if !embedded {
+                    violations.push("6.2.11.4 A font is not embedded".to_string());
+                }
+                if descendant.get(b"Subtype").and_then(Object::as_name).ok()
+                    == Some(b"CIDFontType2")
+                    && !descendant.has(b"CIDToGIDMap")
+                {
+                    violations.push("6.2.11.3.2 A CIDFontType2 has no CIDToGIDMap".to_string());
+                }
+            }
+        }
+
+        if let Ok(properties) = catalog.get(b"OCProperties") {
+            let config = object(object(properties).as_dict().unwrap().get(b"D").unwrap());
+            if !config.as_dict().unwrap().has(b"Name") {
+                violations.push("6.9 The optional content configuration has no name".to_string());
+            }
+        }
+
+        let attached: Vec<&Object> = catalog
+            .get(b"AF")
+            .and_then(Object::as_array)
+            .map(|files| files.iter().collect())
+            .unwrap_or_default();
+        for file in attached {
+            let file = object(file).as_dict().unwrap();
+            if !file.has(b"AFRelationship") {
+                violations.push("6.8 An attached file has no AFRelationship".to_string());
+            }
+            let stream = file
+                .get(b"EF")
+                .and_then(Object::as_dict)
+                .and_then(|ef| ef.get(b"F"))
+                .map(|f| object(f).as_stream().unwrap());
+            if !stream.is_ok_and(|stream| stream.dict.has(b"Subtype")) {
+                violations.push("6.8 An attached file has no MIME type".to_string());
+            }
+        }
+        violations
+    }
+
+    #[test]
+    fn test_pdf_is_pdf_a_3b_with_the_xml_attached() {
+        let invoice = Invoice::issued("2023-001", "2023-06-01", "1210 EUR")
+            .with_customer("Acme")
+            .with_revenue("Income:Work", "-1000 EUR");
+        let written = e_invoice(&invoice, &options()).unwrap();
+
+        let pdf = factur_x_pdf(&invoice, &written, FacturXProfile::En16931).unwrap();
+
+        assert_eq!(Vec::<String>::new(), pdf_a_violations(&pdf));
+        let document = Document::load_mem(&pdf).unwrap();
+        let names = document.catalog().unwrap().get(b"Names").unwrap();
+        let files = names.as_dict().unwrap().get(b"EmbeddedFiles").unwrap();
+        let files = files.as_dict().unwrap().get(b"Names").unwrap();
+        let file_spec = document
+            .dereference(&files.as_array().unwrap()[1])
+            .unwrap()
+            .1
+            .as_dict()
+            .unwrap();
+        let file = file_spec.get(b"EF").unwrap().as_dict().unwrap().get(b"F");
+        let file = document
+            .get_object(file.unwrap().as_reference().unwrap())
+            .unwrap()
+            .as_stream()
+            .unwrap();
+        let xml = String::from_utf8(content(file)).unwrap();
+        let read = read_cii(XmlDocument::read(&xml).unwrap().root()).unwrap();
+        assert_eq!(written.payable, read.payable);
+    }
+
+    #[test]
+    fn test_reads_what_it_writes() {
+        let invoice = Invoice::issued("2023-001", "2023-06-01", "1210 EUR")
+            .with_customer("Acme")
+            .with_revenue("Income:Work", "-1000 EUR");
+        let written = e_invoice(&invoice, &options()).unwrap();
+
+        let xml = invoice_cii(&written, FacturXProfile::En16931);
+        let read = read_cii(XmlDocument::read(&xml).unwrap().root()).unwrap();
+
+        assert_eq!(written.number, read.number);
+        assert_eq!(written.issue_date, read.issue_date);
+        assert_eq!(written.due_date, read.due_date);
+        assert_eq!(written.lines.len(), read.lines.len());
+        assert_eq!(written.lines[0].net_amount, read.lines[0].net_amount);
+        assert_eq!(TaxCategory::Standard, read.taxes[0].category);
+        assert_eq!(written.tax_total, read.tax_total);
+        assert_eq!(written.payable, read.payable);
+        assert_eq!(Some("NL91ABNA0417164300".to_string()), read.payment.iban);
+    }
+}
@@ -0,0 +1,128 @@
+//! Invoices read from a CSV file, such as one exported from a spreadsheet.
+
+use std::error::Error;
+
+use chrono::NaiveDate;
+
+use crate::{
+    adapters::config::CsvColumns,
+    domain::{
+        invoice::{default_income_account, NewInvoice},
+        money::Money,
+    },
+    error::TabulaError,
+};
+
+use super::{read_csv, CsvOptions};
+
+/// Reads a date as written by tabula, or as Dutch spreadsheets write them.
+fn read_date(value: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .or_else(|_| NaiveDate::parse_from_str(value, "%d-%m-%Y"))
+        .map_err(|_| format!("Expected a date like 2023-06-30, got \"{}\"", value))
+}
+
+/// Reads an amount like `120,50 EUR`, or `120,50` in the default currency.
+fn read_total(value: &str, decimal_separator: char, currency: &str) -> Result<Money, String> {
+    let value = value.replace(decimal_separator, ".");
+    let value = if value.contains(' ') {
+        value
+    } else {
+        format!("{} {}", value, currency)
+    };
+    value.parse().map_err(|e| format!("{}", e))
+}
+
+/// The invoices in the rows of a CSV with a header. Only the total is required, the other
+/// columns are filled in when the invoices are created when they are missing or empty.
+pub fn invoices_from_csv(
+    text: &str,
+    options: &CsvOptions,
+    columns: &CsvColumns,
+    currency: &str,
+) -> Result<Vec<NewInvoice>, Box<dyn Error>> {
+    let mut rows = read_csv(text, options.delimiter)?.into_iter();
+    let header = rows.next().ok_or("The CSV has no header")?;
+    let index = |column: &str| header.iter().position(|h| h.trim() == column);
+    let total_index =
+        index(&columns.total).ok_or_else(|| format!("The CSV has no {} column", columns.total))?;
+
+    rows.enumerate()
+        .map(|(n, row)| {
+            let field = |column: &str| {
+                index(column)
+                    .and_then(|i| row.get(i))
+                    .map(|value| value.trim().to_string())
+                    .filter(|value| !value.is_empty())
+            };
+            let read = || -> Result<NewInvoice, String> {
+                let total = row.get(total_index).map(|t| t.trim()).unwrap_or_default();
+                Ok(NewInvoice {
+                    number: field(&columns.number),
+                    date: field(&columns.date).map(|d| read_date(&d)).transpose()?,
+                    due_date: field(&columns.due_date)
+                        .map(|d| read_date(&d))
+                        .transpose()?,
+                    customer: field(&columns.customer),
+                    billing_email: None,
+                    narration: field(&columns.narration).unwrap_or_default(),
+                    total: read_total(total, options.decimal_separator, currency)?,
+                    account: field(&columns.account).unwrap_or_else(default_income_account),
+                })
+            };
+            // Row 1 is the header
+            read().map_err(|e| TabulaError::Validation(format!("Row {}: {}", n + 2, e)).into())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_reads_dutch_spreadsheets_with_mapped_columns() {
+        let columns = CsvColumns {
+            number: "Factuurnummer".to_string(),
+            date: "Datum".to_string(),
+            customer: "Klant".to_string(),
+            total: "Bedrag".to_string(),
+            ..CsvColumns::default()
+        };
+        let options = CsvOptions {
+            delimiter: ';',
+            decimal_separator: ',',
+            ..CsvOptions::default()
+        };
+        let csv = "Factuurnummer;Datum;Klant;Bedrag\n\
+                   2023-010;30-06-2023;\"Acme; Co\";1250,50\n\
+                   ;;Globex;99 USD\n";
+
+        let invoices = invoices_from_csv(csv, &options, &columns, "EUR").unwrap();
+
+        assert_eq!(2, invoices.len());
+        assert_eq!(Some("2023-010".to_string()), invoices[0].number);
+        assert_eq!(NaiveDate::from_ymd_opt(2023, 6, 30), invoices[0].date);
+        assert_eq!(Some("Acme; Co".to_string()), invoices[0].customer);
+        assert_eq!("1250.50 EUR", invoices[0].total.to_string());
+        assert_eq!("Income:Work", invoices[0].account);
+        assert_eq!(None, invoices[1].number);
+        assert_eq!(None, invoices[1].date);
+        assert_eq!("99 USD", invoices[1].total.to_string());
+    }
+
+    #[test]
+    fn test_names_the_row_that_cannot_be_read() {
+        let csv = "Number,Total\n2023-001,100\n2023-002,a lot\n";
+
+        let err = invoices_from_csv(csv, &CsvOptions::default(), &CsvColumns::default(), "EUR")
+            .unwrap_err();
+
+        assert_eq!(
+            "Row 3: Expected an amount like \"1337 USD\", got \"a lot\"",
+            err.to_string()
+        );
+    }
+}
@@ -0,0 +1,130 @@
+use crate::{domain::money::Money, services::interest::InterestCalculation};
+
+use super::{entries::quoted, tabular::Tabular, CsvOptions, Output};
+
+impl InterestCalculation {
+    fn interest_invoice_number(&self) -> String {
+        format!("{}-interest", self.invoice.number)
+    }
+
+    /// The interest invoice, with a row for each period between changes of the rate.
+    fn tabular(&self) -> Tabular {
+        let mut tabular = Tabular::new(&["From", "Until", "Days", "Rate", "Principal", "Interest"]);
+        tabular.add_field("Invoice", self.interest_invoice_number());
+        tabular.add_field("Date issued", &self.date);
+        tabular.add_field("Due date", &self.due_date);
+        tabular.add_field(
+            "Customer",
+            self.invoice.customer.clone().unwrap_or_default(),
+        );
+        tabular.add_field("Overdue invoice", &self.invoice.number);
+        tabular.add_field("Principal", &self.principal);
+        for period in &self.periods {
+            tabular.add_row(vec![
+                period.from.to_string(),
+                period.until.to_string(),
+                period.days.to_string(),
+                format!("{}%", period.rate),
+                period.principal.to_string(),
+                period.interest.to_string(),
+            ]);
+        }
+        tabular.add_total("Statutory interest", &self.interest);
+        tabular.add_total("Collection costs", &self.collection_costs);
+        tabular.add_total("Total", &self.total);
+        tabular.set_amounts(&["Days", "Rate", "Principal", "Interest"]);
+        tabular.set_currency_column("Principal");
+        tabular
+    }
+}
+
+impl Output for InterestCalculation {
+    fn as_json(&self) -> String {
+        serde_json::to_string_pretty(&self).unwrap()
+    }
+
+    fn as_txt(&self) -> String {
+        self.tabular().txt()
+    }
+
+    fn as_beancount(&self) -> String {
+        let negative = |money: &Money| Money::new(-money.amount, &money.currency);
+
+        let narration = format!(
+            "Statutory interest and collection costs for invoice {}",
+            self.invoice.number
+        );
+        let mut entry = format!("{} ! {}\n", self.date, quoted(&narration));
+        entry.push_str(&format!(
+            "\tinvoice_number: {}\n",
+            quoted(&self.interest_invoice_number())
+        ));
+        entry.push_str(&format!(
+            "\tinterest_for: {}\n",
+            quoted(&self.invoice.number.0)
+        ));
+        if let Some(customer) = &self.invoice.customer {
+            entry.push_str(&format!("\tcustomer: {}\n", quoted(customer)));
+        }
+        entry.push_str(&format!("\tdue: {}\n", self.due_date));
+        entry.push_str(&format!("\tAssets:AccountsReceivable\t{}\n", self.total));
+        entry.push_str(&format!(
+            "\tIncome:StatutoryInterest\t{}\n",
+            negative(&self.interest)
+        ));
+        if !self.collection_costs.amount.is_zero() {
+            entry.push_str(&format!(
+                "\tIncome:CollectionCosts\t{}\n",
+                negative(&self.collection_costs)
+            ));
+        }
+        entry
+    }
+
+    fn as_csv(&self, options: &CsvOptions) -> String {
+        self.tabular().csv(options)
+    }
+
+    fn as_markdown(&self) -> String {
+        self.tabular().markdown()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::domain::invoice::{Invoice, InvoiceNumber};
+
+    use super::*;
+
+    #[test]
+    fn test_as_beancount_escapes_the_customer() {
+        let money = |amount: &str| amount.parse::<Money>().unwrap();
+        let calculation = InterestCalculation {
+            date: "2024-01-31".into(),
+            due_date: "2024-03-01".into(),
+            invoice: Invoice {
+                number: InvoiceNumber("2022-001".to_string()),
+                customer: Some("Acme \"Tools\" \\ Co".to_string()),
+                ..Invoice::default()
+            },
+            principal: money("1000 EUR"),
+            periods: vec![],
+            interest: money("124.37 EUR"),
+            collection_costs: money("0 EUR"),
+            total: money("124.37 EUR"),
+        };
+
+        assert_eq!(
+            "2024-01-31 ! \"Statutory interest and collection costs for invoice 2022-001\"\n\
+             \tinvoice_number: \"2022-001-interest\"\n\
+             \tinterest_for: \"2022-001\"\n\
+             \tcustomer: \"Acme \\\"Tools\\\" \\\\ Co\"\n\
+             \tdue: 2024-03-01\n\
+             \tAssets:AccountsReceivable\t124.37 EUR\n\
+             \tIncome:StatutoryInterest\t-124.37 EUR\n",
+            calculation.as_beancount()
+        );
+    }
+}
@@ -0,0 +1,302 @@
+//! `tabula lifecycle`: the event-sourced invoice aggregate on the command line.
+
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+};
+
+use chrono::{Local, NaiveDate};
+use cqrs_es::{Aggregate, CqrsFramework, DomainEvent, EventStore};
+use serde::Serialize;
+
+use crate::{
+    adapters::{
+        cli::entries::{check_account, check_text, invoice_entry},
+        config::Config,
+        ledger_storage::{FileLedgerStorage, LedgerStorage},
+    },
+    domain::invoice::InvoiceNumber,
+    error::TabulaError,
+    invoicing::{
+        aggregate::InvoiceAggregate, command::InvoiceCommand, event::InvoiceEvent,
+        queries::LedgerProjection, services::InvoiceServices, store::FileEventStore,
+    },
+};
+
+use super::{arguments::LifecycleActions, tabular::Tabular, CsvOptions, Output};
+
+#[derive(Serialize)]
+pub struct InvoiceHistory {
+    pub invoice: InvoiceAggregate,
+    pub events: Vec<HistoryEvent>,
+}
+
+#[derive(Serialize)]
+pub struct HistoryEvent {
+    pub sequence: usize,
+    pub event: InvoiceEvent,
+}
+
+fn describe(event: &InvoiceEvent) -> String {
+    match event {
+        InvoiceEvent::InvoiceDrafted { date, total, .. } => {
+            format!("Drafted on {} for {}", date, total)
+        }
+        InvoiceEvent::InvoiceIssued { date, due_date } => {
+            format!("Issued on {}, due on {}", date, due_date)
+        }
+        InvoiceEvent::InvoiceSent { date, to } => format!("Sent to {} on {}", to, date),
+        InvoiceEvent::PaymentRegistered { date, amount, .. } => {
+            format!("Paid {} on {}", amount, date)
+        }
+        InvoiceEvent::InvoiceCredited {
+            credit_note_number,
+            date,
+            ..
+        } => format!("Credited by {} on {}", credit_note_number, date),
+        InvoiceEvent::InvoiceVoided { date, reason } => {
+            format!("Voided on {}: {}", date, reason)
+        }
+    }
+}
+
+/// The current state of the invoice, with a row for each event that led to it.
+fn history_tabular(history: &InvoiceHistory) -> Tabular {
+    let status = serde_json::to_value(history.invoice.status).unwrap();
+
+    let mut tabular = Tabular::new(&["#", "Event", "Description"]);
+    tabular.add_field("Invoice", &history.invoice.number);
+    tabular.add_field("Status", status.as_str().unwrap_or_default());
+    tabular.add_field(
+        "Total",
+        history
+            .invoice
+            .total
+            .as_ref()
+            .map(|total| total.to_string())
+            .unwrap_or_default(),
+    );
+    for event in &history.events {
+        tabular.add_row(vec![
+            event.sequence.to_string(),
+            event.event.event_type(),
+            describe(&event.event),
+        ]);
+    }
+    tabular
+}
+
+impl Output for InvoiceHistory {
+    fn as_json(&self) -> String {
+        serde_json::to_string_pretty(&self).unwrap()
+    }
+
+    fn as_txt(&self) -> String {
+        history_tabular(self).txt()
+    }
+
+    fn as_beancount(&self) -> String {
+        [self.invoice.invoice(), self.invoice.credit_note()]
+            .iter()
+            .flatten()
+            .map(invoice_entry)
+            .collect()
+    }
+
+    fn as_csv(&self, options: &CsvOptions) -> String {
+        history_tabular(self).csv(options)
+    }
+
+    fn as_markdown(&self) -> String {
+        history_tabular(self).markdown()
+    }
+}
+
+async fn history(
+    store: &FileEventStore<InvoiceAggregate>,
+    number: &str,
+) -> Result<InvoiceHistory, Box<dyn Error>> {
+    let mut invoice = InvoiceAggregate::default();
+    let mut events = vec![];
+    for envelope in store.load_events(number).await? {
+        invoice.apply(envelope.payload.clone());
+        events.push(HistoryEvent {
+            sequence: envelope.sequence,
+            event: envelope.payload,
+        });
+    }
+    if events.is_empty() {
+        return Err(format!("Invoice {} has no lifecycle events", number).into());
+    }
+
+    Ok(InvoiceHistory { invoice, events })
+}
+
+/// The action as it is recorded in the audit log, such as `lifecycle pay`.
+pub fn action_name(action: &LifecycleActions) -> &'static str {
+    match action {
+        LifecycleActions::Draft(_) => "lifecycle draft",
+        LifecycleActions::Issue(_) => "lifecycle issue",
+        LifecycleActions::Send(_) => "lifecycle send",
+        LifecycleActions::Pay(_) => "lifecycle pay",
+        LifecycleActions::Credit(_) => "lifecycle credit",
+        LifecycleActions::Void(_) => "lifecycle void",
+        LifecycleActions::History(_) => "lifecycle history",
+    }
+}
+
+/// Refuses a number for a new invoice or credit note that the ledger or the lifecycle already
+/// has.
+async fn check_new_number(
+    number: &str,
+    ledger: &Path,
+    store: &FileEventStore<InvoiceAggregate>,
+) -> Result<(), Box<dyn Error>> {
+    check_text("invoice number", number)?;
+    let number = InvoiceNumber(number.to_string());
+    if FileLedgerStorage::open(ledger)?
+        .find_invoice(&number)
+        .is_ok()
+    {
+        let message = format!("Invoice {} is already in the ledger", number);
+        return Err(TabulaError::Validation(message).into());
+    }
+    if !store.load_events(&number.0).await?.is_empty() {
+        let message = format!("Invoice {} already has lifecycle events", number);
+        return Err(TabulaError::Validation(message).into());
+    }
+    Ok(())
+}
+
+/// The invoice number and aggregate command of an action.
+async fn command(
+    action: LifecycleActions,
+    ledger: &Path,
+    store: &FileEventStore<InvoiceAggregate>,
+) -> Result<(String, InvoiceCommand), Box<dyn Error>> {
+    let today = || Local::now().date_naive();
+    let date = |date: Option<NaiveDate>| date.unwrap_or_else(today);
+
+    Ok(match action {
+        LifecycleActions::Draft(args) => {
+            let number = InvoiceNumber(args.invoice_number.clone());
+            check_new_number(&args.invoice_number, ledger, store).await?;
+            check_text("customer", args.customer.as_deref().unwrap_or_default())?;
+            check_text(
+                "billing email",
+                args.billing_email.as_deref().unwrap_or_default(),
+            )?;
+            check_text("narration", &args.narration)?;
+            check_account(&args.account)?;
+            let narration = if args.narration.is_empty() {
+                format!("Invoice #{}", number)
+            } else {
+                args.narration
+            };
+            (
+                args.invoice_number.clone(),
+                InvoiceCommand::Draft {
+                    number: args.invoice_number,
+                    date: date(args.date),
+                    customer: args.customer,
+                    billing_email: args.billing_email,
+                    narration,
+                    total: args.total,
+                    account: args.account,
+                },
+            )
+        }
+        LifecycleActions::Issue(args) => (
+            args.invoice_number,
+            InvoiceCommand::Issue {
+                date: date(args.date),
+                due_date: args.due_date,
+            },
+        ),
+        LifecycleActions::Send(args) => (
+            args.invoice_number,
+            InvoiceCommand::Send {
+                date: date(args.date),
+                to: args.to,
+            },
+        ),
+        LifecycleActions::Pay(args) => (
+            args.invoice_number,
+            InvoiceCommand::RegisterPayment {
+                date: date(args.date),
+                amount: args.amount,
+                account: args.account,
+            },
+        ),
+        LifecycleActions::Credit(args) => {
+            check_new_number(&args.credit_note_number, ledger, store).await?;
+            (
+                args.invoice_number,
+                InvoiceCommand::Credit {
+                    credit_note_number: args.credit_note_number,
+                    date: date(args.date),
+                },
+            )
+        }
+        LifecycleActions::Void(args) => (
+            args.invoice_number,
+            InvoiceCommand::Void {
+                date: today(),
+                reason: args.reason,
+            },
+        ),
+        LifecycleActions::History(_) => return Err("Showing the history is not a command".into()),
+    })
+}
+
+/// Where the events that are not in the ledger yet are kept: next to the events, as
+/// `invoice-events.pending.jsonl` for `invoice-events.jsonl`.
+fn pending_path(events: &Path) -> PathBuf {
+    let stem = events.file_stem().unwrap_or_default().to_string_lossy();
+    events.with_file_name(format!("{}.pending.jsonl", stem))
+}
+
+/// Runs a lifecycle action on the invoices of a ledger, and shows the invoice after it.
+pub fn run_lifecycle(
+    action: LifecycleActions,
+    ledger: &Path,
+    config: &Config,
+) -> Result<Box<dyn Output>, Box<dyn Error>> {
+    let base = ledger.parent().unwrap_or_else(|| Path::new(""));
+    let store = FileEventStore::new(&base.join(&config.lifecycle.events));
+    let runtime = tokio::runtime::Builder::new_current_thread().build()?;
+
+    let action = match action {
+        LifecycleActions::History(args) => {
+            return Ok(Box::new(
+                runtime.block_on(history(&store, &args.invoice_number))?,
+            ))
+        }
+        action => action,
+    };
+    let projection = LedgerProjection::new(
+        ledger.to_path_buf(),
+        store.clone(),
+        &base.join(pending_path(&config.lifecycle.events)),
+    );
+    let failure = projection.failure();
+
+    runtime.block_on(async {
+        // Events that earlier commands could not write to the ledger go first
+        projection.replay().await?;
+        let (number, command) = command(action, ledger, &store).await?;
+        let cqrs = CqrsFramework::new(
+            store.clone(),
+            vec![Box::new(projection)],
+            InvoiceServices {
+                payment_terms_days: config.payment_terms_days,
+            },
+        );
+
+        cqrs.execute(&number, command).await?;
+        if let Some(failure) = failure.lock().unwrap().take() {
+            return Err(failure.into());
+        }
+        Ok(Box::new(history(&store, &number).await?) as Box<dyn Output>)
+    })
+}
@@ -0,0 +1,112 @@
+use crate::services::dunning::{ReminderLetter, ReminderLetters};
+
+use super::{entries::quoted, tabular::Tabular, CsvOptions, Output};
+
+impl ReminderLetter {
+    /// The reminder, above the invoice it is about.
+    fn tabular(&self) -> Tabular {
+        let mut tabular = Tabular::new(&[]);
+        tabular.add_field("Reminder", &self.level);
+        tabular.add_field("Date", &self.date);
+        tabular.add_field("Days overdue", self.days_overdue);
+        tabular.add_field("Outstanding", &self.outstanding);
+        tabular
+    }
+}
+
+impl Output for ReminderLetter {
+    fn as_json(&self) -> String {
+        serde_json::to_string_pretty(&self).unwrap()
+    }
+
+    fn as_txt(&self) -> String {
+        format!("{}\n{}", self.tabular().txt(), self.invoice.as_txt())
+    }
+
+    fn as_beancount(&self) -> String {
+        format!(
+            "{} custom \"reminder\" {} {}\n",
+            self.date,
+            quoted(&self.invoice.number.0),
+            quoted(self.level.as_str())
+        )
+    }
+
+    fn as_csv(&self, options: &CsvOptions) -> String {
+        reminders_tabular(std::slice::from_ref(self)).csv(options)
+    }
+
+    fn as_markdown(&self) -> String {
+        format!(
+            "{}\n{}",
+            self.tabular().markdown(),
+            self.invoice.as_markdown()
+        )
+    }
+}
+
+/// A row for each letter, such as to review a dry run.
+fn reminders_tabular(letters: &[ReminderLetter]) -> Tabular {
+    let mut tabular = Tabular::new(&[
+        "Invoice",
+        "Customer",
+        "Reminder",
+        "Days overdue",
+        "Outstanding",
+    ]);
+    for letter in letters {
+        tabular.add_row(vec![
+            letter.invoice.number.to_string(),
+            letter.invoice.customer.clone().unwrap_or_default(),
+            letter.level.to_string(),
+            letter.days_overdue.to_string(),
+            letter.outstanding.to_string(),
+        ]);
+    }
+    tabular.set_amounts(&["Days overdue", "Outstanding"]);
+    tabular.set_currency_column("Outstanding");
+    tabular
+}
+
+impl Output for ReminderLetters {
+    fn as_json(&self) -> String {
+        serde_json::to_string_pretty(&self).unwrap()
+    }
+
+    fn as_txt(&self) -> String {
+        if !self.dry_run {
+            return self
+                .letters
+                .iter()
+                .map(|letter| letter.as_txt())
+                .collect::<Vec<String>>()
+                .join("\n\n");
+        }
+
+        reminders_tabular(&self.letters).txt()
+    }
+
+    fn as_beancount(&self) -> String {
+        self.letters
+            .iter()
+            .map(|letter| letter.as_beancount())
+            .collect()
+    }
+
+    fn as_csv(&self, options: &CsvOptions) -> String {
+        reminders_tabular(&self.letters).csv(options)
+    }
+
+    fn as_markdown(&self) -> String {
+        if !self.dry_run {
+            return self
+                .letters
+                .iter()
+                .map(|letter| letter.as_markdown())
+                .collect::<Vec<String>>()
+                .join("\n");
+        }
+
+        reminders_tabular(&self.letters).markdown()
+    }
+}
@@ -0,0 +1,191 @@
+use rust_decimal::Decimal;
+
+use crate::services::{
+    forecast::Forecast,
+    payment_behaviour::PaymentBehaviour,
+    revenue::{sparkline, RevenueReport},
+};
+
+use super::{tabular::Tabular, CsvOptions, Output};
+
+/// Reports have no ledger entries of their own, so they are rendered as beancount comments.
+pub(super) fn as_comment(txt: &str) -> String {
+    txt.lines()
+        .map(|line| format!("; {}", line).trim_end().to_string())
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn forecast_tabular(forecast: &Forecast) -> Tabular {
+    let mut tabular = Tabular::new(&["Week", "Starting", "Inflow", "Outflow", "Balance"]);
+    tabular.add_field("Start", &forecast.start);
+    tabular.add_field("Opening balance", &forecast.opening_balance);
+    tabular.add_field("Threshold", optional(&forecast.threshold));
+    for (number, week) in forecast.weeks.iter().enumerate() {
+        tabular.add_row(vec![
+            (number + 1).to_string(),
+            week.start.to_string(),
+            week.inflow.to_string(),
+            week.outflow.to_string(),
+            week.balance.to_string(),
+        ]);
+    }
+    if let (Some((number, week)), Some(threshold)) =
+        (forecast.first_shortfall(), &forecast.threshold)
+    {
+        tabular.set_footer(format!(
+            "Warning: balance of {} in week {} ({}) is below the threshold of {}",
+            week.balance,
+            number + 1,
+            week.start,
+            threshold
+        ));
+    }
+    tabular.set_amounts(&["Inflow", "Outflow", "Balance"]);
+    tabular.set_currency_column("Inflow");
+    tabular
+}
+
+impl Output for Forecast {
+    fn as_json(&self) -> String {
+        serde_json::to_string_pretty(&self).unwrap()
+    }
+
+    fn as_txt(&self) -> String {
+        forecast_tabular(self).txt()
+    }
+
+    fn as_beancount(&self) -> String {
+        as_comment(&self.as_txt())
+    }
+
+    fn as_csv(&self, options: &CsvOptions) -> String {
+        forecast_tabular(self).csv(options)
+    }
+
+    fn as_markdown(&self) -> String {
+        forecast_tabular(self).markdown()
+    }
+}
+
+fn percentage(value: &Decimal) -> String {
+    format!("{}%", value)
+}
+
+fn change(value: &Option<Decimal>) -> String {
+    match value {
+        Some(value) if value.is_sign_positive() => format!("+{}%", value),
+        Some(value) => format!("{}%", value),
+        None => "".to_string(),
+    }
+}
+
+fn revenue_tabular(report: &RevenueReport) -> Tabular {
+    let mut header = vec!["Group", "Amount", "Share", "Previous year", "Change"];
+    if report.sparkline {
+        header.push("Trend");
+    }
+
+    let mut tabular = Tabular::new(&header);
+    tabular.add_field("Year", report.year);
+    tabular.add_field("Total", &report.total);
+    for row in &report.rows {
+        let mut cells = vec![
+            row.group.clone(),
+            row.amount.to_string(),
+            percentage(&row.share),
+            row.previous.to_string(),
+            change(&row.change),
+        ];
+        if report.sparkline {
+            let monthly: Vec<Decimal> = row.monthly.iter().map(|m| m.amount).collect();
+            cells.push(sparkline(&monthly));
+        }
+        tabular.add_row(cells);
+    }
+    tabular.set_amounts(&["Amount", "Share", "Previous year", "Change"]);
+    tabular.set_currency_column("Amount");
+    tabular
+}
+
+impl Output for RevenueReport {
+    fn as_json(&self) -> String {
+        serde_json::to_string_pretty(&self).unwrap()
+    }
+
+    fn as_txt(&self) -> String {
+        revenue_tabular(self).txt()
+    }
+
+    fn as_beancount(&self) -> String {
+        as_comment(&self.as_txt())
+    }
+
+    fn as_csv(&self, options: &CsvOptions) -> String {
+        revenue_tabular(self).csv(options)
+    }
+
+    fn as_markdown(&self) -> String {
+        revenue_tabular(self).markdown()
+    }
+}
+
+fn optional<T: ToString>(value: &Option<T>) -> String {
+    value.as_ref().map(|v| v.to_string()).unwrap_or_default()
+}
+
+fn payment_behaviour_tabular(behaviour: &PaymentBehaviour) -> Tabular {
+    let mut tabular = Tabular::new(&[
+        "Customer",
+        "Invoices",
+        "Paid",
+        "Avg. days to pay",
+        "Avg. days late",
+        "Paid on time",
+    ]);
+    tabular.add_field("As of", &behaviour.as_of);
+    tabular.add_field("Receivables", &behaviour.receivables);
+    tabular.add_field("Sales", &behaviour.sales);
+    tabular.add_field(
+        format!("Days sales outstanding ({} days)", behaviour.window_days),
+        optional(&behaviour.days_sales_outstanding),
+    );
+    for customer in &behaviour.customers {
+        tabular.add_row(vec![
+            customer.customer.clone(),
+            customer.invoices.to_string(),
+            customer.paid.to_string(),
+            optional(&customer.average_days_to_pay),
+            optional(&customer.average_days_late),
+            customer
+                .paid_on_time
+                .as_ref()
+                .map(percentage)
+                .unwrap_or_default(),
+        ]);
+    }
+    tabular.set_amounts(&["Avg. days to pay", "Avg. days late", "Paid on time"]);
+    tabular
+}
+
+impl Output for PaymentBehaviour {
+    fn as_json(&self) -> String {
+        serde_json::to_string_pretty(&self).unwrap()
+    }
+
+    fn as_txt(&self) -> String {
+        payment_behaviour_tabular(self).txt()
+    }
+
+    fn as_beancount(&self) -> String {
+        as_comment(&self.as_txt())
+    }
+
+    fn as_csv(&self, options: &CsvOptions) -> String {
+        payment_behaviour_tabular(self).csv(options)
+    }
+
+    fn as_markdown(&self) -> String {
+        payment_behaviour_tabular(self).markdown()
+    }
+}
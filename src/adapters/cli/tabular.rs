@@ -0,0 +1,368 @@
+//! Output that is a table, rendered as text, Markdown or CSV from the same cells.
+
+use std::{borrow::Cow, error::Error, fmt::Display};
+
+use prettytable::{Cell, Row, Table};
+use rust_decimal::Decimal;
+
+use crate::domain::money::Money;
+
+/// A table with fields above it and text below it, such as an invoice with the invoice
+/// number and dates, a table of line items and the narration.
+#[derive(Debug, Default)]
+pub struct Tabular {
+    fields: Vec<(String, String)>,
+    header: Vec<String>,
+    rows: Vec<Vec<String>>,
+    totals: Vec<(String, String)>,
+    footer: Option<String>,
+    amounts: Vec<usize>,
+    currency_column: Option<usize>,
+}
+
+impl Tabular {
+    pub fn new(header: &[&str]) -> Self {
+        Self {
+            header: header.iter().map(|h| h.to_string()).collect(),
+            ..Self::default()
+        }
+    }
+
+    pub fn add_field(&mut self, key: impl Display, value: impl Display) {
+        self.fields.push((key.to_string(), value.to_string()));
+    }
+
+    pub fn add_row(&mut self, row: Vec<String>) {
+        self.rows.push(row);
+    }
+
+    /// Marks the columns of numbers and amounts, which CSV writes with its decimal separator.
+    pub fn set_amounts(&mut self, columns: &[&str]) {
+        self.amounts = (0..self.header.len())
+            .filter(|column| columns.contains(&self.header[*column].as_str()))
+            .collect();
+    }
+
+    /// Adds a field below the table, such as the total of an amount column.
+    pub fn add_total(&mut self, key: impl Display, value: impl Display) {
+        self.totals.push((key.to_string(), value.to_string()));
+    }
+
+    /// Writes the amounts as plain numbers in CSV, with their currency in a column of its own
+    /// before the column `before`, so that spreadsheets can add them up.
+    pub fn set_currency_column(&mut self, before: &str) {
+        self.currency_column = self.header.iter().position(|column| column == before);
+    }
+
+    pub fn set_footer(&mut self, footer: impl Display) {
+        self.footer = Some(footer.to_string());
+    }
+
+    /// The table is left out when it has no rows, unless there is nothing else to show.
+    fn shows_table(&self) -> bool {
+        !self.rows.is_empty()
+            || (self.fields.is_empty() && self.totals.is_empty() && self.footer.is_none())
+    }
+
+    fn render(&self, field: impl Fn(&(String, String)) -> String, table: String) -> String {
+        let mut parts = vec![];
+        if !self.fields.is_empty() {
+            parts.push(self.fields.iter().map(&field).collect());
+        }
+        if self.shows_table() {
+            parts.push(table);
+        }
+        if !self.totals.is_empty() {
+            parts.push(self.totals.iter().map(&field).collect());
+        }
+        parts.extend(self.footer.clone());
+        parts.join("\n")
+    }
+
+    pub fn txt(&self) -> String {
+        let mut table = Table::new();
+        for row in std::iter::once(&self.header).chain(&self.rows) {
+            table.add_row(Row::new(row.iter().map(|cell| Cell::new(cell)).collect()));
+        }
+
+        self.render(
+            |(key, value)| format!("{}: {}\n", key, value),
+            table.to_string(),
+        )
+    }
+
+    /// GitHub-flavoured Markdown, with the fields and totals as lists. The columns are padded,
+    /// so it reads as well as plain text, such as in an email.
+    pub fn markdown(&self) -> String {
+        let escape = |cell: &String| cell.replace('|', "\\|").replace('\n', "<br>");
+        let header: Vec<String> = self.header.iter().map(escape).collect();
+        let rows: Vec<Vec<String>> = self
+            .rows
+            .iter()
+            .map(|row| row.iter().map(escape).collect())
+            .collect();
+        let widths: Vec<usize> = (0..header.len())
+            .map(|column| {
+                std::iter::once(&header)
+                    .chain(&rows)
+                    .filter_map(|row| row.get(column))
+                    .map(|cell| cell.chars().count())
+                    .max()
+                    .unwrap_or_default()
+                    .max(3)
+            })
+            .collect();
+        let line = |row: &[String]| {
+            let cells: Vec<String> = widths
+                .iter()
+                .enumerate()
+                .map(|(column, width)| {
+                    let cell = row.get(column).map(String::as_str).unwrap_or_default();
+                    format!("{:width$}", cell, width = width)
+                })
+                .collect();
+            format!("| {} |\n", cells.join(" | "))
+        };
+        let separator: Vec<String> = widths.iter().map(|width| "-".repeat(*width)).collect();
+
+        let mut table = line(&header);
+        table.push_str(&line(&separator));
+        for row in &rows {
+            table.push_str(&line(row));
+        }
+
+        let mut markdown = self.render(|(key, value)| format!("- **{}:** {}\n", key, value), table);
+        if self.footer.is_some() {
+            markdown.push('\n');
+        }
+        markdown
+    }
+
+    /// Only the table, as fields and text do not fit in rows. Percentages in the amount
+    /// columns are written as numbers.
+    pub fn csv(&self, options: &CsvOptions) -> String {
+        let mut rows = vec![self.header.clone()];
+        for row in &self.rows {
+            let mut row = row.clone();
+            for column in &self.amounts {
+                if let Some(percentage) = row[*column].strip_suffix('%') {
+                    row[*column] = percentage.to_string();
+                }
+            }
+            rows.push(row);
+        }
+        let Some(at) = self.currency_column else {
+            return csv(&rows, &self.amounts, options);
+        };
+
+        for (number, row) in rows.iter_mut().enumerate() {
+            let mut currency = String::new();
+            if number == 0 {
+                currency = "Currency".to_string();
+            } else {
+                for column in &self.amounts {
+                    if let Ok(money) = row[*column].parse::<Money>() {
+                        currency = money.currency;
+                        row[*column] = money.amount.round_dp(2).to_string();
+                    }
+                }
+            }
+            row.insert(at, currency);
+        }
+        let amounts: Vec<usize> = self
+            .amounts
+            .iter()
+            .map(|column| if *column < at { *column } else { column + 1 })
+            .collect();
+        csv(&rows, &amounts, options)
+    }
+}
+
+/// How tabular output is written as CSV.
+#[derive(Debug, Clone)]
+pub struct CsvOptions {
+    pub delimiter: char,
+    pub decimal_separator: char,
+    /// Writes a row per line item, rather than per invoice
+    pub expand_lines: bool,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: ',',
+            decimal_separator: '.',
+            expand_lines: false,
+        }
+    }
+}
+
+/// Writes a number, or an amount like `120.50 EUR`, with the decimal separator. Other text
+/// is left alone.
+fn localized(field: &str, decimal_separator: char) -> Cow<'_, str> {
+    let number = match field.parse::<Money>() {
+        Ok(money) if money.currency.chars().all(|c| c.is_ascii_uppercase()) => {
+            field.split_whitespace().next().unwrap_or_default()
+        }
+        _ => field,
+    };
+    if decimal_separator == '.' || number.parse::<Decimal>().is_err() {
+        return Cow::Borrowed(field);
+    }
+    Cow::Owned(field.replacen('.', &decimal_separator.to_string(), 1))
+}
+
+/// Renders rows as delimiter separated values, quoting fields where needed. The fields in the
+/// `amounts` columns are written with the decimal separator.
+pub fn csv(rows: &[Vec<String>], amounts: &[usize], options: &CsvOptions) -> String {
+    rows.iter()
+        .map(|row| {
+            row.iter()
+                .enumerate()
+                .map(|(column, field)| {
+                    let field = match amounts.contains(&column) {
+                        true => localized(field, options.decimal_separator),
+                        false => Cow::Borrowed(field.as_str()),
+                    };
+                    if field.contains([options.delimiter, '"', '\n']) {
+                        format!("\"{}\"", field.replace('"', "\"\""))
+                    } else {
+                        field.into_owned()
+                    }
+                })
+                .collect::<Vec<String>>()
+                .join(&options.delimiter.to_string())
+                + "\n"
+        })
+        .collect()
+}
+
+/// Reads delimiter separated values as rows of fields, the reverse of [csv].
+pub fn read_csv(text: &str, delimiter: char) -> Result<Vec<Vec<String>>, Box<dyn Error>> {
+    let mut rows = vec![];
+    let mut row = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            c if quoted => field.push(c),
+            c if c == delimiter => row.push(std::mem::take(&mut field)),
+            '\r' => {}
+            '\n' => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            c => field.push(c),
+        }
+    }
+    if quoted {
+        return Err("The CSV ends inside a quoted field".into());
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    rows.retain(|row| row.iter().any(|field| !field.is_empty()));
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_renders_the_same_cells_as_txt_markdown_and_csv() {
+        let mut tabular = Tabular::new(&["Name", "Amount"]);
+        tabular.add_field("Invoice", "2023-002");
+        tabular.add_row(vec![
+            "Hosting | backups".to_string(),
+            "12.50 EUR".to_string(),
+        ]);
+        tabular.add_row(vec!["Domain".to_string(), "5 EUR".to_string()]);
+        tabular.set_footer("Thank you");
+
+        assert_eq!(
+            "Invoice: 2023-002\n\n\
+             +-------------------+-----------+\n\
+             | Name              | Amount    |\n\
+             +-------------------+-----------+\n\
+             | Hosting | backups | 12.50 EUR |\n\
+             +-------------------+-----------+\n\
+             | Domain            | 5 EUR     |\n\
+             +-------------------+-----------+\n\n\
+             Thank you",
+            tabular.txt()
+        );
+        assert_eq!(
+            "- **Invoice:** 2023-002\n\n\
+             | Name               | Amount    |\n\
+             | ------------------ | --------- |\n\
+             | Hosting \\| backups | 12.50 EUR |\n\
+             | Domain             | 5 EUR     |\n\n\
+             Thank you\n",
+            tabular.markdown()
+        );
+        assert_eq!(
+            "Name,Amount\nHosting | backups,12.50 EUR\nDomain,5 EUR\n",
+            tabular.csv(&CsvOptions::default())
+        );
+    }
+
+    #[test]
+    fn test_shows_an_empty_table_when_there_is_nothing_else() {
+        let tabular = Tabular::new(&["Number", "Date"]);
+
+        assert_eq!("| Number | Date |\n| ------ | ---- |\n", tabular.markdown());
+    }
+
+    #[test]
+    fn test_renders_totals_below_the_table() {
+        let mut tabular = Tabular::new(&["Days", "Interest"]);
+        tabular.add_row(vec!["30".to_string(), "6.58 EUR".to_string()]);
+        tabular.add_total("Total", "6.58 EUR");
+
+        assert_eq!(
+            "+------+----------+\n\
+             | Days | Interest |\n\
+             +------+----------+\n\
+             | 30   | 6.58 EUR |\n\
+             +------+----------+\n\n\
+             Total: 6.58 EUR\n",
+            tabular.txt()
+        );
+        assert!(tabular
+            .markdown()
+            .ends_with("| 30   | 6.58 EUR |\n\n- **Total:** 6.58 EUR\n"));
+    }
+
+    #[test]
+    fn test_writes_amounts_as_numbers_with_a_currency_column() {
+        let mut tabular = Tabular::new(&["Group", "Amount", "Share"]);
+        tabular.add_row(vec![
+            "Acme".to_string(),
+            "1000.50 EUR".to_string(),
+            "66.7%".to_string(),
+        ]);
+        tabular.set_amounts(&["Amount", "Share"]);
+        tabular.set_currency_column("Amount");
+        let options = CsvOptions {
+            delimiter: ';',
+            decimal_separator: ',',
+            ..CsvOptions::default()
+        };
+
+        assert_eq!(
+            "Group;Currency;Amount;Share\nAcme;EUR;1000,50;66,7\n",
+            tabular.csv(&options)
+        );
+    }
+}
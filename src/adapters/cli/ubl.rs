@@ -0,0 +1,353 @@
+//! Electronic invoices as UBL 2.1 documents, following Peppol BIS Billing 3.0.
+
+use std::error::Error;
+
+use rust_decimal::Decimal;
+
+use crate::domain::e_invoice::{
+    EInvoice, EInvoiceLine, Endpoint, Party, PaymentMeans, TaxCategory, TaxSubtotal,
+};
+
+use super::xml::{XmlElement, XmlWriter};
+
+const CUSTOMIZATION_ID: &str =
+    "urn:cen.eu:en16931:2017#compliant#urn:fdc:peppol.eu:2017:poacc:billing:3.0";
+const PROFILE_ID: &str = "urn:fdc:peppol.eu:2017:poacc:billing:01:1.0";
+
+fn amount(w: &mut XmlWriter, tag: &str, currency: &str, amount: Decimal) {
+    w.attributed(tag, "currencyID", currency, &format!("{:.2}", amount));
+}
+
+fn tax_scheme(w: &mut XmlWriter) {
+    w.open("cac:TaxScheme");
+    w.element("cbc:ID", "VAT");
+    w.close("cac:TaxScheme");
+}
+
+fn party(w: &mut XmlWriter, tag: &str, party: &Party) {
+    w.open(tag);
+    w.open("cac:Party");
+    if let Some(endpoint) = &party.endpoint {
+        w.attributed("cbc:EndpointID", "schemeID", &endpoint.scheme, &endpoint.id);
+    }
+    if let Some(name) = &party.name {
+        w.open("cac:PartyName");
+        w.element("cbc:Name", name);
+        w.close("cac:PartyName");
+    }
+    w.open("cac:PostalAddress");
+    w.optional("cbc:StreetName", &party.street);
+    w.optional("cbc:CityName", &party.city);
+    w.optional("cbc:PostalZone", &party.postal_code);
+    w.open("cac:Country");
+    w.element(
+        "cbc:IdentificationCode",
+        party.country.as_deref().unwrap_or_default(),
+    );
+    w.close("cac:Country");
+    w.close("cac:PostalAddress");
+    if let Some(vat_id) = &party.vat_id {
+        w.open("cac:PartyTaxScheme");
+        w.element("cbc:CompanyID", vat_id);
+        tax_scheme(w);
+        w.close("cac:PartyTaxScheme");
+    }
+    w.open("cac:PartyLegalEntity");
+    w.element(
+        "cbc:RegistrationName",
+        party.name.as_deref().unwrap_or_default(),
+    );
+    w.optional("cbc:CompanyID", &party.company_id);
+    w.close("cac:PartyLegalEntity");
+    w.close("cac:Party");
+    w.close(tag);
+}
+
+/// Opens the tax category, which the caller closes after adding what else it needs.
+fn tax_category(w: &mut XmlWriter, tag: &str, category: TaxCategory, rate: Decimal) {
+    w.open(tag);
+    w.element("cbc:ID", category.code());
+    // Peppol leaves the rate out for supplies outside the scope of VAT
+    if category != TaxCategory::OutsideScope {
+        w.element("cbc:Percent", &rate.normalize().to_string());
+    }
+}
+
+/// Renders an invoice as a UBL Invoice, or a credit note as a UBL CreditNote.
+pub fn invoice_ubl(invoice: &EInvoice) -> String {
+    let (root, type_code, line_tag, quantity_tag) = if invoice.credit_note {
+        (
+            "CreditNote",
+            "cbc:CreditNoteTypeCode",
+            "cac:CreditNoteLine",
+            "cbc:CreditedQuantity",
+        )
+    } else {
+        (
+            "Invoice",
+            "cbc:InvoiceTypeCode",
+            "cac:InvoiceLine",
+            "cbc:InvoicedQuantity",
+        )
+    };
+    let currency = invoice.currency.as_str();
+    let namespace = format!("urn:oasis:names:specification:ubl:schema:xsd:{}-2", root);
+    let mut w = XmlWriter::new(
+        root,
+        &[
+            ("", &namespace),
+            (
+                "cac",
+                "urn:oasis:names:specification:ubl:schema:xsd:CommonAggregateComponents-2",
+            ),
+            (
+                "cbc",
+                "urn:oasis:names:specification:ubl:schema:xsd:CommonBasicComponents-2",
+            ),
+        ],
+    );
+
+    w.element("cbc:CustomizationID", CUSTOMIZATION_ID);
+    w.element("cbc:ProfileID", PROFILE_ID);
+    w.element("cbc:ID", &invoice.number);
+    w.element("cbc:IssueDate", &invoice.issue_date.to_string());
+    if !invoice.credit_note {
+        w.optional("cbc:DueDate", &invoice.due_date.map(|d| d.to_string()));
+    }
+    w.element(type_code, invoice.type_code());
+    w.optional("cbc:Note", &invoice.note);
+    w.element("cbc:DocumentCurrencyCode", currency);
+    w.optional("cbc:BuyerReference", &invoice.buyer_reference);
+    if let Some(reference) = &invoice.billing_reference {
+        w.open("cac:BillingReference");
+        w.open("cac:InvoiceDocumentReference");
+        w.element("cbc:ID", reference);
+        w.close("cac:InvoiceDocumentReference");
+        w.close("cac:BillingReference");
+    }
+    party(&mut w, "cac:AccountingSupplierParty", &invoice.seller);
+    party(&mut w, "cac:AccountingCustomerParty", &invoice.buyer);
+
+    // 58 is a SEPA credit transfer
+    w.open("cac:PaymentMeans");
+    w.element("cbc:PaymentMeansCode", "58");
+    if invoice.credit_note {
+        w.optional(
+            "cbc:PaymentDueDate",
+            &invoice.due_date.map(|d| d.to_string()),
+        );
+    }
+    w.element("cbc:PaymentID", &invoice.payment.reference);
+    if let Some(iban) = &invoice.payment.iban {
+        w.open("cac:PayeeFinancialAccount");
+        w.element("cbc:ID", &iban.replace(' ', ""));
+        if let Some(bic) = &invoice.payment.bic {
+            w.open("cac:FinancialInstitutionBranch");
+            w.element("cbc:ID", bic);
+            w.close("cac:FinancialInstitutionBranch");
+        }
+        w.close("cac:PayeeFinancialAccount");
+    }
+    w.close("cac:PaymentMeans");
+    if let Some(terms) = &invoice.payment_terms {
+        w.open("cac:PaymentTerms");
+        w.element("cbc:Note", terms);
+        w.close("cac:PaymentTerms");
+    }
+
+    w.open("cac:TaxTotal");
+    amount(&mut w, "cbc:TaxAmount", currency, invoice.tax_total);
+    for tax in &invoice.taxes {
+        w.open("cac:TaxSubtotal");
+        amount(&mut w, "cbc:TaxableAmount", currency, tax.taxable);
+        amount(&mut w, "cbc:TaxAmount", currency, tax.tax);
+        tax_category(&mut w, "cac:TaxCategory", tax.category, tax.rate);
+        w.optional("cbc:TaxExemptionReason", &tax.exemption_reason);
+        tax_scheme(&mut w);
+        w.close("cac:TaxCategory");
+        w.close("cac:TaxSubtotal");
+    }
+    w.close("cac:TaxTotal");
+
+    w.open("cac:LegalMonetaryTotal");
+    amount(
+        &mut w,
+        "cbc:LineExtensionAmount",
+        currency,
+        invoice.line_total,
+    );
+    amount(
+        &mut w,
+        "cbc:TaxExclusiveAmount",
+        currency,
+        invoice.tax_exclusive,
+    );
+    amount(
+        &mut w,
+        "cbc:TaxInclusiveAmount",
+        currency,
+        invoice.tax_inclusive,
+    );
+    amount(&mut w, "cbc:PayableAmount", currency, invoice.payable);
+    w.close("cac:LegalMonetaryTotal");
+
+    for line in &invoice.lines {
+        w.open(line_tag);
+        w.element("cbc:ID", &line.id);
+        w.attributed(
+            quantity_tag,
+            "unitCode",
+            &line.unit_code,
+            &line.quantity.normalize().to_string(),
+        );
+        amount(&mut w, "cbc:LineExtensionAmount", currency, line.net_amount);
+        w.open("cac:Item");
+        w.element("cbc:Name", &line.name);
+        tax_category(
+            &mut w,
+            "cac:ClassifiedTaxCategory",
+            line.category,
+            line.rate,
+        );
+        tax_scheme(&mut w);
+        w.close("cac:ClassifiedTaxCategory");
+        w.close("cac:Item");
+        w.open("cac:Price");
+        w.attributed(
+            "cbc:PriceAmount",
+            "currencyID",
+            currency,
+            &line.price.normalize().to_string(),
+        );
+        w.close("cac:Price");
+        w.close(line_tag);
+    }
+
+    w.finish()
+}
+
+fn read_party(element: Option<XmlElement>) -> Result<Party, Box<dyn Error>> {
+    let Some(party) = element.and_then(|element| element.child("Party")) else {
+        return Ok(Party::default());
+    };
+    let endpoint = party.find("EndpointID").map(|endpoint| Endpoint {
+        scheme: endpoint
+            .attribute("schemeID")
+            .unwrap_or_default()
+            .to_string(),
+        id: endpoint.value(),
+    });
+    Ok(Party {
+        name: party
+            .text("PartyLegalEntity/RegistrationName")
+            .or_else(|| party.text("PartyName/Name")),
+        vat_id: party.text("PartyTaxScheme/CompanyID"),
+        company_id: party.text("PartyLegalEntity/CompanyID"),
+        endpoint,
+        street: party.text("PostalAddress/StreetName"),
+        city: party.text("PostalAddress/CityName"),
+        postal_code: party.text("PostalAddress/PostalZone"),
+        country: party.text("PostalAddress/Country/IdentificationCode"),
+        reference: None,
+    })
+}
+
+fn read_amount(element: XmlElement, path: &str) -> Result<Decimal, Box<dyn Error>> {
+    Ok(element.parse(path)?.unwrap_or_default())
+}
+
+/// Reads a UBL Invoice or CreditNote, such as one received over Peppol.
+pub fn read_ubl(root: XmlElement) -> Result<EInvoice, Box<dyn Error>> {
+    let credit_note =
+        root.name() == "CreditNote" || root.text("InvoiceTypeCode").as_deref() == Some("381");
+    let (line_tag, quantity_tag) = match root.name() {
+        "CreditNote" => ("CreditNoteLine", "CreditedQuantity"),
+        _ => ("InvoiceLine", "InvoicedQuantity"),
+    };
+
+    let mut lines = vec![];
+    for line in root.children_named(line_tag) {
+        let tax = line.find("Item/ClassifiedTaxCategory");
+        lines.push(EInvoiceLine {
+            id: line.required("ID")?,
+            name: line.text("Item/Name").unwrap_or_default(),
+            quantity: read_amount(line, quantity_tag)?,
+            unit_code: line
+                .find(quantity_tag)
+                .and_then(|quantity| quantity.attribute("unitCode"))
+                .unwrap_or_default()
+                .to_string(),
+            price: read_amount(line, "Price/PriceAmount")?,
+            net_amount: read_amount(line, "LineExtensionAmount")?,
+            category: match tax {
+                Some(tax) => tax.parse("ID")?.unwrap_or(TaxCategory::Standard),
+                None => TaxCategory::Standard,
+            },
+            rate: match tax {
+                Some(tax) => read_amount(tax, "Percent")?,
+                None => Decimal::ZERO,
+            },
+        });
+    }
+
+    let mut taxes = vec![];
+    let tax_total = root.child("TaxTotal");
+    for subtotal in tax_total
+        .into_iter()
+        .flat_map(|total| total.children_named("TaxSubtotal"))
+    {
+        taxes.push(TaxSubtotal {
+            category: subtotal
+                .parse("TaxCategory/ID")?
+                .unwrap_or(TaxCategory::Standard),
+            rate: read_amount(subtotal, "TaxCategory/Percent")?,
+            taxable: read_amount(subtotal, "TaxableAmount")?,
+            tax: read_amount(subtotal, "TaxAmount")?,
+            exemption_reason: subtotal.text("TaxCategory/TaxExemptionReason"),
+        });
+    }
+
+    let totals = root
+        .child("LegalMonetaryTotal")
+        .ok_or_else(|| format!("The {} has no LegalMonetaryTotal", root.name()))?;
+    let payment = root.child("PaymentMeans");
+    Ok(EInvoice {
+        number: root.required("ID")?,
+        issue_date: root
+            .parse("IssueDate")?
+            .ok_or_else(|| format!("The {} has no IssueDate", root.name()))?,
+        due_date: match root.parse("DueDate")? {
+            Some(due_date) => Some(due_date),
+            None => match payment {
+                Some(payment) => payment.parse("PaymentDueDate")?,
+                None => None,
+            },
+        },
+        credit_note,
+        billing_reference: root.text("BillingReference/InvoiceDocumentReference/ID"),
+        note: root.text("Note"),
+        currency: root.required("DocumentCurrencyCode")?,
+        buyer_reference: root.text("BuyerReference"),
+        seller: read_party(root.child("AccountingSupplierParty"))?,
+        buyer: read_party(root.child("AccountingCustomerParty"))?,
+        payment: PaymentMeans {
+            iban: payment.and_then(|p| p.text("PayeeFinancialAccount/ID")),
+            bic: payment
+                .and_then(|p| p.text("PayeeFinancialAccount/FinancialInstitutionBranch/ID")),
+            reference: payment
+                .and_then(|p| p.text("PaymentID"))
+                .unwrap_or_default(),
+        },
+        payment_terms: root.text("PaymentTerms/Note"),
+        lines,
+        taxes,
+        line_total: read_amount(totals, "LineExtensionAmount")?,
+        tax_exclusive: read_amount(totals, "TaxExclusiveAmount")?,
+        tax_total: match tax_total {
+            Some(total) => read_amount(total, "TaxAmount")?,
+            None => Decimal::ZERO,
+        },
+        tax_inclusive: read_amount(totals, "TaxInclusiveAmount")?,
+        payable: read_amount(totals, "PayableAmount")?,
+    })
+}
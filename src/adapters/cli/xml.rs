@@ -0,0 +1,289 @@
+//! A small writer for the XML of electronic invoices, and a reader on top of roxmltree.
+
+use std::{error::Error, fmt::Display, str::FromStr};
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Writes indented XML elements, leaving out optional elements without a value.
+pub struct XmlWriter {
+    xml: String,
+    depth: usize,
+    root: String,
+}
+
+impl XmlWriter {
+    /// Starts a document with the root element and its namespaces.
+    pub fn new(root: &str, namespaces: &[(&str, &str)]) -> Self {
+        let mut xml = format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<{}", root);
+        for (prefix, uri) in namespaces {
+            match prefix.is_empty() {
+                true => xml.push_str(&format!(" xmlns=\"{}\"", uri)),
+                false => xml.push_str(&format!(" xmlns:{}=\"{}\"", prefix, uri)),
+            }
+        }
+        xml.push_str(">\n");
+        Self {
+            xml,
+            depth: 1,
+            root: root.to_string(),
+        }
+    }
+
+    fn indent(&self) -> String {
+        "  ".repeat(self.depth)
+    }
+
+    pub fn open(&mut self, tag: &str) {
+        self.xml.push_str(&format!("{}<{}>\n", self.indent(), tag));
+        self.depth += 1;
+    }
+
+    pub fn close(&mut self, tag: &str) {
+        self.depth -= 1;
+        self.xml.push_str(&format!("{}</{}>\n", self.indent(), tag));
+    }
+
+    pub fn element(&mut self, tag: &str, value: &str) {
+        self.xml.push_str(&format!(
+            "{}<{}>{}</{}>\n",
+            self.indent(),
+            tag,
+            escape(value),
+            tag
+        ));
+    }
+
+    pub fn optional(&mut self, tag: &str, value: &Option<String>) {
+        if let Some(value) = value {
+            self.element(tag, value);
+        }
+    }
+
+    pub fn attributed(&mut self, tag: &str, attribute: &str, attribute_value: &str, value: &str) {
+        self.xml.push_str(&format!(
+            "{}<{} {}=\"{}\">{}</{}>\n",
+            self.indent(),
+            tag,
+            attribute,
+            escape(attribute_value),
+            escape(value),
+            tag
+        ));
+    }
+
+    pub fn finish(mut self) -> String {
+        self.xml.push_str(&format!("</{}>\n", self.root));
+        self.xml
+    }
+}
+
+/// How deep elements may nest. roxmltree reads elements recursively, so a deeper document
+/// could overflow the stack, while invoices nest about ten deep.
+const MAX_DEPTH: usize = 256;
+
+/// Refuses documents with elements nested deeper than `MAX_DEPTH`, skipping comments, CDATA,
+/// processing instructions and attribute values. Whether the document is well-formed is left
+/// to roxmltree.
+fn check_depth(xml: &str) -> Result<(), Box<dyn Error>> {
+    let skip_past = |rest: &str, end: &str| rest.find(end).map_or(rest.len(), |at| at + end.len());
+    let mut depth = 0usize;
+    let mut rest = xml;
+    while let Some(at) = rest.find('<') {
+        rest = &rest[at..];
+        let end = if rest.starts_with("<!--") {
+            skip_past(rest, "-->")
+        } else if rest.starts_with("<![CDATA[") {
+            skip_past(rest, "]]>")
+        } else if rest.starts_with("<?") {
+            skip_past(rest, "?>")
+        } else if rest.starts_with("<!") {
+            skip_past(rest, ">")
+        } else {
+            // The tag ends at the first > outside of a quoted attribute value
+            let mut quote = None;
+            let end = rest
+                .char_indices()
+                .find(|&(_, c)| match quote {
+                    Some(q) => {
+                        if c == q {
+                            quote = None;
+                        }
+                        false
+                    }
+                    None => {
+                        if c == '"' || c == '\'' {
+                            quote = Some(c);
+                        }
+                        c == '>'
+                    }
+                })
+                .map_or(rest.len(), |(at, _)| at);
+            let tag = &rest[..end];
+            if tag.starts_with("</") {
+                depth = depth.saturating_sub(1);
+            } else if !tag.ends_with('/') {
+                depth += 1;
+                if depth > MAX_DEPTH {
+                    return Err(format!(
+                        "Expected elements to be nested at most {} deep",
+                        MAX_DEPTH
+                    )
+                    .into());
+                }
+            }
+            end
+        };
+        rest = &rest[end..];
+    }
+    Ok(())
+}
+
+/// A parsed document, which its elements borrow from.
+pub struct XmlDocument<'x>(roxmltree::Document<'x>);
+
+impl<'x> XmlDocument<'x> {
+    /// Parses a document. DTDs are not supported.
+    pub fn read(xml: &'x str) -> Result<Self, Box<dyn Error>> {
+        let xml = xml.trim_start_matches('\u{feff}');
+        check_depth(xml)?;
+        Ok(XmlDocument(roxmltree::Document::parse(xml)?))
+    }
+
+    pub fn root(&self) -> XmlElement<'_, 'x> {
+        XmlElement(self.0.root_element())
+    }
+}
+
+/// An element of a parsed document. Names are without their namespace prefix, which is all
+/// UBL and CII need to tell their elements apart.
+#[derive(Clone, Copy, Debug)]
+pub struct XmlElement<'d, 'x>(roxmltree::Node<'d, 'x>);
+
+impl<'d, 'x> XmlElement<'d, 'x> {
+    pub fn name(&self) -> &'x str {
+        self.0.tag_name().name()
+    }
+
+    /// The trimmed text of the element itself.
+    pub fn value(&self) -> String {
+        let text: String = self.0.children().filter_map(|node| node.text()).collect();
+        text.trim().to_string()
+    }
+
+    pub fn child(&self, name: &str) -> Option<XmlElement<'d, 'x>> {
+        self.children_named(name).next()
+    }
+
+    pub fn children_named<'n>(&self, name: &'n str) -> impl Iterator<Item = XmlElement<'d, 'x>> + 'n
+    where
+        'd: 'n,
+        'x: 'n,
+    {
+        self.0
+            .children()
+            .filter(move |node| node.is_element() && node.tag_name().name() == name)
+            .map(XmlElement)
+    }
+
+    /// The first element at `path`, with the names of the elements separated by `/`.
+    pub fn find(&self, path: &str) -> Option<XmlElement<'d, 'x>> {
+        path.split('/')
+            .try_fold(*self, |element, name| element.child(name))
+    }
+
+    /// The trimmed text at `path`, when there is any.
+    pub fn text(&self, path: &str) -> Option<String> {
+        self.find(path)
+            .map(|element| element.value())
+            .filter(|text| !text.is_empty())
+    }
+
+    /// The text at `path`, which the document must have.
+    pub fn required(&self, path: &str) -> Result<String, Box<dyn Error>> {
+        self.text(path)
+            .ok_or_else(|| format!("The {} has no {}", self.name(), path).into())
+    }
+
+    /// The text at `path` read as a `T`, when there is any.
+    pub fn parse<T: FromStr>(&self, path: &str) -> Result<Option<T>, Box<dyn Error>>
+    where
+        T::Err: Display,
+    {
+        self.text(path)
+            .map(|text| {
+                text.parse::<T>().map_err(|err| {
+                    format!("The {} has {} \"{}\": {}", self.name(), path, text, err).into()
+                })
+            })
+            .transpose()
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&'d str> {
+        self.0
+            .attributes()
+            .find(|attribute| attribute.name() == name)
+            .map(|attribute| attribute.value())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_reads_what_the_writer_writes() {
+        let mut w = XmlWriter::new("Invoice", &[("cac", "urn:cac"), ("cbc", "urn:cbc")]);
+        w.element("cbc:Note", "Design & <build>");
+        w.open("cac:Line");
+        w.attributed("cbc:Quantity", "unitCode", "HUR", "10");
+        w.close("cac:Line");
+        let xml = w
+            .finish()
+            .replacen("?>\n", "?>\n<!-- sent by mail -->\n", 1);
+
+        let document = XmlDocument::read(&xml).unwrap();
+        let invoice = document.root();
+
+        assert_eq!("Invoice", invoice.name());
+        assert_eq!(Some("Design & <build>".to_string()), invoice.text("Note"));
+        let quantity = invoice.find("Line/Quantity").unwrap();
+        assert_eq!(Some("HUR"), quantity.attribute("unitCode"));
+        assert_eq!("10", quantity.value());
+        assert_eq!(
+            "The Invoice has no Line/Price",
+            invoice.required("Line/Price").unwrap_err().to_string()
+        );
+        assert!(XmlDocument::read("<Invoice><ID>1</Invoice>").is_err());
+    }
+
+    #[test]
+    fn test_reads_markup_characters_in_attributes() {
+        let xml = r#"<Invoice note="a > b"><![CDATA[<1>]]> &amp; 2</Invoice>"#;
+
+        let document = XmlDocument::read(xml).unwrap();
+
+        assert_eq!(Some("a > b"), document.root().attribute("note"));
+        assert_eq!("<1> & 2", document.root().value());
+    }
+
+    #[test]
+    fn test_refuses_deeply_nested_documents() {
+        let nested = |depth| format!("{}{}", "<a b=\"/>\">".repeat(depth), "</a>".repeat(depth));
+
+        let deepest = XmlDocument::read(&nested(MAX_DEPTH)).is_ok();
+        let deeper = XmlDocument::read(&nested(100_000)).err().unwrap();
+
+        assert!(deepest);
+        assert_eq!(
+            "Expected elements to be nested at most 256 deep",
+            deeper.to_string()
+        );
+    }
+}
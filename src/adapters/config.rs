@@ -0,0 +1,556 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use chrono::NaiveTime;
+
+use crate::{
+    domain::{
+        bill::AccountRule,
+        e_invoice::{Party, TaxRule},
+        recurring::{RecurringInvoice, RecurringItem},
+    },
+    error::TabulaError,
+};
+
+/// Settings read from a TOML file passed with `--config` or `TABULA_CONFIG`.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Currency used by reports when none is given on the command line
+    pub currency: String,
+    /// Days after the invoice date that an invoice without a `due` date is expected to be paid
+    pub payment_terms_days: i64,
+    pub forecast: ForecastConfig,
+    pub reminders: RemindersConfig,
+    pub interest: InterestConfig,
+    pub daemon: DaemonConfig,
+    pub notification: NotificationConfig,
+    pub webhooks: WebhooksConfig,
+    pub documents: DocumentsConfig,
+    pub audit: AuditConfig,
+    pub lifecycle: LifecycleConfig,
+    pub cache: CacheConfig,
+    pub seller: SellerConfig,
+    /// The details of customers for electronic invoices, by their name in the ledger
+    pub customers: HashMap<String, Party>,
+    pub e_invoice: EInvoiceConfig,
+    pub bills: BillsConfig,
+    pub csv: CsvConfig,
+}
+
+impl Config {
+    pub fn load(path: Option<&Path>) -> Result<Self, Box<dyn Error>> {
+        match path {
+            Some(path) => {
+                let content = fs::read_to_string(path).map_err(|source| TabulaError::Io {
+                    path: path.to_path_buf(),
+                    source,
+                })?;
+                toml::from_str(&content).map_err(|err| {
+                    TabulaError::Config(format!("Invalid config {}: {}", path.display(), err))
+                        .into()
+                })
+            }
+            None => Ok(Self::default()),
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            currency: "EUR".to_string(),
+            payment_terms_days: 30,
+            forecast: ForecastConfig::default(),
+            reminders: RemindersConfig::default(),
+            interest: InterestConfig::default(),
+            daemon: DaemonConfig::default(),
+            notification: NotificationConfig::default(),
+            webhooks: WebhooksConfig::default(),
+            documents: DocumentsConfig::default(),
+            audit: AuditConfig::default(),
+            lifecycle: LifecycleConfig::default(),
+            cache: CacheConfig::default(),
+            seller: SellerConfig::default(),
+            customers: HashMap::new(),
+            e_invoice: EInvoiceConfig::default(),
+            bills: BillsConfig::default(),
+            csv: CsvConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ForecastConfig {
+    /// Accounts, or account prefixes, whose balance is the starting point of a forecast
+    pub bank_accounts: Vec<String>,
+    /// Warn when the projected balance drops below this amount
+    pub threshold: Option<Decimal>,
+    /// Expenses that come back on a schedule, such as rent and subscriptions
+    pub recurring: Vec<RecurringItem>,
+}
+
+impl Default for ForecastConfig {
+    fn default() -> Self {
+        Self {
+            bank_accounts: vec!["Assets:Bank".to_string()],
+            threshold: None,
+            recurring: vec![],
+        }
+    }
+}
+
+/// When to send reminders for overdue invoices, in days after the due date.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct RemindersConfig {
+    pub first_after_days: i64,
+    pub second_after_days: i64,
+    pub final_after_days: i64,
+}
+
+impl Default for RemindersConfig {
+    fn default() -> Self {
+        Self {
+            first_after_days: 7,
+            second_after_days: 21,
+            final_after_days: 35,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct InterestConfig {
+    /// A TOML file with statutory interest rates, replacing the ones shipped with tabula
+    pub rates_file: Option<PathBuf>,
+}
+
+/// The jobs `tabula daemon` runs each day. A job without a time does not run.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct DaemonConfig {
+    /// How often to check the ledger files for changes
+    pub poll_seconds: u64,
+    pub recurring_invoices_at: Option<NaiveTime>,
+    pub recurring_invoices: Vec<RecurringInvoice>,
+    pub reminders_at: Option<NaiveTime>,
+    pub reports_at: Option<NaiveTime>,
+    /// The reports to write, by their name under `tabula reports`
+    pub reports: Vec<String>,
+    pub reports_dir: PathBuf,
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        Self {
+            poll_seconds: 5,
+            recurring_invoices_at: None,
+            recurring_invoices: vec![],
+            reminders_at: None,
+            reports_at: None,
+            reports: vec!["forecast".to_string(), "revenue".to_string()],
+            reports_dir: PathBuf::from("reports"),
+        }
+    }
+}
+
+/// How invoices are emailed. Messages are written to `drop_dir` as `.eml` files when it is
+/// set, and sent over SMTP otherwise.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct NotificationConfig {
+    /// The address invoices are sent from
+    pub from: Option<String>,
+    pub drop_dir: Option<PathBuf>,
+    pub smtp: SmtpConfig,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: u16,
+    /// Upgrade the connection with STARTTLS. Without it, mail is sent in plain text, which is
+    /// only fit for a server on the same machine
+    pub starttls: bool,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl Default for SmtpConfig {
+    fn default() -> Self {
+        Self {
+            host: "localhost".to_string(),
+            port: 25,
+            starttls: false,
+            username: None,
+            password: None,
+        }
+    }
+}
+
+/// Where the invoices sent to customers are archived.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct DocumentsConfig {
+    pub backend: DocumentsBackend,
+    /// Relative to the directory of the ledger, unless it is an absolute path
+    pub dir: PathBuf,
+    pub s3: S3Config,
+}
+
+impl Default for DocumentsConfig {
+    fn default() -> Self {
+        Self {
+            backend: DocumentsBackend::Filesystem,
+            dir: PathBuf::from("documents"),
+            s3: S3Config::default(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DocumentsBackend {
+    Filesystem,
+    S3,
+}
+
+/// A bucket on Amazon S3, or on any server with the same API, such as MinIO.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct S3Config {
+    pub bucket: String,
+    /// Put in front of every key, such as `tabula/`
+    pub prefix: String,
+    pub region: String,
+    /// The URL of a server other than Amazon S3, such as `http://localhost:9000`. Buckets are
+    /// then addressed by path instead of by host name
+    pub endpoint: Option<String>,
+    /// Taken from `AWS_ACCESS_KEY_ID` when not set
+    pub access_key_id: Option<String>,
+    /// Taken from `AWS_SECRET_ACCESS_KEY` when not set
+    pub secret_access_key: Option<String>,
+    /// Lock each document for this many days. The bucket must have object lock enabled
+    pub retention_days: Option<u32>,
+    pub retention_mode: RetentionMode,
+}
+
+impl Default for S3Config {
+    fn default() -> Self {
+        Self {
+            bucket: String::new(),
+            prefix: String::new(),
+            region: "us-east-1".to_string(),
+            endpoint: None,
+            access_key_id: None,
+            secret_access_key: None,
+            retention_days: None,
+            retention_mode: RetentionMode::Compliance,
+        }
+    }
+}
+
+/// Under compliance nobody can delete a locked document, under governance users with a
+/// special permission can.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RetentionMode {
+    Governance,
+    Compliance,
+}
+
+impl RetentionMode {
+    pub fn as_header(&self) -> &'static str {
+        match self {
+            RetentionMode::Governance => "GOVERNANCE",
+            RetentionMode::Compliance => "COMPLIANCE",
+        }
+    }
+}
+
+/// The log of every change tabula makes to the ledger.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct AuditConfig {
+    pub enabled: bool,
+    /// Relative to the directory of the ledger, unless it is an absolute path
+    pub path: PathBuf,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            path: PathBuf::from("audit.jsonl"),
+        }
+    }
+}
+
+/// The SQLite database that keeps what was read from each ledger file, so that files that
+/// did not change are not parsed again.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    pub enabled: bool,
+    /// Relative to the directory of the ledger, unless it is an absolute path
+    pub path: PathBuf,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: PathBuf::from(".tabula-cache.sqlite"),
+        }
+    }
+}
+
+/// Where `tabula lifecycle` keeps the events of invoices.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct LifecycleConfig {
+    /// Relative to the directory of the ledger, unless it is an absolute path
+    pub events: PathBuf,
+}
+
+impl Default for LifecycleConfig {
+    fn default() -> Self {
+        Self {
+            events: PathBuf::from("invoice-events.jsonl"),
+        }
+    }
+}
+
+/// Endpoints that `tabula daemon` posts invoice events to.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct WebhooksConfig {
+    pub endpoints: Vec<WebhookEndpoint>,
+    /// Where events that could not be delivered are kept, to be tried again later
+    pub outbox: PathBuf,
+    /// Where the invoices are kept as the webhooks last heard of them, so each change is
+    /// announced once, by whichever of the daemon, the CLI and the HTTP API sees it first
+    pub state: PathBuf,
+    /// How often a delivery is tried with backoff, before it is tried once a minute
+    pub attempts: u32,
+    /// The wait before the second attempt, doubled for each attempt after that
+    pub backoff_ms: u64,
+}
+
+impl Default for WebhooksConfig {
+    fn default() -> Self {
+        Self {
+            endpoints: vec![],
+            outbox: PathBuf::from("webhooks-outbox.jsonl"),
+            state: PathBuf::from("webhooks-state.json"),
+            attempts: 3,
+            backoff_ms: 500,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct WebhookEndpoint {
+    pub url: String,
+    /// Key of the HMAC-SHA256 signature in the `X-Tabula-Signature` header
+    pub secret: Option<String>,
+}
+
+/// Who sends the invoices, and the bank account they are paid on.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct SellerConfig {
+    #[serde(flatten)]
+    pub party: Party,
+    pub iban: Option<String>,
+    pub bic: Option<String>,
+}
+
+/// The VAT and units of electronic invoices.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct EInvoiceConfig {
+    /// The standard VAT rate, in percent, of income accounts without a tax rule
+    pub vat_rate: Decimal,
+    /// The unit of line items without `line_item_unit`, as a UN/ECE recommendation 20 code
+    pub unit_code: String,
+    /// The VAT category and rate of income accounts that are not standard rated
+    pub tax: Vec<TaxRule>,
+}
+
+impl Default for EInvoiceConfig {
+    fn default() -> Self {
+        Self {
+            vat_rate: Decimal::from(21),
+            unit_code: "C62".to_string(),
+            tax: vec![],
+        }
+    }
+}
+
+/// How bills received as electronic invoices are booked.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct BillsConfig {
+    /// The expense account of lines, by supplier and item. The first matching rule applies
+    pub accounts: Vec<AccountRule>,
+    /// The expense account of lines no rule matches
+    pub default_account: String,
+    /// The account of the VAT on bills
+    pub vat_account: String,
+}
+
+impl Default for BillsConfig {
+    fn default() -> Self {
+        Self {
+            accounts: vec![],
+            default_account: "Expenses:Unsorted".to_string(),
+            vat_account: "Assets:VAT:Input".to_string(),
+        }
+    }
+}
+
+/// How CSV files are written and read, such as `;` and `,` for Dutch spreadsheets.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct CsvConfig {
+    pub delimiter: char,
+    pub decimal_separator: char,
+    /// The headers of the columns `invoices import` reads
+    pub columns: CsvColumns,
+}
+
+impl Default for CsvConfig {
+    fn default() -> Self {
+        Self {
+            delimiter: ',',
+            decimal_separator: '.',
+            columns: CsvColumns::default(),
+        }
+    }
+}
+
+/// The header of the column of each field of an imported invoice. The defaults are the
+/// headers `invoices list --format csv` writes, so a list can be imported again.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CsvColumns {
+    pub number: String,
+    pub date: String,
+    pub due_date: String,
+    pub customer: String,
+    pub narration: String,
+    pub total: String,
+    pub account: String,
+}
+
+impl Default for CsvColumns {
+    fn default() -> Self {
+        Self {
+            number: "Number".to_string(),
+            date: "Date".to_string(),
+            due_date: "Due date".to_string(),
+            customer: "Customer".to_string(),
+            narration: "Narration".to_string(),
+            total: "Total".to_string(),
+            account: "Account".to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_partial_config_falls_back_to_defaults() {
+        let config: Config = toml::from_str(
+            r#"
+            currency = "USD"
+
+            [[forecast.recurring]]
+            description = "Rent"
+            amount = "1200"
+            currency = "USD"
+            every = "monthly"
+            start = "2023-01-01"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!("USD", config.currency);
+        assert_eq!(30, config.payment_terms_days);
+        assert_eq!(
+            vec!["Assets:Bank".to_string()],
+            config.forecast.bank_accounts
+        );
+        assert_eq!(1, config.forecast.recurring.len());
+    }
+
+    #[test]
+    fn test_daemon_recurring_invoices() {
+        let config: Config = toml::from_str(
+            r#"
+            [daemon]
+            recurring_invoices_at = "06:00"
+
+            [[daemon.recurring_invoices]]
+            customer = "Acme"
+            description = "Hosting"
+            amount = "50"
+            currency = "EUR"
+            every = "monthly"
+            start = "2023-01-01"
+            "#,
+        )
+        .unwrap();
+
+        let hosting = &config.daemon.recurring_invoices[0];
+        assert_eq!("Income:Work", hosting.account);
+        assert_eq!("Hosting", hosting.item.description);
+        assert_eq!(
+            NaiveTime::from_hms_opt(6, 0, 0),
+            config.daemon.recurring_invoices_at
+        );
+        assert_eq!(None, config.daemon.reminders_at);
+    }
+
+    #[test]
+    fn test_seller_and_customers() {
+        let config: Config = toml::from_str(
+            r#"
+            [seller]
+            name = "Tabula B.V."
+            endpoint = "0106:12345678"
+            iban = "NL91ABNA0417164300"
+
+            [customers.Acme]
+            vat_id = "NL987654321B01"
+
+            [[e_invoice.tax]]
+            account = "Income:Export"
+            category = "G"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(Some("Tabula B.V.".to_string()), config.seller.party.name);
+        assert_eq!("0106", config.seller.party.endpoint.unwrap().scheme);
+        assert_eq!(Some("NL91ABNA0417164300".to_string()), config.seller.iban);
+        assert!(config.customers["Acme"].vat_id.is_some());
+        assert_eq!(Decimal::ZERO, config.e_invoice.tax[0].rate);
+        assert_eq!(Decimal::from(21), config.e_invoice.vat_rate);
+    }
+}
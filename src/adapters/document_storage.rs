@@ -1 +1,206 @@
+use std::{
+    error::Error,
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+};
 
+use sha2::{Digest, Sha256};
+
+use crate::{
+    domain::{bill::Bill, invoice::Invoice},
+    error::TabulaError,
+    services::bills::supplier_component,
+};
+
+use super::config::{DocumentsBackend, DocumentsConfig};
+
+pub mod s3;
+
+/// Keeps the documents sent to customers, exactly as they were sent. A document that has been
+/// stored is never replaced: mistakes are corrected with a credit note and a new invoice,
+/// which get documents of their own.
+pub trait DocumentStorage {
+    /// Stores a document under `key`, such as `invoices/2023/2023-002.pdf`, and returns where
+    /// it was stored. Storing the same content again is allowed, different content is not.
+    /// `content_type` is the media type of the content, such as `application/pdf`.
+    fn store(
+        &self,
+        key: &str,
+        content: &[u8],
+        content_type: &str,
+    ) -> Result<StoredDocument, Box<dyn Error>>;
+    /// Fetches a document by the location `store` returned for it.
+    fn fetch(&self, location: &str) -> Result<Vec<u8>, Box<dyn Error>>;
+    /// The document stored under `key`, if there is one.
+    fn find(&self, key: &str) -> Result<Option<StoredDocument>, Box<dyn Error>>;
+}
+
+#[derive(Debug)]
+pub struct StoredDocument {
+    /// Where to find the document, as recorded in the `document` metadata
+    pub location: String,
+    pub sha256: String,
+}
+
+/// Stores documents in a directory, which is relative to the ledger when it is a relative
+/// path, so the `document` metadata reads like beancount's own document paths.
+pub struct FileDocumentStorage {
+    base: PathBuf,
+    dir: PathBuf,
+}
+
+impl FileDocumentStorage {
+    pub fn new(base: &Path, dir: &Path) -> Self {
+        Self {
+            base: base.to_path_buf(),
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    fn location(&self, key: &str) -> PathBuf {
+        self.dir.join(key)
+    }
+}
+
+impl DocumentStorage for FileDocumentStorage {
+    fn store(
+        &self,
+        key: &str,
+        content: &[u8],
+        _content_type: &str,
+    ) -> Result<StoredDocument, Box<dyn Error>> {
+        let location = self.location(key);
+        let path = self.base.join(&location);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(mut file) => file.write_all(content)?,
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+                if fs::read(&path)? != content {
+                    return Err(document_exists(&location.display().to_string()));
+                }
+            }
+            Err(err) => return Err(err.into()),
+        }
+
+        Ok(StoredDocument {
+            location: location.display().to_string(),
+            sha256: sha256_hex(content),
+        })
+    }
+
+    fn fetch(&self, location: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        let path = self.base.join(location);
+        fs::read(&path).map_err(|source| TabulaError::Io { path, source }.into())
+    }
+
+    fn find(&self, key: &str) -> Result<Option<StoredDocument>, Box<dyn Error>> {
+        let location = self.location(key);
+        let path = self.base.join(&location);
+        match fs::read(&path) {
+            Ok(content) => Ok(Some(StoredDocument {
+                location: location.display().to_string(),
+                sha256: sha256_hex(&content),
+            })),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(source) => Err(TabulaError::Io { path, source }.into()),
+        }
+    }
+}
+
+/// The document storage for a ledger file, as configured.
+pub fn document_storage(
+    config: &DocumentsConfig,
+    ledger: &Path,
+) -> Result<Box<dyn DocumentStorage>, Box<dyn Error>> {
+    match config.backend {
+        DocumentsBackend::Filesystem => {
+            let base = ledger.parent().unwrap_or_else(|| Path::new(""));
+            Ok(Box::new(FileDocumentStorage::new(base, &config.dir)))
+        }
+        DocumentsBackend::S3 => Ok(Box::new(s3::S3DocumentStorage::new(&config.s3)?)),
+    }
+}
+
+/// The key of a document of an invoice, such as `invoices/<year>/<number>.pdf` for
+/// `extension` `pdf`.
+pub fn invoice_document_key(invoice: &Invoice, extension: &str) -> String {
+    format!(
+        "invoices/{}/{}.{}",
+        invoice.date.0.format("%Y"),
+        invoice.number.0.replace(['/', '\\'], "-"),
+        extension
+    )
+}
+
+/// The key of the XML of a bill: `bills/<year>/<supplier>-<number>.xml`.
+pub fn bill_document_key(bill: &Bill) -> String {
+    format!(
+        "bills/{}/{}-{}.xml",
+        bill.date.0.format("%Y"),
+        supplier_component(bill.supplier.as_deref().unwrap_or_default()),
+        bill.number.replace(['/', '\\'], "-")
+    )
+}
+
+/// The media types of the documents that are stored.
+pub const PDF: &str = "application/pdf";
+pub const HTML: &str = "text/html; charset=utf-8";
+pub const XML: &str = "application/xml";
+
+pub fn sha256_hex(content: &[u8]) -> String {
+    hex::encode(Sha256::digest(content))
+}
+
+/// A document with other content is stored under the same key. Issued documents are never
+/// replaced, so this is refused as invalid input.
+fn document_exists(location: &str) -> Box<dyn Error> {
+    Box::new(TabulaError::Validation(format!(
+        "A different document is already stored as {}. Issued documents are never replaced, \
+         correct the invoice with a credit note instead",
+        location
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::error::exit_code;
+
+    #[test]
+    fn test_stored_documents_are_not_replaced() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let storage = FileDocumentStorage::new(dir.path(), Path::new("documents"));
+
+        let stored = storage
+            .store("invoices/2023/2023-002.pdf", b"invoice", PDF)
+            .unwrap();
+        let again = storage.store("invoices/2023/2023-002.pdf", b"invoice", PDF);
+        let changed = storage.store("invoices/2023/2023-002.pdf", b"changed", PDF);
+
+        assert_eq!("documents/invoices/2023/2023-002.pdf", stored.location);
+        assert_eq!(sha256_hex(b"invoice"), stored.sha256);
+        assert!(again.is_ok());
+        assert_eq!(5, exit_code(changed.unwrap_err().as_ref()));
+        assert_eq!(
+            b"invoice".to_vec(),
+            storage.fetch(&stored.location).unwrap()
+        );
+        assert_eq!(
+            Some(stored.sha256),
+            storage
+                .find("invoices/2023/2023-002.pdf")
+                .unwrap()
+                .map(|found| found.sha256)
+        );
+        assert!(storage
+            .find("invoices/2023/2023-003.pdf")
+            .unwrap()
+            .is_none());
+    }
+}
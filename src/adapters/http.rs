@@ -1 +1,303 @@
+use core::fmt;
+use std::{error::Error, path::PathBuf};
 
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::{
+    commands::{
+        Command, CreateInvoiceCommand, FindInvoiceCommand, ListInvoicesCommand,
+        RegisterPaymentCommand,
+    },
+    error::TabulaError,
+};
+
+use super::{
+    audit::AuditLog,
+    cli::{
+        arguments::{parse_list, parse_report, ListArgs, ReportActions},
+        invoice_query, run_report, Output,
+    },
+    config::Config,
+    ledger_storage::FileLedgerStorage,
+    logger,
+    notification::announced,
+    InputAdapter,
+};
+
+/// Serves the invoice commands as a JSON API. The ledger is read again for every request, so
+/// changes made to it by hand are picked up without a restart.
+pub struct HttpAdapter {
+    bind: String,
+    ledger: PathBuf,
+    config: Config,
+    response: String,
+}
+
+impl HttpAdapter {
+    pub fn new(bind: String, ledger: PathBuf, config: Config) -> Self {
+        Self {
+            bind,
+            ledger,
+            config,
+            response: String::new(),
+        }
+    }
+
+    fn handle(
+        &self,
+        method: &Method,
+        path: &str,
+        query: &str,
+        body: &str,
+    ) -> Result<Box<dyn Output>, Box<dyn Error>> {
+        let ledger_storage = FileLedgerStorage::open(&self.ledger)?;
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+        match (method, segments.as_slice()) {
+            (Method::Get, ["invoices"]) => ListInvoicesCommand::new(ledger_storage)
+                .with_query(invoice_query(list_args(query)?, &self.config))
+                .execute(),
+            (Method::Get, ["invoices", number]) => FindInvoiceCommand::new(ledger_storage)
+                .with_invoice_number(decode(number))
+                .execute(),
+            (Method::Post, ["invoices"]) => {
+                let invoice = serde_json::from_str(body).map_err(BadRequestError::from)?;
+                self.audited(method, path, body, || {
+                    CreateInvoiceCommand::new(ledger_storage)
+                        .with_invoice(invoice)
+                        .with_payment_terms_days(self.config.payment_terms_days)
+                        .execute()
+                })
+            }
+            (Method::Post, ["invoices", number, "payments"]) => {
+                let payment = serde_json::from_str(body).map_err(BadRequestError::from)?;
+                self.audited(method, path, body, || {
+                    RegisterPaymentCommand::new(ledger_storage)
+                        .with_invoice_number(decode(number))
+                        .with_payment(payment)
+                        .execute()
+                })
+            }
+            (Method::Get, ["reports", report]) => {
+                run_report(ledger_storage, report_args(report, query)?, &self.config)
+            }
+            _ => Err(Box::new(RouteNotFoundError(format!("{} {}", method, path)))),
+        }
+    }
+
+    /// Runs a request that changes the ledger, recording it in the audit log and announcing it
+    /// to the webhooks.
+    fn audited(
+        &self,
+        method: &Method,
+        path: &str,
+        body: &str,
+        run: impl FnOnce() -> Result<Box<dyn Output>, Box<dyn Error>>,
+    ) -> Result<Box<dyn Output>, Box<dyn Error>> {
+        announced(&self.config, &self.ledger, || {
+            AuditLog::new(&self.config.audit, &self.ledger).record(
+                &format!("{} {}", method, path),
+                vec![body.to_string()],
+                run,
+            )
+        })
+    }
+
+    fn respond(&self, mut request: Request) -> Result<(), Box<dyn Error>> {
+        let mut body = String::new();
+        request.as_reader().read_to_string(&mut body)?;
+
+        let url = request.url().to_string();
+        let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+
+        let (status, json) = match self.handle(request.method(), path, query, &body) {
+            Ok(output) => (200, output.as_json()),
+            Err(err) => {
+                let status = match err.downcast_ref::<TabulaError>() {
+                    Some(TabulaError::NotFound(_)) => 404,
+                    Some(TabulaError::Validation(_)) => 400,
+                    _ if err.is::<RouteNotFoundError>() => 404,
+                    _ if err.is::<BadRequestError>() => 400,
+                    _ => 500,
+                };
+                (
+                    status,
+                    serde_json::json!({ "error": err.to_string() }).to_string(),
+                )
+            }
+        };
+
+        logger::info(&format!("{} {} {}", request.method(), url, status));
+        let content_type = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
+            .expect("Content-Type header is valid");
+        request.respond(
+            Response::from_string(json)
+                .with_status_code(status)
+                .with_header(content_type),
+        )?;
+        Ok(())
+    }
+
+    fn set_response(&mut self, response: String) {
+        self.response = response;
+    }
+}
+
+impl InputAdapter for HttpAdapter {
+    fn run(&mut self) -> Result<(), Box<dyn Error>> {
+        let server = Server::http(&self.bind).map_err(|err| err as Box<dyn Error>)?;
+        // With port 0 the system picks a free port, which is logged for clients to find
+        let address = server
+            .server_addr()
+            .to_ip()
+            .map_or(self.bind.clone(), |address| address.to_string());
+        logger::info(&format!("Listening on http://{}", address));
+
+        for request in server.incoming_requests() {
+            if let Err(err) = self.respond(request) {
+                logger::error(&format!("Could not respond to request: {}", err));
+            }
+        }
+
+        self.set_response(format!("Stopped listening on {}", self.bind));
+        Ok(())
+    }
+
+    fn get_response(&self) -> String {
+        self.response.clone()
+    }
+}
+
+/// Turns a query string into the arguments of a command, with the names of its options on
+/// the command line: `group_by=month` for `--group-by month`.
+fn query_args(query: &str) -> Vec<String> {
+    let mut args = vec![];
+    for (key, value) in query_pairs(query) {
+        let flag = format!("--{}", key.replace('_', "-"));
+        match value.as_str() {
+            "" | "true" => args.push(flag),
+            "false" => {}
+            _ => args.extend([flag, value]),
+        }
+    }
+    args
+}
+
+/// Reads the filters of the invoice list from the query string, with the same names as the
+/// command line: `/invoices?status=open&sort=due`.
+fn list_args(query: &str) -> Result<ListArgs, Box<dyn Error>> {
+    parse_list(query_args(query)).map_err(|err| BadRequestError(err.to_string()).into())
+}
+
+/// Reads the report options from the query string, with the same names and defaults as the
+/// command line: `/reports/revenue?group_by=month&year=2023`.
+fn report_args(report: &str, query: &str) -> Result<ReportActions, Box<dyn Error>> {
+    parse_report(report, query_args(query)).map_err(|err| BadRequestError(err.to_string()).into())
+}
+
+fn query_pairs(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode(key), decode(value))
+        })
+        .collect()
+}
+
+/// Decodes `+` and `%XX` escapes from a URL component.
+fn decode(component: &str) -> String {
+    let bytes = component.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 3 <= bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or_default();
+                match u8::from_str_radix(hex, 16) {
+                    Ok(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    Err(_) => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[derive(Debug)]
+struct BadRequestError(String);
+
+impl Error for BadRequestError {}
+
+impl fmt::Display for BadRequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<serde_json::Error> for BadRequestError {
+    fn from(err: serde_json::Error) -> Self {
+        BadRequestError(err.to_string())
+    }
+}
+
+#[derive(Debug)]
+struct RouteNotFoundError(String);
+
+impl Error for RouteNotFoundError {}
+
+impl fmt::Display for RouteNotFoundError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "No such endpoint: {}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::adapters::cli::arguments::ListStatus;
+
+    use super::*;
+
+    #[test]
+    fn test_report_args_from_query() {
+        let args = report_args("revenue", "group_by=month&year=2023&sparkline=true").unwrap();
+
+        let ReportActions::Revenue(args) = args else {
+            panic!("Expected the revenue report");
+        };
+        assert_eq!(Some(2023), args.year);
+        assert!(args.sparkline);
+    }
+
+    #[test]
+    fn test_list_args_from_query() {
+        let args = list_args("status=overdue&tag=hosting&tag=eu&sort=due&reverse").unwrap();
+
+        assert!(matches!(args.status, Some(ListStatus::Overdue)));
+        assert_eq!(vec!["hosting", "eu"], args.tag);
+        assert!(args.reverse);
+        assert!(list_args("status=late").is_err());
+
+        let args = list_args("where=customer+~+%27Acme%27").unwrap();
+        assert_eq!("customer ~ 'Acme'", args.filter.unwrap().to_string());
+        let err = list_args("where=due+%3C+%27today%27").unwrap_err();
+        assert!(err.is::<BadRequestError>());
+        assert!(err
+            .to_string()
+            .contains("Cannot use < with a date and text at column 1"));
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!("Acme Corp & Co", decode("Acme+Corp+%26+Co"));
+    }
+}
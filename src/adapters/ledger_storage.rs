@@ -1,14 +1,64 @@
-use core::fmt::{self, Display};
-use std::error::Error;
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
 
-use beancount_core::{metadata::MetaValue, Transaction};
+use beancount_core::{
+    metadata::{Meta, MetaValue},
+    Account, AccountType, Booking as BeancountBooking, Directive, Flag, Posting, Transaction,
+};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
 
-use crate::domain::invoice::{Date, Invoice, InvoiceList, InvoiceNumber, LineItem};
+use crate::{
+    adapters::logger,
+    domain::{
+        account::{self, Booking},
+        bill::Bill,
+        invoice::{Date, Invoice, InvoiceList, InvoiceNumber, InvoiceState, LineItem, Revenue},
+        money::Money,
+        payment::Payment,
+        reminder::Reminder,
+    },
+    error::{InvalidValue, Location, TabulaError},
+};
+
+use self::cache::LedgerCache;
+
+pub mod cache;
+pub mod check;
 
 pub trait LedgerStorage {
     fn find_invoice(&self, number: &InvoiceNumber) -> Result<Invoice, Box<dyn Error>>;
     fn find_invoices(&self) -> Result<InvoiceList, Box<dyn Error>>;
+    fn find_bills(&self) -> Result<Vec<Bill>, Box<dyn Error>>;
+    /// Sum, per currency, of all postings on `account_prefix` and its sub-accounts, up to and
+    /// including `until`.
+    fn balance(&self, account_prefix: &str, until: &Date) -> Result<Vec<Money>, Box<dyn Error>>;
+    /// Reminders sent for invoices, recorded as `custom "reminder"` directives.
+    fn find_reminders(&self) -> Result<Vec<Reminder>, Box<dyn Error>>;
+    /// Accounts declared with `open` directives, closed when there is a `close` directive.
+    fn find_accounts(&self) -> Result<Vec<account::Account>, Box<dyn Error>>;
     fn build(&self) -> Result<Invoice, Box<dyn Error>>;
+    /// Adds entries, rendered as beancount, to the end of the ledger.
+    fn append(&self, entries: &str) -> Result<(), Box<dyn Error>>;
+    /// Sets metadata on the transaction of an invoice, in the file it is written in. Values
+    /// are rendered as beancount, so text must be quoted.
+    fn set_invoice_metadata(
+        &self,
+        number: &InvoiceNumber,
+        metadata: &[(&str, String)],
+    ) -> Result<(), Box<dyn Error>>;
+    /// Sets metadata on the transaction of a draft invoice like `set_invoice_metadata`, and
+    /// marks it `*` as issued.
+    fn issue_invoice(
+        &self,
+        number: &InvoiceNumber,
+        metadata: &[(&str, String)],
+    ) -> Result<(), Box<dyn Error>>;
 }
 
 pub struct StdinLedgerStorage {
@@ -19,80 +69,937 @@ impl StdinLedgerStorage {
     pub fn new(stdin: String) -> Self {
         Self { ledger: stdin }
     }
+
+    /// What tabula reads from the ledger, in ledger order.
+    fn facts(&self) -> Result<Vec<Fact>, Box<dyn Error>> {
+        let ledger = beancount_parser::parse(&self.ledger).map_err(|err| TabulaError::Parse {
+            location: Box::default(),
+            message: err.to_string(),
+        })?;
+        Ok(facts(&self.ledger, ledger.directives)?)
+    }
+}
+
+/// A ledger read from a file on disk and the files it `include`s, each parsed on its own.
+/// Changes are appended to the main file. With the cache enabled, only the files that changed
+/// since they were cached are parsed.
+pub struct FileLedgerStorage {
+    path: PathBuf,
+    files: Vec<PathBuf>,
+    /// The facts of all files, with those of an included file where it is included
+    facts: Vec<Fact>,
+}
+
+impl FileLedgerStorage {
+    pub fn open(path: &Path) -> Result<Self, Box<dyn Error>> {
+        if let Some(config) = cache::config() {
+            match LedgerCache::open(&cache::path(config, path)) {
+                Ok(mut cache) => return Self::open_cached(path, &mut cache),
+                Err(err) => logger::warn(&format!(
+                    "Could not open the ledger cache, parsing the whole ledger: {}",
+                    err
+                )),
+            }
+        }
+
+        let mut files = vec![];
+        let facts = read_with_includes(
+            path,
+            &mut files,
+            &mut |file: &Path| file_facts(file, &read_file(file)?),
+            Fact::included,
+        )?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            files,
+            facts,
+        })
+    }
+
+    fn open_cached(path: &Path, cache: &mut LedgerCache) -> Result<Self, Box<dyn Error>> {
+        let mut files = vec![];
+        let facts = read_with_includes(
+            path,
+            &mut files,
+            &mut |file: &Path| cache.facts(file),
+            Fact::included,
+        )?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            files,
+            facts,
+        })
+    }
+
+    /// The main ledger file and all files it includes.
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    /// Removes the transaction of a draft invoice from the file it is written in, as when the
+    /// draft is voided. Issued invoices are credited instead.
+    pub fn remove_draft(&self, number: &InvoiceNumber) -> Result<(), Box<dyn Error>> {
+        self.edit_invoice(number, |content| without_draft(content, number))
+    }
+
+    /// Rewrites the file the invoice is written in with `edit`, which returns None for text
+    /// the invoice is not in.
+    fn edit_invoice(
+        &self,
+        number: &InvoiceNumber,
+        edit: impl Fn(&str) -> Result<Option<String>, TabulaError>,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut edited = None;
+        for file in &self.files {
+            let content = fs::read_to_string(file)?;
+            if let Some(updated) = edit(&content)? {
+                if edited.is_some() {
+                    return Err(ambiguous(number).into());
+                }
+                edited = Some((file, updated));
+            }
+        }
+        let (file, updated) = edited.ok_or_else(|| not_found(number))?;
+        fs::write(file, updated)?;
+        Ok(())
+    }
+}
+
+/// A ledger file that is parsed once, with its invoices indexed by number, for processes that
+/// answer many questions about the same ledger. Changes are appended to the file, so open it
+/// again to see them.
+pub struct IndexedLedgerStorage {
+    file: FileLedgerStorage,
+    invoices: Vec<Invoice>,
+    invoices_by_number: HashMap<String, usize>,
+    bills: Vec<Bill>,
+    reminders: Vec<Reminder>,
+}
+
+impl IndexedLedgerStorage {
+    pub fn open(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let file = FileLedgerStorage::open(path)?;
+        let invoices = file.find_invoices()?.invoices;
+        let invoices_by_number = invoices
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(index, invoice)| (invoice.number.0.clone(), index))
+            .collect();
+        let bills = file.find_bills()?;
+        let reminders = file.find_reminders()?;
+
+        Ok(Self {
+            file,
+            invoices,
+            invoices_by_number,
+            bills,
+            reminders,
+        })
+    }
+
+    pub fn files(&self) -> &[PathBuf] {
+        self.file.files()
+    }
+}
+
+/// Reads a ledger file and the files it `include`s, depth first: what `read` gives for an
+/// included file takes the place of the item that `included` tells includes it. Included
+/// paths are relative to the including file. `files` keeps the files in the order they were
+/// read, and a file that was read already is not read again. The storage, its cache and
+/// `tabula check` all read the ledger this way.
+pub(crate) fn read_with_includes<T, E>(
+    path: &Path,
+    files: &mut Vec<PathBuf>,
+    read: &mut impl FnMut(&Path) -> Result<Vec<T>, E>,
+    included: impl Fn(&T) -> Option<&Path> + Copy,
+) -> Result<Vec<T>, E> {
+    if files.iter().any(|file| file == path) {
+        return Ok(vec![]);
+    }
+    files.push(path.to_path_buf());
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut items = vec![];
+    for item in read(path)? {
+        match included(&item).map(|file| dir.join(file)) {
+            Some(file) => items.extend(read_with_includes(&file, files, read, included)?),
+            None => items.push(item),
+        }
+    }
+    Ok(items)
+}
+
+pub(crate) fn read_file(path: &Path) -> Result<String, TabulaError> {
+    fs::read_to_string(path).map_err(|source| TabulaError::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Several transactions have the number of the invoice to change, so it is unclear which.
+pub(crate) fn ambiguous(number: &InvoiceNumber) -> TabulaError {
+    TabulaError::Validation(format!(
+        "More than one invoice is numbered {}, give each its own number in the ledger first",
+        number
+    ))
+}
+
+/// The facts of a single ledger file, without those of the files it includes. Errors point
+/// at the file.
+pub(crate) fn file_facts(file: &Path, content: &str) -> Result<Vec<Fact>, TabulaError> {
+    let in_file = |location: Location| Location {
+        file: Some(file.to_path_buf()),
+        ..location
+    };
+    let ledger = beancount_parser::parse(content).map_err(|err| TabulaError::Parse {
+        location: Box::new(in_file(Location::default())),
+        message: err.to_string(),
+    })?;
+    facts(content, ledger.directives).map_err(|err| err.relocate(in_file))
+}
+
+/// The ledger text with metadata set on the transaction of invoice `number`: lines with the
+/// same keys are replaced, others are added below `invoice_number`. With `issue`, the flag of
+/// the transaction becomes `*` as well. None when the invoice is not in this text, and an
+/// error when more than one transaction has its number, as with several `TBD` drafts.
+///
+/// Issued invoices are frozen: metadata can be added to them, but not changed.
+fn with_invoice_metadata(
+    content: &str,
+    number: &InvoiceNumber,
+    metadata: &[(&str, String)],
+    issue: bool,
+) -> Result<Option<String>, TabulaError> {
+    let mut lines: Vec<String> = content.lines().map(String::from).collect();
+    let Some((header, at)) = invoice_lines(&lines, number)? else {
+        return Ok(None);
+    };
+    let indent: String = lines[at]
+        .chars()
+        .take_while(|c| c.is_whitespace())
+        .collect();
+    let start = header.map_or(0, |header| header + 1);
+    let issued = header.is_some_and(|header| matches!(flag(&lines[header]), Some("*" | "txn")));
+    if issue && issued {
+        let message = format!("Invoice {} is already issued", number);
+        return Err(TabulaError::Validation(message));
+    }
+
+    for (key, value) in metadata.iter().rev() {
+        let line = format!("{}{}: {}", indent, key, value);
+        let prefix = format!("{}:", key);
+        let existing = (start..lines.len())
+            .take_while(|i| lines[*i].starts_with(char::is_whitespace))
+            .find(|i| lines[*i].trim().starts_with(&prefix));
+        match existing {
+            Some(i) if issued && lines[i].trim() != line.trim() => {
+                let message = format!(
+                    "Invoice {} is issued, so its {} cannot be changed. Correct it with a credit \
+                     note",
+                    number, key
+                );
+                return Err(TabulaError::Validation(message));
+            }
+            Some(i) => lines[i] = line,
+            None => lines.insert(at + 1, line),
+        }
+    }
+    if let Some(header) = header.filter(|_| issue) {
+        lines[header] = lines[header].replacen(" ! ", " * ", 1);
+    }
+
+    let mut updated = lines.join("\n");
+    if content.ends_with('\n') {
+        updated.push('\n');
+    }
+    Ok(Some(updated))
+}
+
+/// The ledger text without the transaction of draft invoice `number`, and the blank line
+/// before it. None when the invoice is not in this text.
+fn without_draft(content: &str, number: &InvoiceNumber) -> Result<Option<String>, TabulaError> {
+    let mut lines: Vec<String> = content.lines().map(String::from).collect();
+    let Some((header, at)) = invoice_lines(&lines, number)? else {
+        return Ok(None);
+    };
+    let Some(header) = header.filter(|header| flag(&lines[*header]) == Some("!")) else {
+        let message = format!("Invoice {} is issued, credit it instead", number);
+        return Err(TabulaError::Validation(message));
+    };
+
+    let end = (at..lines.len())
+        .find(|i| !lines[*i].starts_with(char::is_whitespace))
+        .unwrap_or(lines.len());
+    let from = match header.checked_sub(1) {
+        Some(blank) if lines[blank].trim().is_empty() => blank,
+        _ => header,
+    };
+    lines.drain(from..end);
+
+    let mut updated = lines.join("\n");
+    if content.ends_with('\n') && !updated.is_empty() {
+        updated.push('\n');
+    }
+    Ok(Some(updated))
 }
 
-impl<'a> From<Transaction<'a>> for Invoice {
-    fn from(borrowed_tx: Transaction<'a>) -> Self {
+/// Where the transaction of invoice `number` is in the lines of a ledger: its header, if it
+/// has one, and its `invoice_number` line. The metadata and postings of a transaction are the
+/// indented lines below its header. An error when more than one transaction has the number.
+fn invoice_lines(
+    lines: &[String],
+    number: &InvoiceNumber,
+) -> Result<Option<(Option<usize>, usize)>, TabulaError> {
+    let is_number = |line: &str| {
+        line.trim()
+            .strip_prefix("invoice_number:")
+            .map(|value| value.trim().trim_matches('"') == number.0)
+            .unwrap_or(false)
+    };
+    let Some(at) = lines.iter().position(|line| is_number(line)) else {
+        return Ok(None);
+    };
+    if lines[at + 1..].iter().any(|line| is_number(line)) {
+        return Err(ambiguous(number));
+    }
+    let header = (0..at)
+        .rev()
+        .find(|i| !lines[*i].starts_with(char::is_whitespace));
+
+    Ok(Some((header, at)))
+}
+
+/// The flag of a transaction header, such as `*` or `!`.
+fn flag(header: &str) -> Option<&str> {
+    header.split_whitespace().nth(1)
+}
+
+impl<'a> TryFrom<Transaction<'a>> for Invoice {
+    type Error = TabulaError;
+
+    fn try_from(borrowed_tx: Transaction<'a>) -> Result<Self, Self::Error> {
         let tx = borrowed_tx.clone();
 
-        let date: Date = tx.date.into();
-        let due_date: Option<Date> = tx.meta.get("due").map(|d| d.into());
+        let date = date_of(&tx.date)?;
+        let due_date: Option<Date> = meta(&tx.meta, "due")?;
 
         let narration = tx.narration.to_string();
-        let number: InvoiceNumber = tx.meta.get("invoice_number").into();
-        let total = "1337 USD".to_string();
+        let number: InvoiceNumber =
+            meta(&tx.meta, "invoice_number")?.unwrap_or(InvoiceNumber("TBD".to_string()));
+        let total = counter_posting(&tx, "Assets:AccountsReceivable")
+            .and_then(posting_amount)
+            .map(|amount| amount.to_string())
+            .unwrap_or_default();
+        let customer = party(&tx, "customer", "Assets:AccountsReceivable");
+        let revenue = tx
+            .postings
+            .iter()
+            .filter(|p| sub_account(&account_name(&p.account), "Income").is_some())
+            .filter_map(|p| {
+                posting_amount(p).map(|amount| Revenue {
+                    account: account_name(&p.account),
+                    amount: Money::new(-amount.amount, &amount.currency),
+                })
+            })
+            .collect();
 
         let line_items: Vec<LineItem> = tx
             .postings
             .iter()
             .filter(|p| p.meta.get("line_item_name").is_some())
-            .map(|p| {
-                let description =
-                    if let Some(MetaValue::Text(description)) = p.meta.get("line_item_name") {
-                        description.to_string()
-                    } else {
-                        panic!("Expected a text value");
-                    };
+            .map(line_item)
+            .collect::<Result<_, _>>()?;
 
-                LineItem {
-                    description,
-                    unit_price: "13.37 USD".to_string(),
-                    quantity: "100".to_string(),
-                    total: "1337 USD".to_string(),
-                }
-            })
-            .collect();
+        let mut tags: Vec<String> = tx.tags.iter().map(|tag| tag.to_string()).collect();
+        tags.sort();
 
-        Self {
+        Ok(Self {
             date,
             due_date,
             narration,
             number,
             total,
             line_items,
+            customer,
+            payments: vec![],
+            revenue,
+            billing_email: text_meta(&tx, "billing_email"),
+            sent_on: None,
+            credits_invoice: meta(&tx.meta, "credits_invoice")?,
+            document: text_meta(&tx, "document"),
+            document_sha256: text_meta(&tx, "document_sha256"),
+            html_document: text_meta(&tx, "html_document"),
+            html_document_sha256: text_meta(&tx, "html_document_sha256"),
+            tags,
+            metadata: other_meta(&tx),
+            state: match tx.flag {
+                Flag::Okay => InvoiceState::Issued,
+                _ => InvoiceState::Draft,
+            },
+            issued_on: meta(&tx.meta, "issued")?,
+        })
+    }
+}
+
+impl<'a> TryFrom<Transaction<'a>> for Bill {
+    type Error = TabulaError;
+
+    fn try_from(tx: Transaction<'a>) -> Result<Self, Self::Error> {
+        let total = counter_posting(&tx, "Liabilities:AccountsPayable")
+            .and_then(posting_amount)
+            .map(|amount| Money::new(amount.amount.abs(), &amount.currency))
+            .unwrap_or_else(|| Money::zero(""));
+
+        let number: Option<InvoiceNumber> = meta(&tx.meta, "bill_number")?;
+        Ok(Self {
+            date: date_of(&tx.date)?,
+            due_date: meta(&tx.meta, "due")?,
+            number: number.map_or("TBD".to_string(), |number| number.0),
+            supplier: party(&tx, "supplier", "Liabilities:AccountsPayable"),
+            total,
+            payments: vec![],
+        })
+    }
+}
+
+/// Renders an account as it is written in the ledger, e.g. `Assets:AccountsReceivable`.
+pub fn account_name(account: &Account) -> String {
+    let ty = match account.ty {
+        AccountType::Assets => "Assets",
+        AccountType::Liabilities => "Liabilities",
+        AccountType::Equity => "Equity",
+        AccountType::Income => "Income",
+        AccountType::Expenses => "Expenses",
+    };
+    std::iter::once(ty)
+        .chain(account.parts.iter().map(|part| part.as_ref()))
+        .collect::<Vec<&str>>()
+        .join(":")
+}
+
+fn posting_amount(posting: &Posting) -> Option<Money> {
+    match (&posting.units.num, &posting.units.currency) {
+        (Some(num), Some(currency)) => Some(Money::new(*num, currency)),
+        _ => None,
+    }
+}
+
+/// The first posting on `account` or one of its sub-accounts.
+fn counter_posting<'t, 'a>(tx: &'t Transaction<'a>, account: &str) -> Option<&'t Posting<'a>> {
+    tx.postings
+        .iter()
+        .find(|p| sub_account(&account_name(&p.account), account).is_some())
+}
+
+/// The part of `name` below `parent`: `Some("")` for the parent itself, `Some("Acme")` for
+/// `<parent>:Acme` and None for unrelated accounts.
+fn sub_account<'n>(name: &'n str, parent: &str) -> Option<&'n str> {
+    let rest = name.strip_prefix(parent)?;
+    if rest.is_empty() {
+        Some(rest)
+    } else {
+        rest.strip_prefix(':')
+    }
+}
+
+/// A line item from the `line_item_*` metadata of a posting. Without a quantity and unit price
+/// the line is a single unit of the posting amount.
+fn line_item(posting: &Posting) -> Result<LineItem, TabulaError> {
+    let description = match posting.meta.get("line_item_name") {
+        Some(MetaValue::Text(description)) => description.to_string(),
+        Some(other) => {
+            return Err(InvalidValue::from_meta("text", other).for_key("line_item_name"));
+        }
+        None => String::new(),
+    };
+    let amount =
+        posting_amount(posting).map(|amount| Money::new(amount.amount.abs(), &amount.currency));
+    let number = |key| number_meta(&posting.meta, key).map_err(|err| err.for_key(key));
+    let currency = amount
+        .as_ref()
+        .map(|amount| amount.currency.clone())
+        .unwrap_or_default();
+
+    let (quantity, unit_price) = match (
+        number("line_item_quantity")?,
+        number("line_item_unit_price")?,
+    ) {
+        (Some(quantity), Some(unit_price)) => (quantity, Money::new(unit_price, &currency)),
+        _ => (
+            Decimal::ONE,
+            amount.clone().unwrap_or_else(|| Money::zero(&currency)),
+        ),
+    };
+    let total = Money::new((quantity * unit_price.amount).normalize(), &currency);
+
+    Ok(LineItem {
+        description,
+        unit_price: unit_price.to_string(),
+        quantity: quantity.to_string(),
+        total: total.to_string(),
+        unit: match posting.meta.get("line_item_unit") {
+            Some(MetaValue::Text(unit)) | Some(MetaValue::Currency(unit)) => Some(unit.to_string()),
+            _ => None,
+        },
+        account: Some(account_name(&posting.account)),
+    })
+}
+
+/// The metadata under `key`, or None when there is none.
+fn meta<'v, T>(meta: &'v Meta<'v>, key: &str) -> Result<Option<T>, TabulaError>
+where
+    T: TryFrom<&'v MetaValue<'v>, Error = InvalidValue>,
+{
+    meta.get(key)
+        .map(|value| T::try_from(value).map_err(|err| err.for_key(key)))
+        .transpose()
+}
+
+/// The number under `key`, which may also be written as text, or None when there is none.
+fn number_meta(meta: &Meta, key: &str) -> Result<Option<Decimal>, InvalidValue> {
+    match meta.get(key) {
+        Some(MetaValue::Number(number)) => Ok(Some(*number)),
+        Some(MetaValue::Text(text)) => text
+            .parse()
+            .map(Some)
+            .map_err(|_| InvalidValue::new("a number", format!("\"{}\"", text))),
+        Some(other) => Err(InvalidValue::from_meta("a number", other)),
+        None => Ok(None),
+    }
+}
+
+fn text_meta(tx: &Transaction, key: &str) -> Option<String> {
+    match tx.meta.get(key) {
+        Some(MetaValue::Text(text)) => Some(text.to_string()),
+        _ => None,
+    }
+}
+
+/// Metadata keys that are read into fields of an invoice, or added by the parser.
+const INVOICE_META_KEYS: [&str; 12] = [
+    "invoice_number",
+    "issued",
+    "due",
+    "customer",
+    "billing_email",
+    "credits_invoice",
+    "document",
+    "document_sha256",
+    "html_document",
+    "html_document_sha256",
+    "filename",
+    "lineno",
+];
+
+/// The metadata of an invoice transaction not read into other fields, as text.
+fn other_meta(tx: &Transaction) -> BTreeMap<String, String> {
+    tx.meta
+        .iter()
+        .filter(|(key, _)| !INVOICE_META_KEYS.contains(&key.as_ref()))
+        .filter_map(|(key, value)| {
+            let value = match value {
+                MetaValue::Text(text) | MetaValue::Currency(text) => text.to_string(),
+                MetaValue::Number(number) => number.to_string(),
+                MetaValue::Bool(value) => value.to_string(),
+                MetaValue::Date(date) => date.to_string(),
+                _ => return None,
+            };
+            Some((key.to_string(), value))
+        })
+        .collect()
+}
+
+/// The customer or supplier of a transaction: taken from the `meta_key` metadata, else from the
+/// sub-account of the counter posting (`Assets:AccountsReceivable:Acme`), else from the payee.
+fn party(tx: &Transaction, meta_key: &str, account: &str) -> Option<String> {
+    if let Some(MetaValue::Text(name)) = tx.meta.get(meta_key) {
+        return Some(name.to_string());
+    }
+
+    let from_account = counter_posting(tx, account).and_then(|p| {
+        let name = account_name(&p.account);
+        sub_account(&name, account)
+            .filter(|sub| !sub.is_empty())
+            .map(|sub| sub.to_string())
+    });
+
+    from_account.or_else(|| tx.payee.as_ref().map(|payee| payee.to_string()))
+}
+
+/// What tabula reads from a single directive of a ledger. Facts only link to each other, such
+/// as payments to the invoice they pay, when the ledger is queried, so the facts of a file do
+/// not depend on the other files of the ledger and can be cached per file.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) enum Fact {
+    Invoice(Box<Invoice>),
+    Bill(Bill),
+    /// A payment of the invoice or bill whose number is in the `paid` metadata, which is
+    /// `paid_invoice` or `paid_bill`
+    Payment {
+        paid: String,
+        number: String,
+        payment: Payment,
+    },
+    Sent {
+        number: String,
+        date: Date,
+    },
+    Reminder(Reminder),
+    Posting {
+        date: Date,
+        account: String,
+        amount: Money,
+    },
+    Open(account::Account),
+    Close {
+        account: String,
+        date: Date,
+    },
+    /// An `include`d file, relative to the file that includes it
+    Include(PathBuf),
+}
+
+impl Fact {
+    fn included(&self) -> Option<&Path> {
+        match self {
+            Fact::Include(file) => Some(file),
+            _ => None,
+        }
+    }
+}
+
+/// The facts of the directives of a ledger, in order. `content` is the text the directives
+/// were parsed from, to tell where metadata tabula cannot read is.
+pub(crate) fn facts(content: &str, directives: Vec<Directive>) -> Result<Vec<Fact>, TabulaError> {
+    let mut facts = vec![];
+
+    for directive in directives {
+        match directive {
+            Directive::Transaction(tx) => {
+                let located = |err: TabulaError| locate(err, content, tx.source);
+                if tx.meta.get("bill_number").is_some() {
+                    facts.push(Fact::Bill(Bill::try_from(tx.clone()).map_err(located)?));
+                }
+                if tx.meta.get("invoice_number").is_some() {
+                    let invoice = Invoice::try_from(tx.clone()).map_err(located)?;
+                    facts.push(Fact::Invoice(Box::new(invoice)));
+                }
+                for paid in ["paid_invoice", "paid_bill"] {
+                    let Some(number) = meta::<InvoiceNumber>(&tx.meta, paid).map_err(located)?
+                    else {
+                        continue;
+                    };
+                    let amount = tx
+                        .postings
+                        .iter()
+                        .filter_map(posting_amount)
+                        .find(|amount| amount.amount > Decimal::ZERO);
+                    if let Some(amount) = amount {
+                        facts.push(Fact::Payment {
+                            paid: paid.to_string(),
+                            number: number.0,
+                            payment: Payment {
+                                date: date_of(&tx.date).map_err(located)?,
+                                amount,
+                            },
+                        });
+                    }
+                }
+                for posting in &tx.postings {
+                    if let Some(amount) = posting_amount(posting) {
+                        facts.push(Fact::Posting {
+                            date: date_of(&tx.date).map_err(located)?,
+                            account: account_name(&posting.account),
+                            amount,
+                        });
+                    }
+                }
+            }
+            Directive::Custom(custom) if custom.name == "sent" => {
+                if let Some(number) = custom.args.first() {
+                    facts.push(Fact::Sent {
+                        number: number.to_string(),
+                        date: date_of(&custom.date)
+                            .map_err(|err| locate(err, content, custom.source))?,
+                    });
+                }
+            }
+            Directive::Custom(custom) if custom.name == "reminder" => {
+                if let [number, level, ..] = custom.args.as_slice() {
+                    if let Ok(level) = level.parse() {
+                        facts.push(Fact::Reminder(Reminder {
+                            date: date_of(&custom.date)
+                                .map_err(|err| locate(err, content, custom.source))?,
+                            invoice_number: InvoiceNumber(number.to_string()),
+                            level,
+                        }));
+                    }
+                }
+            }
+            Directive::Open(open) => facts.push(Fact::Open(account::Account {
+                name: account_name(&open.account),
+                opened_on: date_of(&open.date).map_err(|err| locate(err, content, open.source))?,
+                closed_on: None,
+                currencies: open.currencies.iter().map(|c| c.to_string()).collect(),
+                booking: open.booking.map(|booking| match booking {
+                    BeancountBooking::Strict => Booking::Strict,
+                    BeancountBooking::None => Booking::None,
+                    BeancountBooking::Average => Booking::Average,
+                    BeancountBooking::Fifo => Booking::Fifo,
+                    BeancountBooking::Lifo => Booking::Lifo,
+                }),
+            })),
+            Directive::Close(close) => facts.push(Fact::Close {
+                account: account_name(&close.account),
+                date: date_of(&close.date).map_err(|err| locate(err, content, close.source))?,
+            }),
+            Directive::Include(include) => {
+                facts.push(Fact::Include(PathBuf::from(include.filename.as_ref())))
+            }
+            _ => {}
+        }
+    }
+
+    Ok(facts)
+}
+
+/// The date of a directive. An invalid one is reported like metadata, under `date`.
+fn date_of(date: &beancount_core::Date) -> Result<Date, TabulaError> {
+    Date::try_from(date.clone()).map_err(|err| err.for_key("date"))
+}
+
+/// The line of `content` that `source`, a slice of it, starts on, counting from 1.
+fn line_of(content: &str, source: &str) -> Option<usize> {
+    let offset = (source.as_ptr() as usize).checked_sub(content.as_ptr() as usize)?;
+    Some(content.get(..offset)?.matches('\n').count() + 1)
+}
+
+/// The lines of the directive that `source`, a slice of `content`, starts: its first line and
+/// the indented lines below it, with their line numbers counting from 1.
+pub(crate) fn directive_lines<'c>(content: &'c str, source: Option<&str>) -> Vec<(usize, &'c str)> {
+    let Some(start) = source.and_then(|source| line_of(content, source)) else {
+        return vec![];
+    };
+    let mut lines = content.lines().enumerate().skip(start - 1);
+    let Some((_, header)) = lines.next() else {
+        return vec![];
+    };
+    let body = lines
+        .take_while(|(_, line)| line.starts_with([' ', '\t']))
+        .map(|(n, line)| (n + 1, line));
+    std::iter::once((start, header)).chain(body).collect()
+}
+
+/// A location showing the first line of a directive and, when there is one, the offending
+/// line below it.
+pub(crate) fn directive_location(
+    lines: &[(usize, &str)],
+    offending: Option<(usize, &str)>,
+) -> Location {
+    let mut snippet: Vec<(usize, String)> = lines
+        .first()
+        .map(|(n, header)| (*n, header.to_string()))
+        .into_iter()
+        .collect();
+    if let Some((n, line)) = offending {
+        snippet.push((n, line.to_string()));
+    }
+    Location {
+        file: None,
+        line: snippet.last().map(|(line, _)| *line),
+        snippet,
+    }
+}
+
+/// Points an error about a directive at its lines in `content`: its first line and, for
+/// metadata, the line with the key.
+fn locate(err: TabulaError, content: &str, source: Option<&str>) -> TabulaError {
+    let lines = directive_lines(content, source);
+    if lines.is_empty() {
+        return err;
+    }
+    let body = &lines[1..];
+    let offending = match &err {
+        TabulaError::InvalidMetadata { key, found, .. } => {
+            let prefix = format!("{}:", key);
+            let has_key = |line: &str| line.trim_start().starts_with(&prefix);
+            body.iter()
+                .find(|(_, line)| has_key(line) && line.contains(found.as_str()))
+                .or_else(|| body.iter().find(|(_, line)| has_key(line)))
+                .copied()
+        }
+        _ => None,
+    };
+
+    let at = directive_location(&lines, offending);
+    err.relocate(|location| Location {
+        file: location.file,
+        ..at
+    })
+}
+
+/// Payments in a ledger, keyed by the number found in their `paid` metadata.
+fn payments_by_number(facts: &[Fact], paid: &str) -> HashMap<String, Vec<Payment>> {
+    let mut payments: HashMap<String, Vec<Payment>> = HashMap::new();
+
+    for fact in facts {
+        if let Fact::Payment {
+            paid: key,
+            number,
+            payment,
+        } = fact
+        {
+            if key == paid {
+                payments
+                    .entry(number.clone())
+                    .or_default()
+                    .push(payment.clone());
+            }
+        }
+    }
+
+    payments
+}
+
+/// The last date each invoice was emailed, from `custom "sent"` directives.
+fn sent_dates(facts: &[Fact]) -> HashMap<String, Date> {
+    let mut sent: HashMap<String, Date> = HashMap::new();
+
+    for fact in facts {
+        let Fact::Sent { number, date } = fact else {
+            continue;
+        };
+        let last = sent.entry(number.clone()).or_insert(date.clone());
+        if date > last {
+            *last = date.clone();
+        }
+    }
+
+    sent
+}
+
+fn invoices(facts: &[Fact]) -> InvoiceList {
+    let mut payments = payments_by_number(facts, "paid_invoice");
+    let sent = sent_dates(facts);
+
+    let invoices = facts
+        .iter()
+        .filter_map(|fact| match fact {
+            Fact::Invoice(invoice) => Some(invoice.as_ref().clone()),
+            _ => None,
+        })
+        .map(|mut invoice| {
+            invoice.payments = payments.remove(&invoice.number.0).unwrap_or_default();
+            invoice.sent_on = sent.get(&invoice.number.0).cloned();
+            invoice
+        })
+        .collect();
+
+    InvoiceList { invoices }
+}
+
+fn bills(facts: &[Fact]) -> Vec<Bill> {
+    let mut payments = payments_by_number(facts, "paid_bill");
+
+    facts
+        .iter()
+        .filter_map(|fact| match fact {
+            Fact::Bill(bill) => Some(bill.clone()),
+            _ => None,
+        })
+        .map(|mut bill| {
+            bill.payments = payments.remove(&bill.number).unwrap_or_default();
+            bill
+        })
+        .collect()
+}
+
+fn balance(facts: &[Fact], account_prefix: &str, until: &Date) -> Vec<Money> {
+    let mut balances: Vec<Money> = vec![];
+
+    for fact in facts {
+        let Fact::Posting {
+            date,
+            account,
+            amount,
+        } = fact
+        else {
+            continue;
+        };
+        if date > until || sub_account(account, account_prefix).is_none() {
+            continue;
+        }
+        match balances.iter_mut().find(|b| b.currency == amount.currency) {
+            Some(balance) => balance.amount += amount.amount,
+            None => balances.push(amount.clone()),
+        }
+    }
+
+    balances
+}
+
+fn reminders(facts: &[Fact]) -> Vec<Reminder> {
+    facts
+        .iter()
+        .filter_map(|fact| match fact {
+            Fact::Reminder(reminder) => Some(reminder.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn accounts(facts: &[Fact]) -> Vec<account::Account> {
+    let mut accounts: Vec<account::Account> = vec![];
+
+    for fact in facts {
+        match fact {
+            Fact::Open(account) => accounts.push(account.clone()),
+            Fact::Close { account, date } => {
+                if let Some(account) = accounts.iter_mut().find(|a| &a.name == account) {
+                    account.closed_on = Some(date.clone());
+                }
+            }
+            _ => {}
         }
     }
+
+    accounts
+}
+
+fn find_invoice(facts: &[Fact], number: &InvoiceNumber) -> Result<Invoice, Box<dyn Error>> {
+    invoices(facts)
+        .invoices
+        .into_iter()
+        .find(|invoice| &invoice.number == number)
+        .ok_or_else(|| not_found(number))
 }
 
 impl LedgerStorage for StdinLedgerStorage {
     fn find_invoice(&self, number: &InvoiceNumber) -> Result<Invoice, Box<dyn Error>> {
-        let invoices = self.find_invoices()?.invoices;
-
-        invoices
-            .into_iter()
-            .find(|invoice| &invoice.number == number)
-            .ok_or_else(|| Box::new(NotFoundError) as Box<dyn Error>)
+        find_invoice(&self.facts()?, number)
     }
 
     fn find_invoices(&self) -> Result<InvoiceList, Box<dyn Error>> {
-        let ledger = beancount_parser::parse(&self.ledger)?;
-        let invoices = ledger
-            .directives
-            .clone()
-            .into_iter()
-            .filter_map(|directive| {
-                // Only keep Transactions
-                if let beancount_core::Directive::Transaction(tx) = directive {
-                    Some(tx)
-                } else {
-                    None
-                }
-            })
-            .filter(|d| d.meta.get("invoice_number").is_some()) // Only keep Transactions with an invoice_number
-            .map(|tx| tx.into()) // Convert Transaction into Invoice
-            .collect();
+        Ok(invoices(&self.facts()?))
+    }
+
+    fn find_bills(&self) -> Result<Vec<Bill>, Box<dyn Error>> {
+        Ok(bills(&self.facts()?))
+    }
 
-        Ok(InvoiceList { invoices })
+    fn balance(&self, account_prefix: &str, until: &Date) -> Result<Vec<Money>, Box<dyn Error>> {
+        Ok(balance(&self.facts()?, account_prefix, until))
+    }
+
+    fn find_reminders(&self) -> Result<Vec<Reminder>, Box<dyn Error>> {
+        Ok(reminders(&self.facts()?))
+    }
+
+    fn find_accounts(&self) -> Result<Vec<account::Account>, Box<dyn Error>> {
+        Ok(accounts(&self.facts()?))
     }
 
     fn build(&self) -> Result<Invoice, Box<dyn Error>> {
@@ -100,15 +1007,529 @@ impl LedgerStorage for StdinLedgerStorage {
 
         Ok(invoice)
     }
+
+    fn append(&self, _entries: &str) -> Result<(), Box<dyn Error>> {
+        Err(read_only())
+    }
+
+    fn set_invoice_metadata(
+        &self,
+        _number: &InvoiceNumber,
+        _metadata: &[(&str, String)],
+    ) -> Result<(), Box<dyn Error>> {
+        Err(read_only())
+    }
+
+    fn issue_invoice(
+        &self,
+        _number: &InvoiceNumber,
+        _metadata: &[(&str, String)],
+    ) -> Result<(), Box<dyn Error>> {
+        Err(read_only())
+    }
+}
+
+impl LedgerStorage for FileLedgerStorage {
+    fn find_invoice(&self, number: &InvoiceNumber) -> Result<Invoice, Box<dyn Error>> {
+        find_invoice(&self.facts, number)
+    }
+
+    fn find_invoices(&self) -> Result<InvoiceList, Box<dyn Error>> {
+        Ok(invoices(&self.facts))
+    }
+
+    fn find_bills(&self) -> Result<Vec<Bill>, Box<dyn Error>> {
+        Ok(bills(&self.facts))
+    }
+
+    fn balance(&self, account_prefix: &str, until: &Date) -> Result<Vec<Money>, Box<dyn Error>> {
+        Ok(balance(&self.facts, account_prefix, until))
+    }
+
+    fn find_reminders(&self) -> Result<Vec<Reminder>, Box<dyn Error>> {
+        Ok(reminders(&self.facts))
+    }
+
+    fn find_accounts(&self) -> Result<Vec<account::Account>, Box<dyn Error>> {
+        Ok(accounts(&self.facts))
+    }
+
+    fn build(&self) -> Result<Invoice, Box<dyn Error>> {
+        Ok(Invoice::default())
+    }
+
+    fn append(&self, entries: &str) -> Result<(), Box<dyn Error>> {
+        let mut file = OpenOptions::new().append(true).open(&self.path)?;
+        write!(file, "\n{}", entries)?;
+        Ok(())
+    }
+
+    fn set_invoice_metadata(
+        &self,
+        number: &InvoiceNumber,
+        metadata: &[(&str, String)],
+    ) -> Result<(), Box<dyn Error>> {
+        self.edit_invoice(number, |content| {
+            with_invoice_metadata(content, number, metadata, false)
+        })
+    }
+
+    fn issue_invoice(
+        &self,
+        number: &InvoiceNumber,
+        metadata: &[(&str, String)],
+    ) -> Result<(), Box<dyn Error>> {
+        self.edit_invoice(number, |content| {
+            with_invoice_metadata(content, number, metadata, true)
+        })
+    }
+}
+
+impl LedgerStorage for IndexedLedgerStorage {
+    fn find_invoice(&self, number: &InvoiceNumber) -> Result<Invoice, Box<dyn Error>> {
+        self.invoices_by_number
+            .get(&number.0)
+            .map(|index| self.invoices[*index].clone())
+            .ok_or_else(|| not_found(number))
+    }
+
+    fn find_invoices(&self) -> Result<InvoiceList, Box<dyn Error>> {
+        Ok(InvoiceList {
+            invoices: self.invoices.clone(),
+        })
+    }
+
+    fn find_bills(&self) -> Result<Vec<Bill>, Box<dyn Error>> {
+        Ok(self.bills.clone())
+    }
+
+    fn balance(&self, account_prefix: &str, until: &Date) -> Result<Vec<Money>, Box<dyn Error>> {
+        self.file.balance(account_prefix, until)
+    }
+
+    fn find_reminders(&self) -> Result<Vec<Reminder>, Box<dyn Error>> {
+        Ok(self.reminders.clone())
+    }
+
+    fn find_accounts(&self) -> Result<Vec<account::Account>, Box<dyn Error>> {
+        self.file.find_accounts()
+    }
+
+    fn build(&self) -> Result<Invoice, Box<dyn Error>> {
+        self.file.build()
+    }
+
+    fn append(&self, entries: &str) -> Result<(), Box<dyn Error>> {
+        self.file.append(entries)
+    }
+
+    fn set_invoice_metadata(
+        &self,
+        number: &InvoiceNumber,
+        metadata: &[(&str, String)],
+    ) -> Result<(), Box<dyn Error>> {
+        self.file.set_invoice_metadata(number, metadata)
+    }
+
+    fn issue_invoice(
+        &self,
+        number: &InvoiceNumber,
+        metadata: &[(&str, String)],
+    ) -> Result<(), Box<dyn Error>> {
+        self.file.issue_invoice(number, metadata)
+    }
 }
 
-#[derive(Debug)]
-struct NotFoundError;
+/// Lets commands borrow a storage that outlives them, such as the one the daemon keeps.
+impl<S: LedgerStorage> LedgerStorage for &S {
+    fn find_invoice(&self, number: &InvoiceNumber) -> Result<Invoice, Box<dyn Error>> {
+        (*self).find_invoice(number)
+    }
+
+    fn find_invoices(&self) -> Result<InvoiceList, Box<dyn Error>> {
+        (*self).find_invoices()
+    }
+
+    fn find_bills(&self) -> Result<Vec<Bill>, Box<dyn Error>> {
+        (*self).find_bills()
+    }
+
+    fn balance(&self, account_prefix: &str, until: &Date) -> Result<Vec<Money>, Box<dyn Error>> {
+        (*self).balance(account_prefix, until)
+    }
+
+    fn find_reminders(&self) -> Result<Vec<Reminder>, Box<dyn Error>> {
+        (*self).find_reminders()
+    }
+
+    fn find_accounts(&self) -> Result<Vec<account::Account>, Box<dyn Error>> {
+        (*self).find_accounts()
+    }
 
-impl Error for NotFoundError {}
+    fn build(&self) -> Result<Invoice, Box<dyn Error>> {
+        (*self).build()
+    }
+
+    fn append(&self, entries: &str) -> Result<(), Box<dyn Error>> {
+        (*self).append(entries)
+    }
+
+    fn set_invoice_metadata(
+        &self,
+        number: &InvoiceNumber,
+        metadata: &[(&str, String)],
+    ) -> Result<(), Box<dyn Error>> {
+        (*self).set_invoice_metadata(number, metadata)
+    }
+
+    fn issue_invoice(
+        &self,
+        number: &InvoiceNumber,
+        metadata: &[(&str, String)],
+    ) -> Result<(), Box<dyn Error>> {
+        (*self).issue_invoice(number, metadata)
+    }
+}
+
+/// Lets commands work on a storage that is chosen at runtime, e.g. from `--ledger`.
+impl LedgerStorage for Box<dyn LedgerStorage> {
+    fn find_invoice(&self, number: &InvoiceNumber) -> Result<Invoice, Box<dyn Error>> {
+        self.as_ref().find_invoice(number)
+    }
+
+    fn find_invoices(&self) -> Result<InvoiceList, Box<dyn Error>> {
+        self.as_ref().find_invoices()
+    }
+
+    fn find_bills(&self) -> Result<Vec<Bill>, Box<dyn Error>> {
+        self.as_ref().find_bills()
+    }
+
+    fn balance(&self, account_prefix: &str, until: &Date) -> Result<Vec<Money>, Box<dyn Error>> {
+        self.as_ref().balance(account_prefix, until)
+    }
+
+    fn find_reminders(&self) -> Result<Vec<Reminder>, Box<dyn Error>> {
+        self.as_ref().find_reminders()
+    }
+
+    fn find_accounts(&self) -> Result<Vec<account::Account>, Box<dyn Error>> {
+        self.as_ref().find_accounts()
+    }
+
+    fn build(&self) -> Result<Invoice, Box<dyn Error>> {
+        self.as_ref().build()
+    }
+
+    fn append(&self, entries: &str) -> Result<(), Box<dyn Error>> {
+        self.as_ref().append(entries)
+    }
+
+    fn set_invoice_metadata(
+        &self,
+        number: &InvoiceNumber,
+        metadata: &[(&str, String)],
+    ) -> Result<(), Box<dyn Error>> {
+        self.as_ref().set_invoice_metadata(number, metadata)
+    }
+
+    fn issue_invoice(
+        &self,
+        number: &InvoiceNumber,
+        metadata: &[(&str, String)],
+    ) -> Result<(), Box<dyn Error>> {
+        self.as_ref().issue_invoice(number, metadata)
+    }
+}
+
+fn read_only() -> Box<dyn Error> {
+    Box::new(TabulaError::Config(
+        "The ledger was read from stdin and cannot be changed. Pass it with --ledger instead"
+            .to_string(),
+    ))
+}
+
+fn not_found(number: &InvoiceNumber) -> Box<dyn Error> {
+    Box::new(TabulaError::NotFound(format!("Invoice {}", number.0)))
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_invoice_metadata_is_replaced_or_added() {
+        let ledger = r#"2023-06-01 ! "Invoice #1"
+	document: "old.pdf"
+	invoice_number: "2023-001"
+	Assets:AccountsReceivable	100 EUR
+	Income:Work	-100 EUR
+
+2023-06-02 ! "Invoice #2"
+	invoice_number: "2023-002"
+	Assets:AccountsReceivable	100 EUR
+	Income:Work	-100 EUR
+"#;
+        let metadata = [
+            ("document", "\"new.pdf\"".to_string()),
+            ("document_sha256", "\"abc\"".to_string()),
+        ];
+
+        let number = InvoiceNumber("2023-001".to_string());
+
+        let actual = with_invoice_metadata(ledger, &number, &metadata, false).unwrap();
+
+        let expected = r#"2023-06-01 ! "Invoice #1"
+	document: "new.pdf"
+	invoice_number: "2023-001"
+	document_sha256: "abc"
+	Assets:AccountsReceivable	100 EUR
+	Income:Work	-100 EUR
+
+2023-06-02 ! "Invoice #2"
+	invoice_number: "2023-002"
+	Assets:AccountsReceivable	100 EUR
+	Income:Work	-100 EUR
+"#;
+        assert_eq!(Some(expected.to_string()), actual);
+    }
+
+    #[test]
+    fn test_issuing_marks_the_invoice_and_freezes_it() {
+        let ledger = r#"2023-06-05 ! "Invoice #TBD"
+	invoice_number: "TBD"
+	Assets:AccountsReceivable	100 EUR
+	Income:Work	-100 EUR
+"#;
+        let metadata = [
+            ("invoice_number", "\"2023-003\"".to_string()),
+            ("issued", "2023-06-06".to_string()),
+        ];
+
+        let draft = InvoiceNumber("TBD".to_string());
+
+        let issued = with_invoice_metadata(ledger, &draft, &metadata, true)
+            .unwrap()
+            .unwrap();
+
+        let expected = r#"2023-06-05 * "Invoice #TBD"
+	invoice_number: "2023-003"
+	issued: 2023-06-06
+	Assets:AccountsReceivable	100 EUR
+	Income:Work	-100 EUR
+"#;
+        assert_eq!(expected, issued);
+
+        let number = InvoiceNumber("2023-003".to_string());
+        let err = with_invoice_metadata(&issued, &number, &metadata, true).unwrap_err();
+        assert_eq!("Invoice 2023-003 is already issued", err.to_string());
+
+        let document = [("document", "\"2023-003.pdf\"".to_string())];
+        let archived = with_invoice_metadata(&issued, &number, &document, false).unwrap();
+        assert!(archived.unwrap().contains("\tdocument: \"2023-003.pdf\"\n"));
+
+        let backdated = [("issued", "2023-06-01".to_string())];
+        let err = with_invoice_metadata(&issued, &number, &backdated, false).unwrap_err();
+        assert_eq!(
+            "Invoice 2023-003 is issued, so its issued cannot be changed. Correct it with a credit \
+             note",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn test_drafts_with_the_same_number_are_not_guessed_at() {
+        let ledger = r#"2023-06-05 ! "Invoice #TBD"
+	invoice_number: "TBD"
+	Assets:AccountsReceivable	100 EUR
+	Income:Work	-100 EUR
+
+2023-06-06 ! "Another invoice #TBD"
+	invoice_number: "TBD"
+	Assets:AccountsReceivable	200 EUR
+	Income:Work	-200 EUR
+"#;
+        let metadata = [
+            ("invoice_number", "\"2023-003\"".to_string()),
+            ("issued", "2023-06-06".to_string()),
+        ];
+
+        let draft = InvoiceNumber("TBD".to_string());
+
+        let err = with_invoice_metadata(ledger, &draft, &metadata, true).unwrap_err();
+        assert_eq!(
+            "More than one invoice is numbered TBD, give each its own number in the ledger first",
+            err.to_string()
+        );
+        assert_eq!(5, err.exit_code());
+    }
+
+    #[test]
+    fn test_voided_drafts_are_removed_and_issued_invoices_kept() {
+        let ledger = r#"2023-06-01 * "Invoice #1"
+	invoice_number: "2023-001"
+	Assets:AccountsReceivable	100 EUR
+	Income:Work	-100 EUR
+
+2023-06-02 ! "Invoice #2"
+	invoice_number: "2023-002"
+	Assets:AccountsReceivable	100 EUR
+	Income:Work	-100 EUR
+
+2023-06-03 custom "sent" "2023-001" "billing@acme.example"
+"#;
+
+        let voided = without_draft(ledger, &InvoiceNumber("2023-002".to_string())).unwrap();
+        let issued = without_draft(ledger, &InvoiceNumber("2023-001".to_string())).unwrap_err();
+
+        let expected = r#"2023-06-01 * "Invoice #1"
+	invoice_number: "2023-001"
+	Assets:AccountsReceivable	100 EUR
+	Income:Work	-100 EUR
+
+2023-06-03 custom "sent" "2023-001" "billing@acme.example"
+"#;
+        assert_eq!(Some(expected.to_string()), voided);
+        assert_eq!(
+            "Invoice 2023-001 is issued, credit it instead",
+            issued.to_string()
+        );
+    }
+
+    #[test]
+    fn test_invalid_metadata_is_reported_at_its_line_in_the_included_file() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let ledger = dir.path().join("ledger.beancount");
+        let invoices = dir.path().join("2023.beancount");
+        fs::write(
+            &ledger,
+            "option \"title\" \"Books\"\n\ninclude \"2023.beancount\"\n",
+        )
+        .unwrap();
+        fs::write(
+            &invoices,
+            "2023-06-01 ! \"Invoice #1\"\n\tinvoice_number: \"2023-001\"\n\tdue: \"next week\"\n\tAssets:AccountsReceivable\t1000 EUR\n\tIncome:Work\t-1000 EUR\n",
+        )
+        .unwrap();
+        let mut cache = LedgerCache::open(&dir.path().join("cache.sqlite")).unwrap();
+
+        let uncached = FileLedgerStorage::open(&ledger).and_then(|storage| storage.find_invoices());
+        let cached = FileLedgerStorage::open_cached(&ledger, &mut cache);
+
+        let expected = format!(
+            "Expected due to be a date, found \"next week\"
+  --> {}:3
+  |
+1 | 2023-06-01 ! \"Invoice #1\"
+  | ...
+3 | \tdue: \"next week\"
+  |",
+            invoices.display()
+        );
+        let uncached = uncached.err().unwrap();
+        assert_eq!(3, crate::error::exit_code(uncached.as_ref()));
+        assert_eq!(expected, uncached.to_string());
+        assert_eq!(expected, cached.err().unwrap().to_string());
+    }
+
+    #[test]
+    fn test_revenue_of_a_credit_note_is_negative() {
+        let ledger = r#"2023-06-01 * "Invoice #1"
+	invoice_number: "2023-001"
+	Assets:AccountsReceivable	100 EUR
+	Income:Work	-100 EUR
+
+2023-06-10 * "Credit note #2"
+	invoice_number: "2023-002"
+	credits_invoice: "2023-001"
+	Assets:AccountsReceivable	-100 EUR
+	Income:Work	100 EUR
+"#;
+
+        let invoices = StdinLedgerStorage::new(ledger.to_string())
+            .find_invoices()
+            .unwrap()
+            .invoices;
+
+        assert_eq!("100 EUR", invoices[0].revenue[0].amount.to_string());
+        assert_eq!("-100 EUR", invoices[1].revenue[0].amount.to_string());
+    }
+
+    #[test]
+    fn test_balance_includes_sub_accounts_but_not_accounts_sharing_a_prefix() {
+        let posting = |account: &str, amount: &str| Fact::Posting {
+            date: "2023-06-01".into(),
+            account: account.to_string(),
+            amount: amount.parse().unwrap(),
+        };
+        let facts = [
+            posting("Assets:Bank", "100 EUR"),
+            posting("Assets:Bank:Savings", "50 EUR"),
+            posting("Assets:Banking", "25 EUR"),
+        ];
+
+        let actual = balance(&facts, "Assets:Bank", &"2023-12-31".into());
+
+        assert_eq!(vec!["150 EUR".parse::<Money>().unwrap()], actual);
+    }
+
+    #[test]
+    fn test_parse_error_names_the_file() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let ledger = dir.path().join("ledger.beancount");
+        let broken = dir.path().join("broken.beancount");
+        fs::write(&ledger, "include \"broken.beancount\"\n").unwrap();
+        fs::write(&broken, "\tAssets:Bank\t100 EUR\n").unwrap();
+
+        let err = FileLedgerStorage::open(&ledger).err().unwrap();
+
+        let Some(TabulaError::Parse { location, .. }) = err.downcast_ref::<TabulaError>() else {
+            panic!("Expected a parse error, got {}", err);
+        };
+        assert_eq!(Some(broken), location.file);
+    }
+
+    #[test]
+    fn test_included_files_are_read_in_place_and_once() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let ledger = dir.path().join("ledger.beancount");
+        fs::create_dir(dir.path().join("2023")).unwrap();
+        fs::write(
+            &ledger,
+            "include \"2023/invoices.beancount\"\n\n2023-07-01 ! \"Invoice #3\"\n\tinvoice_number: \"2023-003\"\n\tAssets:AccountsReceivable\t300 EUR\n\tIncome:Work\t-300 EUR\n\ninclude \"2023/invoices.beancount\"\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("2023/invoices.beancount"),
+            "include \"more.beancount\"\n\n2023-06-01 ! \"Invoice #1\"\n\tinvoice_number: \"2023-001\"\n\tAssets:AccountsReceivable\t100 EUR\n\tIncome:Work\t-100 EUR\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("2023/more.beancount"),
+            "2023-06-15 ! \"Invoice #2\"\n\tinvoice_number: \"2023-002\"\n\tAssets:AccountsReceivable\t200 EUR\n\tIncome:Work\t-200 EUR\n",
+        )
+        .unwrap();
+
+        let storage = FileLedgerStorage::open(&ledger).unwrap();
+        let numbers: Vec<String> = storage
+            .find_invoices()
+            .unwrap()
+            .invoices
+            .into_iter()
+            .map(|invoice| invoice.number.0)
+            .collect();
+        let files = check::ledger_files(&ledger).unwrap();
 
-impl Display for NotFoundError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Invoice not found")
+        assert_eq!(vec!["2023-002", "2023-001", "2023-003"], numbers);
+        assert_eq!(3, storage.files().len());
+        assert_eq!(
+            storage.files().to_vec(),
+            files
+                .into_iter()
+                .filter_map(|file| file.path)
+                .collect::<Vec<_>>()
+        );
     }
 }
@@ -0,0 +1,497 @@
+//! A SQLite database with the facts read from each ledger file, so that a file is only parsed
+//! again when it changed. A file is known by its path, and is unchanged when its modification
+//! time and size are, or else when the SHA-256 hash of its content is. A file that was
+//! modified shortly before it was cached is always hashed, as an edit within the resolution
+//! of the file times can keep both.
+
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::OnceLock,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use chrono::NaiveDate;
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+use rust_decimal::Decimal;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    adapters::{config::CacheConfig, logger},
+    domain::{invoice::Date, money::Money, payment::Payment},
+    error::TabulaError,
+};
+
+use super::{file_facts, read_file, Fact};
+
+/// Bump when what is read from a ledger changes, so that older caches are rebuilt.
+const SCHEMA_VERSION: i32 = 8;
+
+const SCHEMA: &str = "
+DROP TABLE IF EXISTS facts;
+DROP TABLE IF EXISTS postings;
+DROP TABLE IF EXISTS payments;
+DROP TABLE IF EXISTS invoices;
+DROP TABLE IF EXISTS files;
+
+CREATE TABLE files (
+    id INTEGER PRIMARY KEY,
+    path TEXT NOT NULL UNIQUE,
+    modified INTEGER NOT NULL,
+    size INTEGER NOT NULL,
+    sha256 TEXT NOT NULL,
+    -- When the content was last hashed
+    checked INTEGER NOT NULL
+);
+
+CREATE TABLE invoices (
+    file_id INTEGER NOT NULL REFERENCES files (id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    number TEXT NOT NULL,
+    customer TEXT,
+    date TEXT NOT NULL,
+    invoice TEXT NOT NULL
+);
+CREATE INDEX invoices_file ON invoices (file_id);
+
+CREATE TABLE payments (
+    file_id INTEGER NOT NULL REFERENCES files (id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    paid TEXT NOT NULL,
+    number TEXT NOT NULL,
+    date TEXT NOT NULL,
+    amount TEXT NOT NULL,
+    currency TEXT NOT NULL
+);
+CREATE INDEX payments_file ON payments (file_id);
+
+CREATE TABLE postings (
+    file_id INTEGER NOT NULL REFERENCES files (id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    date TEXT NOT NULL,
+    account TEXT NOT NULL,
+    amount TEXT NOT NULL,
+    currency TEXT NOT NULL
+);
+CREATE INDEX postings_file ON postings (file_id);
+
+-- Everything else, such as bills, reminders and accounts, as JSON
+CREATE TABLE facts (
+    file_id INTEGER NOT NULL REFERENCES files (id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    fact TEXT NOT NULL
+);
+CREATE INDEX facts_file ON facts (file_id);
+";
+
+/// The coarsest resolution of file modification times, that of FAT, in nanoseconds. A file
+/// modified less than this before it was hashed may change again without its time changing.
+const MODIFIED_RESOLUTION: i64 = 2_000_000_000;
+
+static CACHE: OnceLock<CacheConfig> = OnceLock::new();
+
+/// Makes every ledger file that is opened go through the cache, when it is enabled.
+pub fn init(config: &CacheConfig) {
+    if config.enabled {
+        let _ = CACHE.set(config.clone());
+    }
+}
+
+pub(super) fn config() -> Option<&'static CacheConfig> {
+    CACHE.get()
+}
+
+/// Where the cache of a ledger is kept.
+pub fn path(config: &CacheConfig, ledger: &Path) -> PathBuf {
+    ledger
+        .parent()
+        .unwrap_or_else(|| Path::new(""))
+        .join(&config.path)
+}
+
+/// A file as it was when its facts were cached.
+struct CachedFile {
+    id: i64,
+    modified: i64,
+    size: i64,
+    sha256: String,
+    checked: i64,
+}
+
+impl CachedFile {
+    /// Whether the file is known to be unchanged without reading it.
+    fn is_unchanged(&self, modified: i64, size: i64) -> bool {
+        self.modified == modified
+            && self.size == size
+            && self.modified < self.checked - MODIFIED_RESOLUTION
+    }
+}
+
+pub struct LedgerCache {
+    connection: Connection,
+}
+
+impl LedgerCache {
+    pub fn open(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut connection = Connection::open(path)?;
+        connection.busy_timeout(Duration::from_secs(5))?;
+        connection.pragma_update(None, "foreign_keys", true)?;
+
+        // Another process may be creating the cache at the same time
+        let tx = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let version: i32 = tx.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        if version != SCHEMA_VERSION {
+            // The cache only holds what the ledger holds, so it is rebuilt rather than migrated
+            tx.execute_batch(SCHEMA)?;
+            tx.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+        }
+        tx.commit()?;
+
+        Ok(Self { connection })
+    }
+
+    /// The facts of a single ledger file, without those of the files it includes. The file is
+    /// only parsed when it changed since it was cached.
+    pub(super) fn facts(&mut self, file: &Path) -> Result<Vec<Fact>, Box<dyn Error>> {
+        let metadata = fs::metadata(file).map_err(|source| TabulaError::Io {
+            path: file.to_path_buf(),
+            source,
+        })?;
+        let key = fs::canonicalize(file)?.to_string_lossy().to_string();
+        let modified = nanos(metadata.modified()?)?;
+        let size = metadata.len() as i64;
+
+        let cached = self
+            .connection
+            .query_row(
+                "SELECT id, modified, size, sha256, checked FROM files WHERE path = ?1",
+                [&key],
+                |row| {
+                    Ok(CachedFile {
+                        id: row.get(0)?,
+                        modified: row.get(1)?,
+                        size: row.get(2)?,
+                        sha256: row.get(3)?,
+                        checked: row.get(4)?,
+                    })
+                },
+            )
+            .optional()?;
+        if let Some(cached) = cached.as_ref().filter(|c| c.is_unchanged(modified, size)) {
+            return self.load(cached.id);
+        }
+
+        let checked = nanos(SystemTime::now())?;
+        let content = read_file(file)?;
+        let sha256 = hex::encode(Sha256::digest(content.as_bytes()));
+        if let Some(cached) = cached.filter(|cached| cached.sha256 == sha256) {
+            self.connection.execute(
+                "UPDATE files SET modified = ?1, size = ?2, checked = ?3 WHERE id = ?4",
+                params![modified, size, checked, cached.id],
+            )?;
+            return self.load(cached.id);
+        }
+
+        logger::debug(&format!("Parsing {}", file.display()));
+        let facts = file_facts(file, &content)?;
+        self.store(&key, modified, size, &sha256, checked, &facts)?;
+        Ok(facts)
+    }
+
+    fn store(
+        &mut self,
+        path: &str,
+        modified: i64,
+        size: i64,
+        sha256: &str,
+        checked: i64,
+        facts: &[Fact],
+    ) -> Result<(), Box<dyn Error>> {
+        let tx = self.connection.transaction()?;
+        tx.execute("DELETE FROM files WHERE path = ?1", [path])?;
+        tx.execute(
+            "INSERT INTO files (path, modified, size, sha256, checked) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![path, modified, size, sha256, checked],
+        )?;
+        let id = tx.last_insert_rowid();
+
+        for (position, fact) in facts.iter().enumerate() {
+            match fact {
+                Fact::Invoice(invoice) => tx.execute(
+                    "INSERT INTO invoices (file_id, position, number, customer, date, invoice)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    params![
+                        id,
+                        position,
+                        invoice.number.0,
+                        invoice.customer,
+                        invoice.date.to_string(),
+                        serde_json::to_string(invoice)?
+                    ],
+                )?,
+                Fact::Payment {
+                    paid,
+                    number,
+                    payment,
+                } => tx.execute(
+                    "INSERT INTO payments (file_id, position, paid, number, date, amount, currency)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                    params![
+                        id,
+                        position,
+                        paid,
+                        number,
+                        payment.date.to_string(),
+                        payment.amount.amount.to_string(),
+                        payment.amount.currency
+                    ],
+                )?,
+                Fact::Posting {
+                    date,
+                    account,
+                    amount,
+                } => tx.execute(
+                    "INSERT INTO postings (file_id, position, date, account, amount, currency)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    params![
+                        id,
+                        position,
+                        date.to_string(),
+                        account,
+                        amount.amount.to_string(),
+                        amount.currency
+                    ],
+                )?,
+                fact => tx.execute(
+                    "INSERT INTO facts (file_id, position, fact) VALUES (?1, ?2, ?3)",
+                    params![id, position, serde_json::to_string(fact)?],
+                )?,
+            };
+        }
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// The facts of a cached file, in the order they were read.
+    fn load(&self, file_id: i64) -> Result<Vec<Fact>, Box<dyn Error>> {
+        let mut facts: Vec<(i64, Fact)> = vec![];
+
+        let rows = self
+            .connection
+            .prepare("SELECT position, invoice FROM invoices WHERE file_id = ?1")?
+            .query_map([file_id], |row| Ok((row.get(0)?, row.get::<_, String>(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+        for (position, invoice) in rows {
+            facts.push((position, Fact::Invoice(serde_json::from_str(&invoice)?)));
+        }
+
+        let rows = self
+            .connection
+            .prepare(
+                "SELECT position, paid, number, date, amount, currency
+                 FROM payments WHERE file_id = ?1",
+            )?
+            .query_map([file_id], |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, String>(4)?,
+                    row.get::<_, String>(5)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        for (position, paid, number, date, amount, currency) in rows {
+            let payment = Payment {
+                date: date_from(&date)?,
+                amount: Money::new(Decimal::from_str(&amount)?, &currency),
+            };
+            facts.push((
+                position,
+                Fact::Payment {
+                    paid,
+                    number,
+                    payment,
+                },
+            ));
+        }
+
+        let rows = self
+            .connection
+            .prepare(
+                "SELECT position, date, account, amount, currency FROM postings WHERE file_id = ?1",
+            )?
+            .query_map([file_id], |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, String>(4)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        for (position, date, account, amount, currency) in rows {
+            facts.push((
+                position,
+                Fact::Posting {
+                    date: date_from(&date)?,
+                    account,
+                    amount: Money::new(Decimal::from_str(&amount)?, &currency),
+                },
+            ));
+        }
+
+        let rows = self
+            .connection
+            .prepare("SELECT position, fact FROM facts WHERE file_id = ?1")?
+            .query_map([file_id], |row| Ok((row.get(0)?, row.get::<_, String>(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+        for (position, fact) in rows {
+            facts.push((position, serde_json::from_str(&fact)?));
+        }
+
+        facts.sort_by_key(|(position, _)| *position);
+        Ok(facts.into_iter().map(|(_, fact)| fact).collect())
+    }
+}
+
+/// A time as nanoseconds since the Unix epoch.
+fn nanos(time: SystemTime) -> Result<i64, Box<dyn Error>> {
+    Ok(time.duration_since(UNIX_EPOCH)?.as_nanos() as i64)
+}
+
+fn date_from(text: &str) -> Result<Date, Box<dyn Error>> {
+    Ok(Date(NaiveDate::parse_from_str(text, "%Y-%m-%d")?))
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::adapters::ledger_storage::{FileLedgerStorage, LedgerStorage};
+
+    use super::*;
+
+    fn cached_files(cache: &LedgerCache) -> Vec<(String, i64, String)> {
+        let mut files: Vec<(String, i64, String)> = cache
+            .connection
+            .prepare("SELECT path, id, sha256 FROM files")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn test_only_changed_files_are_parsed_again() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let ledger = dir.path().join("ledger.beancount");
+        let invoices = dir.path().join("2023.beancount");
+        fs::write(
+            &ledger,
+            "include \"2023.beancount\"\n\n2023-07-01 * \"Payment\"\n\tpaid_invoice: \"2023-001\"\n\tAssets:Bank\t400 EUR\n\tAssets:AccountsReceivable\t-400 EUR\n",
+        )
+        .unwrap();
+        fs::write(
+            &invoices,
+            "2023-06-01 ! \"Invoice #1\"\n\tinvoice_number: \"2023-001\"\n\tcustomer: \"Acme\"\n\tAssets:AccountsReceivable\t1000 EUR\n\tIncome:Work\t-1000 EUR\n",
+        )
+        .unwrap();
+        let mut cache = LedgerCache::open(&dir.path().join("cache.sqlite")).unwrap();
+
+        let uncached = FileLedgerStorage::open(&ledger).unwrap();
+        let cold = FileLedgerStorage::open_cached(&ledger, &mut cache).unwrap();
+        let before = cached_files(&cache);
+        let warm = FileLedgerStorage::open_cached(&ledger, &mut cache).unwrap();
+        let mut content = fs::read_to_string(&invoices).unwrap();
+        content.push_str("\n2023-06-02 custom \"sent\" \"2023-001\" \"billing@acme.example\"\n");
+        fs::write(&invoices, content).unwrap();
+        let changed = FileLedgerStorage::open_cached(&ledger, &mut cache).unwrap();
+        // An edit that keeps the size, and is made within the resolution of the file times
+        let modified = fs::metadata(&invoices).unwrap().modified().unwrap();
+        let content = fs::read_to_string(&invoices).unwrap();
+        fs::write(&invoices, content.replace("Acme", "Acne")).unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&invoices)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        let same_size = FileLedgerStorage::open_cached(&ledger, &mut cache).unwrap();
+
+        let json = |storage: &FileLedgerStorage| {
+            serde_json::to_string(&storage.find_invoices().unwrap()).unwrap()
+        };
+        assert_eq!(2, cold.files().len());
+        assert_eq!(json(&uncached), json(&cold));
+        assert_eq!(json(&cold), json(&warm));
+        assert_eq!(
+            vec![Money::new(600.into(), "EUR")],
+            warm.balance("Assets:AccountsReceivable", &"2023-12-31".into())
+                .unwrap()
+        );
+        assert_eq!(
+            Some("2023-06-02".into()),
+            changed.find_invoices().unwrap().invoices[0].sent_on
+        );
+        assert_eq!(
+            Some("Acne".to_string()),
+            same_size.find_invoices().unwrap().invoices[0].customer
+        );
+        let after = cached_files(&cache);
+        assert_eq!(before[1], after[1], "the main ledger did not change");
+        assert_ne!(before[0].2, after[0].2, "the included file changed");
+    }
+
+    #[test]
+    fn test_files_modified_long_before_they_were_cached_are_not_read_again() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let ledger = dir.path().join("ledger.beancount");
+        fs::write(
+            &ledger,
+            "2023-06-01 ! \"Invoice #1\"\n\tinvoice_number: \"2023-001\"\n\tcustomer: \"Acme\"\n\tAssets:AccountsReceivable\t1000 EUR\n\tIncome:Work\t-1000 EUR\n",
+        )
+        .unwrap();
+        let an_hour_ago = SystemTime::now() - Duration::from_secs(3600);
+        let set_modified = |time: SystemTime| {
+            fs::File::options()
+                .write(true)
+                .open(&ledger)
+                .unwrap()
+                .set_modified(time)
+                .unwrap()
+        };
+        set_modified(an_hour_ago);
+        let mut cache = LedgerCache::open(&dir.path().join("cache.sqlite")).unwrap();
+        let customer = |cache: &mut LedgerCache| {
+            FileLedgerStorage::open_cached(&ledger, cache)
+                .unwrap()
+                .find_invoices()
+                .unwrap()
+                .invoices[0]
+                .customer
+                .clone()
+        };
+
+        let cold = customer(&mut cache);
+        // Keeps the size and the modification time, so the cache does not read it
+        let content = fs::read_to_string(&ledger).unwrap();
+        fs::write(&ledger, content.replace("Acme", "Acne")).unwrap();
+        set_modified(an_hour_ago);
+        let same_time = customer(&mut cache);
+        set_modified(SystemTime::now());
+        let touched = customer(&mut cache);
+
+        assert_eq!(Some("Acme".to_string()), cold);
+        assert_eq!(Some("Acme".to_string()), same_time);
+        assert_eq!(Some("Acne".to_string()), touched);
+    }
+}
@@ -0,0 +1,636 @@
+//! Whether a ledger follows the conventions tabula reads it by, file by file, so that each
+//! problem is reported at its own line.
+
+use core::fmt;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use beancount_core::{metadata::MetaValue, Directive, Flag, Posting, Transaction};
+use chrono::Datelike;
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::{
+    adapters::document_storage::sha256_hex,
+    domain::{
+        invoice::{Date, InvoiceNumber},
+        money::Money,
+    },
+    error::{InvalidValue, Location, TabulaError},
+    services::e_invoice::{tax_rule, EInvoiceOptions},
+};
+
+use super::{
+    account_name, directive_lines, directive_location, number_meta, posting_amount, read_file,
+    read_with_includes, sub_account,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    /// Tabula reads the ledger as intended, but it breaks a habit, such as the numbering
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// A convention the ledger breaks, and where.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Which convention, such as `due-date`
+    pub code: &'static str,
+    pub message: String,
+    #[serde(flatten)]
+    pub location: Location,
+}
+
+pub struct CheckOptions {
+    /// The VAT rates of income accounts
+    pub e_invoice: EInvoiceOptions,
+    /// The directory `document` metadata is relative to, or None to not check documents, as
+    /// when they are kept on S3
+    pub documents: Option<PathBuf>,
+}
+
+/// A file of a ledger, or the ledger read from stdin when there is no path.
+pub struct LedgerFile {
+    pub path: Option<PathBuf>,
+    pub content: String,
+}
+
+/// A file of the ledger, or where it includes another.
+enum Part {
+    File(LedgerFile),
+    Include(PathBuf),
+}
+
+/// Reads a ledger file and the files it `include`s, each on its own.
+pub fn ledger_files(path: &Path) -> Result<Vec<LedgerFile>, TabulaError> {
+    let parts = read_with_includes(
+        path,
+        &mut vec![],
+        &mut |file: &Path| {
+            let content = read_file(file)?;
+            // A file that does not parse is reported by `check`, without what it includes
+            let includes: Vec<Part> = beancount_parser::parse(&content)
+                .map(|ledger| ledger.directives)
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|directive| match directive {
+                    Directive::Include(include) => {
+                        Some(Part::Include(PathBuf::from(include.filename.as_ref())))
+                    }
+                    _ => None,
+                })
+                .collect();
+            let file = Part::File(LedgerFile {
+                path: Some(file.to_path_buf()),
+                content,
+            });
+            Ok::<_, TabulaError>(std::iter::once(file).chain(includes).collect())
+        },
+        |part| match part {
+            Part::Include(file) => Some(file.as_path()),
+            Part::File(_) => None,
+        },
+    )?;
+
+    Ok(parts
+        .into_iter()
+        .filter_map(|part| match part {
+            Part::File(file) => Some(file),
+            Part::Include(_) => None,
+        })
+        .collect())
+}
+
+/// The conventions the invoices in the ledger break, file by file in line order. Fails when a
+/// file is not valid beancount.
+pub fn check(files: &[LedgerFile], options: &CheckOptions) -> Result<Vec<Diagnostic>, TabulaError> {
+    let mut numbers: Vec<(String, Date, Location)> = vec![];
+    let mut diagnostics = vec![];
+
+    for file in files {
+        let ledger = beancount_parser::parse(&file.content).map_err(|err| TabulaError::Parse {
+            location: Box::new(Location {
+                file: file.path.clone(),
+                ..Location::default()
+            }),
+            message: err.to_string(),
+        })?;
+        for directive in ledger.directives {
+            let Directive::Transaction(tx) = directive else {
+                continue;
+            };
+            if !tx.meta.contains_key("invoice_number") {
+                continue;
+            }
+            let mut invoice = CheckedInvoice {
+                file: file.path.clone(),
+                lines: directive_lines(&file.content, tx.source),
+                diagnostics: vec![],
+            };
+            invoice.check(&tx, &mut numbers, options);
+            diagnostics.extend(invoice.diagnostics);
+        }
+    }
+
+    diagnostics.extend(duplicate_numbers(numbers));
+    let position = |diagnostic: &Diagnostic| {
+        let file = files
+            .iter()
+            .position(|file| file.path == diagnostic.location.file);
+        (file, diagnostic.location.line)
+    };
+    diagnostics.sort_by_key(position);
+    Ok(diagnostics)
+}
+
+/// Invoices with the number of an invoice of an earlier date, or of the same date and earlier
+/// in the ledger, as the files of a ledger are not read in the order of their lines.
+fn duplicate_numbers(mut numbers: Vec<(String, Date, Location)>) -> Vec<Diagnostic> {
+    numbers.sort_by(|(a, a_date, _), (b, b_date, _)| (a, a_date).cmp(&(b, b_date)));
+    numbers
+        .chunk_by(|(a, _, _), (b, _, _)| a == b)
+        .filter_map(|invoices| invoices.split_first())
+        .flat_map(|((number, _, first), rest)| {
+            rest.iter().map(move |(_, _, location)| Diagnostic {
+                severity: Severity::Error,
+                code: "duplicate-number",
+                message: format!(
+                    "Invoice number {} is already used at {}",
+                    number,
+                    first.position()
+                ),
+                location: location.clone(),
+            })
+        })
+        .collect()
+}
+
+/// The transaction of an invoice being checked, with its lines to point diagnostics at.
+struct CheckedInvoice<'c> {
+    file: Option<PathBuf>,
+    lines: Vec<(usize, &'c str)>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl CheckedInvoice<'_> {
+    /// Where the first line of the transaction for which `offending` holds is, or the
+    /// transaction itself when there is none.
+    fn at(&self, offending: impl Fn(&str) -> bool) -> Location {
+        let line = self
+            .lines
+            .iter()
+            .skip(1)
+            .find(|(_, line)| offending(line.trim_start()))
+            .copied();
+        Location {
+            file: self.file.clone(),
+            ..directive_location(&self.lines, line)
+        }
+    }
+
+    fn report(
+        &mut self,
+        severity: Severity,
+        code: &'static str,
+        message: String,
+        location: Location,
+    ) {
+        self.diagnostics.push(Diagnostic {
+            severity,
+            code,
+            message,
+            location,
+        });
+    }
+
+    fn invalid(&mut self, key: &str, err: InvalidValue) {
+        let prefix = format!("{}:", key);
+        let location = self.at(|line| line.starts_with(&prefix) && line.contains(&err.found));
+        self.report(
+            Severity::Error,
+            "invalid-metadata",
+            format!(
+                "Expected {} to be {}, found {}",
+                key, err.expected, err.found
+            ),
+            location,
+        );
+    }
+
+    fn check(
+        &mut self,
+        tx: &Transaction,
+        numbers: &mut Vec<(String, Date, Location)>,
+        options: &CheckOptions,
+    ) {
+        let date = match Date::try_from(tx.date.clone()) {
+            Ok(date) => date,
+            Err(err) => return self.invalid("date", err),
+        };
+        self.check_number(tx, &date, numbers);
+        self.check_due_date(tx, &date);
+
+        let receivable = tx.postings.iter().any(|posting| {
+            sub_account(&account_name(&posting.account), "Assets:AccountsReceivable").is_some()
+        });
+        if !receivable {
+            let location = self.at(|_| false);
+            self.report(
+                Severity::Error,
+                "receivable",
+                "The invoice has no posting on Assets:AccountsReceivable".to_string(),
+                location,
+            );
+        }
+
+        for posting in &tx.postings {
+            if posting.meta.keys().any(|key| key.starts_with("line_item_")) {
+                self.check_line_item(posting);
+            }
+        }
+        self.check_vat(tx, &options.e_invoice);
+        if let Some(base) = &options.documents {
+            self.check_document(tx, base, "document");
+            self.check_document(tx, base, "html_document");
+        }
+    }
+
+    fn check_number(
+        &mut self,
+        tx: &Transaction,
+        date: &Date,
+        numbers: &mut Vec<(String, Date, Location)>,
+    ) {
+        let Some(value) = tx.meta.get("invoice_number") else {
+            return;
+        };
+        let number = match InvoiceNumber::try_from(value) {
+            Ok(number) => number,
+            Err(err) => return self.invalid("invoice_number", err),
+        };
+        let location = self.at(|line| line.starts_with("invoice_number:"));
+
+        if number.0 == "TBD" {
+            if matches!(tx.flag, Flag::Okay) {
+                self.report(
+                    Severity::Error,
+                    "draft-number",
+                    "The invoice is marked * but its number is still TBD. Number it, or mark it ! \
+                     while it is a draft"
+                        .to_string(),
+                    location,
+                );
+            }
+            return;
+        }
+
+        numbers.push((number.0.clone(), date.clone(), location.clone()));
+
+        let year = date.0.year();
+        let numbered = number
+            .0
+            .strip_prefix(&format!("{}-", year))
+            .is_some_and(|sequence| {
+                !sequence.is_empty() && sequence.chars().all(|c| c.is_ascii_digit())
+            });
+        if !numbered {
+            self.report(
+                Severity::Warning,
+                "numbering",
+                format!(
+                    "Invoice number {} does not follow the numbering of {}, such as {}-001",
+                    number, year, year
+                ),
+                location,
+            );
+        }
+    }
+
+    fn check_due_date(&mut self, tx: &Transaction, date: &Date) {
+        match tx.meta.get("due").map(Date::try_from) {
+            Some(Err(err)) => self.invalid("due", err),
+            Some(Ok(due)) if &due < date => {
+                let location = self.at(|line| line.starts_with("due:"));
+                self.report(
+                    Severity::Error,
+                    "due-date",
+                    format!("The invoice is due on {}, before its date {}", due, date),
+                    location,
+                );
+            }
+            _ => {}
+        }
+    }
+
+    /// Line items have a name, and a quantity and unit price that make up their posting.
+    fn check_line_item(&mut self, posting: &Posting) {
+        let account = account_name(&posting.account);
+        let name = match posting.meta.get("line_item_name") {
+            Some(MetaValue::Text(name)) => name.to_string(),
+            Some(other) => {
+                return self.invalid("line_item_name", InvalidValue::from_meta("text", other));
+            }
+            None => {
+                let location = self.at(|line| line.split_whitespace().any(|word| word == account));
+                return self.report(
+                    Severity::Error,
+                    "line-item",
+                    format!("The line item on {} has no line_item_name", account),
+                    location,
+                );
+            }
+        };
+        let location = self.at(|line| line.starts_with("line_item_name:") && line.contains(&name));
+
+        let mut number = |key| match number_meta(&posting.meta, key) {
+            Ok(number) => Some(number),
+            Err(err) => {
+                self.invalid(key, err);
+                None
+            }
+        };
+        let (Some(quantity), Some(unit_price)) =
+            (number("line_item_quantity"), number("line_item_unit_price"))
+        else {
+            return;
+        };
+        match (quantity, unit_price, posting_amount(posting)) {
+            (Some(quantity), Some(unit_price), Some(amount)) => {
+                let total = (quantity * unit_price).normalize();
+                if total.round_dp(2) != amount.amount.abs() {
+                    self.report(
+                        Severity::Error,
+                        "line-item",
+                        format!(
+                            "Line item \"{}\" comes to {} × {} = {}, but its posting is {}",
+                            name,
+                            quantity,
+                            unit_price,
+                            Money::new(total, &amount.currency),
+                            Money::new(amount.amount.abs(), &amount.currency)
+                        ),
+                        location,
+                    );
+                }
+            }
+            (Some(_), None, _) | (None, Some(_), _) => {
+                let (has, lacks) = match quantity {
+                    Some(_) => ("line_item_quantity", "line_item_unit_price"),
+                    None => ("line_item_unit_price", "line_item_quantity"),
+                };
+                self.report(
+                    Severity::Error,
+                    "line-item",
+                    format!("Line item \"{}\" has {} but no {}", name, has, lacks),
+                    location,
+                );
+            }
+            _ => {}
+        }
+    }
+
+    /// The VAT booked on `Liabilities:VAT` is the VAT of the income accounts at their rates.
+    /// Invoices without VAT postings are not checked, as their VAT is left to e-invoices.
+    fn check_vat(&mut self, tx: &Transaction, options: &EInvoiceOptions) {
+        let amounts = |parent: &str| -> Vec<(String, Money)> {
+            tx.postings
+                .iter()
+                .map(|posting| (account_name(&posting.account), posting))
+                .filter(|(account, _)| sub_account(account, parent).is_some())
+                .filter_map(|(account, posting)| {
+                    posting_amount(posting).map(|amount| (account, amount))
+                })
+                .collect()
+        };
+        let vat = amounts("Liabilities:VAT");
+        let Some((vat_account, first)) = vat.first() else {
+            return;
+        };
+        let booked: Decimal = vat.iter().map(|(_, amount)| amount.amount).sum();
+
+        let mut taxable: BTreeMap<Decimal, Decimal> = BTreeMap::new();
+        for (account, amount) in amounts("Income") {
+            let rule = tax_rule(options, &account);
+            *taxable.entry(rule.rate.normalize()).or_default() += amount.amount;
+        }
+        let expected: Decimal = taxable
+            .iter()
+            .map(|(rate, taxable)| (taxable * rate / Decimal::ONE_HUNDRED).round_dp(2))
+            .sum();
+
+        if booked.abs() != expected.abs() {
+            let location = self.at(|line| line.split_whitespace().any(|word| word == vat_account));
+            self.report(
+                Severity::Error,
+                "vat",
+                format!(
+                    "The VAT booked is {}, but the VAT rates of the income accounts come to {}",
+                    Money::new(booked.abs(), &first.currency),
+                    Money::new(expected.abs(), &first.currency)
+                ),
+                location,
+            );
+        }
+    }
+
+    /// The archived document under the `field` metadata exists, with the hash that was
+    /// recorded under `<field>_sha256` when it was stored.
+    fn check_document(&mut self, tx: &Transaction, base: &Path, field: &str) {
+        let Some(MetaValue::Text(document)) = tx.meta.get(field) else {
+            return;
+        };
+        let sha256_field = format!("{}_sha256", field);
+        let path = base.join(document.as_ref());
+        let content = match fs::read(&path) {
+            Ok(content) => content,
+            Err(err) => {
+                let location = self.at(|line| line.starts_with(&format!("{}:", field)));
+                return self.report(
+                    Severity::Error,
+                    "document",
+                    format!("Could not read the document {}: {}", path.display(), err),
+                    location,
+                );
+            }
+        };
+
+        let location = self.at(|line| line.starts_with(&format!("{}:", sha256_field)));
+        match tx.meta.get(sha256_field.as_str()) {
+            Some(MetaValue::Text(sha256)) if sha256_hex(&content) == *sha256 => {}
+            Some(_) => self.report(
+                Severity::Error,
+                "document",
+                format!(
+                    "The document {} has changed since it was archived, its hash is not the {}",
+                    path.display(),
+                    sha256_field
+                ),
+                location,
+            ),
+            None => self.report(
+                Severity::Error,
+                "document",
+                format!(
+                    "The document {} has no {} to check it against",
+                    path.display(),
+                    sha256_field
+                ),
+                self.at(|line| line.starts_with(&format!("{}:", field))),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn diagnostics(
+        ledger: &str,
+        documents: Option<PathBuf>,
+    ) -> Vec<(Severity, &'static str, usize)> {
+        let files = [LedgerFile {
+            path: None,
+            content: ledger.to_string(),
+        }];
+        let options = CheckOptions {
+            e_invoice: EInvoiceOptions::default(),
+            documents,
+        };
+        check(&files, &options)
+            .unwrap()
+            .into_iter()
+            .map(|diagnostic| {
+                (
+                    diagnostic.severity,
+                    diagnostic.code,
+                    diagnostic.location.line.unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_invoices_following_the_conventions_have_no_diagnostics() {
+        let ledger = r#"
+2023-01-10 * "Invoice #1"
+	invoice_number: "2023-001"
+	due: 2023-02-09
+	Assets:AccountsReceivable	1210 EUR
+	Income:Work	-1000 EUR
+		line_item_name: "Hours"
+		line_item_quantity: 10
+		line_item_unit_price: 100
+	Liabilities:VAT	-210 EUR
+
+2023-01-11 ! "Draft"
+	invoice_number: "TBD"
+	Assets:AccountsReceivable	100 EUR
+	Income:Work	-100 EUR
+"#;
+
+        assert_eq!(
+            Vec::<(Severity, &str, usize)>::new(),
+            diagnostics(ledger, None)
+        );
+    }
+
+    #[test]
+    fn test_each_broken_convention_is_reported_at_its_line() {
+        let ledger = r#"
+2023-01-10 * "Invoice #1"
+	invoice_number: "2023-001"
+	due: 2023-01-01
+	Assets:AccountsReceivable	1200 EUR
+	Income:Work	-1000 EUR
+		line_item_name: "Hours"
+		line_item_quantity: 10
+		line_item_unit_price: 90
+	Liabilities:VAT	-200 EUR
+
+2023-01-11 * "Invoice #2"
+	invoice_number: "2023-001"
+	Assets:Bank	100 EUR
+	Income:Work	-100 EUR
+
+2023-01-12 * "Invoice #3"
+	invoice_number: "TBD"
+	Assets:AccountsReceivable	100 EUR
+	Income:Work	-100 EUR
+
+2023-01-13 * "Invoice #4"
+	invoice_number: "INV-4"
+	due: "next week"
+	Assets:AccountsReceivable	100 EUR
+	Income:Work	-100 EUR
+		line_item_quantity: 1
+"#;
+
+        assert_eq!(
+            vec![
+                (Severity::Error, "due-date", 4),
+                (Severity::Error, "line-item", 7),
+                (Severity::Error, "vat", 10),
+                (Severity::Error, "receivable", 12),
+                (Severity::Error, "duplicate-number", 13),
+                (Severity::Error, "draft-number", 18),
+                (Severity::Warning, "numbering", 23),
+                (Severity::Error, "invalid-metadata", 24),
+                (Severity::Error, "line-item", 26),
+            ],
+            diagnostics(ledger, None)
+        );
+    }
+
+    #[test]
+    fn test_archived_documents_must_match_their_hash() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        fs::write(dir.path().join("2023-001.pdf"), b"changed").unwrap();
+        let ledger = format!(
+            r#"
+2023-01-10 * "Invoice #1"
+	invoice_number: "2023-001"
+	document: "2023-001.pdf"
+	document_sha256: "{}"
+	Assets:AccountsReceivable	100 EUR
+	Income:Work	-100 EUR
+
+2023-01-11 * "Invoice #2"
+	invoice_number: "2023-002"
+	document: "2023-002.pdf"
+	Assets:AccountsReceivable	100 EUR
+	Income:Work	-100 EUR
+"#,
+            sha256_hex(b"invoice")
+        );
+
+        assert_eq!(
+            vec![
+                (Severity::Error, "document", 5),
+                (Severity::Error, "document", 11),
+            ],
+            diagnostics(&ledger, Some(dir.path().to_path_buf()))
+        );
+        assert_eq!(
+            Vec::<(Severity, &str, usize)>::new(),
+            diagnostics(&ledger, None)
+        );
+    }
+}
@@ -1 +1,144 @@
+//! Messages about what tabula is doing, written to stderr so they never mix with the output
+//! of a command. People read them as text, log collectors as JSON lines.
 
+use std::{fmt, sync::OnceLock};
+
+use chrono::{Local, SecondsFormat};
+use clap::ValueEnum;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    const ALL: [Level; 5] = [
+        Level::Error,
+        Level::Warn,
+        Level::Info,
+        Level::Debug,
+        Level::Trace,
+    ];
+
+    /// This level made more verbose `verbose` times and quieter `quiet` times, as with `-vv`
+    /// or `-q`.
+    pub fn adjust(self, verbose: u8, quiet: u8) -> Level {
+        let index = (self as i32 + verbose as i32 - quiet as i32).clamp(0, 4);
+        Self::ALL[index as usize]
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    Human,
+    Json,
+}
+
+#[derive(Debug)]
+struct Logger {
+    level: Level,
+    format: LogFormat,
+}
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+/// Sets the level and format for the rest of the process. Until then, and when called a
+/// second time, messages up to `info` are logged as text.
+pub fn init(level: Level, format: LogFormat) {
+    let _ = LOGGER.set(Logger { level, format });
+}
+
+fn logger() -> &'static Logger {
+    LOGGER.get_or_init(|| Logger {
+        level: Level::Info,
+        format: LogFormat::Human,
+    })
+}
+
+/// One message as a line in `format`, without the newline.
+fn line(format: LogFormat, level: Level, message: &str) -> String {
+    let now = Local::now();
+    match format {
+        LogFormat::Human => format!(
+            "{} {:5} {}",
+            now.format("%F %T"),
+            level.to_string().to_uppercase(),
+            message
+        ),
+        LogFormat::Json => serde_json::json!({
+            "timestamp": now.to_rfc3339_opts(SecondsFormat::Millis, false),
+            "level": level.to_string(),
+            "message": message,
+        })
+        .to_string(),
+    }
+}
+
+pub fn log(level: Level, message: &str) {
+    let logger = logger();
+    if level <= logger.level {
+        eprintln!("{}", line(logger.format, level, message));
+    }
+}
+
+pub fn error(message: &str) {
+    log(Level::Error, message)
+}
+
+pub fn warn(message: &str) {
+    log(Level::Warn, message)
+}
+
+pub fn info(message: &str) {
+    log(Level::Info, message)
+}
+
+pub fn debug(message: &str) {
+    log(Level::Debug, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_flags_adjust_the_level() {
+        assert_eq!(Level::Debug, Level::Info.adjust(1, 0));
+        assert_eq!(Level::Trace, Level::Info.adjust(5, 0));
+        assert_eq!(Level::Error, Level::Info.adjust(0, 2));
+        assert_eq!(Level::Warn, Level::Debug.adjust(1, 3));
+    }
+
+    #[test]
+    fn test_json_lines() {
+        let line = line(
+            LogFormat::Json,
+            Level::Warn,
+            "Could not load \"main.beancount\"",
+        );
+        let json: serde_json::Value = serde_json::from_str(&line).unwrap();
+
+        assert_eq!("warn", json["level"]);
+        assert_eq!("Could not load \"main.beancount\"", json["message"]);
+        assert!(json["timestamp"].is_string());
+    }
+}
@@ -1,11 +1,13 @@
 use std::error::Error;
 
+pub mod audit;
 pub mod cli;
-mod document_storage;
+pub mod config;
+pub mod document_storage;
 mod http;
 pub mod ledger_storage;
-mod logger;
-mod notification;
+pub mod logger;
+pub mod notification;
 
 pub trait InputAdapter {
     fn run(&mut self) -> Result<(), Box<dyn Error>>;
@@ -1 +1,652 @@
+use std::{
+    error::Error,
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
+use chrono::{DateTime, Local, Utc};
+use hmac::{Hmac, Mac};
+use lettre::{
+    message::{header::ContentType, Attachment, MultiPart, SinglePart},
+    transport::smtp::authentication::Credentials,
+    Message, SmtpTransport, Transport,
+};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::{
+    domain::invoice::Invoice,
+    services::{
+        dunning::ReminderLetter,
+        lifecycle::{InvoiceEvent, Lifecycle},
+    },
+};
+
+use super::{
+    cli::Output,
+    config::{Config, NotificationConfig, SmtpConfig, WebhookEndpoint, WebhooksConfig},
+    ledger_storage::{FileLedgerStorage, LedgerStorage},
+    logger,
+};
+
+/// How long to wait before trying a delivery again, once its attempts with backoff are used up.
+const RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Delivers email messages.
+pub trait Notifier {
+    fn send(&self, message: &Message) -> Result<(), Box<dyn Error>>;
+}
+
+pub struct SmtpNotifier {
+    transport: SmtpTransport,
+}
+
+impl SmtpNotifier {
+    pub fn new(config: &SmtpConfig) -> Result<Self, Box<dyn Error>> {
+        let mut builder = if config.starttls {
+            SmtpTransport::starttls_relay(&config.host)?
+        } else {
+            SmtpTransport::builder_dangerous(&config.host)
+        }
+        .port(config.port);
+
+        if let Some(username) = &config.username {
+            builder = builder.credentials(Credentials::new(
+                username.clone(),
+                config.password.clone().unwrap_or_default(),
+            ));
+        }
+
+        Ok(Self {
+            transport: builder.build(),
+        })
+    }
+}
+
+impl Notifier for SmtpNotifier {
+    fn send(&self, message: &Message) -> Result<(), Box<dyn Error>> {
+        self.transport.send(message)?;
+        Ok(())
+    }
+}
+
+/// Writes each message as an `.eml` file to a directory instead of sending it, for example to
+/// check invoices before they go out, or to hand them to another mail program.
+pub struct FileDropNotifier {
+    dir: PathBuf,
+}
+
+impl FileDropNotifier {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+}
+
+impl Notifier for FileDropNotifier {
+    fn send(&self, message: &Message) -> Result<(), Box<dyn Error>> {
+        fs::create_dir_all(&self.dir)?;
+
+        let to = message
+            .envelope()
+            .to()
+            .first()
+            .map(|address| address.to_string())
+            .unwrap_or_default();
+        let name = format!("{}-{}.eml", Local::now().format("%Y%m%dT%H%M%S%.3f"), to);
+        fs::write(self.dir.join(name), message.formatted())?;
+        Ok(())
+    }
+}
+
+/// The notifier the configuration asks for: a file drop when `drop_dir` is set, else SMTP.
+pub fn notifier(config: &NotificationConfig) -> Result<Box<dyn Notifier>, Box<dyn Error>> {
+    match &config.drop_dir {
+        Some(dir) => Ok(Box::new(FileDropNotifier::new(dir.clone()))),
+        None => Ok(Box::new(SmtpNotifier::new(&config.smtp)?)),
+    }
+}
+
+/// An email with the invoice as `html`, with Markdown as the plain text alternative, and `pdf`
+/// attached.
+pub fn invoice_message(
+    invoice: &Invoice,
+    html: String,
+    pdf: Vec<u8>,
+    from: &str,
+    to: &str,
+) -> Result<Message, Box<dyn Error>> {
+    let pdf = Attachment::new(format!("invoice-{}.pdf", invoice.number))
+        .body(pdf, ContentType::parse("application/pdf")?);
+
+    let message = Message::builder()
+        .from(from.parse()?)
+        .to(to.parse()?)
+        .subject(format!("Invoice {}", invoice.number))
+        .multipart(
+            MultiPart::mixed()
+                .multipart(MultiPart::alternative_plain_html(
+                    invoice.as_markdown(),
+                    html,
+                ))
+                .singlepart(pdf),
+        )?;
+
+    Ok(message)
+}
+
+/// An email with a reminder letter as text, and the PDF of the overdue invoice attached.
+pub fn reminder_message(
+    letter: &ReminderLetter,
+    pdf: Vec<u8>,
+    from: &str,
+    to: &str,
+) -> Result<Message, Box<dyn Error>> {
+    let invoice = &letter.invoice;
+    let pdf = Attachment::new(format!("invoice-{}.pdf", invoice.number))
+        .body(pdf, ContentType::parse("application/pdf")?);
+
+    let message = Message::builder()
+        .from(from.parse()?)
+        .to(to.parse()?)
+        .subject(format!("{} for invoice {}", letter.level, invoice.number))
+        .multipart(
+            MultiPart::mixed()
+                .singlepart(SinglePart::plain(letter.as_txt()))
+                .singlepart(pdf),
+        )?;
+
+    Ok(message)
+}
+
+/// Signs a webhook body for the `X-Tabula-Signature` header: `sha256=` followed by the hex
+/// encoded HMAC-SHA256 of the body.
+pub fn signature(secret: &str, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(body.as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// An event on its way to one endpoint, as kept in the outbox.
+#[derive(Serialize, Deserialize)]
+struct Delivery {
+    url: String,
+    event: String,
+    body: String,
+    /// How often the delivery was tried
+    #[serde(default)]
+    tries: u32,
+    /// When to try the delivery again
+    #[serde(default)]
+    retry_at: Option<DateTime<Utc>>,
+}
+
+/// Posts invoice events as signed JSON to the configured endpoints. Deliveries that fail are
+/// written to the outbox, one JSON object per line, and tried again by `flush_outbox` once
+/// their backoff has passed, so no caller waits for an endpoint that is down.
+pub struct WebhookNotifier {
+    endpoints: Vec<WebhookEndpoint>,
+    outbox: PathBuf,
+    state: PathBuf,
+    attempts: u32,
+    backoff: Duration,
+    agent: ureq::Agent,
+}
+
+impl WebhookNotifier {
+    pub fn new(config: &WebhooksConfig) -> Self {
+        Self {
+            endpoints: config.endpoints.clone(),
+            outbox: config.outbox.clone(),
+            state: config.state.clone(),
+            attempts: config.attempts.max(1),
+            backoff: Duration::from_millis(config.backoff_ms),
+            agent: ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(10))
+                .build(),
+        }
+    }
+
+    /// Posts `{"event": "invoice.paid", "invoice": {..}}` to every endpoint once, and returns
+    /// how many deliveries went to the outbox.
+    pub fn publish(&self, event: InvoiceEvent, invoice: &Invoice) -> Result<usize, Box<dyn Error>> {
+        let body = serde_json::json!({ "event": event.name(), "invoice": invoice }).to_string();
+
+        let failed: Vec<Delivery> = self
+            .endpoints
+            .iter()
+            .map(|endpoint| Delivery {
+                url: endpoint.url.clone(),
+                event: event.name().to_string(),
+                body: body.clone(),
+                tries: 0,
+                retry_at: None,
+            })
+            .filter_map(|delivery| self.try_delivery(delivery))
+            .collect();
+        if failed.is_empty() {
+            return Ok(0);
+        }
+
+        let mut outbox = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.outbox)?;
+        for delivery in &failed {
+            writeln!(outbox, "{}", serde_json::to_string(delivery)?)?;
+        }
+
+        Ok(failed.len())
+    }
+
+    /// Tries the deliveries in the outbox whose backoff has passed, and returns how many got
+    /// through. Deliveries to endpoints that are no longer configured are dropped.
+    pub fn flush_outbox(&self) -> Result<usize, Box<dyn Error>> {
+        let Ok(content) = fs::read_to_string(&self.outbox) else {
+            return Ok(0);
+        };
+        let pending = content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect::<Result<Vec<Delivery>, _>>()?;
+        if pending.is_empty() {
+            return Ok(0);
+        }
+
+        let now = Utc::now();
+        let mut delivered = 0;
+        let mut failed = vec![];
+        for delivery in pending {
+            if self.endpoint(&delivery.url).is_none() {
+                continue;
+            }
+            if delivery.retry_at.is_some_and(|at| at > now) {
+                failed.push(delivery);
+                continue;
+            }
+            match self.try_delivery(delivery) {
+                Some(delivery) => failed.push(delivery),
+                None => delivered += 1,
+            }
+        }
+
+        let mut remaining = String::new();
+        for delivery in &failed {
+            remaining.push_str(&serde_json::to_string(delivery)?);
+            remaining.push('\n');
+        }
+        fs::write(&self.outbox, remaining)?;
+
+        Ok(delivered)
+    }
+
+    /// Announces the events that lead from the invoices as the webhooks last heard of them to
+    /// `lifecycle`, and keeps `lifecycle` as the last announced. When nothing was announced
+    /// yet, the events since `baseline` are published, or none without one, so a first run
+    /// does not announce every invoice.
+    pub fn announce(
+        &self,
+        invoices: &[Invoice],
+        lifecycle: Lifecycle,
+        baseline: Option<Lifecycle>,
+    ) -> Result<(), Box<dyn Error>> {
+        let last = match fs::read_to_string(&self.state) {
+            Ok(json) => Some(serde_json::from_str::<Lifecycle>(&json)?),
+            Err(err) if err.kind() == ErrorKind::NotFound => baseline,
+            Err(err) => return Err(err.into()),
+        };
+        if last.as_ref() == Some(&lifecycle) {
+            return Ok(());
+        }
+
+        for (event, number) in last
+            .map(|last| last.events_until(&lifecycle))
+            .unwrap_or_default()
+        {
+            let Some(invoice) = invoices.iter().find(|invoice| invoice.number == number) else {
+                continue;
+            };
+            match self.publish(event, invoice)? {
+                0 => logger::info(&format!("Published {} for {}", event.name(), number)),
+                _ => logger::info(&format!(
+                    "Could not deliver {} for {}, kept it in the outbox",
+                    event.name(),
+                    number
+                )),
+            }
+        }
+        fs::write(&self.state, serde_json::to_string(&lifecycle)?)?;
+
+        Ok(())
+    }
+
+    fn endpoint(&self, url: &str) -> Option<&WebhookEndpoint> {
+        self.endpoints.iter().find(|endpoint| endpoint.url == url)
+    }
+
+    /// Posts a delivery once, and returns it with the time of its next try when that failed.
+    /// The wait doubles after each try, until the attempts are used up, after which it is
+    /// tried once a minute.
+    fn try_delivery(&self, mut delivery: Delivery) -> Option<Delivery> {
+        let endpoint = self.endpoint(&delivery.url)?;
+        self.post(endpoint, &delivery).err()?;
+
+        delivery.tries += 1;
+        let wait = if delivery.tries < self.attempts {
+            self.backoff * 2u32.saturating_pow(delivery.tries - 1)
+        } else {
+            RETRY_INTERVAL
+        };
+        delivery.retry_at = chrono::Duration::from_std(wait)
+            .ok()
+            .map(|wait| Utc::now() + wait);
+        Some(delivery)
+    }
+
+    fn post(&self, endpoint: &WebhookEndpoint, delivery: &Delivery) -> Result<(), Box<dyn Error>> {
+        let mut request = self
+            .agent
+            .post(&endpoint.url)
+            .set("Content-Type", "application/json")
+            .set("X-Tabula-Event", &delivery.event);
+        if let Some(secret) = &endpoint.secret {
+            request = request.set("X-Tabula-Signature", &signature(secret, &delivery.body));
+        }
+        request.send_string(&delivery.body)?;
+        Ok(())
+    }
+}
+
+/// Runs a command that changes the ledger, then announces to the webhooks what it did to the
+/// invoices. The change stands when the announcement fails, which is only logged, and is then
+/// announced by the daemon.
+pub fn announced<T>(
+    config: &Config,
+    ledger: &Path,
+    run: impl FnOnce() -> Result<T, Box<dyn Error>>,
+) -> Result<T, Box<dyn Error>> {
+    if config.webhooks.endpoints.is_empty() {
+        return run();
+    }
+
+    let read = || -> Result<(Vec<Invoice>, Lifecycle), Box<dyn Error>> {
+        let invoices = FileLedgerStorage::open(ledger)?.find_invoices()?.invoices;
+        let today = Local::now().date_naive();
+        let lifecycle = Lifecycle::new(&invoices, today, config.payment_terms_days);
+        Ok((invoices, lifecycle))
+    };
+    let before = read().ok().map(|(_, lifecycle)| lifecycle);
+
+    let result = run()?;
+
+    let announcement = read().and_then(|(invoices, after)| {
+        WebhookNotifier::new(&config.webhooks).announce(&invoices, after, before)
+    });
+    if let Err(err) = announcement {
+        logger::warn(&format!(
+            "Announcing the change to webhooks failed: {}",
+            err
+        ));
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Read},
+        net::TcpListener,
+        thread,
+        time::Instant,
+    };
+
+    use chrono::NaiveDate;
+
+    use crate::{
+        adapters::cli::document::{invoice_html, invoice_pdf},
+        domain::{invoice::InvoiceNumber, money::Money, payment::Payment},
+    };
+
+    use super::*;
+
+    /// Accepts one request on `listener`, answers it with 204 and returns the request.
+    fn receive(listener: TcpListener) -> thread::JoinHandle<String> {
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request = String::new();
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+                request.push_str(&line);
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            request.push_str(&String::from_utf8(body).unwrap());
+            stream
+                .write_all(b"HTTP/1.1 204 No Content\r\nContent-Length: 0\r\n\r\n")
+                .unwrap();
+            request
+        })
+    }
+
+    /// Accepts one SMTP session on `listener`, answers every command as a mail server that
+    /// takes any message would, and returns the commands with the message data.
+    fn receive_mail(listener: TcpListener) -> thread::JoinHandle<String> {
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut session = String::new();
+            let mut in_data = false;
+            stream.write_all(b"220 sink ESMTP\r\n").unwrap();
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 {
+                    break;
+                }
+                session.push_str(&line);
+                let reply: &[u8] = if in_data {
+                    if line != ".\r\n" {
+                        continue;
+                    }
+                    in_data = false;
+                    b"250 queued\r\n"
+                } else if line.starts_with("EHLO") {
+                    b"250-sink\r\n250 AUTH PLAIN LOGIN\r\n"
+                } else if line.starts_with("AUTH") {
+                    b"235 authenticated\r\n"
+                } else if line.starts_with("DATA") {
+                    in_data = true;
+                    b"354 go ahead\r\n"
+                } else if line.starts_with("QUIT") {
+                    stream.write_all(b"221 bye\r\n").unwrap();
+                    break;
+                } else {
+                    b"250 ok\r\n"
+                };
+                stream.write_all(reply).unwrap();
+            }
+            session
+        })
+    }
+
+    fn webhooks(port: u16, dir: &Path, backoff_ms: u64) -> WebhookNotifier {
+        WebhookNotifier::new(&WebhooksConfig {
+            endpoints: vec![WebhookEndpoint {
+                url: format!("http://127.0.0.1:{}/hooks", port),
+                secret: Some("s3cret".to_string()),
+            }],
+            outbox: dir.join("outbox"),
+            state: dir.join("state"),
+            attempts: 2,
+            backoff_ms,
+        })
+    }
+
+    /// A port nothing listens on.
+    fn closed_port() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().port()
+    }
+
+    #[test]
+    fn test_webhook_posts_signed_event() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let notifier = webhooks(listener.local_addr().unwrap().port(), dir.path(), 0);
+        let receiver = receive(listener);
+
+        let invoice = Invoice::issued("2023-001", "2023-06-01", "100 EUR");
+        let queued = notifier.publish(InvoiceEvent::Paid, &invoice).unwrap();
+
+        let request = receiver.join().unwrap();
+        let (_, body) = request.split_once("\r\n\r\n").unwrap();
+        assert_eq!(0, queued);
+        assert!(request.starts_with("POST /hooks"));
+        assert!(request.contains("X-Tabula-Event: invoice.paid"));
+        assert!(request.contains(&format!(
+            "X-Tabula-Signature: {}",
+            signature("s3cret", body)
+        )));
+        assert!(body.contains("\"number\":\"2023-001\""));
+        assert!(!dir.path().join("outbox").exists());
+    }
+
+    #[test]
+    fn test_undelivered_webhook_waits_in_outbox() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let outbox = dir.path().join("outbox");
+        let port = closed_port();
+        let notifier = webhooks(port, dir.path(), 0);
+        let invoice = Invoice::issued("2023-001", "2023-06-01", "100 EUR");
+
+        assert_eq!(1, notifier.publish(InvoiceEvent::Sent, &invoice).unwrap());
+        assert_eq!(1, fs::read_to_string(&outbox).unwrap().lines().count());
+
+        let receiver = receive(TcpListener::bind(("127.0.0.1", port)).unwrap());
+        assert_eq!(1, notifier.flush_outbox().unwrap());
+        assert!(receiver.join().unwrap().contains("invoice.sent"));
+        assert_eq!("", fs::read_to_string(&outbox).unwrap());
+    }
+
+    #[test]
+    fn test_smtp_sends_invoice_message() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let notifier = SmtpNotifier::new(&SmtpConfig {
+            host: "127.0.0.1".to_string(),
+            port: listener.local_addr().unwrap().port(),
+            starttls: false,
+            username: Some("tabula".to_string()),
+            password: Some("s3cret".to_string()),
+        })
+        .unwrap();
+        let receiver = receive_mail(listener);
+        let invoice = Invoice::issued("2023-001", "2023-06-01", "100 EUR");
+        let message = invoice_message(
+            &invoice,
+            invoice_html(&invoice),
+            invoice_pdf(&invoice).unwrap(),
+            "billing@example.com",
+            "acme@example.com",
+        )
+        .unwrap();
+
+        notifier.send(&message).unwrap();
+
+        let session = receiver.join().unwrap();
+        assert!(session.contains("AUTH PLAIN"));
+        assert!(session.contains("MAIL FROM:<billing@example.com>"));
+        assert!(session.contains("RCPT TO:<acme@example.com>"));
+        assert!(session.contains("Subject: Invoice 2023-001"));
+        assert!(session.contains("Content-Type: application/pdf"));
+    }
+
+    #[test]
+    fn test_failed_delivery_waits_for_its_backoff_without_blocking() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let outbox = dir.path().join("outbox");
+        let notifier = webhooks(closed_port(), dir.path(), 60_000);
+        let invoice = Invoice::issued("2023-001", "2023-06-01", "100 EUR");
+
+        let started = Instant::now();
+        assert_eq!(1, notifier.publish(InvoiceEvent::Sent, &invoice).unwrap());
+        assert_eq!(0, notifier.flush_outbox().unwrap());
+
+        assert!(started.elapsed() < Duration::from_secs(10));
+        let delivery: Delivery =
+            serde_json::from_str(&fs::read_to_string(&outbox).unwrap()).unwrap();
+        assert_eq!(1, delivery.tries);
+        assert!(delivery.retry_at.unwrap() > Utc::now() + chrono::Duration::seconds(50));
+    }
+
+    #[test]
+    fn test_announces_each_change_once() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let notifier = webhooks(listener.local_addr().unwrap().port(), dir.path(), 0);
+        let today = NaiveDate::from_ymd_opt(2023, 6, 10).unwrap();
+        let lifecycle = |invoices: &[Invoice]| Lifecycle::new(invoices, today, 30);
+        let open = vec![Invoice::issued("2023-001", "2023-06-01", "100 EUR")];
+        let mut paid = open[0].clone();
+        paid.payments.push(Payment {
+            date: "2023-06-05".into(),
+            amount: Money::new(100.into(), "EUR"),
+        });
+        let paid = vec![paid];
+
+        // Nothing was announced yet, so this only takes stock
+        notifier.announce(&open, lifecycle(&open), None).unwrap();
+        let receiver = receive(listener);
+        notifier
+            .announce(&paid, lifecycle(&paid), Some(lifecycle(&open)))
+            .unwrap();
+        notifier.announce(&paid, lifecycle(&paid), None).unwrap();
+
+        assert!(receiver.join().unwrap().contains("invoice.paid"));
+        assert!(!dir.path().join("outbox").exists());
+    }
+
+    #[test]
+    fn test_file_drop_writes_invoice_message() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let invoice = Invoice {
+            number: InvoiceNumber("2023-001".to_string()),
+            ..Invoice::default()
+        };
+        let message = invoice_message(
+            &invoice,
+            invoice_html(&invoice),
+            invoice_pdf(&invoice).unwrap(),
+            "billing@example.com",
+            "acme@example.com",
+        )
+        .unwrap();
+
+        FileDropNotifier::new(dir.path().to_path_buf())
+            .send(&message)
+            .unwrap();
+
+        let files: Vec<PathBuf> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(1, files.len());
+        let eml = String::from_utf8_lossy(&fs::read(&files[0]).unwrap()).into_owned();
+        assert!(eml.contains("To: acme@example.com"));
+        assert!(eml.contains("Subject: Invoice 2023-001"));
+        assert!(eml.contains("Content-Type: text/plain"));
+        assert!(eml.contains("Content-Type: text/html"));
+        assert!(eml.contains("Content-Type: application/pdf"));
+    }
+}
@@ -1,6 +1,44 @@
-use std::error::Error;
+use std::{error::Error, path::PathBuf};
 
-use crate::{adapters::{ledger_storage::LedgerStorage, cli::Output}, domain::invoice::InvoiceNumber};
+use chrono::{Datelike, Duration, NaiveDate};
+use rust_decimal::Decimal;
+
+use crate::{
+    adapters::{
+        cli::{
+            bills::read_e_invoice,
+            document::{invoice_html, invoice_pdf},
+            entries::{
+                bill_entry, check_account, check_text, invoice_entry, payment_entry, quoted,
+                sent_entry,
+            },
+            factur_x::factur_x_pdf,
+            Output,
+        },
+        document_storage::{
+            bill_document_key, invoice_document_key, sha256_hex, DocumentStorage, StoredDocument,
+            HTML, PDF, XML,
+        },
+        ledger_storage::{ambiguous, LedgerStorage},
+        notification::{invoice_message, reminder_message, Notifier},
+    },
+    domain::{
+        e_invoice::FacturXProfile,
+        invoice::{Date, Invoice, InvoiceList, InvoiceNumber, InvoiceState, NewInvoice, Revenue},
+        payment::{NewPayment, Payment},
+        query::InvoiceQuery,
+    },
+    error::TabulaError,
+    services::{
+        bills::{book_bill, BillImportOptions},
+        dunning::{due_reminders, DunningOptions, ReminderLetters},
+        e_invoice::{e_invoice, validate_factur_x, EInvoiceError, EInvoiceOptions},
+        forecast::{forecast, ForecastOptions},
+        interest::{statutory_interest, InterestOptions},
+        payment_behaviour::{payment_behaviour, PaymentBehaviourOptions},
+        revenue::{revenue, RevenueOptions},
+    },
+};
 
 pub trait Command {
     type LedgerStorageType: LedgerStorage;
@@ -31,17 +69,24 @@ impl<S: LedgerStorage> Command for BuildInvoiceCommand<S> {
 
 pub struct ListInvoicesCommand<S: LedgerStorage> {
     ledger_storage: S,
+    query: InvoiceQuery,
 }
 
 impl<S: LedgerStorage> Command for ListInvoicesCommand<S> {
     type LedgerStorageType = S;
 
     fn new(ledger_storage: S) -> Self {
-        Self { ledger_storage }
+        Self {
+            ledger_storage,
+            query: InvoiceQuery::default(),
+        }
     }
 
     fn execute(&self) -> Result<Box<dyn Output>, Box<dyn Error>> {
-        Ok(Box::new(self.ledger_storage().find_invoices()?))
+        let invoices = self.ledger_storage().find_invoices()?.invoices;
+        Ok(Box::new(InvoiceList {
+            invoices: self.query.apply(invoices),
+        }))
     }
 
     fn ledger_storage(&self) -> &S {
@@ -49,6 +94,12 @@ impl<S: LedgerStorage> Command for ListInvoicesCommand<S> {
     }
 }
 
+impl<S: LedgerStorage> ListInvoicesCommand<S> {
+    pub fn with_query(self, query: InvoiceQuery) -> Self {
+        Self { query, ..self }
+    }
+}
+
 pub struct FindInvoiceCommand<S: LedgerStorage> {
     ledger_storage: S,
     invoice_number: String,
@@ -58,12 +109,17 @@ impl<S: LedgerStorage> Command for FindInvoiceCommand<S> {
     type LedgerStorageType = S;
 
     fn new(ledger_storage: S) -> Self {
-        Self { ledger_storage, invoice_number: "".to_string() }
+        Self {
+            ledger_storage,
+            invoice_number: "".to_string(),
+        }
     }
 
     fn execute(&self) -> Result<Box<dyn Output>, Box<dyn Error>> {
         let invoice_number = InvoiceNumber(self.invoice_number.clone());
-        Ok(Box::new(self.ledger_storage().find_invoice(&invoice_number)?))
+        Ok(Box::new(
+            self.ledger_storage().find_invoice(&invoice_number)?,
+        ))
     }
 
     fn ledger_storage(&self) -> &S {
@@ -73,6 +129,993 @@ impl<S: LedgerStorage> Command for FindInvoiceCommand<S> {
 
 impl<S: LedgerStorage> FindInvoiceCommand<S> {
     pub fn with_invoice_number(self, invoice_number: String) -> Self {
-        Self { invoice_number, ..self }
+        Self {
+            invoice_number,
+            ..self
+        }
+    }
+}
+
+pub struct ForecastCommand<S: LedgerStorage> {
+    ledger_storage: S,
+    options: ForecastOptions,
+}
+
+impl<S: LedgerStorage> Command for ForecastCommand<S> {
+    type LedgerStorageType = S;
+
+    fn new(ledger_storage: S) -> Self {
+        Self {
+            ledger_storage,
+            options: ForecastOptions::default(),
+        }
+    }
+
+    fn execute(&self) -> Result<Box<dyn Output>, Box<dyn Error>> {
+        let options = &self.options;
+        let start = Date(options.start);
+
+        let mut opening_balance = Decimal::ZERO;
+        for account in &options.bank_accounts {
+            opening_balance += self
+                .ledger_storage()
+                .balance(account, &start)?
+                .into_iter()
+                .filter(|balance| balance.currency == options.currency)
+                .map(|balance| balance.amount)
+                .sum::<Decimal>();
+        }
+
+        let invoices = self.ledger_storage().find_invoices()?.invoices;
+        let bills = self.ledger_storage().find_bills()?;
+
+        Ok(Box::new(forecast(
+            options,
+            opening_balance,
+            &invoices,
+            &bills,
+        )))
+    }
+
+    fn ledger_storage(&self) -> &S {
+        &self.ledger_storage
+    }
+}
+
+impl<S: LedgerStorage> ForecastCommand<S> {
+    pub fn with_options(self, options: ForecastOptions) -> Self {
+        Self { options, ..self }
+    }
+}
+
+pub struct RevenueCommand<S: LedgerStorage> {
+    ledger_storage: S,
+    options: RevenueOptions,
+}
+
+impl<S: LedgerStorage> Command for RevenueCommand<S> {
+    type LedgerStorageType = S;
+
+    fn new(ledger_storage: S) -> Self {
+        Self {
+            ledger_storage,
+            options: RevenueOptions::default(),
+        }
+    }
+
+    fn execute(&self) -> Result<Box<dyn Output>, Box<dyn Error>> {
+        let invoices = self.ledger_storage().find_invoices()?.invoices;
+        Ok(Box::new(revenue(&self.options, &invoices)))
+    }
+
+    fn ledger_storage(&self) -> &S {
+        &self.ledger_storage
+    }
+}
+
+impl<S: LedgerStorage> RevenueCommand<S> {
+    pub fn with_options(self, options: RevenueOptions) -> Self {
+        Self { options, ..self }
+    }
+}
+
+pub struct CustomerStatsCommand<S: LedgerStorage> {
+    ledger_storage: S,
+    options: PaymentBehaviourOptions,
+}
+
+impl<S: LedgerStorage> Command for CustomerStatsCommand<S> {
+    type LedgerStorageType = S;
+
+    fn new(ledger_storage: S) -> Self {
+        Self {
+            ledger_storage,
+            options: PaymentBehaviourOptions::default(),
+        }
+    }
+
+    fn execute(&self) -> Result<Box<dyn Output>, Box<dyn Error>> {
+        let invoices = self.ledger_storage().find_invoices()?.invoices;
+        Ok(Box::new(payment_behaviour(&self.options, &invoices)))
+    }
+
+    fn ledger_storage(&self) -> &S {
+        &self.ledger_storage
+    }
+}
+
+impl<S: LedgerStorage> CustomerStatsCommand<S> {
+    pub fn with_options(self, options: PaymentBehaviourOptions) -> Self {
+        Self { options, ..self }
+    }
+}
+
+pub struct RemindCommand<S: LedgerStorage> {
+    ledger_storage: S,
+    options: DunningOptions,
+    dry_run: bool,
+    /// Emails the reminders from the address, when given
+    notifier: Option<(Box<dyn Notifier>, String)>,
+}
+
+impl<S: LedgerStorage> Command for RemindCommand<S> {
+    type LedgerStorageType = S;
+
+    fn new(ledger_storage: S) -> Self {
+        Self {
+            ledger_storage,
+            options: DunningOptions::default(),
+            dry_run: false,
+            notifier: None,
+        }
+    }
+
+    /// Records the reminders, after emailing them when there is a notifier. When an email
+    /// cannot be sent, the reminders sent before it are recorded and the error is returned, so
+    /// the next run sends the rest.
+    fn execute(&self) -> Result<Box<dyn Output>, Box<dyn Error>> {
+        let invoices = self.ledger_storage().find_invoices()?.invoices;
+        let reminders = self.ledger_storage().find_reminders()?;
+
+        let mut letters = ReminderLetters {
+            letters: due_reminders(&self.options, &invoices, &reminders),
+            dry_run: self.dry_run,
+        };
+        if self.dry_run {
+            return Ok(Box::new(letters));
+        }
+
+        let mut failed = None;
+        if let Some((notifier, from)) = &self.notifier {
+            for (sent, letter) in letters.letters.iter().enumerate() {
+                let Some(to) = &letter.invoice.billing_email else {
+                    continue;
+                };
+                let message = invoice_pdf(&letter.invoice)
+                    .and_then(|pdf| reminder_message(letter, pdf, from, to))
+                    .and_then(|message| notifier.send(&message));
+                if let Err(err) = message {
+                    failed = Some((sent, err));
+                    break;
+                }
+            }
+        }
+        if let Some((sent, _)) = &failed {
+            letters.letters.truncate(*sent);
+        }
+
+        if !letters.letters.is_empty() {
+            self.ledger_storage().append(&letters.as_beancount())?;
+        }
+        match failed {
+            Some((_, err)) => Err(err),
+            None => Ok(Box::new(letters)),
+        }
+    }
+
+    fn ledger_storage(&self) -> &S {
+        &self.ledger_storage
+    }
+}
+
+impl<S: LedgerStorage> RemindCommand<S> {
+    pub fn with_options(self, options: DunningOptions) -> Self {
+        Self { options, ..self }
+    }
+
+    pub fn with_dry_run(self, dry_run: bool) -> Self {
+        Self { dry_run, ..self }
+    }
+
+    /// Emails each reminder from `from` to the billing email of its invoice.
+    pub fn with_notifier(self, notifier: Box<dyn Notifier>, from: String) -> Self {
+        Self {
+            notifier: Some((notifier, from)),
+            ..self
+        }
+    }
+}
+
+pub struct InterestCommand<S: LedgerStorage> {
+    ledger_storage: S,
+    options: InterestOptions,
+    invoice_number: String,
+}
+
+impl<S: LedgerStorage> Command for InterestCommand<S> {
+    type LedgerStorageType = S;
+
+    fn new(ledger_storage: S) -> Self {
+        Self {
+            ledger_storage,
+            options: InterestOptions::default(),
+            invoice_number: "".to_string(),
+        }
+    }
+
+    fn execute(&self) -> Result<Box<dyn Output>, Box<dyn Error>> {
+        let invoice_number = InvoiceNumber(self.invoice_number.clone());
+        let invoice = self.ledger_storage().find_invoice(&invoice_number)?;
+        // The interest invoice repeats these, so they are checked like any other input
+        check_text("invoice number", &invoice.number.0)?;
+        check_text("customer", invoice.customer.as_deref().unwrap_or_default())?;
+
+        Ok(Box::new(
+            statutory_interest(&self.options, &invoice).map_err(TabulaError::from)?,
+        ))
+    }
+
+    fn ledger_storage(&self) -> &S {
+        &self.ledger_storage
+    }
+}
+
+impl<S: LedgerStorage> InterestCommand<S> {
+    pub fn with_options(self, options: InterestOptions) -> Self {
+        Self { options, ..self }
+    }
+
+    pub fn with_invoice_number(self, invoice_number: String) -> Self {
+        Self {
+            invoice_number,
+            ..self
+        }
+    }
+}
+
+/// The invoice a new invoice becomes next to the invoices already in the ledger.
+fn create_invoice(
+    new_invoice: &NewInvoice,
+    invoices: &InvoiceList,
+    payment_terms_days: i64,
+) -> Result<Invoice, Box<dyn Error>> {
+    let date = new_invoice
+        .date
+        .unwrap_or_else(|| chrono::Local::now().date_naive());
+    for (field, text) in [
+        ("invoice number", new_invoice.number.as_deref()),
+        ("customer", new_invoice.customer.as_deref()),
+        ("billing email", new_invoice.billing_email.as_deref()),
+        ("narration", Some(new_invoice.narration.as_str())),
+    ] {
+        check_text(field, text.unwrap_or_default())?;
+    }
+    check_account(&new_invoice.account)?;
+    let number = match &new_invoice.number {
+        Some(number) => InvoiceNumber(number.clone()),
+        None => invoices.next_number(date.year()),
+    };
+    if invoices
+        .invoices
+        .iter()
+        .any(|invoice| invoice.number == number)
+    {
+        let message = format!("Invoice {} already exists", number);
+        return Err(TabulaError::Validation(message).into());
+    }
+    let narration = if new_invoice.narration.is_empty() {
+        format!("Invoice #{}", number)
+    } else {
+        new_invoice.narration.clone()
+    };
+
+    Ok(Invoice {
+        date: Date(date),
+        due_date: Some(Date(
+            new_invoice
+                .due_date
+                .unwrap_or(date + Duration::days(payment_terms_days)),
+        )),
+        narration,
+        number,
+        total: new_invoice.total.to_string(),
+        line_items: vec![],
+        customer: new_invoice.customer.clone(),
+        payments: vec![],
+        revenue: vec![Revenue {
+            account: new_invoice.account.clone(),
+            amount: new_invoice.total.clone(),
+        }],
+        billing_email: new_invoice.billing_email.clone(),
+        sent_on: None,
+        credits_invoice: None,
+        document: None,
+        document_sha256: None,
+        html_document: None,
+        html_document_sha256: None,
+        tags: vec![],
+        metadata: Default::default(),
+        state: InvoiceState::Draft,
+        issued_on: None,
+    })
+}
+
+pub struct CreateInvoiceCommand<S: LedgerStorage> {
+    ledger_storage: S,
+    invoice: Option<NewInvoice>,
+    payment_terms_days: i64,
+}
+
+impl<S: LedgerStorage> Command for CreateInvoiceCommand<S> {
+    type LedgerStorageType = S;
+
+    fn new(ledger_storage: S) -> Self {
+        Self {
+            ledger_storage,
+            invoice: None,
+            payment_terms_days: 30,
+        }
+    }
+
+    fn execute(&self) -> Result<Box<dyn Output>, Box<dyn Error>> {
+        let new_invoice = self.invoice.as_ref().ok_or("No invoice to create")?;
+        let invoices = self.ledger_storage().find_invoices()?;
+        let invoice = create_invoice(new_invoice, &invoices, self.payment_terms_days)?;
+        self.ledger_storage().append(&invoice_entry(&invoice))?;
+
+        Ok(Box::new(invoice))
+    }
+
+    fn ledger_storage(&self) -> &S {
+        &self.ledger_storage
+    }
+}
+
+impl<S: LedgerStorage> CreateInvoiceCommand<S> {
+    pub fn with_invoice(self, invoice: NewInvoice) -> Self {
+        Self {
+            invoice: Some(invoice),
+            ..self
+        }
+    }
+
+    pub fn with_payment_terms_days(self, payment_terms_days: i64) -> Self {
+        Self {
+            payment_terms_days,
+            ..self
+        }
+    }
+}
+
+/// Books several new invoices at once, or none of them when one cannot be created.
+pub struct ImportInvoicesCommand<S: LedgerStorage> {
+    ledger_storage: S,
+    invoices: Vec<NewInvoice>,
+    payment_terms_days: i64,
+    dry_run: bool,
+}
+
+impl<S: LedgerStorage> Command for ImportInvoicesCommand<S> {
+    type LedgerStorageType = S;
+
+    fn new(ledger_storage: S) -> Self {
+        Self {
+            ledger_storage,
+            invoices: vec![],
+            payment_terms_days: 30,
+            dry_run: false,
+        }
+    }
+
+    fn execute(&self) -> Result<Box<dyn Output>, Box<dyn Error>> {
+        let mut invoices = self.ledger_storage().find_invoices()?;
+        let mut created = InvoiceList { invoices: vec![] };
+        for new_invoice in &self.invoices {
+            let invoice = create_invoice(new_invoice, &invoices, self.payment_terms_days)?;
+            invoices.invoices.push(invoice.clone());
+            created.invoices.push(invoice);
+        }
+        if !self.dry_run && !created.invoices.is_empty() {
+            self.ledger_storage().append(&created.as_beancount())?;
+        }
+
+        Ok(Box::new(created))
+    }
+
+    fn ledger_storage(&self) -> &S {
+        &self.ledger_storage
+    }
+}
+
+impl<S: LedgerStorage> ImportInvoicesCommand<S> {
+    pub fn with_invoices(self, invoices: Vec<NewInvoice>) -> Self {
+        Self { invoices, ..self }
+    }
+
+    pub fn with_payment_terms_days(self, payment_terms_days: i64) -> Self {
+        Self {
+            payment_terms_days,
+            ..self
+        }
+    }
+
+    pub fn with_dry_run(self, dry_run: bool) -> Self {
+        Self { dry_run, ..self }
+    }
+}
+
+pub struct RegisterPaymentCommand<S: LedgerStorage> {
+    ledger_storage: S,
+    invoice_number: String,
+    payment: Option<NewPayment>,
+}
+
+impl<S: LedgerStorage> Command for RegisterPaymentCommand<S> {
+    type LedgerStorageType = S;
+
+    fn new(ledger_storage: S) -> Self {
+        Self {
+            ledger_storage,
+            invoice_number: "".to_string(),
+            payment: None,
+        }
+    }
+
+    fn execute(&self) -> Result<Box<dyn Output>, Box<dyn Error>> {
+        let new_payment = self.payment.as_ref().ok_or("No payment to register")?;
+        check_account(&new_payment.account)?;
+        let invoice_number = InvoiceNumber(self.invoice_number.clone());
+        let mut invoice = self.ledger_storage().find_invoice(&invoice_number)?;
+
+        let payment = Payment {
+            date: Date(
+                new_payment
+                    .date
+                    .unwrap_or_else(|| chrono::Local::now().date_naive()),
+            ),
+            amount: new_payment.amount.clone(),
+        };
+        self.ledger_storage().append(&payment_entry(
+            &invoice_number,
+            &payment,
+            &new_payment.account,
+        ))?;
+        invoice.payments.push(payment);
+
+        Ok(Box::new(invoice))
+    }
+
+    fn ledger_storage(&self) -> &S {
+        &self.ledger_storage
+    }
+}
+
+impl<S: LedgerStorage> RegisterPaymentCommand<S> {
+    pub fn with_invoice_number(self, invoice_number: String) -> Self {
+        Self {
+            invoice_number,
+            ..self
+        }
+    }
+
+    pub fn with_payment(self, payment: NewPayment) -> Self {
+        Self {
+            payment: Some(payment),
+            ..self
+        }
+    }
+}
+
+pub struct SendInvoiceCommand<S: LedgerStorage> {
+    ledger_storage: S,
+    invoice_number: String,
+    notifier: Option<Box<dyn Notifier>>,
+    documents: Option<Box<dyn DocumentStorage>>,
+    from: Option<String>,
+    to: Option<String>,
+}
+
+impl<S: LedgerStorage> Command for SendInvoiceCommand<S> {
+    type LedgerStorageType = S;
+
+    fn new(ledger_storage: S) -> Self {
+        Self {
+            ledger_storage,
+            invoice_number: "".to_string(),
+            notifier: None,
+            documents: None,
+            from: None,
+            to: None,
+        }
+    }
+
+    fn execute(&self) -> Result<Box<dyn Output>, Box<dyn Error>> {
+        let notifier = self.notifier.as_ref().ok_or("No notifier to send with")?;
+        let from = self
+            .from
+            .as_ref()
+            .ok_or("Set notification.from in the configuration to send invoices")?;
+        let invoice_number = InvoiceNumber(self.invoice_number.clone());
+        let mut invoice = self.ledger_storage().find_invoice(&invoice_number)?;
+        refuse_draft(&invoice)?;
+
+        let to = self
+            .to
+            .clone()
+            .or_else(|| invoice.billing_email.clone())
+            .ok_or_else(|| format!("Invoice {} has no billing_email", invoice_number))?;
+        let (pdf, html) = match &self.documents {
+            Some(documents) => {
+                archived_documents(self.ledger_storage(), documents.as_ref(), &mut invoice)?
+            }
+            None => (invoice_pdf(&invoice)?, invoice_html(&invoice)),
+        };
+        notifier.send(&invoice_message(&invoice, html, pdf, from, &to)?)?;
+
+        let today = Date(chrono::Local::now().date_naive());
+        self.ledger_storage()
+            .append(&sent_entry(&invoice_number, &today, &to))?;
+        invoice.sent_on = Some(today);
+
+        Ok(Box::new(invoice))
+    }
+
+    fn ledger_storage(&self) -> &S {
+        &self.ledger_storage
+    }
+}
+
+impl<S: LedgerStorage> SendInvoiceCommand<S> {
+    pub fn with_invoice_number(self, invoice_number: String) -> Self {
+        Self {
+            invoice_number,
+            ..self
+        }
+    }
+
+    pub fn with_notifier(self, notifier: Box<dyn Notifier>) -> Self {
+        Self {
+            notifier: Some(notifier),
+            ..self
+        }
+    }
+
+    /// Archives the PDF and HTML when the invoice is first sent, and sends the archived ones
+    /// after that.
+    pub fn with_document_storage(self, documents: Box<dyn DocumentStorage>) -> Self {
+        Self {
+            documents: Some(documents),
+            ..self
+        }
+    }
+
+    /// The sender, and the recipient instead of the invoice's `billing_email` when given.
+    pub fn with_addresses(self, from: Option<String>, to: Option<String>) -> Self {
+        Self { from, to, ..self }
+    }
+}
+
+/// Drafts can still change, so they are neither sent nor archived until they are issued.
+fn refuse_draft(invoice: &Invoice) -> Result<(), TabulaError> {
+    if invoice.state.is_draft() {
+        return Err(TabulaError::Validation(format!(
+            "Invoice {} is a draft, issue it with `tabula invoices issue` first",
+            invoice.number
+        )));
+    }
+
+    Ok(())
+}
+
+/// The PDF and HTML of an invoice as they were archived, fetched from where the ledger says
+/// and checked against their recorded hashes. A document that is not archived yet is
+/// archived now, and recorded in the ledger with its hash.
+fn archived_documents<S: LedgerStorage>(
+    ledger_storage: &S,
+    documents: &dyn DocumentStorage,
+    invoice: &mut Invoice,
+) -> Result<(Vec<u8>, String), Box<dyn Error>> {
+    let mut metadata = vec![];
+
+    let pdf = match &invoice.document {
+        Some(location) => fetch_verified(
+            documents,
+            location,
+            invoice.document_sha256.as_deref(),
+            "document_sha256",
+        )?,
+        None => {
+            let pdf = invoice_pdf(invoice)?;
+            let stored = documents.store(&invoice_document_key(invoice, "pdf"), &pdf, PDF)?;
+            metadata.push(("document", quoted(&stored.location)));
+            metadata.push(("document_sha256", quoted(&stored.sha256)));
+            invoice.document = Some(stored.location);
+            invoice.document_sha256 = Some(stored.sha256);
+            pdf
+        }
+    };
+
+    let html = match &invoice.html_document {
+        Some(location) => String::from_utf8(fetch_verified(
+            documents,
+            location,
+            invoice.html_document_sha256.as_deref(),
+            "html_document_sha256",
+        )?)?,
+        None => {
+            let html = invoice_html(invoice);
+            let key = invoice_document_key(invoice, "html");
+            let stored = documents.store(&key, html.as_bytes(), HTML)?;
+            metadata.push(("html_document", quoted(&stored.location)));
+            metadata.push(("html_document_sha256", quoted(&stored.sha256)));
+            invoice.html_document = Some(stored.location);
+            invoice.html_document_sha256 = Some(stored.sha256);
+            html
+        }
+    };
+
+    if !metadata.is_empty() {
+        ledger_storage.set_invoice_metadata(&invoice.number, &metadata)?;
+    }
+
+    Ok((pdf, html))
+}
+
+/// Fetches an archived document, which must have the recorded `sha256`. A document without a
+/// recorded hash cannot be trusted to be the one that was sent.
+fn fetch_verified(
+    documents: &dyn DocumentStorage,
+    location: &str,
+    sha256: Option<&str>,
+    field: &str,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let sha256 = sha256.ok_or_else(|| {
+        TabulaError::Validation(format!(
+            "{} has no {} to check it against, record the hash it was archived with",
+            location, field
+        ))
+    })?;
+
+    let content = documents.fetch(location)?;
+    if sha256_hex(&content) != sha256 {
+        let message = format!("{} does not match its {}", location, field);
+        return Err(TabulaError::Validation(message).into());
+    }
+
+    Ok(content)
+}
+
+pub struct ArchiveInvoiceCommand<S: LedgerStorage> {
+    ledger_storage: S,
+    invoice_number: String,
+    documents: Option<Box<dyn DocumentStorage>>,
+}
+
+impl<S: LedgerStorage> Command for ArchiveInvoiceCommand<S> {
+    type LedgerStorageType = S;
+
+    fn new(ledger_storage: S) -> Self {
+        Self {
+            ledger_storage,
+            invoice_number: "".to_string(),
+            documents: None,
+        }
+    }
+
+    fn execute(&self) -> Result<Box<dyn Output>, Box<dyn Error>> {
+        let documents = self.documents.as_ref().ok_or("No document storage")?;
+        let invoice_number = InvoiceNumber(self.invoice_number.clone());
+        let mut invoice = self.ledger_storage().find_invoice(&invoice_number)?;
+        refuse_draft(&invoice)?;
+        if let (Some(document), Some(_)) = (&invoice.document, &invoice.html_document) {
+            return Err(format!(
+                "Invoice {} is already archived as {}",
+                invoice_number, document
+            )
+            .into());
+        }
+
+        archived_documents(self.ledger_storage(), documents.as_ref(), &mut invoice)?;
+
+        Ok(Box::new(invoice))
+    }
+
+    fn ledger_storage(&self) -> &S {
+        &self.ledger_storage
+    }
+}
+
+impl<S: LedgerStorage> ArchiveInvoiceCommand<S> {
+    pub fn with_invoice_number(self, invoice_number: String) -> Self {
+        Self {
+            invoice_number,
+            ..self
+        }
+    }
+
+    pub fn with_document_storage(self, documents: Box<dyn DocumentStorage>) -> Self {
+        Self {
+            documents: Some(documents),
+            ..self
+        }
+    }
+}
+
+/// Issues a draft invoice: numbers it when it is still `TBD`, archives its PDF and HTML, and
+/// marks it `*` with the day it was issued. Issued invoices are only corrected with credit
+/// notes.
+pub struct IssueInvoiceCommand<S: LedgerStorage> {
+    ledger_storage: S,
+    invoice_number: String,
+    date: Option<NaiveDate>,
+    documents: Option<Box<dyn DocumentStorage>>,
+}
+
+impl<S: LedgerStorage> Command for IssueInvoiceCommand<S> {
+    type LedgerStorageType = S;
+
+    fn new(ledger_storage: S) -> Self {
+        Self {
+            ledger_storage,
+            invoice_number: "".to_string(),
+            date: None,
+            documents: None,
+        }
+    }
+
+    fn execute(&self) -> Result<Box<dyn Output>, Box<dyn Error>> {
+        let documents = self.documents.as_ref().ok_or("No document storage")?;
+        let draft_number = InvoiceNumber(self.invoice_number.clone());
+        let mut invoice = self.ledger_storage().find_invoice(&draft_number)?;
+        if invoice.state == InvoiceState::Issued {
+            let message = format!("Invoice {} is already issued", draft_number);
+            return Err(TabulaError::Validation(message).into());
+        }
+
+        // Checked before anything is archived under a number meant for another draft
+        let invoices = self.ledger_storage().find_invoices()?;
+        let numbered = |other: &&Invoice| other.number == draft_number;
+        if invoices.invoices.iter().filter(numbered).count() > 1 {
+            return Err(ambiguous(&draft_number).into());
+        }
+        let issued_on = Date(
+            self.date
+                .unwrap_or_else(|| chrono::Local::now().date_naive()),
+        );
+        if draft_number.0 == "TBD" {
+            invoice.number = invoices.next_number(issued_on.0.year());
+        }
+        invoice.state = InvoiceState::Issued;
+        invoice.issued_on = Some(issued_on.clone());
+        let (stored_html, stored) = issued_documents(documents.as_ref(), &invoice)?;
+
+        self.ledger_storage().issue_invoice(
+            &draft_number,
+            &[
+                ("invoice_number", quoted(&invoice.number.0)),
+                ("issued", issued_on.to_string()),
+                ("document", quoted(&stored.location)),
+                ("document_sha256", quoted(&stored.sha256)),
+                ("html_document", quoted(&stored_html.location)),
+                ("html_document_sha256", quoted(&stored_html.sha256)),
+            ],
+        )?;
+        invoice.document = Some(stored.location);
+        invoice.document_sha256 = Some(stored.sha256);
+        invoice.html_document = Some(stored_html.location);
+        invoice.html_document_sha256 = Some(stored_html.sha256);
+
+        Ok(Box::new(invoice))
+    }
+
+    fn ledger_storage(&self) -> &S {
+        &self.ledger_storage
+    }
+}
+
+/// The HTML and PDF of an invoice that is being issued. Documents that are already stored, by
+/// an attempt that did not get to record them in the ledger, are kept, as stored documents are
+/// never replaced. They may also be of another draft that got the same number, or of this one
+/// before it was edited, so the HTML, which unlike the PDF renders the same every time, is
+/// compared with the stored one. It is stored before the PDF, so a stored PDF always has one.
+fn issued_documents(
+    documents: &dyn DocumentStorage,
+    invoice: &Invoice,
+) -> Result<(StoredDocument, StoredDocument), Box<dyn Error>> {
+    let html = invoice_html(invoice).into_bytes();
+    let html_key = invoice_document_key(invoice, "html");
+    let pdf_key = invoice_document_key(invoice, "pdf");
+    let stored_html = match documents.find(&html_key)? {
+        Some(stored) if stored.sha256 == sha256_hex(&html) => stored,
+        Some(stored) => return Err(archived_for_another(invoice, &stored).into()),
+        None => match documents.find(&pdf_key)? {
+            Some(stored) => return Err(archived_for_another(invoice, &stored).into()),
+            None => documents.store(&html_key, &html, HTML)?,
+        },
+    };
+    let stored = match documents.find(&pdf_key)? {
+        Some(stored) => stored,
+        None => documents.store(&pdf_key, &invoice_pdf(invoice)?, PDF)?,
+    };
+    Ok((stored_html, stored))
+}
+
+fn archived_for_another(invoice: &Invoice, stored: &StoredDocument) -> TabulaError {
+    TabulaError::Validation(format!(
+        "{} is already archived for another draft of invoice {}. Stored documents are never \
+         replaced: remove it if it was never sent, or give this draft another number",
+        stored.location, invoice.number
+    ))
+}
+
+impl<S: LedgerStorage> IssueInvoiceCommand<S> {
+    pub fn with_invoice_number(self, invoice_number: String) -> Self {
+        Self {
+            invoice_number,
+            ..self
+        }
+    }
+
+    /// The day the invoice is issued, today when not given.
+    pub fn with_date(self, date: Option<NaiveDate>) -> Self {
+        Self { date, ..self }
+    }
+
+    pub fn with_document_storage(self, documents: Box<dyn DocumentStorage>) -> Self {
+        Self {
+            documents: Some(documents),
+            ..self
+        }
+    }
+}
+
+pub struct FacturXCommand<S: LedgerStorage> {
+    ledger_storage: S,
+    invoice_number: String,
+    options: EInvoiceOptions,
+    profile: FacturXProfile,
+    output: PathBuf,
+}
+
+impl<S: LedgerStorage> Command for FacturXCommand<S> {
+    type LedgerStorageType = S;
+
+    fn new(ledger_storage: S) -> Self {
+        Self {
+            ledger_storage,
+            invoice_number: "".to_string(),
+            options: EInvoiceOptions::default(),
+            profile: FacturXProfile::default(),
+            output: PathBuf::new(),
+        }
+    }
+
+    fn execute(&self) -> Result<Box<dyn Output>, Box<dyn Error>> {
+        let invoice_number = InvoiceNumber(self.invoice_number.clone());
+        let invoice = self.ledger_storage().find_invoice(&invoice_number)?;
+        let e_invoice = e_invoice(&invoice, &self.options)?;
+        let violations = validate_factur_x(&e_invoice, self.profile);
+        if !violations.is_empty() {
+            return Err(EInvoiceError::Invalid(invoice_number, violations).into());
+        }
+
+        let pdf = factur_x_pdf(&invoice, &e_invoice, self.profile)?;
+        std::fs::write(&self.output, pdf)
+            .map_err(|e| format!("Could not write {}: {}", self.output.display(), e))?;
+
+        Ok(Box::new(invoice))
+    }
+
+    fn ledger_storage(&self) -> &S {
+        &self.ledger_storage
+    }
+}
+
+impl<S: LedgerStorage> FacturXCommand<S> {
+    pub fn with_invoice_number(self, invoice_number: String) -> Self {
+        Self {
+            invoice_number,
+            ..self
+        }
+    }
+
+    pub fn with_options(self, options: EInvoiceOptions) -> Self {
+        Self { options, ..self }
+    }
+
+    pub fn with_profile(self, profile: FacturXProfile) -> Self {
+        Self { profile, ..self }
+    }
+
+    pub fn with_output(self, output: PathBuf) -> Self {
+        Self { output, ..self }
+    }
+}
+
+pub struct ImportBillCommand<S: LedgerStorage> {
+    ledger_storage: S,
+    xml: String,
+    options: BillImportOptions,
+    documents: Option<Box<dyn DocumentStorage>>,
+    dry_run: bool,
+}
+
+impl<S: LedgerStorage> Command for ImportBillCommand<S> {
+    type LedgerStorageType = S;
+
+    fn new(ledger_storage: S) -> Self {
+        Self {
+            ledger_storage,
+            xml: "".to_string(),
+            options: BillImportOptions::default(),
+            documents: None,
+            dry_run: false,
+        }
+    }
+
+    fn execute(&self) -> Result<Box<dyn Output>, Box<dyn Error>> {
+        let e_invoice = read_e_invoice(&self.xml)?;
+        let mut entry = book_bill(
+            &e_invoice,
+            &self.ledger_storage().find_bills()?,
+            &self.options,
+        )?;
+        // The supplier writes these, so they are checked like any other input
+        check_text("bill number", &entry.bill.number)?;
+        check_text(
+            "supplier",
+            entry.bill.supplier.as_deref().unwrap_or_default(),
+        )?;
+        check_text("narration", &entry.narration)?;
+        check_account(&entry.payable_account)?;
+        for posting in &entry.postings {
+            check_account(&posting.account)?;
+        }
+        if self.dry_run {
+            return Ok(Box::new(entry));
+        }
+
+        let documents = self.documents.as_ref().ok_or("No document storage")?;
+        let stored = documents.store(&bill_document_key(&entry.bill), self.xml.as_bytes(), XML)?;
+        entry.document = Some(stored.location);
+        entry.document_sha256 = Some(stored.sha256);
+        self.ledger_storage().append(&bill_entry(&entry))?;
+
+        Ok(Box::new(entry))
+    }
+
+    fn ledger_storage(&self) -> &S {
+        &self.ledger_storage
+    }
+}
+
+impl<S: LedgerStorage> ImportBillCommand<S> {
+    pub fn with_xml(self, xml: String) -> Self {
+        Self { xml, ..self }
+    }
+
+    pub fn with_options(self, options: BillImportOptions) -> Self {
+        Self { options, ..self }
+    }
+
+    pub fn with_document_storage(self, documents: Box<dyn DocumentStorage>) -> Self {
+        Self {
+            documents: Some(documents),
+            ..self
+        }
+    }
+
+    pub fn with_dry_run(self, dry_run: bool) -> Self {
+        Self { dry_run, ..self }
     }
 }
@@ -0,0 +1,473 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use chrono::{Days, Local, NaiveDate, NaiveDateTime, NaiveTime};
+use tokio::signal::unix::{signal, SignalKind};
+
+use crate::{
+    adapters::{
+        audit::AuditLog,
+        cli::{
+            arguments::{parse_report, RemindArgs},
+            dunning_options, run_report,
+        },
+        config::Config,
+        ledger_storage::{IndexedLedgerStorage, LedgerStorage},
+        logger,
+        notification::{notifier, WebhookNotifier},
+    },
+    commands::{Command, CreateInvoiceCommand, RemindCommand},
+    domain::{invoice::NewInvoice, money::Money},
+    services::lifecycle::Lifecycle,
+};
+
+/// How many days back the daemon makes up for recurring invoices it missed, e.g. because it
+/// was not running over a weekend.
+const CATCH_UP_DAYS: u64 = 7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Job {
+    RecurringInvoices,
+    Reminders,
+    Reports,
+}
+
+impl Job {
+    fn name(&self) -> &'static str {
+        match self {
+            Job::RecurringInvoices => "recurring invoices",
+            Job::Reminders => "reminders",
+            Job::Reports => "reports",
+        }
+    }
+}
+
+/// Keeps the ledger parsed in memory, reloading it when one of its files changes, and runs
+/// the jobs from the configuration once a day.
+pub struct Daemon {
+    ledger: PathBuf,
+    config: Config,
+    /// The last ledger that could be parsed
+    storage: Option<IndexedLedgerStorage>,
+    /// Whether the files changed since `storage` was loaded, but could not be parsed
+    stale: bool,
+    modified: Vec<(PathBuf, Option<SystemTime>)>,
+    last_runs: HashMap<Job, NaiveDate>,
+    webhooks: Option<WebhookNotifier>,
+}
+
+impl Daemon {
+    pub fn new(ledger: PathBuf, config: Config) -> Self {
+        let webhooks = if config.webhooks.endpoints.is_empty() {
+            None
+        } else {
+            Some(WebhookNotifier::new(&config.webhooks))
+        };
+
+        Self {
+            ledger,
+            config,
+            storage: None,
+            stale: false,
+            modified: vec![],
+            last_runs: HashMap::new(),
+            webhooks,
+        }
+    }
+
+    /// Watches the ledger and runs the jobs that are due, until SIGTERM or Ctrl-C. A job
+    /// that is running is finished before shutting down.
+    pub fn run(&mut self) -> Result<(), Box<dyn Error>> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+
+        runtime.block_on(async {
+            let mut terminate = signal(SignalKind::terminate())?;
+            let poll = Duration::from_secs(self.config.daemon.poll_seconds.max(1));
+            let mut interval = tokio::time::interval(poll);
+
+            logger::info(&format!("Watching {}", self.ledger.display()));
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => self.tick(Local::now().naive_local()),
+                    _ = terminate.recv() => break,
+                    _ = tokio::signal::ctrl_c() => break,
+                }
+            }
+            logger::info("Shutting down");
+
+            Ok::<(), Box<dyn Error>>(())
+        })
+    }
+
+    fn tick(&mut self, now: NaiveDateTime) {
+        if self.storage.is_none() || self.has_changed() {
+            // The failure is logged, and the jobs wait for a ledger that can be parsed
+            let _ = self.reload();
+        }
+
+        for (job, at) in self.schedule() {
+            let today = now.date();
+            if now.time() < at || self.last_runs.get(&job) == Some(&today) {
+                continue;
+            }
+            // Jobs on a stale ledger could hand out an invoice number that is already taken
+            if self.stale {
+                logger::warn(&format!(
+                    "Not running {} until {} can be loaded",
+                    job.name(),
+                    self.ledger.display()
+                ));
+                continue;
+            }
+
+            // A job that failed is tried again on the next tick
+            match self.run_job(job, today) {
+                Ok(summary) => {
+                    self.last_runs.insert(job, today);
+                    logger::info(&format!("Ran {}: {}", job.name(), summary))
+                }
+                Err(err) => logger::error(&format!("Running {} failed: {}", job.name(), err)),
+            }
+        }
+
+        self.publish_events(now.date());
+    }
+
+    fn schedule(&self) -> Vec<(Job, NaiveTime)> {
+        let daemon = &self.config.daemon;
+        [
+            (Job::RecurringInvoices, daemon.recurring_invoices_at),
+            (Job::Reminders, daemon.reminders_at),
+            (Job::Reports, daemon.reports_at),
+        ]
+        .into_iter()
+        .filter_map(|(job, at)| at.map(|at| (job, at)))
+        .collect()
+    }
+
+    fn has_changed(&self) -> bool {
+        let files: Vec<PathBuf> = self.modified.iter().map(|(file, _)| file.clone()).collect();
+        modification_times(&files) != self.modified
+    }
+
+    /// Parses the ledger again. When it cannot be parsed, for example because it was saved
+    /// halfway through an edit, the last good state is kept until the files change again, but
+    /// it is marked stale so no job runs on it.
+    fn reload(&mut self) -> Result<(), Box<dyn Error>> {
+        match IndexedLedgerStorage::open(&self.ledger) {
+            Ok(storage) => {
+                self.modified = modification_times(storage.files());
+                logger::info(&format!(
+                    "Loaded {} from {} file(s)",
+                    self.ledger.display(),
+                    storage.files().len()
+                ));
+                self.storage = Some(storage);
+                self.stale = false;
+                Ok(())
+            }
+            Err(err) => {
+                let files = match &self.storage {
+                    Some(storage) => storage.files().to_vec(),
+                    None => vec![self.ledger.clone()],
+                };
+                self.modified = modification_times(&files);
+                self.stale = true;
+                logger::warn(&format!(
+                    "Could not load {}, keeping the last good state: {}",
+                    self.ledger.display(),
+                    err
+                ));
+                Err(err)
+            }
+        }
+    }
+
+    fn storage(&self) -> Result<&IndexedLedgerStorage, Box<dyn Error>> {
+        self.storage
+            .as_ref()
+            .ok_or_else(|| "The ledger has not been loaded yet".into())
+    }
+
+    fn run_job(&mut self, job: Job, today: NaiveDate) -> Result<String, Box<dyn Error>> {
+        let audit = AuditLog::new(&self.config.audit, &self.ledger);
+        let command = format!("daemon {}", job.name());
+        match job {
+            Job::RecurringInvoices => {
+                audit.record(&command, vec![], || self.create_recurring_invoices(today))
+            }
+            Job::Reminders => audit.record(&command, vec![], || self.record_reminders(today)),
+            Job::Reports => self.write_reports(today),
+        }
+    }
+
+    /// Creates the invoices of recurring items that occurred in the last days, unless an
+    /// invoice with the same date and narration already exists.
+    fn create_recurring_invoices(&mut self, today: NaiveDate) -> Result<String, Box<dyn Error>> {
+        let from = today - Days::new(CATCH_UP_DAYS);
+        let until = today + Days::new(1);
+
+        let invoices = self.storage()?.find_invoices()?.invoices;
+        let mut due = vec![];
+        for recurring in &self.config.daemon.recurring_invoices {
+            for date in recurring.item.occurrences(from, until) {
+                let narration = format!("{} {}", recurring.item.description, date);
+                if invoices
+                    .iter()
+                    .any(|invoice| invoice.date.0 == date && invoice.narration == narration)
+                {
+                    continue;
+                }
+                due.push(NewInvoice {
+                    number: None,
+                    date: Some(date),
+                    due_date: None,
+                    customer: Some(recurring.customer.clone()),
+                    billing_email: recurring.billing_email.clone(),
+                    narration,
+                    total: Money::new(recurring.item.amount, &recurring.item.currency),
+                    account: recurring.account.clone(),
+                });
+            }
+        }
+
+        let created = due.len();
+        for new_invoice in due {
+            CreateInvoiceCommand::new(self.storage()?)
+                .with_invoice(new_invoice)
+                .with_payment_terms_days(self.config.payment_terms_days)
+                .execute()?;
+            // The next invoice needs the number after this one
+            self.reload()?;
+        }
+
+        Ok(format!("created {} invoice(s)", created))
+    }
+
+    fn record_reminders(&mut self, today: NaiveDate) -> Result<String, Box<dyn Error>> {
+        let args = RemindArgs {
+            dry_run: false,
+            date: Some(today),
+            filter: None,
+        };
+        let mut command =
+            RemindCommand::new(self.storage()?).with_options(dunning_options(&args, &self.config));
+        if let Some(from) = &self.config.notification.from {
+            command = command.with_notifier(notifier(&self.config.notification)?, from.clone());
+        }
+        let result = command.execute();
+        // Reminders sent before an email failed are recorded either way
+        self.reload()?;
+        let reminders = result?;
+
+        // Each reminder is recorded on a line of its own
+        let recorded = reminders.as_beancount().lines().count();
+        Ok(format!("recorded {} reminder(s)", recorded))
+    }
+
+    fn write_reports(&mut self, today: NaiveDate) -> Result<String, Box<dyn Error>> {
+        let dir = &self.config.daemon.reports_dir;
+        fs::create_dir_all(dir)?;
+
+        for name in &self.config.daemon.reports {
+            let report = parse_report(name, vec![])?;
+            let output = run_report(self.storage()?, report, &self.config)?;
+            fs::write(
+                dir.join(format!("{}-{}.json", name, today)),
+                output.as_json(),
+            )?;
+        }
+
+        Ok(format!(
+            "wrote {} report(s) to {}",
+            self.config.daemon.reports.len(),
+            dir.display()
+        ))
+    }
+
+    /// Tries the webhook deliveries in the outbox again, and announces what happened to
+    /// invoices since they were last announced, here or by a command that changed the ledger.
+    /// The first announcement only takes stock, so it does not announce every invoice.
+    fn publish_events(&self, today: NaiveDate) {
+        let (Some(webhooks), Some(storage)) = (&self.webhooks, &self.storage) else {
+            return;
+        };
+
+        match webhooks.flush_outbox() {
+            Ok(0) => {}
+            Ok(delivered) => {
+                logger::info(&format!("Delivered {} event(s) from the outbox", delivered))
+            }
+            Err(err) => logger::warn(&format!("Reading the webhook outbox failed: {}", err)),
+        }
+        if self.stale {
+            return;
+        }
+
+        let invoices = match storage.find_invoices() {
+            Ok(list) => list.invoices,
+            Err(err) => {
+                return logger::warn(&format!("Reading invoices for webhooks failed: {}", err))
+            }
+        };
+        let lifecycle = Lifecycle::new(&invoices, today, self.config.payment_terms_days);
+        if let Err(err) = webhooks.announce(&invoices, lifecycle, None) {
+            logger::warn(&format!("Publishing events failed: {}", err));
+        }
+    }
+}
+
+fn modification_times(files: &[PathBuf]) -> Vec<(PathBuf, Option<SystemTime>)> {
+    files
+        .iter()
+        .map(|file| {
+            let modified = fs::metadata(file).and_then(|m| m.modified()).ok();
+            (file.clone(), modified)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::domain::recurring::{Frequency, RecurringInvoice, RecurringItem};
+
+    use super::*;
+
+    const LEDGER: &str = r#"
+2023-06-01 ! "Invoice #1"
+	invoice_number: "2023-001"
+	Assets:AccountsReceivable:Acme	100 EUR
+	Income:Work	-100 EUR
+"#;
+
+    fn daemon(ledger: &assert_fs::NamedTempFile) -> Daemon {
+        let mut config = Config::default();
+        config.daemon.recurring_invoices_at = NaiveTime::from_hms_opt(6, 0, 0);
+        config.daemon.recurring_invoices.push(RecurringInvoice {
+            customer: "Acme".to_string(),
+            billing_email: None,
+            account: "Income:Hosting".to_string(),
+            item: RecurringItem {
+                description: "Hosting".to_string(),
+                amount: 50.into(),
+                currency: "EUR".to_string(),
+                every: Frequency::Monthly,
+                start: NaiveDate::from_ymd_opt(2023, 1, 1).unwrap(),
+                end: None,
+            },
+        });
+        Daemon::new(ledger.path().to_path_buf(), config)
+    }
+
+    fn invoice_numbers(daemon: &Daemon) -> Vec<String> {
+        daemon
+            .storage()
+            .unwrap()
+            .find_invoices()
+            .unwrap()
+            .invoices
+            .into_iter()
+            .map(|invoice| invoice.number.0)
+            .collect()
+    }
+
+    #[test]
+    fn test_recurring_invoices_are_created_once() {
+        let ledger = assert_fs::NamedTempFile::new("ledger.beancount").unwrap();
+        fs::write(ledger.path(), LEDGER).unwrap();
+        let mut daemon = daemon(&ledger);
+        let now = NaiveDate::from_ymd_opt(2023, 7, 3)
+            .unwrap()
+            .and_hms_opt(7, 0, 0)
+            .unwrap();
+
+        daemon.tick(now);
+        daemon.last_runs.clear();
+        daemon.tick(now);
+
+        assert_eq!(vec!["2023-001", "2023-002"], invoice_numbers(&daemon));
+    }
+
+    #[test]
+    fn test_keeps_last_good_state_when_ledger_cannot_be_parsed() {
+        let ledger = assert_fs::NamedTempFile::new("ledger.beancount").unwrap();
+        fs::write(ledger.path(), LEDGER).unwrap();
+        let mut daemon = daemon(&ledger);
+        daemon.reload().unwrap();
+
+        fs::write(ledger.path(), format!("{}\n2023-07-0", LEDGER)).unwrap();
+        assert!(daemon.reload().is_err());
+
+        assert_eq!(vec!["2023-001"], invoice_numbers(&daemon));
+    }
+
+    #[test]
+    fn test_runs_no_jobs_on_a_stale_ledger() {
+        let ledger = assert_fs::NamedTempFile::new("ledger.beancount").unwrap();
+        fs::write(ledger.path(), LEDGER).unwrap();
+        let mut daemon = daemon(&ledger);
+        daemon.reload().unwrap();
+        let now = NaiveDate::from_ymd_opt(2023, 7, 3)
+            .unwrap()
+            .and_hms_opt(7, 0, 0)
+            .unwrap();
+
+        fs::write(ledger.path(), format!("{}\n2023-07-0", LEDGER)).unwrap();
+        daemon.tick(now);
+        assert_eq!(vec!["2023-001"], invoice_numbers(&daemon));
+
+        fs::write(ledger.path(), LEDGER).unwrap();
+        daemon.tick(now);
+        assert_eq!(vec!["2023-001", "2023-002"], invoice_numbers(&daemon));
+    }
+
+    #[test]
+    fn test_emails_reminders_and_retries_when_sending_failed() {
+        let ledger = assert_fs::NamedTempFile::new("ledger.beancount").unwrap();
+        fs::write(
+            ledger.path(),
+            LEDGER.replace(
+                "\tinvoice_number",
+                "\tbilling_email: \"billing@acme.example\"\n\tinvoice_number",
+            ),
+        )
+        .unwrap();
+        let drop_dir = assert_fs::TempDir::new().unwrap();
+        // A file where the directory should be, so the first email cannot be written
+        let blocked = drop_dir.path().join("blocked");
+        fs::write(&blocked, "").unwrap();
+
+        let mut config = Config::default();
+        config.daemon.reminders_at = NaiveTime::from_hms_opt(9, 0, 0);
+        config.notification.from = Some("invoices@example.com".to_string());
+        config.notification.drop_dir = Some(blocked.clone());
+        let mut daemon = Daemon::new(ledger.path().to_path_buf(), config);
+        let now = NaiveDate::from_ymd_opt(2023, 7, 20)
+            .unwrap()
+            .and_hms_opt(10, 0, 0)
+            .unwrap();
+
+        daemon.tick(now);
+        assert!(daemon
+            .storage()
+            .unwrap()
+            .find_reminders()
+            .unwrap()
+            .is_empty());
+
+        fs::remove_file(&blocked).unwrap();
+        daemon.tick(now);
+
+        assert_eq!(1, daemon.storage().unwrap().find_reminders().unwrap().len());
+        assert_eq!(1, fs::read_dir(&blocked).unwrap().count());
+    }
+}
@@ -0,0 +1,117 @@
+use core::fmt;
+use std::{error::Error, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use super::invoice::Date;
+
+/// How lots of a commodity held in an account are matched when they are reduced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Booking {
+    Strict,
+    None,
+    Average,
+    Fifo,
+    Lifo,
+}
+
+impl Booking {
+    /// The name used for this method in the ledger.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Booking::Strict => "STRICT",
+            Booking::None => "NONE",
+            Booking::Average => "AVERAGE",
+            Booking::Fifo => "FIFO",
+            Booking::Lifo => "LIFO",
+        }
+    }
+}
+
+impl fmt::Display for Booking {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for Booking {
+    type Err = ParseBookingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_uppercase().as_str() {
+            "STRICT" => Ok(Booking::Strict),
+            "NONE" => Ok(Booking::None),
+            "AVERAGE" => Ok(Booking::Average),
+            "FIFO" => Ok(Booking::Fifo),
+            "LIFO" => Ok(Booking::Lifo),
+            _ => Err(ParseBookingError(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseBookingError(String);
+
+impl Error for ParseBookingError {}
+
+impl fmt::Display for ParseBookingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Expected a booking method of strict, none, average, fifo or lifo, got \"{}\"",
+            self.0
+        )
+    }
+}
+
+/// An account as it is declared in the ledger with `open` and `close` directives.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Account {
+    pub name: String,
+    pub opened_on: Date,
+    pub closed_on: Option<Date>,
+    /// The only currencies the account may hold. Any currency when empty
+    pub currencies: Vec<String>,
+    pub booking: Option<Booking>,
+}
+
+impl Account {
+    /// Whether postings on `date` may use this account.
+    pub fn is_open_on(&self, date: &Date) -> bool {
+        self.opened_on <= *date && self.closed_on.as_ref().is_none_or(|closed| date < closed)
+    }
+}
+
+/// Whether `name` is a valid beancount account: one of the five root types followed by one
+/// or more components that start with a capital letter or a digit.
+pub fn is_valid_name(name: &str) -> bool {
+    let mut parts = name.split(':');
+    let root = parts.next().unwrap_or_default();
+    let components: Vec<&str> = parts.collect();
+
+    ["Assets", "Liabilities", "Equity", "Income", "Expenses"].contains(&root)
+        && !components.is_empty()
+        && components.iter().all(|component| {
+            let mut chars = component.chars();
+            chars
+                .next()
+                .is_some_and(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+                && chars.all(|c| c.is_alphanumeric() || c == '-')
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_account_names_follow_beancount() {
+        assert!(is_valid_name("Assets:Bank"));
+        assert!(is_valid_name("Expenses:Car:2023-Lease"));
+        assert!(!is_valid_name("Assets"));
+        assert!(!is_valid_name("Bank:Checking"));
+        assert!(!is_valid_name("Assets:bank"));
+        assert!(!is_valid_name("Assets::Bank"));
+    }
+}
@@ -0,0 +1,69 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use super::{invoice::Date, money::Money, payment::Payment};
+
+/// An invoice received from a supplier. Recognized in the ledger by `bill_number` metadata.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Bill {
+    pub date: Date,
+    pub due_date: Option<Date>,
+    pub number: String,
+    pub supplier: Option<String>,
+    pub total: Money,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub payments: Vec<Payment>,
+}
+
+impl Bill {
+    pub fn outstanding(&self) -> Money {
+        let paid: Decimal = self.payments.iter().map(|p| p.amount.amount).sum();
+        Money::new(self.total.amount - paid, &self.total.currency)
+    }
+}
+
+/// Which expense account the lines of incoming bills are booked on. A rule applies when all
+/// it sets matches: the supplier by name, and the item when it is part of the name of a line.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AccountRule {
+    pub supplier: Option<String>,
+    pub item: Option<String>,
+    pub account: String,
+}
+
+impl AccountRule {
+    pub fn matches(&self, supplier: &str, item: &str) -> bool {
+        let supplier_matches = self
+            .supplier
+            .as_ref()
+            .is_none_or(|name| name.eq_ignore_ascii_case(supplier));
+        let item_matches = self
+            .item
+            .as_ref()
+            .is_none_or(|part| item.to_lowercase().contains(&part.to_lowercase()));
+        supplier_matches && item_matches
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct BillPosting {
+    pub account: String,
+    pub amount: Money,
+}
+
+/// A bill received as an electronic invoice, as it is booked: the lines on expense accounts
+/// and the VAT on the input VAT account, against the account payable of the supplier.
+#[derive(Debug, Serialize, Clone)]
+pub struct BillEntry {
+    #[serde(flatten)]
+    pub bill: Bill,
+    pub narration: String,
+    /// Such as `Liabilities:AccountsPayable:HostingBV`
+    pub payable_account: String,
+    pub postings: Vec<BillPosting>,
+    /// Where the original XML is kept, once it has been stored
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub document: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub document_sha256: Option<String>,
+}
@@ -0,0 +1,297 @@
+//! An invoice as the European standard for electronic invoicing, EN 16931, describes it: with
+//! the legal details of both parties, the VAT per category and the totals spelled out.
+
+use core::fmt;
+use std::{error::Error, str::FromStr};
+
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+/// An electronic address a party receives invoices on, written as `<scheme>:<id>`, such as
+/// `0106:12345678` for a KvK number on the Peppol network. The scheme is a code from the
+/// Electronic Address Scheme (EAS) code list.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Endpoint {
+    pub scheme: String,
+    pub id: String,
+}
+
+impl FromStr for Endpoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some((scheme, id)) if !scheme.is_empty() && !id.is_empty() => Ok(Self {
+                scheme: scheme.to_string(),
+                id: id.to_string(),
+            }),
+            _ => Err(format!(
+                "Expected an electronic address like \"0106:12345678\", got \"{}\"",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.scheme, self.id)
+    }
+}
+
+impl<'de> Deserialize<'de> for Endpoint {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// The seller or buyer of an invoice.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct Party {
+    /// The registered name. Buyers default to their name in the ledger
+    pub name: Option<String>,
+    /// Prefixed with the country code, such as NL123456789B01
+    pub vat_id: Option<String>,
+    /// The number in the trade register, such as a KvK number
+    pub company_id: Option<String>,
+    pub endpoint: Option<Endpoint>,
+    pub street: Option<String>,
+    pub city: Option<String>,
+    pub postal_code: Option<String>,
+    /// The ISO 3166-1 code of the country, such as NL
+    pub country: Option<String>,
+    /// What a buyer asked to be quoted on its invoices, such as a purchase order number or a
+    /// cost centre
+    pub reference: Option<String>,
+}
+
+/// The VAT categories of the UNCL5305 code list that EN 16931 allows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+pub enum TaxCategory {
+    #[serde(rename = "S")]
+    Standard,
+    #[serde(rename = "Z")]
+    ZeroRated,
+    #[serde(rename = "E")]
+    Exempt,
+    /// VAT is paid by the buyer
+    #[serde(rename = "AE")]
+    ReverseCharge,
+    /// Supplies to a business in another EU country
+    #[serde(rename = "K")]
+    IntraCommunity,
+    /// Exports outside the EU
+    #[serde(rename = "G")]
+    Export,
+    #[serde(rename = "O")]
+    OutsideScope,
+}
+
+impl TaxCategory {
+    pub fn code(&self) -> &'static str {
+        match self {
+            TaxCategory::Standard => "S",
+            TaxCategory::ZeroRated => "Z",
+            TaxCategory::Exempt => "E",
+            TaxCategory::ReverseCharge => "AE",
+            TaxCategory::IntraCommunity => "K",
+            TaxCategory::Export => "G",
+            TaxCategory::OutsideScope => "O",
+        }
+    }
+
+    /// Why no VAT is charged, for the categories that must say so and usually say the same.
+    pub fn default_exemption_reason(&self) -> Option<&'static str> {
+        match self {
+            TaxCategory::ReverseCharge => Some("Reverse charge"),
+            TaxCategory::IntraCommunity => Some("Intra-Community supply"),
+            TaxCategory::Export => Some("Export outside the EU"),
+            TaxCategory::OutsideScope => Some("Not subject to VAT"),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for TaxCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.code())
+    }
+}
+
+impl FromStr for TaxCategory {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [
+            TaxCategory::Standard,
+            TaxCategory::ZeroRated,
+            TaxCategory::Exempt,
+            TaxCategory::ReverseCharge,
+            TaxCategory::IntraCommunity,
+            TaxCategory::Export,
+            TaxCategory::OutsideScope,
+        ]
+        .into_iter()
+        .find(|category| category.code() == s)
+        .ok_or_else(|| format!("Expected a VAT category such as S or AE, got \"{}\"", s))
+    }
+}
+
+/// The VAT on what is booked on an income account, or on any account below it.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TaxRule {
+    pub account: String,
+    pub category: TaxCategory,
+    /// In percent
+    #[serde(default)]
+    pub rate: Decimal,
+    pub exemption_reason: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct EInvoiceLine {
+    pub id: String,
+    pub name: String,
+    pub quantity: Decimal,
+    /// A UN/ECE recommendation 20 code, such as HUR for hours or C62 for pieces
+    pub unit_code: String,
+    pub price: Decimal,
+    pub net_amount: Decimal,
+    pub category: TaxCategory,
+    pub rate: Decimal,
+}
+
+/// The VAT over all lines with the same category and rate.
+#[derive(Debug, Clone)]
+pub struct TaxSubtotal {
+    pub category: TaxCategory,
+    pub rate: Decimal,
+    pub taxable: Decimal,
+    pub tax: Decimal,
+    pub exemption_reason: Option<String>,
+}
+
+/// How the seller wants to be paid: by credit transfer to its bank account.
+#[derive(Debug, Clone, Default)]
+pub struct PaymentMeans {
+    pub iban: Option<String>,
+    pub bic: Option<String>,
+    /// What the buyer should quote with the payment
+    pub reference: String,
+}
+
+/// An invoice or credit note with everything EN 16931 asks for. Amounts are in the document
+/// currency, and positive on credit notes as well.
+#[derive(Debug, Clone)]
+pub struct EInvoice {
+    pub number: String,
+    pub issue_date: NaiveDate,
+    pub due_date: Option<NaiveDate>,
+    pub credit_note: bool,
+    /// The invoice a credit note credits
+    pub billing_reference: Option<String>,
+    pub note: Option<String>,
+    pub currency: String,
+    pub buyer_reference: Option<String>,
+    pub seller: Party,
+    pub buyer: Party,
+    pub payment: PaymentMeans,
+    pub payment_terms: Option<String>,
+    pub lines: Vec<EInvoiceLine>,
+    pub taxes: Vec<TaxSubtotal>,
+    /// The sum of the net amounts of the lines
+    pub line_total: Decimal,
+    pub tax_exclusive: Decimal,
+    pub tax_total: Decimal,
+    pub tax_inclusive: Decimal,
+    pub payable: Decimal,
+}
+
+impl EInvoice {
+    /// The UNCL1001 code of the document: 380 for an invoice, 381 for a credit note.
+    pub fn type_code(&self) -> &'static str {
+        if self.credit_note {
+            "381"
+        } else {
+            "380"
+        }
+    }
+}
+
+/// How much of an invoice the XML embedded in a Factur-X (ZUGFeRD) PDF describes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FacturXProfile {
+    /// Only the totals and the parties, for bookkeeping
+    Minimum,
+    /// The lines as well
+    Basic,
+    /// Everything EN 16931 describes
+    #[default]
+    En16931,
+}
+
+impl FacturXProfile {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FacturXProfile::Minimum => "minimum",
+            FacturXProfile::Basic => "basic",
+            FacturXProfile::En16931 => "en16931",
+        }
+    }
+
+    /// The specification the XML declares it follows.
+    pub fn guideline(&self) -> &'static str {
+        match self {
+            FacturXProfile::Minimum => "urn:factur-x.eu:1p0:minimum",
+            FacturXProfile::Basic => "urn:cen.eu:en16931:2017#compliant#urn:factur-x.eu:1p0:basic",
+            FacturXProfile::En16931 => "urn:cen.eu:en16931:2017",
+        }
+    }
+
+    /// The name of the profile in the metadata of the PDF.
+    pub fn conformance_level(&self) -> &'static str {
+        match self {
+            FacturXProfile::Minimum => "MINIMUM",
+            FacturXProfile::Basic => "BASIC",
+            FacturXProfile::En16931 => "EN 16931",
+        }
+    }
+}
+
+impl fmt::Display for FacturXProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for FacturXProfile {
+    type Err = ParseFacturXProfileError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace(' ', "").as_str() {
+            "minimum" => Ok(FacturXProfile::Minimum),
+            "basic" => Ok(FacturXProfile::Basic),
+            "en16931" => Ok(FacturXProfile::En16931),
+            _ => Err(ParseFacturXProfileError(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseFacturXProfileError(String);
+
+impl Error for ParseFacturXProfileError {}
+
+impl fmt::Display for ParseFacturXProfileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Expected a Factur-X profile of minimum, basic or en16931, got \"{}\"",
+            self.0
+        )
+    }
+}
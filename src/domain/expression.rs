@@ -0,0 +1,1115 @@
+//! A small language to select invoices, after the `WHERE` clause of beancount's query language:
+//! `status = 'open' and due < today() - 30 and customer ~ 'Acme'`.
+//!
+//! An expression is parsed and type-checked once, so a mistake is reported with the column it
+//! is at before any invoice is read, and then evaluated for each invoice.
+
+use std::{cmp::Ordering, fmt, str::FromStr};
+
+use chrono::{Datelike, Duration, NaiveDate};
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+
+use super::invoice::{Invoice, LineItem};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Type {
+    Bool,
+    Number,
+    Text,
+    Date,
+    /// The tags of an invoice
+    Set,
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Type::Bool => "a condition",
+            Type::Number => "a number",
+            Type::Text => "text",
+            Type::Date => "a date",
+            Type::Set => "a set of tags",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Column {
+    Number,
+    Date,
+    /// The due date, or the date plus the payment terms
+    Due,
+    Customer,
+    Narration,
+    Total,
+    Currency,
+    Outstanding,
+    Paid,
+    /// `open` or `paid`
+    Status,
+    Overdue,
+    Tags,
+    Sent,
+    Email,
+    Credits,
+    Description,
+    Quantity,
+    UnitPrice,
+    Amount,
+}
+
+const COLUMNS: [(&str, Column, Type); 19] = [
+    ("number", Column::Number, Type::Text),
+    ("date", Column::Date, Type::Date),
+    ("due", Column::Due, Type::Date),
+    ("customer", Column::Customer, Type::Text),
+    ("narration", Column::Narration, Type::Text),
+    ("total", Column::Total, Type::Number),
+    ("currency", Column::Currency, Type::Text),
+    ("outstanding", Column::Outstanding, Type::Number),
+    ("paid", Column::Paid, Type::Number),
+    ("status", Column::Status, Type::Text),
+    ("overdue", Column::Overdue, Type::Bool),
+    ("tags", Column::Tags, Type::Set),
+    ("sent", Column::Sent, Type::Date),
+    ("email", Column::Email, Type::Text),
+    ("credits", Column::Credits, Type::Text),
+    ("description", Column::Description, Type::Text),
+    ("quantity", Column::Quantity, Type::Number),
+    ("unit_price", Column::UnitPrice, Type::Number),
+    ("amount", Column::Amount, Type::Number),
+];
+
+const STATUSES: [&str; 2] = ["open", "paid"];
+
+impl Column {
+    fn name(self) -> &'static str {
+        COLUMNS.iter().find(|(_, c, _)| *c == self).unwrap().0
+    }
+
+    fn column_type(self) -> Type {
+        COLUMNS.iter().find(|(_, c, _)| *c == self).unwrap().2
+    }
+
+    /// Whether the column is of a line item, rather than of the invoice
+    fn of_line(self) -> bool {
+        matches!(
+            self,
+            Column::Description | Column::Quantity | Column::UnitPrice | Column::Amount
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Function {
+    Today,
+    Meta,
+    Year,
+    Month,
+    Any,
+}
+
+const FUNCTIONS: [(&str, Function); 5] = [
+    ("today", Function::Today),
+    ("meta", Function::Meta),
+    ("year", Function::Year),
+    ("month", Function::Month),
+    ("any", Function::Any),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    And,
+    Or,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    /// Contains the text, ignoring case
+    Match,
+    NotMatch,
+    In,
+    Add,
+    Sub,
+}
+
+impl Operator {
+    fn symbol(self) -> &'static str {
+        match self {
+            Operator::And => "and",
+            Operator::Or => "or",
+            Operator::Eq => "=",
+            Operator::Ne => "!=",
+            Operator::Lt => "<",
+            Operator::Le => "<=",
+            Operator::Gt => ">",
+            Operator::Ge => ">=",
+            Operator::Match => "~",
+            Operator::NotMatch => "!~",
+            Operator::In => "in",
+            Operator::Add => "+",
+            Operator::Sub => "-",
+        }
+    }
+
+    fn comparison(symbol: &str) -> Option<Self> {
+        match symbol {
+            "=" => Some(Operator::Eq),
+            "!=" => Some(Operator::Ne),
+            "<" => Some(Operator::Lt),
+            "<=" => Some(Operator::Le),
+            ">" => Some(Operator::Gt),
+            ">=" => Some(Operator::Ge),
+            "~" => Some(Operator::Match),
+            "!~" => Some(Operator::NotMatch),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    /// A value the invoice does not have, such as the customer of an invoice without one
+    Null,
+    Bool(bool),
+    Number(Decimal),
+    Text(String),
+    Date(NaiveDate),
+    Set(Vec<String>),
+}
+
+impl Value {
+    fn value_type(&self) -> Option<Type> {
+        match self {
+            Value::Null => None,
+            Value::Bool(_) => Some(Type::Bool),
+            Value::Number(_) => Some(Type::Number),
+            Value::Text(_) => Some(Type::Text),
+            Value::Date(_) => Some(Type::Date),
+            Value::Set(_) => Some(Type::Set),
+        }
+    }
+
+    fn partial_cmp(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+            (Value::Number(a), Value::Number(b)) => Some(a.cmp(b)),
+            (Value::Text(a), Value::Text(b)) => Some(a.cmp(b)),
+            (Value::Date(a), Value::Date(b)) => Some(a.cmp(b)),
+            _ => None,
+        }
+    }
+}
+
+/// A place in the expression, in characters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Span {
+    start: usize,
+    end: usize,
+}
+
+impl Span {
+    fn to(self, other: Span) -> Span {
+        Span {
+            start: self.start,
+            end: other.end,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Literal(Value),
+    Column(Column),
+    Call(Function, Vec<Spanned>),
+    Not(Box<Spanned>),
+    Negate(Box<Spanned>),
+    Binary(Operator, Box<Spanned>, Box<Spanned>),
+}
+
+#[derive(Debug, Clone)]
+struct Spanned {
+    node: Node,
+    span: Span,
+}
+
+/// What is wrong with an expression, and where.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExpressionError {
+    pub message: String,
+    pub expression: String,
+    /// The first character at fault, counting from 1
+    pub column: usize,
+    /// The number of characters at fault
+    pub width: usize,
+}
+
+impl ExpressionError {
+    fn new(expression: &str, span: Span, message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            expression: expression.to_string(),
+            column: span.start + 1,
+            width: (span.end - span.start).max(1),
+        }
+    }
+}
+
+impl fmt::Display for ExpressionError {
+    /// The message, followed by the expression with the part at fault underlined.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} at column {}", self.message, self.column)?;
+        writeln!(f, "  {}", self.expression)?;
+        write!(
+            f,
+            "  {}{}",
+            " ".repeat(self.column - 1),
+            "^".repeat(self.width)
+        )
+    }
+}
+
+impl std::error::Error for ExpressionError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Text(String),
+    Number(Decimal),
+    Date(NaiveDate),
+    Symbol(&'static str),
+}
+
+const SYMBOLS: [&str; 13] = [
+    "!=", "<=", ">=", "!~", "(", ")", ",", "=", "<", ">", "~", "+", "-",
+];
+
+const KEYWORDS: [&str; 6] = ["and", "or", "not", "in", "true", "false"];
+
+fn tokenize(source: &str) -> Result<Vec<(Token, Span)>, ExpressionError> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        let token = if c == '\'' || c == '"' {
+            let Some(length) = chars[i + 1..].iter().position(|&q| q == c) else {
+                let span = Span {
+                    start,
+                    end: chars.len(),
+                };
+                return Err(ExpressionError::new(source, span, "Unterminated text"));
+            };
+            i += length + 2;
+            Token::Text(chars[start + 1..i - 1].iter().collect())
+        } else if c.is_ascii_digit() {
+            let is_date = |i: usize| {
+                chars.len() >= i + 10
+                    && chars[i..i + 10].iter().enumerate().all(|(n, c)| match n {
+                        4 | 7 => *c == '-',
+                        _ => c.is_ascii_digit(),
+                    })
+            };
+            if is_date(i) {
+                i += 10;
+                let text: String = chars[start..i].iter().collect();
+                let date = NaiveDate::parse_from_str(&text, "%Y-%m-%d").map_err(|_| {
+                    ExpressionError::new(source, Span { start, end: i }, "Invalid date")
+                })?;
+                Token::Date(date)
+            } else {
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+                let text: String = chars[start..i].iter().collect();
+                let number = text.parse().map_err(|_| {
+                    ExpressionError::new(source, Span { start, end: i }, "Invalid number")
+                })?;
+                Token::Number(number)
+            }
+        } else if c.is_alphabetic() || c == '_' {
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            Token::Word(chars[start..i].iter().collect())
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            let Some(symbol) = SYMBOLS.iter().find(|symbol| rest.starts_with(**symbol)) else {
+                let span = Span { start, end: i + 1 };
+                let message = format!("Unexpected character '{}'", c);
+                return Err(ExpressionError::new(source, span, message));
+            };
+            i += symbol.chars().count();
+            Token::Symbol(symbol)
+        };
+        tokens.push((token, Span { start, end: i }));
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    tokens: Vec<(Token, Span)>,
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(token, _)| token)
+    }
+
+    /// The span of the next token, or the place after the expression at its end
+    fn peek_span(&self) -> Span {
+        match self.tokens.get(self.position) {
+            Some((_, span)) => *span,
+            None => {
+                let end = self.source.chars().count();
+                Span {
+                    start: end,
+                    end: end + 1,
+                }
+            }
+        }
+    }
+
+    fn advance(&mut self) -> Option<(Token, Span)> {
+        let next = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        next
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword))
+    }
+
+    fn is_symbol(&self, symbol: &str) -> bool {
+        matches!(self.peek(), Some(Token::Symbol(found)) if *found == symbol)
+    }
+
+    fn error(&self, span: Span, message: impl Into<String>) -> ExpressionError {
+        ExpressionError::new(self.source, span, message)
+    }
+
+    /// An error for the next token, which is not what `expected` describes
+    fn unexpected(&self, expected: &str) -> ExpressionError {
+        let span = self.peek_span();
+        match self.peek() {
+            Some(_) => {
+                let found: String = self
+                    .source
+                    .chars()
+                    .skip(span.start)
+                    .take(span.end - span.start)
+                    .collect();
+                self.error(span, format!("Expected {}, found '{}'", expected, found))
+            }
+            None => self.error(span, format!("Expected {} at the end", expected)),
+        }
+    }
+
+    fn binary(operator: Operator, left: Spanned, right: Spanned) -> Spanned {
+        Spanned {
+            span: left.span.to(right.span),
+            node: Node::Binary(operator, Box::new(left), Box::new(right)),
+        }
+    }
+
+    fn parse(&mut self) -> Result<Spanned, ExpressionError> {
+        let expression = self.or()?;
+        if self.peek().is_some() {
+            return Err(self.unexpected("'and' or 'or'"));
+        }
+        Ok(expression)
+    }
+
+    fn or(&mut self) -> Result<Spanned, ExpressionError> {
+        let mut left = self.and()?;
+        while self.is_keyword("or") {
+            self.advance();
+            let right = self.and()?;
+            left = Self::binary(Operator::Or, left, right);
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Spanned, ExpressionError> {
+        let mut left = self.not()?;
+        while self.is_keyword("and") {
+            self.advance();
+            let right = self.not()?;
+            left = Self::binary(Operator::And, left, right);
+        }
+        Ok(left)
+    }
+
+    fn not(&mut self) -> Result<Spanned, ExpressionError> {
+        if self.is_keyword("not") {
+            let (_, span) = self.advance().unwrap();
+            let operand = self.not()?;
+            return Ok(Spanned {
+                span: span.to(operand.span),
+                node: Node::Not(Box::new(operand)),
+            });
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Spanned, ExpressionError> {
+        let left = self.sum()?;
+        let operator = match self.peek() {
+            Some(Token::Symbol(symbol)) => Operator::comparison(symbol),
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case("in") => Some(Operator::In),
+            _ => None,
+        };
+        let Some(operator) = operator else {
+            return Ok(left);
+        };
+        self.advance();
+        let right = self.sum()?;
+        Ok(Self::binary(operator, left, right))
+    }
+
+    fn sum(&mut self) -> Result<Spanned, ExpressionError> {
+        let mut left = self.unary()?;
+        loop {
+            let operator = if self.is_symbol("+") {
+                Operator::Add
+            } else if self.is_symbol("-") {
+                Operator::Sub
+            } else {
+                return Ok(left);
+            };
+            self.advance();
+            let right = self.unary()?;
+            left = Self::binary(operator, left, right);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Spanned, ExpressionError> {
+        if self.is_symbol("-") {
+            let (_, span) = self.advance().unwrap();
+            let operand = self.unary()?;
+            return Ok(Spanned {
+                span: span.to(operand.span),
+                node: Node::Negate(Box::new(operand)),
+            });
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Spanned, ExpressionError> {
+        let span = self.peek_span();
+        let node = match self.peek().cloned() {
+            Some(Token::Text(text)) => Node::Literal(Value::Text(text)),
+            Some(Token::Number(number)) => Node::Literal(Value::Number(number)),
+            Some(Token::Date(date)) => Node::Literal(Value::Date(date)),
+            Some(Token::Symbol("(")) => {
+                self.advance();
+                let inner = self.or()?;
+                if !self.is_symbol(")") {
+                    return Err(self.unexpected("')'"));
+                }
+                let (_, end) = self.advance().unwrap();
+                return Ok(Spanned {
+                    node: inner.node,
+                    span: span.to(end),
+                });
+            }
+            Some(Token::Word(word)) => {
+                let name = word.to_lowercase();
+                if name == "true" || name == "false" {
+                    Node::Literal(Value::Bool(name == "true"))
+                } else if KEYWORDS.contains(&name.as_str()) {
+                    return Err(self.unexpected("a value"));
+                } else if self.tokens.get(self.position + 1).map(|(token, _)| token)
+                    == Some(&Token::Symbol("("))
+                {
+                    return self.call(&name, span);
+                } else {
+                    match COLUMNS.iter().find(|(column, _, _)| *column == name) {
+                        Some((_, column, _)) => Node::Column(*column),
+                        None => {
+                            let names: Vec<&str> =
+                                COLUMNS.iter().map(|(name, _, _)| *name).collect();
+                            let message = format!(
+                                "Unknown column '{}', expected one of {}",
+                                word,
+                                names.join(", ")
+                            );
+                            return Err(self.error(span, message));
+                        }
+                    }
+                }
+            }
+            _ => return Err(self.unexpected("a value")),
+        };
+        self.advance();
+        Ok(Spanned { node, span })
+    }
+
+    fn call(&mut self, name: &str, span: Span) -> Result<Spanned, ExpressionError> {
+        let Some((_, function)) = FUNCTIONS.iter().find(|(function, _)| *function == name) else {
+            let names: Vec<&str> = FUNCTIONS.iter().map(|(name, _)| *name).collect();
+            let message = format!(
+                "Unknown function '{}', expected one of {}",
+                name,
+                names.join(", ")
+            );
+            return Err(self.error(span, message));
+        };
+        // The name and the opening parenthesis
+        self.advance();
+        self.advance();
+        let mut arguments = vec![];
+        if !self.is_symbol(")") {
+            loop {
+                arguments.push(self.or()?);
+                if !self.is_symbol(",") {
+                    break;
+                }
+                self.advance();
+            }
+        }
+        if !self.is_symbol(")") {
+            return Err(self.unexpected("',' or ')'"));
+        }
+        let (_, end) = self.advance().unwrap();
+        Ok(Spanned {
+            node: Node::Call(*function, arguments),
+            span: span.to(end),
+        })
+    }
+}
+
+struct Checker<'a> {
+    source: &'a str,
+}
+
+impl Checker<'_> {
+    fn error(&self, span: Span, message: impl Into<String>) -> ExpressionError {
+        ExpressionError::new(self.source, span, message)
+    }
+
+    fn expect(
+        &self,
+        expression: &Spanned,
+        expected: Type,
+        in_any: bool,
+    ) -> Result<(), ExpressionError> {
+        let found = self.check(expression, in_any)?;
+        if found != expected {
+            let message = format!("Expected {}, found {}", expected, found);
+            return Err(self.error(expression.span, message));
+        }
+        Ok(())
+    }
+
+    /// The type of an expression. `in_any` tells whether it is inside `any()`, where the
+    /// columns of a line item can be used.
+    fn check(&self, expression: &Spanned, in_any: bool) -> Result<Type, ExpressionError> {
+        let span = expression.span;
+        match &expression.node {
+            Node::Literal(value) => Ok(value.value_type().unwrap()),
+            Node::Column(column) => {
+                if column.of_line() && !in_any {
+                    let message = format!(
+                        "{} is a column of line items, use it inside any()",
+                        column.name()
+                    );
+                    return Err(self.error(span, message));
+                }
+                Ok(column.column_type())
+            }
+            Node::Call(function, arguments) => self.check_call(*function, arguments, span, in_any),
+            Node::Not(operand) => {
+                self.expect(operand, Type::Bool, in_any)?;
+                Ok(Type::Bool)
+            }
+            Node::Negate(operand) => {
+                self.expect(operand, Type::Number, in_any)?;
+                Ok(Type::Number)
+            }
+            Node::Binary(operator, left, right) => {
+                self.check_binary(*operator, left, right, span, in_any)
+            }
+        }
+    }
+
+    fn check_call(
+        &self,
+        function: Function,
+        arguments: &[Spanned],
+        span: Span,
+        in_any: bool,
+    ) -> Result<Type, ExpressionError> {
+        let (parameter, result) = match function {
+            Function::Today => (None, Type::Date),
+            Function::Meta => (Some(Type::Text), Type::Text),
+            Function::Year | Function::Month => (Some(Type::Date), Type::Number),
+            Function::Any => (Some(Type::Bool), Type::Bool),
+        };
+        let name = FUNCTIONS.iter().find(|(_, f)| *f == function).unwrap().0;
+        match (parameter, arguments) {
+            (None, []) => {}
+            (None, _) => {
+                return Err(self.error(span, format!("{}() takes no arguments", name)));
+            }
+            (Some(parameter), [argument]) => {
+                if function == Function::Any && in_any {
+                    return Err(self.error(span, "any() cannot be used inside any()"));
+                }
+                self.expect(argument, parameter, in_any || function == Function::Any)?;
+            }
+            (Some(parameter), _) => {
+                let message = format!("{}() takes one argument, {}", name, parameter);
+                return Err(self.error(span, message));
+            }
+        }
+        Ok(result)
+    }
+
+    fn check_binary(
+        &self,
+        operator: Operator,
+        left: &Spanned,
+        right: &Spanned,
+        span: Span,
+        in_any: bool,
+    ) -> Result<Type, ExpressionError> {
+        if matches!(operator, Operator::And | Operator::Or) {
+            self.expect(left, Type::Bool, in_any)?;
+            self.expect(right, Type::Bool, in_any)?;
+            return Ok(Type::Bool);
+        }
+        let types = (self.check(left, in_any)?, self.check(right, in_any)?);
+        let mismatch = || {
+            let message = format!(
+                "Cannot use {} with {} and {}",
+                operator.symbol(),
+                types.0,
+                types.1
+            );
+            Err(self.error(span, message))
+        };
+        match operator {
+            Operator::Eq | Operator::Ne => {
+                if types.0 != types.1 || types.0 == Type::Set {
+                    return mismatch();
+                }
+                self.check_status(left, right)?;
+                self.check_status(right, left)?;
+                Ok(Type::Bool)
+            }
+            Operator::Lt | Operator::Le | Operator::Gt | Operator::Ge => match types {
+                (Type::Number, Type::Number)
+                | (Type::Text, Type::Text)
+                | (Type::Date, Type::Date) => Ok(Type::Bool),
+                _ => mismatch(),
+            },
+            Operator::Match | Operator::NotMatch => match types {
+                (Type::Text, Type::Text) => Ok(Type::Bool),
+                _ => mismatch(),
+            },
+            Operator::In => match types {
+                (Type::Text, Type::Set) => Ok(Type::Bool),
+                _ => mismatch(),
+            },
+            Operator::Add => match types {
+                (Type::Number, Type::Number) => Ok(Type::Number),
+                (Type::Date, Type::Number) | (Type::Number, Type::Date) => Ok(Type::Date),
+                _ => mismatch(),
+            },
+            Operator::Sub => match types {
+                (Type::Number, Type::Number) | (Type::Date, Type::Date) => Ok(Type::Number),
+                (Type::Date, Type::Number) => Ok(Type::Date),
+                _ => mismatch(),
+            },
+            Operator::And | Operator::Or => unreachable!(),
+        }
+    }
+
+    /// Catches a comparison of the status with a status that does not exist.
+    fn check_status(&self, column: &Spanned, value: &Spanned) -> Result<(), ExpressionError> {
+        if let (Node::Column(Column::Status), Node::Literal(Value::Text(status))) =
+            (&column.node, &value.node)
+        {
+            if !STATUSES.contains(&status.as_str()) {
+                let mut message = format!("The status is 'open' or 'paid', not '{}'", status);
+                if status == "overdue" {
+                    message.push_str(", use overdue for open invoices past their due date");
+                }
+                return Err(self.error(value.span, message));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What an expression is evaluated against.
+struct Scope<'a> {
+    invoice: &'a Invoice,
+    /// The line item inside `any()`
+    line: Option<&'a LineItem>,
+    today: NaiveDate,
+    payment_terms_days: i64,
+}
+
+/// The number an amount such as `65 USD` starts with
+fn leading_number(text: &str) -> Value {
+    text.split_whitespace()
+        .next()
+        .and_then(|number| number.parse().ok())
+        .map_or(Value::Null, Value::Number)
+}
+
+fn text(text: Option<&String>) -> Value {
+    text.map_or(Value::Null, |text| Value::Text(text.clone()))
+}
+
+impl Scope<'_> {
+    fn column(&self, column: Column) -> Value {
+        let invoice = self.invoice;
+        let total = invoice.total_amount();
+        let outstanding = invoice.outstanding();
+        match column {
+            Column::Number => Value::Text(invoice.number.0.clone()),
+            Column::Date => Value::Date(invoice.date.0),
+            Column::Due => Value::Date(invoice.expected_due_date(self.payment_terms_days)),
+            Column::Customer => text(invoice.customer.as_ref()),
+            Column::Narration => Value::Text(invoice.narration.clone()),
+            Column::Total => total.map_or(Value::Null, |total| Value::Number(total.amount)),
+            Column::Currency => total.map_or(Value::Null, |total| Value::Text(total.currency)),
+            Column::Outstanding => {
+                outstanding.map_or(Value::Null, |outstanding| Value::Number(outstanding.amount))
+            }
+            Column::Paid => Value::Number(invoice.payments.iter().map(|p| p.amount.amount).sum()),
+            Column::Status => match outstanding {
+                Some(outstanding) if outstanding.amount > Decimal::ZERO => {
+                    Value::Text("open".to_string())
+                }
+                Some(_) => Value::Text("paid".to_string()),
+                None => Value::Null,
+            },
+            Column::Overdue => Value::Bool(
+                invoice.is_open()
+                    && invoice.expected_due_date(self.payment_terms_days) < self.today,
+            ),
+            Column::Tags => Value::Set(invoice.tags.clone()),
+            Column::Sent => invoice
+                .sent_on
+                .as_ref()
+                .map_or(Value::Null, |date| Value::Date(date.0)),
+            Column::Email => text(invoice.billing_email.as_ref()),
+            Column::Credits => invoice
+                .credits_invoice
+                .as_ref()
+                .map_or(Value::Null, |number| Value::Text(number.0.clone())),
+            Column::Description => self
+                .line
+                .map_or(Value::Null, |line| Value::Text(line.description.clone())),
+            Column::Quantity => self
+                .line
+                .map_or(Value::Null, |line| leading_number(&line.quantity)),
+            Column::UnitPrice => self
+                .line
+                .map_or(Value::Null, |line| leading_number(&line.unit_price)),
+            Column::Amount => self
+                .line
+                .map_or(Value::Null, |line| leading_number(&line.total)),
+        }
+    }
+
+    fn call(&self, function: Function, arguments: &[Spanned]) -> Value {
+        match function {
+            Function::Today => Value::Date(self.today),
+            Function::Meta => match self.evaluate(&arguments[0]) {
+                Value::Text(key) => text(self.invoice.metadata.get(&key)),
+                _ => Value::Null,
+            },
+            Function::Year | Function::Month => match self.evaluate(&arguments[0]) {
+                Value::Date(date) if function == Function::Year => {
+                    Value::Number(date.year().into())
+                }
+                Value::Date(date) => Value::Number(date.month().into()),
+                _ => Value::Null,
+            },
+            Function::Any => Value::Bool(self.invoice.line_items.iter().any(|line| {
+                let scope = Scope {
+                    line: Some(line),
+                    ..*self
+                };
+                scope.evaluate(&arguments[0]) == Value::Bool(true)
+            })),
+        }
+    }
+
+    fn evaluate(&self, expression: &Spanned) -> Value {
+        match &expression.node {
+            Node::Literal(value) => value.clone(),
+            Node::Column(column) => self.column(*column),
+            Node::Call(function, arguments) => self.call(*function, arguments),
+            Node::Not(operand) => match self.evaluate(operand) {
+                Value::Bool(value) => Value::Bool(!value),
+                _ => Value::Null,
+            },
+            Node::Negate(operand) => match self.evaluate(operand) {
+                Value::Number(number) => Value::Number(-number),
+                _ => Value::Null,
+            },
+            Node::Binary(Operator::And, left, right) => {
+                let value = self.evaluate(left) == Value::Bool(true)
+                    && self.evaluate(right) == Value::Bool(true);
+                Value::Bool(value)
+            }
+            Node::Binary(Operator::Or, left, right) => {
+                let value = self.evaluate(left) == Value::Bool(true)
+                    || self.evaluate(right) == Value::Bool(true);
+                Value::Bool(value)
+            }
+            Node::Binary(operator, left, right) => {
+                binary(*operator, self.evaluate(left), self.evaluate(right))
+            }
+        }
+    }
+}
+
+fn days(number: Decimal) -> Duration {
+    Duration::days(number.trunc().to_i64().unwrap_or_default())
+}
+
+/// Applies an operator other than `and` and `or`. A comparison with a missing value is false,
+/// and arithmetic with one is missing too.
+fn binary(operator: Operator, left: Value, right: Value) -> Value {
+    if left == Value::Null || right == Value::Null {
+        return match operator {
+            Operator::Add | Operator::Sub => Value::Null,
+            _ => Value::Bool(false),
+        };
+    }
+    let ordering = left.partial_cmp(&right);
+    match (operator, left, right) {
+        (Operator::Eq, _, _) => Value::Bool(ordering == Some(Ordering::Equal)),
+        (Operator::Ne, _, _) => Value::Bool(ordering != Some(Ordering::Equal)),
+        (Operator::Lt, _, _) => Value::Bool(ordering == Some(Ordering::Less)),
+        (Operator::Le, _, _) => Value::Bool(ordering.is_some_and(|o| o != Ordering::Greater)),
+        (Operator::Gt, _, _) => Value::Bool(ordering == Some(Ordering::Greater)),
+        (Operator::Ge, _, _) => Value::Bool(ordering.is_some_and(|o| o != Ordering::Less)),
+        (Operator::Match | Operator::NotMatch, Value::Text(text), Value::Text(pattern)) => {
+            let found = text.to_lowercase().contains(&pattern.to_lowercase());
+            Value::Bool(found == (operator == Operator::Match))
+        }
+        (Operator::In, Value::Text(text), Value::Set(set)) => Value::Bool(set.contains(&text)),
+        (Operator::Add, Value::Number(a), Value::Number(b)) => Value::Number(a + b),
+        (Operator::Add, Value::Date(date), Value::Number(n))
+        | (Operator::Add, Value::Number(n), Value::Date(date)) => Value::Date(date + days(n)),
+        (Operator::Sub, Value::Number(a), Value::Number(b)) => Value::Number(a - b),
+        (Operator::Sub, Value::Date(date), Value::Number(n)) => Value::Date(date - days(n)),
+        (Operator::Sub, Value::Date(a), Value::Date(b)) => Value::Number((a - b).num_days().into()),
+        _ => Value::Null,
+    }
+}
+
+/// A parsed and type-checked condition on invoices.
+#[derive(Debug, Clone)]
+pub struct Expression {
+    source: String,
+    root: Spanned,
+}
+
+impl Expression {
+    pub fn parse(source: &str) -> Result<Self, ExpressionError> {
+        let mut parser = Parser {
+            source,
+            tokens: tokenize(source)?,
+            position: 0,
+        };
+        let root = parser.parse()?;
+        let found = Checker { source }.check(&root, false)?;
+        if found != Type::Bool {
+            let message = format!("Expected a condition, found {}", found);
+            return Err(ExpressionError::new(source, root.span, message));
+        }
+        Ok(Self {
+            source: source.to_string(),
+            root,
+        })
+    }
+
+    /// Whether the invoice meets the condition. `today` and the payment terms tell when
+    /// an invoice without a due date is due.
+    pub fn matches(&self, invoice: &Invoice, today: NaiveDate, payment_terms_days: i64) -> bool {
+        let scope = Scope {
+            invoice,
+            line: None,
+            today,
+            payment_terms_days,
+        };
+        scope.evaluate(&self.root) == Value::Bool(true)
+    }
+}
+
+impl FromStr for Expression {
+    type Err = ExpressionError;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        Expression::parse(source)
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::domain::{invoice::InvoiceNumber, money::Money, payment::Payment};
+
+    use super::*;
+
+    fn invoices() -> Vec<Invoice> {
+        let mut paid = Invoice {
+            date: "2023-01-10".into(),
+            number: InvoiceNumber("2023-001".to_string()),
+            customer: Some("Acme Corp".to_string()),
+            total: "1000 EUR".to_string(),
+            tags: vec!["consulting".to_string()],
+            ..Invoice::default()
+        };
+        paid.payments.push(Payment {
+            date: "2023-02-01".into(),
+            amount: Money::new(Decimal::from(1000), "EUR"),
+        });
+        let mut hosting = Invoice {
+            date: "2023-03-01".into(),
+            number: InvoiceNumber("2023-002".to_string()),
+            customer: Some("Globex".to_string()),
+            total: "50 USD".to_string(),
+            ..Invoice::default()
+        };
+        hosting.line_items.push(LineItem {
+            description: "Backups for March".to_string(),
+            quantity: "2".to_string(),
+            unit_price: "25 USD".to_string(),
+            total: "50 USD".to_string(),
+            unit: None,
+            account: None,
+        });
+        hosting
+            .metadata
+            .insert("project".to_string(), "hosting".to_string());
+        let open = Invoice {
+            date: "2023-06-01".into(),
+            number: InvoiceNumber("2023-003".to_string()),
+            customer: None,
+            total: "250 EUR".to_string(),
+            ..Invoice::default()
+        };
+        vec![paid, hosting, open]
+    }
+
+    fn select(expression: &str) -> String {
+        let expression = Expression::parse(expression).unwrap();
+        let today = NaiveDate::from_ymd_opt(2023, 6, 15).unwrap();
+        invoices()
+            .iter()
+            .filter(|invoice| expression.matches(invoice, today, 30))
+            .map(|invoice| invoice.number.0.as_str().to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    #[test]
+    fn test_selects_invoices() {
+        assert_eq!(
+            "2023-002",
+            select("status = 'open' and due < today() - 30 and customer ~ 'glob'")
+        );
+        assert_eq!("2023-002,2023-003", select("STATUS = 'open'"));
+        assert_eq!("2023-002", select("overdue"));
+        assert_eq!("2023-001", select("customer !~ 'globex'"));
+        assert_eq!(
+            "2023-001,2023-003",
+            select("currency = 'EUR' and total >= 250")
+        );
+        assert_eq!("2023-003", select("date > 2023-03-01"));
+        assert_eq!("2023-001,2023-003", select("not (month(date) = 3)"));
+        assert_eq!(
+            "2023-001",
+            select("'consulting' in tags or paid > 0 and year(date) = 2022")
+        );
+        assert_eq!(
+            "2023-002",
+            select("any(description ~ 'backup' and quantity = 2)")
+        );
+        assert_eq!("2023-002", select("any(unit_price = 25 and amount = 50)"));
+        assert_eq!("2023-002", select("meta('project') = \"hosting\""));
+        assert_eq!(
+            "2023-003",
+            select("outstanding = -(-250) and due - date = 30")
+        );
+        assert_eq!("", select("customer = 'Nobody' or false"));
+    }
+
+    #[test]
+    fn test_points_at_the_offending_column() {
+        let error = |expression: &str| {
+            let error = Expression::parse(expression).unwrap_err();
+            (error.message, error.column, error.width)
+        };
+
+        assert_eq!(
+            ("Cannot use < with a date and text".to_string(), 21, 17),
+            error("status = 'open' and due < 'yesterday'")
+        );
+        assert_eq!(
+            (
+                "The status is 'open' or 'paid', not 'overdue', use overdue for open invoices past their due date"
+                    .to_string(),
+                10,
+                9
+            ),
+            error("status = 'overdue'")
+        );
+        assert_eq!(
+            (
+                "description is a column of line items, use it inside any()".to_string(),
+                1,
+                11
+            ),
+            error("description ~ 'x'")
+        );
+        assert_eq!(
+            ("Expected a condition, found a number".to_string(), 1, 9),
+            error("total + 1")
+        );
+        assert_eq!(
+            ("Expected a value at the end".to_string(), 15, 1),
+            error("total > 1 and ")
+        );
+        assert_eq!(
+            ("Unterminated text".to_string(), 12, 4),
+            error("customer = 'Acm")
+        );
+        assert!(error("custmer = 'Acme'")
+            .0
+            .starts_with("Unknown column 'custmer'"));
+
+        let error = Expression::parse("total > 'a'").unwrap_err();
+        assert_eq!(
+            "Cannot use > with a number and text at column 1\n  total > 'a'\n  ^^^^^^^^^^^",
+            error.to_string()
+        );
+    }
+}
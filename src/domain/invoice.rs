@@ -1,56 +1,66 @@
+use std::{collections::BTreeMap, str::FromStr};
+
 use beancount_core::metadata::MetaValue;
 use chrono::NaiveDate;
-use serde::Serialize;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use super::{money::Money, payment::Payment};
+use crate::error::InvalidValue;
 
-#[derive(Debug, PartialEq, Serialize, Clone)]
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
 pub struct InvoiceNumber(pub String);
-impl From<&MetaValue<'_>> for InvoiceNumber {
-    fn from(mv: &MetaValue) -> Self {
+impl TryFrom<&MetaValue<'_>> for InvoiceNumber {
+    type Error = InvalidValue;
+
+    fn try_from(mv: &MetaValue) -> Result<Self, Self::Error> {
         match mv {
-            MetaValue::Text(s) => InvoiceNumber(s.to_string()),
-            MetaValue::Currency(s) => InvoiceNumber(s.to_string()),
-            MetaValue::Number(n) => InvoiceNumber(n.to_string()),
-            _ => panic!("Expected a text value"),
+            MetaValue::Text(s) => Ok(InvoiceNumber(s.to_string())),
+            MetaValue::Currency(s) => Ok(InvoiceNumber(s.to_string())),
+            MetaValue::Number(n) => Ok(InvoiceNumber(n.to_string())),
+            other => Err(InvalidValue::from_meta("text", other)),
         }
     }
 }
 
-impl From<Option<&MetaValue<'_>>> for InvoiceNumber {
-    fn from(mv: Option<&MetaValue>) -> Self {
-        match mv {
-            Some(mv) => InvoiceNumber::from(mv),
-            None => InvoiceNumber("TBD".to_string()),
-        }
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub struct Date(pub NaiveDate);
+impl FromStr for Date {
+    type Err = InvalidValue;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d")
+            .map(Date)
+            .map_err(|_| InvalidValue::new("a date", format!("\"{}\"", value)))
     }
 }
 
-#[derive(Clone)]
-pub struct Date(pub NaiveDate);
+/// Date literals in tests, such as `"2023-01-31".into()`.
+#[cfg(test)]
 impl From<&str> for Date {
     fn from(value: &str) -> Self {
-        let date = NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap();
-        Date(date)
+        value.parse().unwrap()
     }
 }
 
-impl From<String> for Date {
-    fn from(value: String) -> Self {
-        Date::from(value.as_str())
-    }
-}
+/// A date, or text holding one.
+impl TryFrom<&MetaValue<'_>> for Date {
+    type Error = InvalidValue;
 
-impl From<&MetaValue<'_>> for Date {
-    fn from(mv: &MetaValue) -> Self {
+    fn try_from(mv: &MetaValue) -> Result<Self, Self::Error> {
         match mv {
-            MetaValue::Date(date) => date.clone().into(),
-            _ => panic!("Expected a date value"),
+            MetaValue::Date(date) => date.clone().try_into(),
+            MetaValue::Text(text) => text.parse(),
+            other => Err(InvalidValue::from_meta("a date", other)),
         }
     }
 }
 
-impl From<beancount_core::Date<'_>> for Date {
-    fn from(date: beancount_core::Date) -> Self {
-        date.to_string().into()
+impl TryFrom<beancount_core::Date<'_>> for Date {
+    type Error = InvalidValue;
+
+    fn try_from(date: beancount_core::Date) -> Result<Self, Self::Error> {
+        date.to_string().parse()
     }
 }
 
@@ -60,15 +70,53 @@ impl Serialize for Date {
     }
 }
 
-#[derive(Serialize, Clone)]
+impl<'de> Deserialize<'de> for Date {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        NaiveDate::parse_from_str(&s, "%Y-%m-%d")
+            .map(Date)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct LineItem {
     pub description: String,
     pub unit_price: String,
     pub quantity: String,
     pub total: String,
+    /// The unit of the quantity as a UN/ECE recommendation 20 code, such as HUR for hours
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+    /// The income account of the posting the line item is on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,
+}
+
+/// The amount an invoice books on a single income account.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Revenue {
+    pub account: String,
+    pub amount: Money,
 }
 
-#[derive(Serialize, Clone)]
+/// Drafts are marked `!` in the ledger and can still change. Issued invoices are marked `*`
+/// and are only corrected with a credit note.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InvoiceState {
+    #[default]
+    Draft,
+    Issued,
+}
+
+impl InvoiceState {
+    pub fn is_draft(&self) -> bool {
+        *self == InvoiceState::Draft
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Invoice {
     pub date: Date,
     pub due_date: Option<Date>,
@@ -76,6 +124,139 @@ pub struct Invoice {
     pub number: InvoiceNumber,
     pub total: String,
     pub line_items: Vec<LineItem>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub customer: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub payments: Vec<Payment>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub revenue: Vec<Revenue>,
+    /// Where the invoice is emailed to, from the `billing_email` metadata
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub billing_email: Option<String>,
+    /// The last day the invoice was emailed to the customer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sent_on: Option<Date>,
+    /// For a credit note, the invoice it credits, from the `credits_invoice` metadata
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credits_invoice: Option<InvoiceNumber>,
+    /// Where the archived copy of the document sent to the customer is kept
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub document: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub document_sha256: Option<String>,
+    /// Where the archived copy of the HTML emailed to the customer is kept
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub html_document: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub html_document_sha256: Option<String>,
+    /// The tags of the transaction, without the `#`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// The other metadata of the transaction, as text
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
+    #[serde(default)]
+    pub state: InvoiceState,
+    /// The day `invoices issue` issued the invoice, from the `issued` metadata
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub issued_on: Option<Date>,
+}
+
+impl Invoice {
+    pub fn total_amount(&self) -> Option<Money> {
+        self.total.parse().ok()
+    }
+
+    /// What is left to be paid, or None when the total cannot be read as an amount.
+    pub fn outstanding(&self) -> Option<Money> {
+        let total = self.total_amount()?;
+        let paid: Decimal = self.payments.iter().map(|p| p.amount.amount).sum();
+        Some(Money::new(total.amount - paid, &total.currency))
+    }
+
+    /// The amounts booked on income accounts. Invoices without income postings count their
+    /// total as revenue on the default income account.
+    pub fn revenue_lines(&self) -> Vec<Revenue> {
+        if !self.revenue.is_empty() {
+            return self.revenue.clone();
+        }
+        self.total_amount()
+            .map(|amount| {
+                vec![Revenue {
+                    account: default_income_account(),
+                    amount,
+                }]
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.outstanding()
+            .map(|outstanding| outstanding.amount > Decimal::ZERO)
+            .unwrap_or(false)
+    }
+
+    /// The date of the payment that settled this invoice, if it has been paid in full.
+    pub fn paid_on(&self) -> Option<NaiveDate> {
+        if self.is_open() {
+            return None;
+        }
+        self.payments.iter().map(|p| p.date.0).max()
+    }
+
+    /// The due date, or the invoice date plus the payment terms when no due date was set.
+    pub fn expected_due_date(&self, payment_terms_days: i64) -> NaiveDate {
+        match &self.due_date {
+            Some(due_date) => due_date.0,
+            None => self.date.0 + chrono::Duration::days(payment_terms_days),
+        }
+    }
+}
+
+/// Invoices in tests, such as
+/// `Invoice::issued("2023-001", "2023-06-01", "100 EUR").with_customer("Acme")`.
+#[cfg(test)]
+impl Invoice {
+    pub fn issued(number: &str, date: &str, total: &str) -> Self {
+        Self {
+            date: date.into(),
+            number: InvoiceNumber(number.to_string()),
+            total: total.to_string(),
+            state: InvoiceState::Issued,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_due_date(self, due_date: &str) -> Self {
+        Self {
+            due_date: Some(due_date.into()),
+            ..self
+        }
+    }
+
+    pub fn with_customer(self, customer: &str) -> Self {
+        Self {
+            customer: Some(customer.to_string()),
+            ..self
+        }
+    }
+
+    pub fn with_payment(mut self, date: &str, amount: &str) -> Self {
+        self.payments.push(Payment {
+            date: date.into(),
+            amount: amount.parse().unwrap(),
+        });
+        self
+    }
+
+    /// Books `amount` on the income account `account`, negative as in the ledger.
+    pub fn with_revenue(mut self, account: &str, amount: &str) -> Self {
+        self.revenue.push(Revenue {
+            account: account.to_string(),
+            amount: amount.parse().unwrap(),
+        });
+        self
+    }
 }
 
 impl Default for Invoice {
@@ -88,6 +269,20 @@ impl Default for Invoice {
             number: InvoiceNumber("TBD".to_string()),
             total: "1337 USD".to_string(),
             line_items: vec![],
+            customer: None,
+            payments: vec![],
+            revenue: vec![],
+            billing_email: None,
+            sent_on: None,
+            credits_invoice: None,
+            document: None,
+            document_sha256: None,
+            html_document: None,
+            html_document_sha256: None,
+            tags: vec![],
+            metadata: BTreeMap::new(),
+            state: InvoiceState::Draft,
+            issued_on: None,
         }
     }
 }
@@ -96,3 +291,42 @@ impl Default for Invoice {
 pub struct InvoiceList {
     pub invoices: Vec<Invoice>,
 }
+
+impl InvoiceList {
+    /// The number following the highest `<year>-<sequence>` number of that year, e.g.
+    /// `2023-004` after `2023-003`.
+    pub fn next_number(&self, year: i32) -> InvoiceNumber {
+        let prefix = format!("{}-", year);
+        let last = self
+            .invoices
+            .iter()
+            .filter_map(|invoice| invoice.number.0.strip_prefix(&prefix))
+            .filter_map(|sequence| sequence.parse::<u32>().ok())
+            .max()
+            .unwrap_or(0);
+
+        InvoiceNumber(format!("{}{:03}", prefix, last + 1))
+    }
+}
+
+/// An invoice to be added to the ledger. Fields that are left out are filled in when it
+/// is created: the next number of the year, today, and the payment terms.
+#[derive(Debug, Deserialize)]
+pub struct NewInvoice {
+    pub number: Option<String>,
+    pub date: Option<NaiveDate>,
+    pub due_date: Option<NaiveDate>,
+    pub customer: Option<String>,
+    pub billing_email: Option<String>,
+    #[serde(default)]
+    pub narration: String,
+    pub total: Money,
+    /// The income account the total is booked on
+    #[serde(default = "default_income_account")]
+    pub account: String,
+}
+
+/// The income account an invoice is booked on when no other is given.
+pub fn default_income_account() -> String {
+    "Income:Work".to_string()
+}
@@ -1 +1,10 @@
+pub mod account;
+pub mod bill;
+pub mod e_invoice;
+pub mod expression;
 pub mod invoice;
+pub mod money;
+pub mod payment;
+pub mod query;
+pub mod recurring;
+pub mod reminder;
@@ -0,0 +1,26 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use super::{invoice::Date, money::Money};
+
+/// A transaction settling (part of) an invoice or bill. Recognized in the ledger by
+/// `paid_invoice` or `paid_bill` metadata holding the number it pays.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Payment {
+    pub date: Date,
+    pub amount: Money,
+}
+
+/// A payment received for an invoice, to be booked from the receivables to `account`.
+#[derive(Debug, Deserialize)]
+pub struct NewPayment {
+    /// Defaults to today
+    pub date: Option<NaiveDate>,
+    pub amount: Money,
+    #[serde(default = "default_bank_account")]
+    pub account: String,
+}
+
+fn default_bank_account() -> String {
+    "Assets:Bank".to_string()
+}
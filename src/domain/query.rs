@@ -0,0 +1,294 @@
+//! Which invoices to show, and in what order. The same query serves lists on the command
+//! line, the HTTP API and reports.
+
+use std::cmp::Ordering;
+
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+
+use super::{expression::Expression, invoice::Invoice};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaymentStatus {
+    /// Not paid in full
+    Open,
+    /// Open after its due date
+    Overdue,
+    Paid,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    Date,
+    Number,
+    /// The due date, or the date plus the payment terms
+    Due,
+    /// The total, regardless of its currency
+    Amount,
+}
+
+#[derive(Debug, Clone)]
+pub struct InvoiceQuery {
+    /// The first invoice date to include
+    pub from: Option<NaiveDate>,
+    /// The last invoice date to include
+    pub to: Option<NaiveDate>,
+    pub customer: Option<String>,
+    pub status: Option<PaymentStatus>,
+    pub min_amount: Option<Decimal>,
+    pub max_amount: Option<Decimal>,
+    pub currency: Option<String>,
+    /// Tags the invoice must all have
+    pub tags: Vec<String>,
+    /// Text to find in the narration or a line item, ignoring case
+    pub search: Option<String>,
+    /// A condition written in the expression language
+    pub filter: Option<Expression>,
+    /// Keeps the order of the ledger when not set
+    pub sort: Option<SortKey>,
+    pub reverse: bool,
+    pub limit: Option<usize>,
+    /// The day an invoice is overdue after
+    pub today: NaiveDate,
+    pub payment_terms_days: i64,
+}
+
+impl Default for InvoiceQuery {
+    fn default() -> Self {
+        Self {
+            from: None,
+            to: None,
+            customer: None,
+            status: None,
+            min_amount: None,
+            max_amount: None,
+            currency: None,
+            tags: vec![],
+            search: None,
+            filter: None,
+            sort: None,
+            reverse: false,
+            limit: None,
+            today: chrono::Local::now().date_naive(),
+            payment_terms_days: 30,
+        }
+    }
+}
+
+impl InvoiceQuery {
+    fn has_status(&self, invoice: &Invoice, status: PaymentStatus) -> bool {
+        match status {
+            PaymentStatus::Open => invoice.is_open(),
+            PaymentStatus::Overdue => {
+                invoice.is_open() && invoice.expected_due_date(self.payment_terms_days) < self.today
+            }
+            PaymentStatus::Paid => invoice
+                .outstanding()
+                .is_some_and(|outstanding| outstanding.amount <= Decimal::ZERO),
+        }
+    }
+
+    pub fn matches(&self, invoice: &Invoice) -> bool {
+        let total = invoice.total_amount();
+        let search = self.search.as_ref().map(|search| search.to_lowercase());
+
+        self.from.is_none_or(|from| invoice.date.0 >= from)
+            && self.to.is_none_or(|to| invoice.date.0 <= to)
+            && self.customer.as_ref().is_none_or(|customer| {
+                invoice
+                    .customer
+                    .as_ref()
+                    .is_some_and(|c| c.eq_ignore_ascii_case(customer))
+            })
+            && self
+                .status
+                .is_none_or(|status| self.has_status(invoice, status))
+            && self
+                .min_amount
+                .is_none_or(|min| total.as_ref().is_some_and(|total| total.amount >= min))
+            && self
+                .max_amount
+                .is_none_or(|max| total.as_ref().is_some_and(|total| total.amount <= max))
+            && self.currency.as_ref().is_none_or(|currency| {
+                total
+                    .as_ref()
+                    .is_some_and(|total| total.currency.eq_ignore_ascii_case(currency))
+            })
+            && self.tags.iter().all(|tag| invoice.tags.contains(tag))
+            && search.is_none_or(|search| {
+                invoice.narration.to_lowercase().contains(&search)
+                    || invoice
+                        .line_items
+                        .iter()
+                        .any(|line| line.description.to_lowercase().contains(&search))
+            })
+            && self
+                .filter
+                .as_ref()
+                .is_none_or(|filter| filter.matches(invoice, self.today, self.payment_terms_days))
+    }
+
+    fn compare(&self, a: &Invoice, b: &Invoice, key: SortKey) -> Ordering {
+        match key {
+            SortKey::Date => a.date.cmp(&b.date),
+            SortKey::Number => a.number.0.cmp(&b.number.0),
+            SortKey::Due => a
+                .expected_due_date(self.payment_terms_days)
+                .cmp(&b.expected_due_date(self.payment_terms_days)),
+            SortKey::Amount => {
+                let amount = |invoice: &Invoice| invoice.total_amount().map(|total| total.amount);
+                amount(a).cmp(&amount(b))
+            }
+        }
+    }
+
+    /// The matching invoices, sorted and limited.
+    pub fn apply(&self, invoices: Vec<Invoice>) -> Vec<Invoice> {
+        let mut invoices: Vec<Invoice> = invoices
+            .into_iter()
+            .filter(|invoice| self.matches(invoice))
+            .collect();
+        if let Some(key) = self.sort {
+            invoices.sort_by(|a, b| self.compare(a, b, key));
+        }
+        if self.reverse {
+            invoices.reverse();
+        }
+        if let Some(limit) = self.limit {
+            invoices.truncate(limit);
+        }
+        invoices
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::domain::invoice::LineItem;
+
+    use super::*;
+
+    fn numbers(invoices: &[Invoice]) -> Vec<&str> {
+        invoices
+            .iter()
+            .map(|invoice| invoice.number.0.as_str())
+            .collect()
+    }
+
+    fn invoices() -> Vec<Invoice> {
+        let paid = Invoice::issued("2023-001", "2023-01-10", "1000 EUR")
+            .with_customer("Acme")
+            .with_payment("2023-02-01", "1000 EUR");
+        let mut hosting =
+            Invoice::issued("2023-002", "2023-03-01", "50 USD").with_customer("Globex");
+        hosting.tags = vec!["hosting".to_string()];
+        hosting.line_items.push(LineItem {
+            description: "Backups for March".to_string(),
+            quantity: "1".to_string(),
+            unit_price: "50 USD".to_string(),
+            total: "50 USD".to_string(),
+            unit: None,
+            account: None,
+        });
+        let open = Invoice::issued("2023-003", "2023-06-01", "250 EUR").with_customer("acme");
+        vec![paid, hosting, open]
+    }
+
+    #[test]
+    fn test_filters_on_every_criterion() {
+        let query = |query: InvoiceQuery| numbers(&query.apply(invoices())).join(",");
+        let today = NaiveDate::from_ymd_opt(2023, 6, 15).unwrap();
+
+        assert_eq!("2023-001,2023-002,2023-003", query(InvoiceQuery::default()));
+        assert_eq!(
+            "2023-002",
+            query(InvoiceQuery {
+                from: NaiveDate::from_ymd_opt(2023, 2, 1),
+                to: NaiveDate::from_ymd_opt(2023, 5, 31),
+                ..InvoiceQuery::default()
+            })
+        );
+        assert_eq!(
+            "2023-001,2023-003",
+            query(InvoiceQuery {
+                customer: Some("ACME".to_string()),
+                ..InvoiceQuery::default()
+            })
+        );
+        assert_eq!(
+            "2023-001",
+            query(InvoiceQuery {
+                status: Some(PaymentStatus::Paid),
+                ..InvoiceQuery::default()
+            })
+        );
+        assert_eq!(
+            "2023-002",
+            query(InvoiceQuery {
+                status: Some(PaymentStatus::Overdue),
+                today,
+                ..InvoiceQuery::default()
+            })
+        );
+        assert_eq!(
+            "2023-002,2023-003",
+            query(InvoiceQuery {
+                status: Some(PaymentStatus::Open),
+                today,
+                ..InvoiceQuery::default()
+            })
+        );
+        assert_eq!(
+            "2023-003",
+            query(InvoiceQuery {
+                min_amount: Some(Decimal::from(100)),
+                max_amount: Some(Decimal::from(500)),
+                ..InvoiceQuery::default()
+            })
+        );
+        assert_eq!(
+            "2023-002",
+            query(InvoiceQuery {
+                currency: Some("usd".to_string()),
+                ..InvoiceQuery::default()
+            })
+        );
+        assert_eq!(
+            "2023-002",
+            query(InvoiceQuery {
+                tags: vec!["hosting".to_string()],
+                ..InvoiceQuery::default()
+            })
+        );
+        assert_eq!(
+            "2023-002",
+            query(InvoiceQuery {
+                search: Some("backups".to_string()),
+                ..InvoiceQuery::default()
+            })
+        );
+        assert_eq!(
+            "2023-003",
+            query(InvoiceQuery {
+                filter: Some("customer = 'acme' and total < 500".parse().unwrap()),
+                ..InvoiceQuery::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_sorts_reverses_and_limits() {
+        let query = InvoiceQuery {
+            sort: Some(SortKey::Amount),
+            reverse: true,
+            limit: Some(2),
+            ..InvoiceQuery::default()
+        };
+
+        assert_eq!(
+            vec!["2023-001", "2023-003"],
+            numbers(&query.apply(invoices()))
+        );
+    }
+}
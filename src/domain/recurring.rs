@@ -0,0 +1,103 @@
+use chrono::{Days, Months, NaiveDate};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use super::invoice::default_income_account;
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Frequency {
+    Weekly,
+    Monthly,
+    Quarterly,
+    Yearly,
+}
+
+impl Frequency {
+    fn next(&self, date: NaiveDate) -> Option<NaiveDate> {
+        match self {
+            Frequency::Weekly => date.checked_add_days(Days::new(7)),
+            Frequency::Monthly => date.checked_add_months(Months::new(1)),
+            Frequency::Quarterly => date.checked_add_months(Months::new(3)),
+            Frequency::Yearly => date.checked_add_months(Months::new(12)),
+        }
+    }
+}
+
+/// A cost that comes back on a fixed schedule, such as rent or a subscription. The forecast
+/// pays it out of the bank accounts on every occurrence.
+#[derive(Debug, Deserialize, Clone)]
+pub struct RecurringItem {
+    pub description: String,
+    pub amount: Decimal,
+    pub currency: String,
+    pub every: Frequency,
+    pub start: NaiveDate,
+    pub end: Option<NaiveDate>,
+}
+
+impl RecurringItem {
+    /// All dates in `from..until` (exclusive) on which this item occurs.
+    pub fn occurrences(&self, from: NaiveDate, until: NaiveDate) -> Vec<NaiveDate> {
+        let last = match self.end {
+            Some(end) if end < until => end.succ_opt().unwrap_or(end),
+            _ => until,
+        };
+
+        let mut dates = vec![];
+        let mut date = Some(self.start);
+        while let Some(current) = date {
+            if current >= last {
+                break;
+            }
+            if current >= from {
+                dates.push(current);
+            }
+            date = self.every.next(current);
+        }
+        dates
+    }
+}
+
+/// An invoice sent on a fixed schedule, such as a retainer or a hosting fee. The description
+/// and the amount of the item become the narration and total of the invoice.
+#[derive(Debug, Deserialize, Clone)]
+pub struct RecurringInvoice {
+    pub customer: String,
+    pub billing_email: Option<String>,
+    /// The income account the total is booked on
+    #[serde(default = "default_income_account")]
+    pub account: String,
+    #[serde(flatten)]
+    pub item: RecurringItem,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_monthly_occurrences_within_range() {
+        let rent = RecurringItem {
+            description: "Rent".to_string(),
+            amount: Decimal::from(1200),
+            currency: "EUR".to_string(),
+            every: Frequency::Monthly,
+            start: NaiveDate::from_ymd_opt(2023, 1, 15).unwrap(),
+            end: None,
+        };
+
+        let actual = rent.occurrences(
+            NaiveDate::from_ymd_opt(2023, 3, 1).unwrap(),
+            NaiveDate::from_ymd_opt(2023, 5, 15).unwrap(),
+        );
+
+        assert_eq!(
+            vec![
+                NaiveDate::from_ymd_opt(2023, 3, 15).unwrap(),
+                NaiveDate::from_ymd_opt(2023, 4, 15).unwrap(),
+            ],
+            actual
+        );
+    }
+}
@@ -0,0 +1,81 @@
+use core::fmt;
+use std::{error::Error, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use super::invoice::{Date, InvoiceNumber};
+
+/// How far the collection of an overdue invoice has progressed.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum DunningLevel {
+    First,
+    Second,
+    Final,
+}
+
+impl DunningLevel {
+    pub fn next(&self) -> Option<Self> {
+        match self {
+            DunningLevel::First => Some(DunningLevel::Second),
+            DunningLevel::Second => Some(DunningLevel::Final),
+            DunningLevel::Final => None,
+        }
+    }
+
+    /// The name used for this level in the ledger.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DunningLevel::First => "first",
+            DunningLevel::Second => "second",
+            DunningLevel::Final => "final",
+        }
+    }
+}
+
+impl FromStr for DunningLevel {
+    type Err = ParseDunningLevelError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "first" => Ok(DunningLevel::First),
+            "second" => Ok(DunningLevel::Second),
+            "final" => Ok(DunningLevel::Final),
+            _ => Err(ParseDunningLevelError(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for DunningLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DunningLevel::First => write!(f, "First reminder"),
+            DunningLevel::Second => write!(f, "Second reminder"),
+            DunningLevel::Final => write!(f, "Final notice"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseDunningLevelError(String);
+
+impl Error for ParseDunningLevelError {}
+
+impl fmt::Display for ParseDunningLevelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Expected a reminder level of first, second or final, got \"{}\"",
+            self.0
+        )
+    }
+}
+
+/// A reminder sent for an overdue invoice. Recorded in the ledger as
+/// `2023-08-01 custom "reminder" "2023-002" "first"`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Reminder {
+    pub date: Date,
+    pub invoice_number: InvoiceNumber,
+    pub level: DunningLevel,
+}
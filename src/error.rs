@@ -0,0 +1,248 @@
+//! The errors tabula reports to the user, each category with its own exit code.
+
+use std::{error::Error, fmt, io, path::PathBuf};
+
+use beancount_core::metadata::MetaValue;
+use serde::Serialize;
+
+/// Where in the ledger something is wrong.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Location {
+    /// None for a ledger read from stdin
+    pub file: Option<PathBuf>,
+    /// Counting from 1
+    pub line: Option<usize>,
+    /// The offending lines of the ledger, with their line numbers
+    #[serde(skip)]
+    pub snippet: Vec<(usize, String)>,
+}
+
+impl Location {
+    /// The file and line, such as `books.beancount:14`.
+    pub fn position(&self) -> String {
+        let file = match &self.file {
+            Some(file) => file.display().to_string(),
+            None => "<stdin>".to_string(),
+        };
+        match self.line {
+            Some(line) => format!("{}:{}", file, line),
+            None => file,
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    /// `  --> books.beancount:14`, followed by the snippet in the style of rustc.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "  --> {}", self.position())?;
+        let Some((last, _)) = self.snippet.last() else {
+            return Ok(());
+        };
+        let width = last.to_string().len();
+        write!(f, "\n{:width$} |", "")?;
+        let mut previous: Option<usize> = None;
+        for (line, text) in &self.snippet {
+            if previous.is_some_and(|previous| line - previous > 1) {
+                write!(f, "\n{:width$} | ...", "")?;
+            }
+            write!(f, "\n{:>width$} | {}", line, text)?;
+            previous = Some(*line);
+        }
+        write!(f, "\n{:width$} |", "")
+    }
+}
+
+/// A value of another type than expected, before it is known where it was read.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidValue {
+    pub expected: &'static str,
+    pub found: String,
+}
+
+impl InvalidValue {
+    pub fn new(expected: &'static str, found: impl Into<String>) -> Self {
+        Self {
+            expected,
+            found: found.into(),
+        }
+    }
+
+    pub fn from_meta(expected: &'static str, value: &MetaValue) -> Self {
+        let found = match value {
+            MetaValue::Text(text) => format!("\"{}\"", text),
+            MetaValue::Number(number) => number.to_string(),
+            MetaValue::Date(date) => date.to_string(),
+            MetaValue::Currency(currency) => currency.to_string(),
+            MetaValue::Bool(true) => "TRUE".to_string(),
+            MetaValue::Bool(false) => "FALSE".to_string(),
+            other => format!("{:?}", other),
+        };
+        Self::new(expected, found)
+    }
+
+    /// The error for this value under `key` in the ledger, before its location is known.
+    pub fn for_key(self, key: &str) -> TabulaError {
+        TabulaError::InvalidMetadata {
+            location: Box::default(),
+            key: key.to_string(),
+            expected: self.expected,
+            found: self.found,
+        }
+    }
+}
+
+impl Error for InvalidValue {}
+
+impl fmt::Display for InvalidValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Expected {}, found {}", self.expected, self.found)
+    }
+}
+
+#[derive(Debug)]
+pub enum TabulaError {
+    /// The ledger is not valid beancount. The message of the parser tells where.
+    Parse {
+        location: Box<Location>,
+        message: String,
+    },
+    /// Metadata tabula reads holds a value of another type
+    InvalidMetadata {
+        location: Box<Location>,
+        key: String,
+        expected: &'static str,
+        found: String,
+    },
+    /// What was looked for, such as `Invoice 2023-001`
+    NotFound(String),
+    /// Input that breaks a rule, such as a number that is already taken
+    Validation(String),
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Config(String),
+}
+
+impl TabulaError {
+    /// The exit code of the process for this category of error. Other errors exit with 1,
+    /// and mistakes in the arguments with 2.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            TabulaError::Parse { .. } | TabulaError::InvalidMetadata { .. } => 3,
+            TabulaError::NotFound(_) => 4,
+            TabulaError::Validation(_) => 5,
+            TabulaError::Io { .. } => 6,
+            TabulaError::Config(_) => 7,
+        }
+    }
+
+    /// The same error at another place, for errors that have one.
+    pub fn relocate(self, relocate: impl FnOnce(Location) -> Location) -> Self {
+        match self {
+            TabulaError::Parse { location, message } => TabulaError::Parse {
+                location: Box::new(relocate(*location)),
+                message,
+            },
+            TabulaError::InvalidMetadata {
+                location,
+                key,
+                expected,
+                found,
+            } => TabulaError::InvalidMetadata {
+                location: Box::new(relocate(*location)),
+                key,
+                expected,
+                found,
+            },
+            other => other,
+        }
+    }
+}
+
+impl Error for TabulaError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TabulaError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for TabulaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TabulaError::Parse { location, message } => {
+                write!(f, "Could not parse the ledger\n{}\n{}", location, message)
+            }
+            TabulaError::InvalidMetadata {
+                location,
+                key,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Expected {} to be {}, found {}\n{}",
+                key, expected, found, location
+            ),
+            TabulaError::NotFound(what) => write!(f, "{} not found", what),
+            TabulaError::Validation(message) | TabulaError::Config(message) => {
+                write!(f, "{}", message)
+            }
+            TabulaError::Io { path, source } => {
+                write!(f, "Could not read {}: {}", path.display(), source)
+            }
+        }
+    }
+}
+
+/// The exit code for any error a command returns.
+pub fn exit_code(err: &(dyn Error + 'static)) -> u8 {
+    match err.downcast_ref::<TabulaError>() {
+        Some(err) => err.exit_code(),
+        None => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_invalid_metadata_shows_the_ledger_lines() {
+        let location = Location {
+            file: Some(PathBuf::from("books.beancount")),
+            line: Some(13),
+            snippet: vec![
+                (11, "2023-01-10 ! \"Invoice #1\"".to_string()),
+                (13, "  due: \"soon\"".to_string()),
+            ],
+        };
+        let err = InvalidValue::new("a date", "\"soon\"")
+            .for_key("due")
+            .relocate(|_| location.clone());
+
+        assert_eq!(3, err.exit_code());
+        assert_eq!(
+            "Expected due to be a date, found \"soon\"
+  --> books.beancount:13
+   |
+11 | 2023-01-10 ! \"Invoice #1\"
+   | ...
+13 |   due: \"soon\"
+   |",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn test_exit_code_of_other_errors_is_one() {
+        let err: Box<dyn Error> = "Something else".into();
+        assert_eq!(1, exit_code(err.as_ref()));
+        let err: Box<dyn Error> = Box::new(TabulaError::NotFound("Invoice 2023-009".to_string()));
+        assert_eq!(4, exit_code(err.as_ref()));
+        assert_eq!("Invoice 2023-009 not found", err.to_string());
+    }
+}
@@ -0,0 +1,11 @@
+//! Invoices as an event-sourced aggregate. Every change is a command that the aggregate
+//! accepts or refuses according to the lifecycle rules, and the resulting events are kept
+//! in an event store and projected into the ledger as beancount entries.
+
+pub mod aggregate;
+pub mod command;
+pub mod errors;
+pub mod event;
+pub mod queries;
+pub mod services;
+pub mod store;
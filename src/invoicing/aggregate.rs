@@ -0,0 +1,471 @@
+use async_trait::async_trait;
+use chrono::{Duration, NaiveDate};
+use cqrs_es::Aggregate;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::domain::{
+    invoice::{Date, Invoice, InvoiceNumber, InvoiceState, Revenue},
+    money::Money,
+};
+
+use super::{
+    command::InvoiceCommand, errors::InvoiceError, event::InvoiceEvent, services::InvoiceServices,
+};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InvoiceStatus {
+    /// Not drafted yet
+    #[default]
+    New,
+    Draft,
+    Issued,
+    Paid,
+    Credited,
+    Voided,
+}
+
+/// An invoice as the sum of its events.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct InvoiceAggregate {
+    pub status: InvoiceStatus,
+    pub number: String,
+    pub customer: Option<String>,
+    pub billing_email: Option<String>,
+    pub narration: String,
+    pub total: Option<Money>,
+    pub account: String,
+    pub date: Option<NaiveDate>,
+    pub issued_on: Option<NaiveDate>,
+    pub due_date: Option<NaiveDate>,
+    pub sent_on: Option<NaiveDate>,
+    pub paid: Decimal,
+    pub credit_note_number: Option<String>,
+    pub credited_on: Option<NaiveDate>,
+}
+
+impl InvoiceAggregate {
+    fn total(&self) -> Result<&Money, InvoiceError> {
+        self.total
+            .as_ref()
+            .ok_or_else(|| InvoiceError::from("The invoice has not been drafted"))
+    }
+
+    /// Refuses a command unless the invoice has been issued and is still open or paid.
+    fn ensure_issued(&self, action: &str) -> Result<(), InvoiceError> {
+        match self.status {
+            InvoiceStatus::Issued | InvoiceStatus::Paid => Ok(()),
+            InvoiceStatus::New => Err(format!("Cannot {} an invoice that does not exist", action)),
+            InvoiceStatus::Draft => Err(format!("Cannot {} a draft, issue it first", action)),
+            InvoiceStatus::Credited => Err(format!("Cannot {} a credited invoice", action)),
+            InvoiceStatus::Voided => Err(format!("Cannot {} a voided invoice", action)),
+        }
+        .map_err(InvoiceError::from)
+    }
+
+    /// The invoice as it is in the ledger: a draft until it is issued, and gone once it is
+    /// voided.
+    pub fn invoice(&self) -> Option<Invoice> {
+        let state = match self.status {
+            InvoiceStatus::New | InvoiceStatus::Voided => return None,
+            InvoiceStatus::Draft => InvoiceState::Draft,
+            _ => InvoiceState::Issued,
+        };
+        let (total, date) = (self.total.as_ref()?, self.date?);
+        Some(Invoice {
+            date: Date(date),
+            due_date: self.due_date.map(Date),
+            narration: self.narration.clone(),
+            number: InvoiceNumber(self.number.clone()),
+            total: total.to_string(),
+            customer: self.customer.clone(),
+            revenue: vec![Revenue {
+                account: self.account.clone(),
+                amount: total.clone(),
+            }],
+            billing_email: self.billing_email.clone(),
+            state,
+            issued_on: self.issued_on.map(Date),
+            ..Invoice::default()
+        })
+    }
+
+    /// The credit note that cancels this invoice, once it has been credited.
+    pub fn credit_note(&self) -> Option<Invoice> {
+        let invoice = self.invoice()?;
+        let number = self.credit_note_number.clone()?;
+        let total = self.total.as_ref()?;
+        let credited = Money::new(-total.amount, &total.currency);
+        Some(Invoice {
+            date: Date(self.credited_on?),
+            narration: format!("Credit note for invoice {}", self.number),
+            number: InvoiceNumber(number),
+            total: credited.to_string(),
+            due_date: None,
+            revenue: vec![Revenue {
+                account: self.account.clone(),
+                amount: credited,
+            }],
+            credits_invoice: Some(invoice.number.clone()),
+            issued_on: None,
+            ..invoice
+        })
+    }
+}
+
+#[async_trait]
+impl Aggregate for InvoiceAggregate {
+    type Command = InvoiceCommand;
+    type Event = InvoiceEvent;
+    type Error = InvoiceError;
+    type Services = InvoiceServices;
+
+    fn aggregate_type() -> String {
+        "Invoice".to_string()
+    }
+
+    async fn handle(
+        &self,
+        command: Self::Command,
+        services: &Self::Services,
+    ) -> Result<Vec<Self::Event>, Self::Error> {
+        match command {
+            InvoiceCommand::Draft {
+                number,
+                date,
+                customer,
+                billing_email,
+                narration,
+                total,
+                account,
+            } => {
+                if self.status != InvoiceStatus::New {
+                    return Err(format!("Invoice {} already exists", self.number).into());
+                }
+                if total.amount <= Decimal::ZERO {
+                    return Err("The total of an invoice must be positive, credit an \
+                                invoice to book a negative amount"
+                        .into());
+                }
+                Ok(vec![InvoiceEvent::InvoiceDrafted {
+                    number,
+                    date,
+                    customer,
+                    billing_email,
+                    narration,
+                    total,
+                    account,
+                }])
+            }
+            InvoiceCommand::Issue { date, due_date } => {
+                match self.status {
+                    InvoiceStatus::Draft => {}
+                    InvoiceStatus::New => return Err("There is no draft to issue".into()),
+                    _ => return Err("Only drafts can be issued".into()),
+                }
+                let due_date =
+                    due_date.unwrap_or(date + Duration::days(services.payment_terms_days));
+                if self.date.is_some_and(|drafted| date < drafted) {
+                    return Err("An invoice cannot be issued before its date".into());
+                }
+                if due_date < date {
+                    return Err("The due date cannot be before the day it is issued".into());
+                }
+                Ok(vec![InvoiceEvent::InvoiceIssued { date, due_date }])
+            }
+            InvoiceCommand::Send { date, to } => {
+                self.ensure_issued("send")?;
+                Ok(vec![InvoiceEvent::InvoiceSent { date, to }])
+            }
+            InvoiceCommand::RegisterPayment {
+                date,
+                amount,
+                account,
+            } => {
+                self.ensure_issued("pay")?;
+                let total = self.total()?;
+                if amount.currency != total.currency {
+                    return Err(format!(
+                        "The invoice is in {}, the payment in {}",
+                        total.currency, amount.currency
+                    )
+                    .into());
+                }
+                if amount.amount <= Decimal::ZERO {
+                    return Err("A payment must be a positive amount".into());
+                }
+                let outstanding = total.amount - self.paid;
+                if amount.amount > outstanding {
+                    return Err(format!(
+                        "The payment of {} is more than the {} outstanding",
+                        amount,
+                        Money::new(outstanding, &total.currency)
+                    )
+                    .into());
+                }
+                Ok(vec![InvoiceEvent::PaymentRegistered {
+                    date,
+                    amount,
+                    account,
+                }])
+            }
+            InvoiceCommand::Credit {
+                credit_note_number,
+                date,
+            } => {
+                self.ensure_issued("credit")?;
+                if credit_note_number == self.number {
+                    return Err("A credit note needs a number of its own".into());
+                }
+                Ok(vec![InvoiceEvent::InvoiceCredited {
+                    credit_note_number,
+                    date,
+                    amount: self.total()?.clone(),
+                }])
+            }
+            InvoiceCommand::Void { date, reason } => match self.status {
+                InvoiceStatus::Draft => Ok(vec![InvoiceEvent::InvoiceVoided { date, reason }]),
+                InvoiceStatus::New => Err("There is no draft to void".into()),
+                InvoiceStatus::Voided => Err("The invoice has already been voided".into()),
+                _ => Err("Issued invoices cannot be voided, credit them instead".into()),
+            },
+        }
+    }
+
+    fn apply(&mut self, event: Self::Event) {
+        match event {
+            InvoiceEvent::InvoiceDrafted {
+                number,
+                date,
+                customer,
+                billing_email,
+                narration,
+                total,
+                account,
+            } => {
+                self.status = InvoiceStatus::Draft;
+                self.number = number;
+                self.date = Some(date);
+                self.customer = customer;
+                self.billing_email = billing_email;
+                self.narration = narration;
+                self.total = Some(total);
+                self.account = account;
+            }
+            InvoiceEvent::InvoiceIssued { date, due_date } => {
+                self.status = InvoiceStatus::Issued;
+                self.issued_on = Some(date);
+                self.due_date = Some(due_date);
+            }
+            InvoiceEvent::InvoiceSent { date, .. } => self.sent_on = Some(date),
+            InvoiceEvent::PaymentRegistered { amount, .. } => {
+                self.paid += amount.amount;
+                if self
+                    .total
+                    .as_ref()
+                    .is_some_and(|total| self.paid >= total.amount)
+                {
+                    self.status = InvoiceStatus::Paid;
+                }
+            }
+            InvoiceEvent::InvoiceCredited {
+                credit_note_number,
+                date,
+                ..
+            } => {
+                self.status = InvoiceStatus::Credited;
+                self.credit_note_number = Some(credit_note_number);
+                self.credited_on = Some(date);
+            }
+            InvoiceEvent::InvoiceVoided { .. } => self.status = InvoiceStatus::Voided,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn day(d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2023, 6, d).unwrap()
+    }
+
+    fn drafted() -> InvoiceEvent {
+        InvoiceEvent::InvoiceDrafted {
+            number: "2023-010".to_string(),
+            date: day(1),
+            customer: Some("Acme".to_string()),
+            billing_email: None,
+            narration: "Design".to_string(),
+            total: Money::new(1000.into(), "EUR"),
+            account: "Income:Work".to_string(),
+        }
+    }
+
+    fn aggregate(events: Vec<InvoiceEvent>) -> InvoiceAggregate {
+        let mut aggregate = InvoiceAggregate::default();
+        for event in events {
+            aggregate.apply(event);
+        }
+        aggregate
+    }
+
+    fn payment(amount: i64) -> InvoiceCommand {
+        InvoiceCommand::RegisterPayment {
+            date: day(20),
+            amount: Money::new(amount.into(), "EUR"),
+            account: "Assets:Bank".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_issue_applies_payment_terms() {
+        let events = aggregate(vec![drafted()])
+            .handle(
+                InvoiceCommand::Issue {
+                    date: day(1),
+                    due_date: None,
+                },
+                &InvoiceServices::default(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            vec![InvoiceEvent::InvoiceIssued {
+                date: day(1),
+                due_date: NaiveDate::from_ymd_opt(2023, 7, 1).unwrap(),
+            }],
+            events
+        );
+    }
+
+    #[tokio::test]
+    async fn test_drafts_are_issued_on_or_after_their_date() {
+        let draft = aggregate(vec![drafted()]);
+        let early = draft
+            .handle(
+                InvoiceCommand::Issue {
+                    date: NaiveDate::from_ymd_opt(2023, 5, 31).unwrap(),
+                    due_date: None,
+                },
+                &InvoiceServices::default(),
+            )
+            .await;
+        let issued = aggregate(vec![
+            drafted(),
+            InvoiceEvent::InvoiceIssued {
+                date: day(2),
+                due_date: day(30),
+            },
+        ]);
+
+        assert_eq!(
+            Err(InvoiceError::from(
+                "An invoice cannot be issued before its date"
+            )),
+            early
+        );
+        assert_eq!(InvoiceState::Draft, draft.invoice().unwrap().state);
+        let invoice = issued.invoice().unwrap();
+        assert_eq!(InvoiceState::Issued, invoice.state);
+        assert_eq!(Date(day(1)), invoice.date);
+        assert_eq!(Some(Date(day(2))), invoice.issued_on);
+    }
+
+    #[tokio::test]
+    async fn test_payments_settle_the_invoice() {
+        let services = InvoiceServices::default();
+        let issued = InvoiceEvent::InvoiceIssued {
+            date: day(1),
+            due_date: day(30),
+        };
+        let mut invoice = aggregate(vec![drafted(), issued]);
+
+        for event in invoice.handle(payment(400), &services).await.unwrap() {
+            invoice.apply(event);
+        }
+        assert_eq!(InvoiceStatus::Issued, invoice.status);
+        let overpaid = invoice.handle(payment(700), &services).await;
+        for event in invoice.handle(payment(600), &services).await.unwrap() {
+            invoice.apply(event);
+        }
+
+        assert_eq!(
+            Err(InvoiceError::from(
+                "The payment of 700 EUR is more than the 600 EUR outstanding"
+            )),
+            overpaid
+        );
+        assert_eq!(InvoiceStatus::Paid, invoice.status);
+    }
+
+    #[tokio::test]
+    async fn test_voided_invoices_cannot_be_paid() {
+        let voided = aggregate(vec![
+            drafted(),
+            InvoiceEvent::InvoiceVoided {
+                date: day(2),
+                reason: "Duplicate".to_string(),
+            },
+        ]);
+
+        let result = voided
+            .handle(payment(1000), &InvoiceServices::default())
+            .await;
+
+        assert_eq!(
+            Err(InvoiceError::from("Cannot pay a voided invoice")),
+            result
+        );
+    }
+
+    #[tokio::test]
+    async fn test_issued_invoices_are_credited_not_voided() {
+        let services = InvoiceServices::default();
+        let issued = aggregate(vec![
+            drafted(),
+            InvoiceEvent::InvoiceIssued {
+                date: day(1),
+                due_date: day(30),
+            },
+        ]);
+
+        let void = issued
+            .handle(
+                InvoiceCommand::Void {
+                    date: day(2),
+                    reason: "Wrong amount".to_string(),
+                },
+                &services,
+            )
+            .await;
+        let credit = issued
+            .handle(
+                InvoiceCommand::Credit {
+                    credit_note_number: "2023-011".to_string(),
+                    date: day(2),
+                },
+                &services,
+            )
+            .await;
+
+        assert_eq!(
+            Err(InvoiceError::from(
+                "Issued invoices cannot be voided, credit them instead"
+            )),
+            void
+        );
+        assert_eq!(
+            Ok(vec![InvoiceEvent::InvoiceCredited {
+                credit_note_number: "2023-011".to_string(),
+                date: day(2),
+                amount: Money::new(1000.into(), "EUR"),
+            }]),
+            credit
+        );
+    }
+}
@@ -0,0 +1,47 @@
+use chrono::NaiveDate;
+use serde::Deserialize;
+
+use crate::domain::money::Money;
+
+/// A change to an invoice, addressed by its number as the aggregate id.
+#[derive(Debug, Deserialize)]
+pub enum InvoiceCommand {
+    /// Starts an invoice as a draft in the ledger, marked `!`, that can still be voided
+    Draft {
+        number: String,
+        /// The invoice date
+        date: NaiveDate,
+        customer: Option<String>,
+        billing_email: Option<String>,
+        narration: String,
+        total: Money,
+        /// The income account the total is booked on
+        account: String,
+    },
+    /// Marks the draft `*` in the ledger, with the day it is issued as `date`. Without a due
+    /// date, the payment terms apply from that day
+    Issue {
+        date: NaiveDate,
+        due_date: Option<NaiveDate>,
+    },
+    Send {
+        date: NaiveDate,
+        to: String,
+    },
+    RegisterPayment {
+        date: NaiveDate,
+        amount: Money,
+        /// The bank account the payment was received on
+        account: String,
+    },
+    /// Cancels an issued invoice with a credit note for its full total
+    Credit {
+        credit_note_number: String,
+        date: NaiveDate,
+    },
+    /// Discards a draft and removes it from the ledger. Issued invoices are credited instead
+    Void {
+        date: NaiveDate,
+        reason: String,
+    },
+}
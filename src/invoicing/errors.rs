@@ -0,0 +1,26 @@
+use core::fmt;
+use std::error::Error;
+
+/// A command that the lifecycle rules do not allow, such as paying a voided invoice.
+#[derive(Debug, PartialEq)]
+pub struct InvoiceError(String);
+
+impl Error for InvoiceError {}
+
+impl fmt::Display for InvoiceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<&str> for InvoiceError {
+    fn from(message: &str) -> Self {
+        Self(message.to_string())
+    }
+}
+
+impl From<String> for InvoiceError {
+    fn from(message: String) -> Self {
+        Self(message)
+    }
+}
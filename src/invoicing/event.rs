@@ -0,0 +1,58 @@
+use chrono::NaiveDate;
+use cqrs_es::DomainEvent;
+use serde::{Deserialize, Serialize};
+
+use crate::domain::money::Money;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum InvoiceEvent {
+    InvoiceDrafted {
+        number: String,
+        date: NaiveDate,
+        customer: Option<String>,
+        billing_email: Option<String>,
+        narration: String,
+        total: Money,
+        account: String,
+    },
+    InvoiceIssued {
+        date: NaiveDate,
+        due_date: NaiveDate,
+    },
+    InvoiceSent {
+        date: NaiveDate,
+        to: String,
+    },
+    PaymentRegistered {
+        date: NaiveDate,
+        amount: Money,
+        account: String,
+    },
+    InvoiceCredited {
+        credit_note_number: String,
+        date: NaiveDate,
+        amount: Money,
+    },
+    InvoiceVoided {
+        date: NaiveDate,
+        reason: String,
+    },
+}
+
+impl DomainEvent for InvoiceEvent {
+    fn event_type(&self) -> String {
+        let event_type = match self {
+            InvoiceEvent::InvoiceDrafted { .. } => "InvoiceDrafted",
+            InvoiceEvent::InvoiceIssued { .. } => "InvoiceIssued",
+            InvoiceEvent::InvoiceSent { .. } => "InvoiceSent",
+            InvoiceEvent::PaymentRegistered { .. } => "PaymentRegistered",
+            InvoiceEvent::InvoiceCredited { .. } => "InvoiceCredited",
+            InvoiceEvent::InvoiceVoided { .. } => "InvoiceVoided",
+        };
+        event_type.to_string()
+    }
+
+    fn event_version(&self) -> String {
+        "1.0".to_string()
+    }
+}
@@ -0,0 +1,207 @@
+use std::{
+    error::Error,
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use cqrs_es::{AggregateContext, EventEnvelope, EventStore, Query};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    adapters::{
+        cli::entries::{invoice_entry, payment_entry, sent_entry},
+        ledger_storage::{FileLedgerStorage, LedgerStorage},
+    },
+    domain::{
+        invoice::{Date, Invoice, InvoiceNumber, InvoiceState},
+        payment::Payment,
+    },
+};
+
+use super::{aggregate::InvoiceAggregate, event::InvoiceEvent, store::FileEventStore};
+
+/// An event that is committed, but not written to the ledger yet.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct PendingEvent {
+    aggregate_id: String,
+    sequence: usize,
+}
+
+/// Writes invoice events to the ledger like the `invoices` commands do: a draft marked `!`,
+/// which is marked `*` when it is issued and removed when it is voided, its payments, when it
+/// was sent and the credit note that cancels it.
+///
+/// Events are committed before they are projected, so events that cannot be written to the
+/// ledger are kept in the `pending` file, and written by `replay` before the next command.
+pub struct LedgerProjection {
+    ledger: PathBuf,
+    store: FileEventStore<InvoiceAggregate>,
+    pending: PathBuf,
+    /// Why the last dispatch could not write to the ledger
+    failure: Arc<Mutex<Option<String>>>,
+}
+
+impl LedgerProjection {
+    pub fn new(ledger: PathBuf, store: FileEventStore<InvoiceAggregate>, pending: &Path) -> Self {
+        Self {
+            ledger,
+            store,
+            pending: pending.to_path_buf(),
+            failure: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Where the projection reports a dispatch that could not write to the ledger, as the
+    /// framework that dispatches does not return errors.
+    pub fn failure(&self) -> Arc<Mutex<Option<String>>> {
+        self.failure.clone()
+    }
+
+    /// Writes the pending events to the ledger, and returns how many there were.
+    pub async fn replay(&self) -> Result<usize, Box<dyn Error>> {
+        let pending = match fs::read_to_string(&self.pending) {
+            Ok(content) => content
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(serde_json::from_str)
+                .collect::<Result<Vec<PendingEvent>, _>>()?,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(0),
+            Err(err) => return Err(err.into()),
+        };
+
+        for (i, event) in pending.iter().enumerate() {
+            let envelope = self
+                .store
+                .load_events(&event.aggregate_id)
+                .await?
+                .into_iter()
+                .find(|envelope| envelope.sequence == event.sequence)
+                .ok_or_else(|| {
+                    format!(
+                        "Event {} of invoice {} is pending, but was not recorded",
+                        event.sequence, event.aggregate_id
+                    )
+                })?;
+            if let Err(err) = self.project(&event.aggregate_id, &envelope).await {
+                // Only what is still missing from the ledger stays pending
+                let remaining: Vec<String> = pending[i..]
+                    .iter()
+                    .map(serde_json::to_string)
+                    .collect::<Result<_, _>>()?;
+                fs::write(&self.pending, remaining.join("\n") + "\n")?;
+                return Err(err);
+            }
+        }
+        fs::remove_file(&self.pending)?;
+
+        Ok(pending.len())
+    }
+
+    fn keep_pending(
+        &self,
+        events: &[EventEnvelope<InvoiceAggregate>],
+    ) -> Result<(), Box<dyn Error>> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.pending)?;
+        for event in events {
+            let pending = PendingEvent {
+                aggregate_id: event.aggregate_id.clone(),
+                sequence: event.sequence,
+            };
+            writeln!(file, "{}", serde_json::to_string(&pending)?)?;
+        }
+        Ok(())
+    }
+
+    /// Writes an event to the ledger, one at a time so that an event that fails leaves those
+    /// before it written.
+    async fn project(
+        &self,
+        aggregate_id: &str,
+        event: &EventEnvelope<InvoiceAggregate>,
+    ) -> Result<(), Box<dyn Error>> {
+        // The events have been committed, so the aggregate includes them
+        let context = self.store.load_aggregate(aggregate_id).await?;
+        let invoice = context.aggregate();
+        let number = InvoiceNumber(invoice.number.clone());
+        let ledger = FileLedgerStorage::open(&self.ledger)?;
+
+        match &event.payload {
+            InvoiceEvent::InvoiceDrafted { .. } => {
+                let draft = Invoice {
+                    state: InvoiceState::Draft,
+                    due_date: None,
+                    issued_on: None,
+                    ..invoice
+                        .invoice()
+                        .ok_or("The invoice has not been drafted")?
+                };
+                ledger.append(&invoice_entry(&draft))
+            }
+            InvoiceEvent::InvoiceIssued { date, due_date } => ledger.issue_invoice(
+                &number,
+                &[
+                    ("issued", Date(*date).to_string()),
+                    ("due", Date(*due_date).to_string()),
+                ],
+            ),
+            InvoiceEvent::InvoiceSent { date, to } => {
+                ledger.append(&sent_entry(&number, &Date(*date), to))
+            }
+            InvoiceEvent::PaymentRegistered {
+                date,
+                amount,
+                account,
+            } => {
+                let payment = Payment {
+                    date: Date(*date),
+                    amount: amount.clone(),
+                };
+                ledger.append(&payment_entry(&number, &payment, account))
+            }
+            InvoiceEvent::InvoiceCredited { .. } => {
+                let credit_note = invoice.credit_note().ok_or("There is no credit note")?;
+                ledger.append(&invoice_entry(&credit_note))
+            }
+            InvoiceEvent::InvoiceVoided { .. } => ledger.remove_draft(&number),
+        }
+    }
+}
+
+#[async_trait]
+impl Query<InvoiceAggregate> for LedgerProjection {
+    async fn dispatch(&self, aggregate_id: &str, events: &[EventEnvelope<InvoiceAggregate>]) {
+        // Kept as text, as the dispatch must stay `Send` across the writes
+        let mut failed = None;
+        for (i, event) in events.iter().enumerate() {
+            if let Err(err) = self.project(aggregate_id, event).await {
+                failed = Some((err.to_string(), &events[i..]));
+                break;
+            }
+        }
+        let Some((err, events)) = failed else {
+            return;
+        };
+
+        let mut message = format!(
+            "The events of invoice {} are recorded, but could not be written to {}: {}",
+            aggregate_id,
+            self.ledger.display(),
+            err
+        );
+        match self.keep_pending(events) {
+            Ok(()) => message.push_str(". They are written before the next lifecycle command"),
+            Err(err) => message.push_str(&format!(
+                ". Keeping them in {} failed too: {}",
+                self.pending.display(),
+                err
+            )),
+        }
+        *self.failure.lock().unwrap() = Some(message);
+    }
+}
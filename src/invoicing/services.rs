@@ -0,0 +1,13 @@
+/// What the invoice aggregate needs to know from outside the invoice itself.
+pub struct InvoiceServices {
+    /// Days after the invoice date that an invoice issued without a due date is due
+    pub payment_terms_days: i64,
+}
+
+impl Default for InvoiceServices {
+    fn default() -> Self {
+        Self {
+            payment_terms_days: 30,
+        }
+    }
+}
@@ -0,0 +1,223 @@
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    marker::PhantomData,
+    path::{Path, PathBuf},
+};
+
+use async_trait::async_trait;
+use cqrs_es::{
+    Aggregate, AggregateContext, AggregateError, DomainEvent, EventEnvelope, EventStore,
+};
+use serde::{Deserialize, Serialize};
+
+/// An event as a line in the event store file.
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredEvent {
+    aggregate_type: String,
+    aggregate_id: String,
+    sequence: usize,
+    event_type: String,
+    event_version: String,
+    payload: serde_json::Value,
+    #[serde(default)]
+    metadata: HashMap<String, String>,
+}
+
+/// Keeps the events of all aggregates of a type in a file, one JSON object per line. Lines are
+/// only ever added, so the file is the full history of every invoice.
+pub struct FileEventStore<A> {
+    path: PathBuf,
+    aggregate: PhantomData<fn() -> A>,
+}
+
+impl<A> FileEventStore<A> {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            aggregate: PhantomData,
+        }
+    }
+}
+
+impl<A> Clone for FileEventStore<A> {
+    fn clone(&self) -> Self {
+        Self::new(&self.path)
+    }
+}
+
+pub struct FileAggregateContext<A> {
+    aggregate_id: String,
+    aggregate: A,
+    /// The sequence of the last event, which the next commit must follow on
+    current_sequence: usize,
+}
+
+impl<A: Aggregate> AggregateContext<A> for FileAggregateContext<A> {
+    fn aggregate(&self) -> &A {
+        &self.aggregate
+    }
+}
+
+impl<A: Aggregate> FileEventStore<A> {
+    fn stored_events(
+        &self,
+        aggregate_id: &str,
+    ) -> Result<Vec<StoredEvent>, AggregateError<A::Error>> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(AggregateError::DatabaseConnectionError(Box::new(err))),
+        };
+
+        let mut events = vec![];
+        for line in content.lines().filter(|line| !line.trim().is_empty()) {
+            let event: StoredEvent = serde_json::from_str(line)
+                .map_err(|err| AggregateError::DeserializationError(Box::new(err)))?;
+            if event.aggregate_type == A::aggregate_type() && event.aggregate_id == aggregate_id {
+                events.push(event);
+            }
+        }
+        Ok(events)
+    }
+}
+
+#[async_trait]
+impl<A: Aggregate> EventStore<A> for FileEventStore<A> {
+    type AC = FileAggregateContext<A>;
+
+    async fn load_events(
+        &self,
+        aggregate_id: &str,
+    ) -> Result<Vec<EventEnvelope<A>>, AggregateError<A::Error>> {
+        self.stored_events(aggregate_id)?
+            .into_iter()
+            .map(|event| {
+                Ok(EventEnvelope {
+                    aggregate_id: event.aggregate_id,
+                    sequence: event.sequence,
+                    payload: serde_json::from_value(event.payload)
+                        .map_err(|err| AggregateError::DeserializationError(Box::new(err)))?,
+                    metadata: event.metadata,
+                })
+            })
+            .collect()
+    }
+
+    async fn load_aggregate(
+        &self,
+        aggregate_id: &str,
+    ) -> Result<Self::AC, AggregateError<A::Error>> {
+        let mut aggregate = A::default();
+        let mut current_sequence = 0;
+        for event in self.load_events(aggregate_id).await? {
+            current_sequence = event.sequence;
+            aggregate.apply(event.payload);
+        }
+
+        Ok(FileAggregateContext {
+            aggregate_id: aggregate_id.to_string(),
+            aggregate,
+            current_sequence,
+        })
+    }
+
+    async fn commit(
+        &self,
+        events: Vec<A::Event>,
+        context: Self::AC,
+        metadata: HashMap<String, String>,
+    ) -> Result<Vec<EventEnvelope<A>>, AggregateError<A::Error>> {
+        // Another process may have changed the aggregate since it was loaded
+        let last = self
+            .stored_events(&context.aggregate_id)?
+            .last()
+            .map_or(0, |event| event.sequence);
+        if last != context.current_sequence {
+            return Err(AggregateError::AggregateConflict);
+        }
+
+        let mut lines = String::new();
+        let mut envelopes = vec![];
+        for (sequence, event) in (context.current_sequence + 1..).zip(events) {
+            let stored = StoredEvent {
+                aggregate_type: A::aggregate_type(),
+                aggregate_id: context.aggregate_id.clone(),
+                sequence,
+                event_type: event.event_type(),
+                event_version: event.event_version(),
+                payload: serde_json::to_value(&event)
+                    .map_err(|err| AggregateError::UnexpectedError(Box::new(err)))?,
+                metadata: metadata.clone(),
+            };
+            lines.push_str(
+                &serde_json::to_string(&stored)
+                    .map_err(|err| AggregateError::UnexpectedError(Box::new(err)))?,
+            );
+            lines.push('\n');
+            envelopes.push(EventEnvelope {
+                aggregate_id: context.aggregate_id.clone(),
+                sequence,
+                payload: event,
+                metadata: metadata.clone(),
+            });
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|err| AggregateError::DatabaseConnectionError(Box::new(err)))?;
+        file.write_all(lines.as_bytes())
+            .map_err(|err| AggregateError::DatabaseConnectionError(Box::new(err)))?;
+
+        Ok(envelopes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        domain::money::Money,
+        invoicing::{aggregate::InvoiceAggregate, event::InvoiceEvent},
+    };
+
+    use super::*;
+
+    fn drafted() -> InvoiceEvent {
+        InvoiceEvent::InvoiceDrafted {
+            number: "2023-010".to_string(),
+            date: NaiveDate::from_ymd_opt(2023, 6, 1).unwrap(),
+            customer: None,
+            billing_email: None,
+            narration: "Design".to_string(),
+            total: Money::new(1000.into(), "EUR"),
+            account: "Income:Work".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_commits_follow_on_the_loaded_sequence() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let store = FileEventStore::<InvoiceAggregate>::new(&dir.path().join("events.jsonl"));
+
+        let first = store.load_aggregate("2023-010").await.unwrap();
+        let stale = store.load_aggregate("2023-010").await.unwrap();
+        let committed = store
+            .commit(vec![drafted()], first, HashMap::new())
+            .await
+            .unwrap();
+        let conflict = store.commit(vec![drafted()], stale, HashMap::new()).await;
+
+        assert_eq!(1, committed[0].sequence);
+        assert!(matches!(conflict, Err(AggregateError::AggregateConflict)));
+        let reloaded = store.load_aggregate("2023-010").await.unwrap();
+        assert_eq!("2023-010", reloaded.aggregate().number);
+        assert_eq!(1, store.load_events("2023-010").await.unwrap().len());
+        assert!(store.load_events("2023-011").await.unwrap().is_empty());
+    }
+}
@@ -1,4 +1,8 @@
+mod account;
 mod adapters;
 mod commands;
+mod daemon;
 mod domain;
+mod error;
+mod invoicing;
 mod services;
@@ -1,13 +1,22 @@
+mod account;
 mod adapters;
 mod commands;
+mod daemon;
 mod domain;
+mod error;
+mod invoicing;
 mod services;
 
+use std::process::ExitCode;
+
 use adapters::{cli::CliAdapter, InputAdapter};
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+fn main() -> ExitCode {
     let mut cli = CliAdapter::default();
-    cli.run()?;
+    if let Err(err) = cli.run() {
+        eprintln!("Error: {}", err);
+        return ExitCode::from(error::exit_code(err.as_ref()));
+    }
     println!("{}", cli.get_response());
-    Ok(())
+    ExitCode::from(cli.exit_code())
 }
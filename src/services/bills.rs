@@ -0,0 +1,309 @@
+use core::fmt;
+use std::error::Error;
+
+use rust_decimal::Decimal;
+
+use crate::domain::{
+    bill::{AccountRule, Bill, BillEntry, BillPosting},
+    e_invoice::EInvoice,
+    invoice::Date,
+    money::Money,
+};
+
+pub struct BillImportOptions {
+    /// The first rule that matches a line gives its account
+    pub accounts: Vec<AccountRule>,
+    /// The account of lines no rule matches
+    pub default_account: String,
+    /// Where the VAT on bills is booked, to be deducted from the VAT on sales
+    pub vat_account: String,
+}
+
+impl Default for BillImportOptions {
+    fn default() -> Self {
+        Self {
+            accounts: vec![],
+            default_account: "Expenses:Unsorted".to_string(),
+            vat_account: "Assets:VAT:Input".to_string(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum BillImportError {
+    NoSupplier(String),
+    /// A bill with the same number from the same supplier is already in the ledger
+    Duplicate {
+        supplier: String,
+        number: String,
+    },
+    /// The lines plus VAT differ from the total of the bill
+    TotalMismatch {
+        number: String,
+        total: Money,
+        computed: Money,
+    },
+}
+
+impl Error for BillImportError {}
+
+impl fmt::Display for BillImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BillImportError::NoSupplier(number) => {
+                write!(f, "Bill {} does not name its supplier", number)
+            }
+            BillImportError::Duplicate { supplier, number } => write!(
+                f,
+                "Bill {} from {} is already in the ledger",
+                number, supplier
+            ),
+            BillImportError::TotalMismatch {
+                number,
+                total,
+                computed,
+            } => write!(
+                f,
+                "Bill {} totals {}, but its lines plus VAT come to {}. Allowances and charges on \
+                 the whole bill are not supported, book it by hand",
+                number, total, computed
+            ),
+        }
+    }
+}
+
+/// The supplier as an account name component: `Hosting B.V.` becomes `HostingBV` and
+/// `Müller & Söhne` becomes `MüllerSöhne`, as Beancount allows letters of any script.
+pub fn supplier_component(supplier: &str) -> String {
+    supplier
+        .split_whitespace()
+        .flat_map(|word| {
+            let mut chars = word.chars().filter(|c| c.is_alphanumeric());
+            chars
+                .next()
+                .into_iter()
+                .flat_map(char::to_uppercase)
+                .chain(chars)
+        })
+        .collect()
+}
+
+fn account<'o>(options: &'o BillImportOptions, supplier: &str, item: &str) -> &'o str {
+    options
+        .accounts
+        .iter()
+        .find(|rule| rule.matches(supplier, item))
+        .map_or(&options.default_account, |rule| &rule.account)
+}
+
+/// Books a bill received as `e_invoice` on the accounts the options give, refusing bills that
+/// are among the `existing` bills already.
+pub fn book_bill(
+    e_invoice: &EInvoice,
+    existing: &[Bill],
+    options: &BillImportOptions,
+) -> Result<BillEntry, BillImportError> {
+    let number = e_invoice.number.clone();
+    let supplier = e_invoice
+        .seller
+        .name
+        .clone()
+        .ok_or_else(|| BillImportError::NoSupplier(number.clone()))?;
+    let duplicate = existing.iter().any(|bill| {
+        bill.number == number
+            && bill
+                .supplier
+                .as_ref()
+                .is_some_and(|name| name.eq_ignore_ascii_case(&supplier))
+    });
+    if duplicate {
+        return Err(BillImportError::Duplicate { supplier, number });
+    }
+
+    // Credit notes are positive as well, and reduce what is owed
+    let sign = match e_invoice.credit_note {
+        true => Decimal::NEGATIVE_ONE,
+        false => Decimal::ONE,
+    };
+    let currency = e_invoice.currency.as_str();
+    let mut postings: Vec<BillPosting> = vec![];
+    let mut book =
+        |account: &str, amount: Decimal| match postings.iter_mut().find(|p| p.account == account) {
+            Some(posting) => posting.amount.amount += amount * sign,
+            None => postings.push(BillPosting {
+                account: account.to_string(),
+                amount: Money::new(amount * sign, currency),
+            }),
+        };
+    // Factur-X MINIMUM has no lines, only the total before VAT
+    if e_invoice.lines.is_empty() {
+        book(account(options, &supplier, ""), e_invoice.tax_exclusive);
+    }
+    for line in &e_invoice.lines {
+        book(account(options, &supplier, &line.name), line.net_amount);
+    }
+    if !e_invoice.tax_total.is_zero() {
+        book(&options.vat_account, e_invoice.tax_total);
+    }
+
+    let computed: Decimal = postings.iter().map(|p| p.amount.amount).sum();
+    let total = Money::new(e_invoice.tax_inclusive * sign, currency);
+    if computed != total.amount {
+        return Err(BillImportError::TotalMismatch {
+            number,
+            total,
+            computed: Money::new(computed, currency),
+        });
+    }
+
+    let payable_account = match supplier_component(&supplier) {
+        component if component.is_empty() => "Liabilities:AccountsPayable".to_string(),
+        component => format!("Liabilities:AccountsPayable:{}", component),
+    };
+    Ok(BillEntry {
+        bill: Bill {
+            date: Date(e_invoice.issue_date),
+            due_date: e_invoice.due_date.map(Date),
+            number: number.clone(),
+            supplier: Some(supplier.clone()),
+            total,
+            payments: vec![],
+        },
+        // Notes often span lines, the narration is a single line
+        narration: e_invoice
+            .note
+            .as_ref()
+            .map(|note| note.split_whitespace().collect::<Vec<_>>().join(" "))
+            .unwrap_or_else(|| format!("Bill {} from {}", number, supplier)),
+        payable_account,
+        postings,
+        document: None,
+        document_sha256: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use pretty_assertions::assert_eq;
+
+    use crate::domain::e_invoice::{EInvoiceLine, Party, PaymentMeans, TaxCategory};
+
+    use super::*;
+
+    fn line(name: &str, net_amount: i64) -> EInvoiceLine {
+        EInvoiceLine {
+            id: name.to_string(),
+            name: name.to_string(),
+            quantity: Decimal::ONE,
+            unit_code: "C62".to_string(),
+            price: net_amount.into(),
+            net_amount: net_amount.into(),
+            category: TaxCategory::Standard,
+            rate: 21.into(),
+        }
+    }
+
+    fn bill(credit_note: bool) -> EInvoice {
+        EInvoice {
+            number: "INV-9".to_string(),
+            issue_date: NaiveDate::from_ymd_opt(2023, 6, 1).unwrap(),
+            due_date: None,
+            credit_note,
+            billing_reference: None,
+            note: None,
+            currency: "EUR".to_string(),
+            buyer_reference: None,
+            seller: Party {
+                name: Some("Hosting B.V.".to_string()),
+                ..Party::default()
+            },
+            buyer: Party::default(),
+            payment: PaymentMeans::default(),
+            payment_terms: None,
+            lines: vec![
+                line("Server", 100),
+                line("Domain name", 10),
+                line("Backup", 20),
+            ],
+            taxes: vec![],
+            line_total: 130.into(),
+            tax_exclusive: 130.into(),
+            tax_total: Decimal::new(2730, 2),
+            tax_inclusive: Decimal::new(15730, 2),
+            payable: Decimal::new(15730, 2),
+        }
+    }
+
+    fn options() -> BillImportOptions {
+        BillImportOptions {
+            accounts: vec![
+                AccountRule {
+                    supplier: Some("hosting b.v.".to_string()),
+                    item: Some("domain".to_string()),
+                    account: "Expenses:Telecom".to_string(),
+                },
+                AccountRule {
+                    supplier: Some("Hosting B.V.".to_string()),
+                    item: None,
+                    account: "Expenses:Software".to_string(),
+                },
+            ],
+            ..BillImportOptions::default()
+        }
+    }
+
+    #[test]
+    fn test_lines_are_booked_on_the_accounts_of_their_rules() {
+        let entry = book_bill(&bill(false), &[], &options()).unwrap();
+        let credit = book_bill(&bill(true), &[], &options()).unwrap();
+
+        let postings: Vec<String> = entry
+            .postings
+            .iter()
+            .map(|p| format!("{} {}", p.account, p.amount))
+            .collect();
+        assert_eq!(
+            vec![
+                "Expenses:Software 120 EUR",
+                "Expenses:Telecom 10 EUR",
+                "Assets:VAT:Input 27.30 EUR"
+            ],
+            postings
+        );
+        assert_eq!(
+            "Liabilities:AccountsPayable:HostingBV",
+            entry.payable_account
+        );
+        assert_eq!("157.30 EUR", entry.bill.total.to_string());
+        assert_eq!("-157.30 EUR", credit.bill.total.to_string());
+        assert_eq!("-120 EUR", credit.postings[0].amount.to_string());
+    }
+
+    #[test]
+    fn test_supplier_component_keeps_letters_of_any_script() {
+        assert_eq!("HostingBV", supplier_component("Hosting B.V."));
+        assert_eq!("MüllerSöhne", supplier_component("müller & Söhne"));
+        assert_eq!("Østergaard", supplier_component("østergaard"));
+    }
+
+    #[test]
+    fn test_a_bill_is_imported_once() {
+        let existing = book_bill(&bill(false), &[], &options()).unwrap().bill;
+        let mut unbalanced = bill(false);
+        unbalanced.tax_inclusive = 150.into();
+
+        let again = book_bill(&bill(false), &[existing], &options()).unwrap_err();
+        let mismatch = book_bill(&unbalanced, &[], &options()).unwrap_err();
+
+        assert_eq!(
+            "Bill INV-9 from Hosting B.V. is already in the ledger",
+            again.to_string()
+        );
+        assert_eq!(
+            "Bill INV-9 totals 150 EUR, but its lines plus VAT come to 157.30 EUR. Allowances \
+             and charges on the whole bill are not supported, book it by hand",
+            mismatch.to_string()
+        );
+    }
+}
@@ -0,0 +1,204 @@
+use chrono::NaiveDate;
+use serde::Serialize;
+
+use crate::domain::{
+    expression::Expression,
+    invoice::{Date, Invoice},
+    money::Money,
+    reminder::{DunningLevel, Reminder},
+};
+
+pub struct DunningOptions {
+    /// The day the reminders are sent
+    pub date: NaiveDate,
+    /// Days after the due date to send the first reminder
+    pub first_after_days: i64,
+    /// Days after the due date to send the second reminder
+    pub second_after_days: i64,
+    /// Days after the due date to send the final notice
+    pub final_after_days: i64,
+    pub payment_terms_days: i64,
+    /// Only remind of the invoices for which this holds
+    pub filter: Option<Expression>,
+}
+
+impl DunningOptions {
+    fn after_days(&self, level: DunningLevel) -> i64 {
+        match level {
+            DunningLevel::First => self.first_after_days,
+            DunningLevel::Second => self.second_after_days,
+            DunningLevel::Final => self.final_after_days,
+        }
+    }
+}
+
+impl Default for DunningOptions {
+    fn default() -> Self {
+        Self {
+            date: chrono::Local::now().date_naive(),
+            first_after_days: 7,
+            second_after_days: 21,
+            final_after_days: 35,
+            payment_terms_days: 30,
+            filter: None,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ReminderLetter {
+    pub date: Date,
+    pub level: DunningLevel,
+    pub days_overdue: i64,
+    pub outstanding: Money,
+    pub invoice: Invoice,
+}
+
+/// The reminders of one run of `invoices remind`.
+#[derive(Serialize)]
+pub struct ReminderLetters {
+    pub letters: Vec<ReminderLetter>,
+    /// Whether the reminders were only listed, and not recorded
+    #[serde(skip)]
+    pub dry_run: bool,
+}
+
+/// Finds the overdue invoices that are due for a reminder, at the level after the last
+/// reminder sent for them. A level is only reached once the invoice is overdue for the
+/// configured number of days, and as long after the previous reminder as the levels are
+/// apart, so a late run never skips a level nor sends two reminders at once. Drafts are not
+/// owed yet, so they get no reminders.
+pub fn due_reminders(
+    options: &DunningOptions,
+    invoices: &[Invoice],
+    reminders: &[Reminder],
+) -> Vec<ReminderLetter> {
+    invoices
+        .iter()
+        .filter(|invoice| !invoice.state.is_draft() && invoice.is_open())
+        .filter(|invoice| {
+            options.filter.as_ref().is_none_or(|filter| {
+                filter.matches(invoice, options.date, options.payment_terms_days)
+            })
+        })
+        .filter_map(|invoice| {
+            let days_overdue =
+                (options.date - invoice.expected_due_date(options.payment_terms_days)).num_days();
+
+            let last = reminders
+                .iter()
+                .filter(|reminder| reminder.invoice_number == invoice.number)
+                .max_by_key(|reminder| reminder.level);
+            let level = match last {
+                Some(last) => last.level.next()?,
+                None => DunningLevel::First,
+            };
+            if days_overdue < options.after_days(level) {
+                return None;
+            }
+            if let Some(last) = last {
+                let since_last = (options.date - last.date.0).num_days();
+                if since_last < options.after_days(level) - options.after_days(last.level) {
+                    return None;
+                }
+            }
+
+            Some(ReminderLetter {
+                date: Date(options.date),
+                level,
+                days_overdue,
+                outstanding: invoice.outstanding()?,
+                invoice: invoice.clone(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::domain::invoice::{InvoiceNumber, InvoiceState};
+
+    use super::*;
+
+    fn reminder(number: &str, level: DunningLevel) -> Reminder {
+        Reminder {
+            date: "2023-07-10".into(),
+            invoice_number: InvoiceNumber(number.to_string()),
+            level,
+        }
+    }
+
+    #[test]
+    fn test_level_follows_previous_reminders() {
+        let invoices = vec![
+            Invoice::issued("2023-001", "2023-06-01", "100 EUR").with_due_date("2023-07-01"),
+            Invoice::issued("2023-002", "2023-06-01", "100 EUR").with_due_date("2023-07-01"),
+            Invoice::issued("2023-003", "2023-06-01", "100 EUR").with_due_date("2023-07-01"),
+            Invoice::issued("2023-004", "2023-06-01", "100 EUR").with_due_date("2023-07-28"),
+        ];
+        let reminders = vec![
+            reminder("2023-002", DunningLevel::First),
+            reminder("2023-003", DunningLevel::First),
+            reminder("2023-003", DunningLevel::Second),
+        ];
+        let options = DunningOptions {
+            date: NaiveDate::from_ymd_opt(2023, 8, 1).unwrap(),
+            ..DunningOptions::default()
+        };
+
+        let actual: Vec<(String, DunningLevel)> = due_reminders(&options, &invoices, &reminders)
+            .into_iter()
+            .map(|letter| (letter.invoice.number.0, letter.level))
+            .collect();
+
+        // 2023-003 is 31 days overdue, too early for the final notice; 2023-004 is only
+        // 4 days overdue.
+        assert_eq!(
+            vec![
+                ("2023-001".to_string(), DunningLevel::First),
+                ("2023-002".to_string(), DunningLevel::Second),
+            ],
+            actual
+        );
+    }
+
+    #[test]
+    fn test_filter_limits_the_invoices_reminded_of() {
+        let invoices = vec![
+            Invoice::issued("2023-001", "2023-06-01", "100 EUR").with_due_date("2023-07-01"),
+            Invoice::issued("2023-002", "2023-06-01", "100 EUR").with_due_date("2023-07-01"),
+        ];
+        let options = DunningOptions {
+            date: NaiveDate::from_ymd_opt(2023, 8, 1).unwrap(),
+            filter: Some("number != '2023-001'".parse().unwrap()),
+            ..DunningOptions::default()
+        };
+
+        let letters = due_reminders(&options, &invoices, &[]);
+
+        assert_eq!(1, letters.len());
+        assert_eq!("2023-002", letters[0].invoice.number.0);
+    }
+
+    #[test]
+    fn test_drafts_are_not_reminded_of() {
+        let invoices = vec![
+            Invoice {
+                state: InvoiceState::Draft,
+                ..Invoice::issued("2023-001", "2023-06-01", "100 EUR").with_due_date("2023-07-01")
+            },
+            Invoice::issued("2023-002", "2023-06-01", "100 EUR").with_due_date("2023-07-01"),
+        ];
+        let options = DunningOptions {
+            date: NaiveDate::from_ymd_opt(2023, 8, 1).unwrap(),
+            ..DunningOptions::default()
+        };
+
+        let letters = due_reminders(&options, &invoices, &[]);
+
+        assert_eq!(1, letters.len());
+        assert_eq!("2023-002", letters[0].invoice.number.0);
+    }
+}
@@ -0,0 +1,775 @@
+use core::fmt;
+use std::{collections::BTreeMap, collections::HashMap, error::Error, str::FromStr};
+
+use rust_decimal::Decimal;
+
+use crate::domain::{
+    e_invoice::{
+        EInvoice, EInvoiceLine, FacturXProfile, Party, PaymentMeans, TaxCategory, TaxRule,
+        TaxSubtotal,
+    },
+    invoice::{default_income_account, Invoice, InvoiceNumber},
+    money::Money,
+};
+
+pub struct EInvoiceOptions {
+    pub seller: Party,
+    pub iban: Option<String>,
+    pub bic: Option<String>,
+    /// The details of buyers, by their name in the ledger
+    pub customers: HashMap<String, Party>,
+    /// The standard VAT rate, in percent, for income accounts without a tax rule
+    pub vat_rate: Decimal,
+    /// The unit of line items without one
+    pub unit_code: String,
+    pub tax: Vec<TaxRule>,
+    pub payment_terms_days: i64,
+}
+
+impl Default for EInvoiceOptions {
+    fn default() -> Self {
+        Self {
+            seller: Party::default(),
+            iban: None,
+            bic: None,
+            customers: HashMap::new(),
+            vat_rate: Decimal::from(21),
+            unit_code: "C62".to_string(),
+            tax: vec![],
+            payment_terms_days: 30,
+        }
+    }
+}
+
+/// A business rule of EN 16931, or of the Peppol BIS Billing 3.0 rules on top of it, that an
+/// electronic invoice breaks.
+#[derive(Debug, PartialEq)]
+pub struct Violation {
+    /// The identifier of the rule, such as BR-06
+    pub rule: &'static str,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}", self.rule, self.message)
+    }
+}
+
+#[derive(Debug)]
+pub enum EInvoiceError {
+    /// Drafts can still change, so they are not sent as e-invoices
+    Draft(InvoiceNumber),
+    UnknownAmount(InvoiceNumber),
+    UnreadableLine(InvoiceNumber, String),
+    /// The ledger total differs from the lines plus VAT
+    TotalMismatch {
+        number: InvoiceNumber,
+        ledger: Money,
+        computed: Money,
+    },
+    Invalid(InvoiceNumber, Vec<Violation>),
+}
+
+impl Error for EInvoiceError {}
+
+impl fmt::Display for EInvoiceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EInvoiceError::Draft(number) => write!(
+                f,
+                "Invoice {} is a draft, issue it with `tabula invoices issue` first",
+                number
+            ),
+            EInvoiceError::UnknownAmount(number) => {
+                write!(f, "The total of invoice {} is not an amount", number)
+            }
+            EInvoiceError::UnreadableLine(number, line) => write!(
+                f,
+                "The quantity or price of line \"{}\" of invoice {} is not a number",
+                line, number
+            ),
+            EInvoiceError::TotalMismatch {
+                number,
+                ledger,
+                computed,
+            } => write!(
+                f,
+                "Invoice {} totals {} in the ledger, but its lines plus VAT come to {}. Book the \
+                 VAT in the ledger, or configure the VAT of its income accounts under [e_invoice]",
+                number, ledger, computed
+            ),
+            EInvoiceError::Invalid(number, violations) => {
+                write!(f, "Invoice {} is not a valid e-invoice:", number)?;
+                for violation in violations {
+                    write!(f, "\n  {}", violation)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The most specific rule for `account`, or the standard rate when none applies.
+pub fn tax_rule(options: &EInvoiceOptions, account: &str) -> TaxRule {
+    options
+        .tax
+        .iter()
+        .filter(|rule| {
+            account == rule.account || account.starts_with(&format!("{}:", rule.account))
+        })
+        .max_by_key(|rule| rule.account.len())
+        .cloned()
+        .unwrap_or_else(|| TaxRule {
+            account: account.to_string(),
+            category: TaxCategory::Standard,
+            rate: options.vat_rate,
+            exemption_reason: None,
+        })
+}
+
+fn line(
+    id: usize,
+    name: &str,
+    quantity: Decimal,
+    unit_code: &str,
+    price: Decimal,
+    net: Decimal,
+    rule: &TaxRule,
+) -> EInvoiceLine {
+    EInvoiceLine {
+        id: (id + 1).to_string(),
+        name: name.to_string(),
+        quantity,
+        unit_code: unit_code.to_string(),
+        price,
+        net_amount: net.round_dp(2),
+        category: rule.category,
+        rate: rule.rate,
+    }
+}
+
+/// Describes an invoice from the ledger as EN 16931 does. The lines are its line items, or
+/// else an amount per income account. Each line item takes the VAT of the income account it
+/// is posted on.
+pub fn e_invoice(invoice: &Invoice, options: &EInvoiceOptions) -> Result<EInvoice, EInvoiceError> {
+    let number = invoice.number.clone();
+    if invoice.state.is_draft() {
+        return Err(EInvoiceError::Draft(number));
+    }
+    let total = invoice
+        .total_amount()
+        .ok_or(EInvoiceError::UnknownAmount(number.clone()))?;
+    let credit_note = total.amount < Decimal::ZERO || invoice.credits_invoice.is_some();
+    let revenue = invoice.revenue_lines();
+    let default_account = default_income_account();
+    let first_account = revenue
+        .first()
+        .map_or(default_account.as_str(), |r| r.account.as_str());
+
+    let single = revenue.len() == 1;
+
+    let lines: Vec<EInvoiceLine> = if invoice.line_items.is_empty() {
+        revenue
+            .iter()
+            .enumerate()
+            .map(|(id, revenue)| {
+                let name = if single && !invoice.narration.is_empty() {
+                    invoice.narration.as_str()
+                } else {
+                    revenue.account.as_str()
+                };
+                let amount = revenue.amount.amount.abs();
+                let rule = tax_rule(options, &revenue.account);
+                line(
+                    id,
+                    name,
+                    Decimal::ONE,
+                    &options.unit_code,
+                    amount,
+                    amount,
+                    &rule,
+                )
+            })
+            .collect()
+    } else {
+        invoice
+            .line_items
+            .iter()
+            .enumerate()
+            .map(|(id, item)| {
+                let unreadable =
+                    || EInvoiceError::UnreadableLine(number.clone(), item.description.clone());
+                let quantity = Decimal::from_str(&item.quantity).map_err(|_| unreadable())?;
+                let price = Money::from_str(&item.unit_price)
+                    .map(|price| price.amount)
+                    .or_else(|_| Decimal::from_str(&item.unit_price))
+                    .map_err(|_| unreadable())?;
+                let net = Money::from_str(&item.total)
+                    .map(|total| total.amount)
+                    .unwrap_or(quantity * price);
+                let unit_code = item.unit.as_deref().unwrap_or(&options.unit_code);
+                let rule = tax_rule(options, item.account.as_deref().unwrap_or(first_account));
+                Ok(line(
+                    id,
+                    &item.description,
+                    quantity.abs(),
+                    unit_code,
+                    price.abs(),
+                    net.abs(),
+                    &rule,
+                ))
+            })
+            .collect::<Result<_, _>>()?
+    };
+
+    let mut groups: BTreeMap<(TaxCategory, Decimal), Decimal> = BTreeMap::new();
+    for line in &lines {
+        *groups
+            .entry((line.category, line.rate.normalize()))
+            .or_default() += line.net_amount;
+    }
+    let taxes: Vec<TaxSubtotal> = groups
+        .into_iter()
+        .map(|((category, rate), taxable)| TaxSubtotal {
+            category,
+            rate,
+            taxable,
+            tax: (taxable * rate / Decimal::ONE_HUNDRED).round_dp(2),
+            exemption_reason: options
+                .tax
+                .iter()
+                .filter(|rule| rule.category == category)
+                .find_map(|rule| rule.exemption_reason.clone())
+                .or(category.default_exemption_reason().map(String::from)),
+        })
+        .collect();
+
+    let line_total: Decimal = lines.iter().map(|line| line.net_amount).sum();
+    let tax_total: Decimal = taxes.iter().map(|tax| tax.tax).sum();
+    let tax_inclusive = line_total + tax_total;
+    if tax_inclusive != total.amount.abs() {
+        return Err(EInvoiceError::TotalMismatch {
+            number,
+            ledger: Money::new(total.amount.abs(), &total.currency),
+            computed: Money::new(tax_inclusive, &total.currency),
+        });
+    }
+
+    let mut buyer = invoice
+        .customer
+        .as_ref()
+        .and_then(|customer| options.customers.get(customer))
+        .cloned()
+        .unwrap_or_default();
+    buyer.name = buyer.name.or(invoice.customer.clone());
+    let due_date = invoice.expected_due_date(options.payment_terms_days);
+
+    Ok(EInvoice {
+        number: number.0.clone(),
+        issue_date: invoice.date.0,
+        due_date: Some(due_date),
+        credit_note,
+        billing_reference: invoice.credits_invoice.as_ref().map(|n| n.0.clone()),
+        note: Some(invoice.narration.clone()).filter(|note| !note.is_empty()),
+        currency: total.currency.clone(),
+        buyer_reference: buyer.reference.clone(),
+        seller: options.seller.clone(),
+        buyer,
+        payment: PaymentMeans {
+            iban: options.iban.clone(),
+            bic: options.bic.clone(),
+            reference: number.0,
+        },
+        payment_terms: Some(format!("Payment before {}", due_date)),
+        lines,
+        taxes,
+        line_total,
+        tax_exclusive: line_total,
+        tax_total,
+        tax_inclusive,
+        payable: tax_inclusive,
+    })
+}
+
+/// Whether `iban` is an IBAN with a correct ISO 7064 check number.
+fn is_valid_iban(iban: &str) -> bool {
+    let iban: String = iban.chars().filter(|c| !c.is_whitespace()).collect();
+    if iban.len() < 15 || iban.len() > 34 || !iban.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return false;
+    }
+    let (head, tail) = iban.split_at(4);
+    let remainder = tail
+        .chars()
+        .chain(head.chars())
+        .try_fold(0u32, |remainder, c| {
+            let digits = c.to_digit(36)?;
+            Some(if digits < 10 {
+                (remainder * 10 + digits) % 97
+            } else {
+                (remainder * 100 + digits) % 97
+            })
+        });
+    remainder == Some(1)
+}
+
+/// Whether a VAT identifier starts with the code of its country, as BR-CO-09 demands.
+fn has_country_prefix(vat_id: &str) -> bool {
+    vat_id.len() > 2 && vat_id[..2].chars().all(|c| c.is_ascii_uppercase())
+}
+
+/// Checks an electronic invoice against the business rules of EN 16931 and Peppol BIS
+/// Billing 3.0 that apply to what tabula writes, the way the official schematron would.
+pub fn validate(invoice: &EInvoice) -> Vec<Violation> {
+    let mut violations = vec![];
+    let mut check = |valid: bool, rule: &'static str, message: String| {
+        if !valid {
+            violations.push(Violation { rule, message });
+        }
+    };
+    let present = |value: &Option<String>| value.as_ref().is_some_and(|v| !v.trim().is_empty());
+
+    check(
+        !invoice.number.trim().is_empty(),
+        "BR-02",
+        "An invoice shall have an invoice number".to_string(),
+    );
+    check(
+        invoice.currency.len() == 3 && invoice.currency.chars().all(|c| c.is_ascii_uppercase()),
+        "BR-05",
+        format!(
+            "The invoice currency {} is not an ISO 4217 code",
+            invoice.currency
+        ),
+    );
+    check(
+        present(&invoice.seller.name),
+        "BR-06",
+        "The seller has no name, set name under [seller]".to_string(),
+    );
+    check(
+        present(&invoice.buyer.name),
+        "BR-07",
+        "The buyer has no name".to_string(),
+    );
+    check(
+        present(&invoice.seller.country),
+        "BR-09",
+        "The seller address has no country code, set country under [seller]".to_string(),
+    );
+    check(
+        present(&invoice.buyer.country),
+        "BR-11",
+        "The buyer address has no country code, set country for the customer under [customers]"
+            .to_string(),
+    );
+    check(
+        !invoice.lines.is_empty(),
+        "BR-16",
+        "An invoice shall have at least one line".to_string(),
+    );
+
+    for line in &invoice.lines {
+        check(
+            !line.name.trim().is_empty(),
+            "BR-25",
+            format!("Line {} has no item name", line.id),
+        );
+        check(
+            !line.unit_code.trim().is_empty(),
+            "BR-23",
+            format!("Line {} has no unit of measure", line.id),
+        );
+        check(
+            line.price >= Decimal::ZERO,
+            "BR-27",
+            format!("The price of line {} is negative", line.id),
+        );
+    }
+
+    let line_total: Decimal = invoice.lines.iter().map(|line| line.net_amount).sum();
+    check(
+        invoice.line_total == line_total,
+        "BR-CO-10",
+        format!(
+            "The sum of line amounts {} is not the sum of the lines, {}",
+            invoice.line_total, line_total
+        ),
+    );
+    check(
+        invoice.tax_exclusive == invoice.line_total,
+        "BR-CO-13",
+        format!(
+            "The total without VAT {} is not the sum of the lines, {}",
+            invoice.tax_exclusive, invoice.line_total
+        ),
+    );
+    let tax_total: Decimal = invoice.taxes.iter().map(|tax| tax.tax).sum();
+    check(
+        invoice.tax_total == tax_total,
+        "BR-CO-14",
+        format!(
+            "The VAT total {} is not the sum of the VAT per category, {}",
+            invoice.tax_total, tax_total
+        ),
+    );
+    check(
+        invoice.tax_inclusive == invoice.tax_exclusive + invoice.tax_total,
+        "BR-CO-15",
+        format!(
+            "The total with VAT {} is not the total without VAT plus VAT",
+            invoice.tax_inclusive
+        ),
+    );
+    check(
+        invoice.payable == invoice.tax_inclusive,
+        "BR-CO-16",
+        format!(
+            "The amount due {} is not the total with VAT",
+            invoice.payable
+        ),
+    );
+    check(
+        !invoice.taxes.is_empty(),
+        "BR-CO-18",
+        "An invoice shall have at least one VAT breakdown".to_string(),
+    );
+    check(
+        invoice.payable <= Decimal::ZERO
+            || invoice.due_date.is_some()
+            || invoice.payment_terms.is_some(),
+        "BR-CO-25",
+        "An invoice with an amount due shall have a due date or payment terms".to_string(),
+    );
+    for (party, vat_id) in [
+        ("seller", &invoice.seller.vat_id),
+        ("buyer", &invoice.buyer.vat_id),
+    ] {
+        if let Some(vat_id) = vat_id {
+            check(
+                has_country_prefix(vat_id),
+                "BR-CO-09",
+                format!(
+                    "The {} VAT identifier {} does not start with a country code",
+                    party, vat_id
+                ),
+            );
+        }
+    }
+
+    for tax in &invoice.taxes {
+        let category = tax.category;
+        check(
+            tax.tax == (tax.taxable * tax.rate / Decimal::ONE_HUNDRED).round_dp(2),
+            "BR-CO-17",
+            format!(
+                "The VAT of category {} is not its taxable amount times its rate",
+                category
+            ),
+        );
+        let (seller_vat, rate, reason) = match category {
+            TaxCategory::Standard => ("BR-S-02", "BR-S-05", None),
+            TaxCategory::ZeroRated => ("BR-Z-02", "BR-Z-05", None),
+            TaxCategory::Exempt => ("BR-E-02", "BR-E-05", Some("BR-E-10")),
+            TaxCategory::ReverseCharge => ("BR-AE-02", "BR-AE-05", Some("BR-AE-10")),
+            TaxCategory::IntraCommunity => ("BR-IC-02", "BR-IC-05", Some("BR-IC-10")),
+            TaxCategory::Export => ("BR-G-02", "BR-G-05", Some("BR-G-10")),
+            TaxCategory::OutsideScope => ("BR-O-02", "BR-O-05", Some("BR-O-10")),
+        };
+        if category == TaxCategory::OutsideScope {
+            check(
+                invoice.seller.vat_id.is_none() && invoice.buyer.vat_id.is_none(),
+                seller_vat,
+                "An invoice with VAT category O shall not have VAT identifiers".to_string(),
+            );
+            check(
+                invoice.taxes.len() == 1,
+                "BR-O-11",
+                "An invoice with VAT category O shall not have other VAT categories".to_string(),
+            );
+        } else {
+            check(
+                present(&invoice.seller.vat_id),
+                seller_vat,
+                format!("An invoice with VAT category {} needs the seller VAT identifier, set vat_id under [seller]", category),
+            );
+        }
+        if category == TaxCategory::Standard {
+            check(
+                tax.rate > Decimal::ZERO,
+                rate,
+                "The standard VAT rate shall be more than zero".to_string(),
+            );
+        } else {
+            check(
+                tax.rate.is_zero(),
+                rate,
+                format!("The VAT rate of category {} shall be zero", category),
+            );
+        }
+        if let Some(rule) = reason {
+            check(
+                present(&tax.exemption_reason),
+                rule,
+                format!("VAT category {} needs an exemption reason, set exemption_reason in its tax rule", category),
+            );
+        }
+        if matches!(
+            category,
+            TaxCategory::ReverseCharge | TaxCategory::IntraCommunity
+        ) {
+            check(
+                present(&invoice.buyer.vat_id),
+                if category == TaxCategory::ReverseCharge {
+                    "BR-AE-03"
+                } else {
+                    "BR-IC-03"
+                },
+                format!(
+                    "An invoice with VAT category {} needs the buyer VAT identifier",
+                    category
+                ),
+            );
+        }
+    }
+
+    match &invoice.payment.iban {
+        Some(iban) => check(
+            is_valid_iban(iban),
+            "BR-61",
+            format!("{} is not a valid IBAN", iban),
+        ),
+        None => check(
+            false,
+            "BR-61",
+            "A credit transfer needs the IBAN of the seller, set iban under [seller]".to_string(),
+        ),
+    }
+    check(
+        present(&invoice.buyer_reference),
+        "PEPPOL-EN16931-R003",
+        "The buyer has no reference, set reference for the customer under [customers]".to_string(),
+    );
+    check(
+        invoice.buyer.endpoint.is_some(),
+        "PEPPOL-EN16931-R010",
+        "The buyer has no electronic address, set endpoint for the customer under [customers]"
+            .to_string(),
+    );
+    check(
+        invoice.seller.endpoint.is_some(),
+        "PEPPOL-EN16931-R020",
+        "The seller has no electronic address, set endpoint under [seller]".to_string(),
+    );
+
+    violations
+}
+
+/// Checks the XML of a Factur-X PDF against the rules its profile must meet. The Peppol
+/// rules do not apply, and the MINIMUM profile leaves out the lines, the VAT breakdown and
+/// how to pay.
+pub fn validate_factur_x(invoice: &EInvoice, profile: FacturXProfile) -> Vec<Violation> {
+    validate(invoice)
+        .into_iter()
+        .filter(|violation| !violation.rule.starts_with("PEPPOL-"))
+        .filter(|violation| {
+            profile != FacturXProfile::Minimum
+                || matches!(
+                    violation.rule,
+                    "BR-02"
+                        | "BR-05"
+                        | "BR-06"
+                        | "BR-07"
+                        | "BR-09"
+                        | "BR-CO-09"
+                        | "BR-CO-15"
+                        | "BR-CO-16"
+                )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::domain::invoice::{InvoiceState, LineItem};
+
+    use super::*;
+
+    fn party(name: &str, vat_id: &str, endpoint: &str) -> Party {
+        Party {
+            name: Some(name.to_string()),
+            vat_id: Some(vat_id.to_string()),
+            endpoint: Some(endpoint.parse().unwrap()),
+            country: Some("NL".to_string()),
+            reference: Some("PO-42".to_string()),
+            ..Party::default()
+        }
+    }
+
+    fn options() -> EInvoiceOptions {
+        EInvoiceOptions {
+            seller: party("Tabula B.V.", "NL123456789B01", "0106:12345678"),
+            iban: Some("NL91 ABNA 0417 1643 00".to_string()),
+            customers: HashMap::from([(
+                "Acme".to_string(),
+                party("Acme B.V.", "NL987654321B01", "0106:87654321"),
+            )]),
+            tax: vec![TaxRule {
+                account: "Income:Export".to_string(),
+                category: TaxCategory::Export,
+                rate: Decimal::ZERO,
+                exemption_reason: None,
+            }],
+            ..EInvoiceOptions::default()
+        }
+    }
+
+    #[test]
+    fn test_vat_is_summed_per_category() {
+        let invoice = Invoice::issued("2023-001", "2023-06-01", "1710 EUR")
+            .with_customer("Acme")
+            .with_revenue("Income:Work", "-1000 EUR")
+            .with_revenue("Income:Export", "-500 EUR");
+
+        let actual = e_invoice(&invoice, &options()).unwrap();
+
+        let taxes: Vec<(&str, String, String)> = actual
+            .taxes
+            .iter()
+            .map(|tax| {
+                (
+                    tax.category.code(),
+                    tax.taxable.to_string(),
+                    tax.tax.to_string(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                ("S", "1000".to_string(), "210".to_string()),
+                ("G", "500".to_string(), "0".to_string())
+            ],
+            taxes
+        );
+        assert_eq!(
+            Some("Export outside the EU".to_string()),
+            actual.taxes[1].exemption_reason
+        );
+        assert_eq!(Decimal::from(1710), actual.payable);
+        assert_eq!(Some("Acme B.V.".to_string()), actual.buyer.name);
+        assert_eq!(Vec::<Violation>::new(), validate(&actual));
+    }
+
+    #[test]
+    fn test_line_items_take_the_vat_of_their_own_account() {
+        let mut invoice = Invoice::issued("2023-001", "2023-06-01", "1710 EUR")
+            .with_customer("Acme")
+            .with_revenue("Income:Work", "-1000 EUR")
+            .with_revenue("Income:Export", "-500 EUR");
+        for (description, total, account) in [
+            ("Shipping", "500 EUR", "Income:Export"),
+            ("Consulting", "1000 EUR", "Income:Work"),
+        ] {
+            invoice.line_items.push(LineItem {
+                description: description.to_string(),
+                unit_price: total.to_string(),
+                quantity: "1".to_string(),
+                total: total.to_string(),
+                unit: None,
+                account: Some(account.to_string()),
+            });
+        }
+
+        let actual = e_invoice(&invoice, &options()).unwrap();
+
+        let lines: Vec<(&str, &str)> = actual
+            .lines
+            .iter()
+            .map(|line| (line.name.as_str(), line.category.code()))
+            .collect();
+        assert_eq!(vec![("Shipping", "G"), ("Consulting", "S")], lines);
+        assert_eq!(Decimal::from(1710), actual.payable);
+    }
+
+    #[test]
+    fn test_drafts_are_refused() {
+        let draft = Invoice {
+            state: InvoiceState::Draft,
+            ..Invoice::issued("2023-001", "2023-06-01", "1210 EUR")
+                .with_customer("Acme")
+                .with_revenue("Income:Work", "-1000 EUR")
+        };
+
+        let actual = e_invoice(&draft, &options()).unwrap_err();
+
+        assert!(matches!(actual, EInvoiceError::Draft(_)));
+    }
+
+    #[test]
+    fn test_ledger_total_must_include_vat() {
+        let invoice = Invoice::issued("2023-001", "2023-06-01", "1000 EUR")
+            .with_customer("Acme")
+            .with_revenue("Income:Work", "-1000 EUR");
+
+        let actual = e_invoice(&invoice, &options()).unwrap_err();
+
+        assert_eq!(
+            "Invoice 2023-001 totals 1000 EUR in the ledger, but its lines plus VAT come to 1210 EUR. \
+             Book the VAT in the ledger, or configure the VAT of its income accounts under [e_invoice]",
+            actual.to_string()
+        );
+    }
+
+    #[test]
+    fn test_validation_reports_missing_details() {
+        let mut options = options();
+        options.seller.vat_id = None;
+        options.iban = Some("NL91ABNA0417164301".to_string());
+        options.customers.clear();
+        let invoice = Invoice::issued("2023-001", "2023-06-01", "1210 EUR")
+            .with_customer("Acme")
+            .with_revenue("Income:Work", "-1000 EUR");
+
+        let actual = validate(&e_invoice(&invoice, &options).unwrap());
+
+        let rules: Vec<&str> = actual.iter().map(|violation| violation.rule).collect();
+        assert_eq!(
+            vec![
+                "BR-11",
+                "BR-S-02",
+                "BR-61",
+                "PEPPOL-EN16931-R003",
+                "PEPPOL-EN16931-R010"
+            ],
+            rules
+        );
+        assert_eq!(
+            "[BR-61] NL91ABNA0417164301 is not a valid IBAN",
+            actual[2].to_string()
+        );
+    }
+
+    #[test]
+    fn test_factur_x_minimum_only_checks_what_it_describes() {
+        let mut options = options();
+        options.seller.vat_id = None;
+        options.iban = None;
+        options.customers.clear();
+        let invoice = e_invoice(
+            &Invoice::issued("2023-001", "2023-06-01", "1210 EUR")
+                .with_customer("Acme")
+                .with_revenue("Income:Work", "-1000 EUR"),
+            &options,
+        );
+        let invoice = invoice.unwrap();
+
+        let minimum = validate_factur_x(&invoice, FacturXProfile::Minimum);
+        let en16931 = validate_factur_x(&invoice, FacturXProfile::En16931);
+
+        assert_eq!(Vec::<Violation>::new(), minimum);
+        let rules: Vec<&str> = en16931.iter().map(|violation| violation.rule).collect();
+        assert_eq!(vec!["BR-11", "BR-S-02", "BR-61"], rules);
+    }
+}
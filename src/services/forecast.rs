@@ -0,0 +1,310 @@
+use chrono::{Duration, NaiveDate};
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::domain::{
+    bill::Bill,
+    expression::Expression,
+    invoice::{Date, Invoice},
+    money::Money,
+    recurring::RecurringItem,
+};
+
+use super::payment_behaviour::average_days_late;
+
+pub struct ForecastOptions {
+    pub start: NaiveDate,
+    pub weeks: u32,
+    pub currency: String,
+    pub threshold: Option<Decimal>,
+    pub bank_accounts: Vec<String>,
+    pub recurring: Vec<RecurringItem>,
+    pub payment_terms_days: i64,
+    /// Shift expected payment dates by the average number of days each customer pays late
+    pub adjust_for_lateness: bool,
+    /// Only expect payment of the invoices for which this holds
+    pub filter: Option<Expression>,
+}
+
+impl Default for ForecastOptions {
+    fn default() -> Self {
+        Self {
+            start: chrono::Local::now().date_naive(),
+            weeks: 13,
+            currency: "EUR".to_string(),
+            threshold: None,
+            bank_accounts: vec!["Assets:Bank".to_string()],
+            recurring: vec![],
+            payment_terms_days: 30,
+            adjust_for_lateness: false,
+            filter: None,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ForecastItem {
+    pub description: String,
+    pub amount: Money,
+}
+
+#[derive(Serialize)]
+pub struct ForecastWeek {
+    pub start: Date,
+    pub inflow: Money,
+    pub outflow: Money,
+    pub balance: Money,
+    pub items: Vec<ForecastItem>,
+}
+
+#[derive(Serialize)]
+pub struct Forecast {
+    pub start: Date,
+    pub opening_balance: Money,
+    pub threshold: Option<Money>,
+    pub weeks: Vec<ForecastWeek>,
+}
+
+impl Forecast {
+    /// The first week in which the projected balance is below the threshold.
+    pub fn first_shortfall(&self) -> Option<(usize, &ForecastWeek)> {
+        let threshold = self.threshold.as_ref()?;
+        self.weeks
+            .iter()
+            .enumerate()
+            .find(|(_, week)| week.balance.amount < threshold.amount)
+    }
+}
+
+/// A single expected movement of money. Negative amounts leave the bank.
+struct CashFlow {
+    date: NaiveDate,
+    amount: Decimal,
+    description: String,
+}
+
+/// Projects the bank balance week by week, starting from `opening_balance`. Everything that is
+/// overdue at the start of the forecast is expected in the first week. Drafts are not
+/// expected to be paid until they are issued.
+pub fn forecast(
+    options: &ForecastOptions,
+    opening_balance: Decimal,
+    invoices: &[Invoice],
+    bills: &[Bill],
+) -> Forecast {
+    let currency = options.currency.as_str();
+    let end = options.start + Duration::weeks(options.weeks.into());
+    let lateness = if options.adjust_for_lateness {
+        average_days_late(invoices, options.payment_terms_days)
+    } else {
+        Default::default()
+    };
+
+    let mut flows: Vec<CashFlow> = vec![];
+
+    let receivable = |invoice: &&Invoice| {
+        !invoice.state.is_draft()
+            && invoice.is_open()
+            && options.filter.as_ref().is_none_or(|filter| {
+                filter.matches(invoice, options.start, options.payment_terms_days)
+            })
+    };
+    for invoice in invoices.iter().filter(receivable) {
+        let Some(outstanding) = invoice.outstanding() else {
+            continue;
+        };
+        if outstanding.currency != currency {
+            continue;
+        }
+        let days_late = invoice
+            .customer
+            .as_ref()
+            .and_then(|customer| lateness.get(customer))
+            .copied()
+            .unwrap_or_default();
+
+        flows.push(CashFlow {
+            date: invoice.expected_due_date(options.payment_terms_days) + Duration::days(days_late),
+            amount: outstanding.amount,
+            description: format!("Invoice {}", invoice.number),
+        });
+    }
+
+    for bill in bills {
+        let outstanding = bill.outstanding();
+        if outstanding.currency != currency || outstanding.amount <= Decimal::ZERO {
+            continue;
+        }
+        let due_date = match &bill.due_date {
+            Some(due_date) => due_date.0,
+            None => bill.date.0 + Duration::days(options.payment_terms_days),
+        };
+        flows.push(CashFlow {
+            date: due_date,
+            amount: -outstanding.amount,
+            description: format!("Bill {}", bill.number),
+        });
+    }
+
+    for item in options
+        .recurring
+        .iter()
+        .filter(|item| item.currency == currency)
+    {
+        for date in item.occurrences(options.start, end) {
+            flows.push(CashFlow {
+                date,
+                amount: -item.amount,
+                description: item.description.clone(),
+            });
+        }
+    }
+
+    let mut balance = opening_balance;
+    let weeks = (0..options.weeks)
+        .map(|week| {
+            let week_start = options.start + Duration::weeks(week.into());
+            let week_end = week_start + Duration::weeks(1);
+            let in_week =
+                |flow: &&CashFlow| (week == 0 || flow.date >= week_start) && flow.date < week_end;
+
+            let flows_in_week: Vec<&CashFlow> = flows.iter().filter(in_week).collect();
+
+            let inflow: Decimal = flows_in_week
+                .iter()
+                .filter(|flow| flow.amount > Decimal::ZERO)
+                .map(|flow| flow.amount)
+                .sum();
+            let outflow: Decimal = flows_in_week
+                .iter()
+                .filter(|flow| flow.amount < Decimal::ZERO)
+                .map(|flow| -flow.amount)
+                .sum();
+            balance += inflow - outflow;
+
+            let items = flows_in_week
+                .iter()
+                .map(|flow| ForecastItem {
+                    description: flow.description.clone(),
+                    amount: Money::new(flow.amount, currency),
+                })
+                .collect();
+
+            ForecastWeek {
+                start: Date(week_start),
+                inflow: Money::new(inflow, currency),
+                outflow: Money::new(outflow, currency),
+                balance: Money::new(balance, currency),
+                items,
+            }
+        })
+        .collect();
+
+    Forecast {
+        start: Date(options.start),
+        opening_balance: Money::new(opening_balance, currency),
+        threshold: options.threshold.map(|t| Money::new(t, currency)),
+        weeks,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::domain::{invoice::InvoiceState, payment::Payment, recurring::Frequency};
+
+    use super::*;
+
+    fn options() -> ForecastOptions {
+        ForecastOptions {
+            start: NaiveDate::from_ymd_opt(2023, 7, 3).unwrap(),
+            weeks: 4,
+            threshold: Some(Decimal::from(500)),
+            ..ForecastOptions::default()
+        }
+    }
+
+    #[test]
+    fn test_open_invoices_arrive_in_week_of_due_date() {
+        let invoices = vec![
+            Invoice::issued("2023-001", "2023-06-01", "100 EUR")
+                .with_due_date("2023-06-15")
+                .with_customer("Acme"),
+            Invoice::issued("2023-002", "2023-06-12", "200 EUR")
+                .with_due_date("2023-07-12")
+                .with_customer("Acme"),
+        ];
+
+        let actual = forecast(&options(), Decimal::from(1000), &invoices, &[]);
+
+        let balances: Vec<String> = actual.weeks.iter().map(|w| w.balance.to_string()).collect();
+        // The overdue invoice is expected in the first week
+        assert_eq!(
+            vec!["1100 EUR", "1300 EUR", "1300 EUR", "1300 EUR"],
+            balances
+        );
+    }
+
+    #[test]
+    fn test_drafts_are_not_expected() {
+        let draft = Invoice {
+            state: InvoiceState::Draft,
+            ..Invoice::issued("TBD", "2023-06-12", "200 EUR")
+                .with_due_date("2023-07-12")
+                .with_customer("Acme")
+        };
+
+        let actual = forecast(&options(), Decimal::from(1000), &[draft], &[]);
+
+        assert!(actual.weeks.iter().all(|week| week.items.is_empty()));
+        assert_eq!("1000 EUR", actual.weeks[3].balance.to_string());
+    }
+
+    #[test]
+    fn test_filter_limits_the_invoices_expected() {
+        let invoices = vec![
+            Invoice::issued("2023-001", "2023-06-01", "100 EUR")
+                .with_due_date("2023-07-05")
+                .with_customer("Acme"),
+            Invoice::issued("2023-002", "2023-06-12", "200 EUR")
+                .with_due_date("2023-07-12")
+                .with_customer("Acme"),
+        ];
+        let options = ForecastOptions {
+            filter: Some("total > 150".parse().unwrap()),
+            ..options()
+        };
+
+        let actual = forecast(&options, Decimal::from(1000), &invoices, &[]);
+
+        assert_eq!("1200 EUR", actual.weeks[3].balance.to_string());
+    }
+
+    #[test]
+    fn test_recurring_items_and_paid_invoices() {
+        let mut paid = Invoice::issued("2023-001", "2023-06-01", "100 EUR")
+            .with_due_date("2023-07-05")
+            .with_customer("Acme");
+        paid.payments.push(Payment {
+            date: "2023-06-20".into(),
+            amount: "100 EUR".parse().unwrap(),
+        });
+        let mut options = options();
+        options.recurring.push(RecurringItem {
+            description: "Rent".to_string(),
+            amount: Decimal::from(600),
+            currency: "EUR".to_string(),
+            every: Frequency::Monthly,
+            start: NaiveDate::from_ymd_opt(2023, 1, 20).unwrap(),
+            end: None,
+        });
+
+        let actual = forecast(&options, Decimal::from(1000), &[paid], &[]);
+
+        assert_eq!("600 EUR", actual.weeks[2].outflow.to_string());
+        assert_eq!("400 EUR", actual.weeks[3].balance.to_string());
+        let (week, _) = actual.first_shortfall().unwrap();
+        assert_eq!(2, week);
+    }
+}
@@ -0,0 +1,338 @@
+use core::fmt;
+use std::error::Error;
+
+use chrono::{Duration, Months, NaiveDate};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    domain::{
+        invoice::{Date, Invoice, InvoiceNumber},
+        money::Money,
+    },
+    error::TabulaError,
+};
+
+const BUNDLED_RATES: &str = include_str!("statutory_interest_rates.toml");
+
+/// The statutory interest rate, in percent per year, from `from` until the next rate.
+#[derive(Debug, Deserialize, Clone)]
+pub struct Rate {
+    pub from: NaiveDate,
+    pub rate: Decimal,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct RateTable {
+    rates: Vec<Rate>,
+}
+
+impl RateTable {
+    /// The rates shipped with tabula, see `statutory_interest_rates.toml`.
+    pub fn bundled() -> Self {
+        Self::from_toml(BUNDLED_RATES).expect("Bundled interest rates are valid")
+    }
+
+    pub fn from_toml(content: &str) -> Result<Self, toml::de::Error> {
+        let mut table: RateTable = toml::from_str(content)?;
+        table.rates.sort_by_key(|rate| rate.from);
+        Ok(table)
+    }
+
+    fn rate_on(&self, date: NaiveDate) -> Option<Decimal> {
+        self.rates
+            .iter()
+            .rev()
+            .find(|rate| rate.from <= date)
+            .map(|rate| rate.rate)
+    }
+
+    /// The dates after `from`, up to and including `until`, on which the rate changes.
+    fn changes(&self, from: NaiveDate, until: NaiveDate) -> impl Iterator<Item = NaiveDate> + '_ {
+        self.rates
+            .iter()
+            .map(|rate| rate.from)
+            .filter(move |date| *date > from && *date <= until)
+    }
+}
+
+impl Default for RateTable {
+    fn default() -> Self {
+        Self::bundled()
+    }
+}
+
+pub struct InterestOptions {
+    /// The last day over which interest is calculated, and the date of the interest invoice
+    pub date: NaiveDate,
+    pub payment_terms_days: i64,
+    pub rates: RateTable,
+}
+
+impl Default for InterestOptions {
+    fn default() -> Self {
+        Self {
+            date: chrono::Local::now().date_naive(),
+            payment_terms_days: 30,
+            rates: RateTable::bundled(),
+        }
+    }
+}
+
+/// A stretch of days with the same rate over the same principal.
+#[derive(Serialize)]
+pub struct InterestPeriod {
+    pub from: Date,
+    pub until: Date,
+    pub days: i64,
+    pub rate: Decimal,
+    pub principal: Money,
+    pub interest: Money,
+}
+
+#[derive(Serialize)]
+pub struct InterestCalculation {
+    pub date: Date,
+    pub due_date: Date,
+    pub invoice: Invoice,
+    pub principal: Money,
+    pub periods: Vec<InterestPeriod>,
+    pub interest: Money,
+    pub collection_costs: Money,
+    pub total: Money,
+}
+
+#[derive(Debug)]
+pub enum InterestError {
+    /// Drafts are not owed yet
+    Draft(InvoiceNumber),
+    NotOverdue(InvoiceNumber),
+    UnknownAmount(InvoiceNumber),
+    NoRate(NaiveDate),
+}
+
+impl Error for InterestError {}
+
+impl fmt::Display for InterestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InterestError::Draft(number) => {
+                write!(f, "Invoice {} is a draft, so no interest is owed", number)
+            }
+            InterestError::NotOverdue(number) => write!(f, "Invoice {} is not overdue", number),
+            InterestError::UnknownAmount(number) => {
+                write!(f, "The total of invoice {} is not an amount", number)
+            }
+            InterestError::NoRate(date) => {
+                write!(f, "No statutory interest rate is known for {}", date)
+            }
+        }
+    }
+}
+
+impl From<InterestError> for TabulaError {
+    fn from(err: InterestError) -> Self {
+        match err {
+            InterestError::NoRate(_) => TabulaError::Config(err.to_string()),
+            _ => TabulaError::Validation(err.to_string()),
+        }
+    }
+}
+
+/// Calculates the statutory commercial interest over what is still outstanding on an
+/// invoice, from the day after its due date up to and including `options.date`. Each year
+/// the interest is added to the principal, as art. 6:119a BW prescribes.
+pub fn statutory_interest(
+    options: &InterestOptions,
+    invoice: &Invoice,
+) -> Result<InterestCalculation, InterestError> {
+    let number = invoice.number.clone();
+    if invoice.state.is_draft() {
+        return Err(InterestError::Draft(number));
+    }
+    let outstanding = invoice
+        .outstanding()
+        .ok_or(InterestError::UnknownAmount(number.clone()))?;
+    let currency = outstanding.currency.as_str();
+
+    let start = invoice.expected_due_date(options.payment_terms_days) + Duration::days(1);
+    if start > options.date || outstanding.amount <= Decimal::ZERO {
+        return Err(InterestError::NotOverdue(number));
+    }
+    let end = options.date + Duration::days(1);
+
+    let mut principal = outstanding.amount;
+    let mut interest = Decimal::ZERO;
+    let mut periods = vec![];
+
+    let mut year = 0;
+    let mut year_start = start;
+    while year_start < end {
+        year += 1;
+        let year_end = (start + Months::new(12 * year)).min(end);
+
+        let mut boundaries: Vec<NaiveDate> = options
+            .rates
+            .changes(year_start, year_end - Duration::days(1))
+            .collect();
+        boundaries.push(year_end);
+
+        let mut accrued = Decimal::ZERO;
+        let mut from = year_start;
+        for until in boundaries {
+            let rate = options
+                .rates
+                .rate_on(from)
+                .ok_or(InterestError::NoRate(from))?;
+            let days = (until - from).num_days();
+            let amount = (principal * rate / Decimal::ONE_HUNDRED * Decimal::from(days)
+                / Decimal::from(365))
+            .round_dp(2);
+
+            periods.push(InterestPeriod {
+                from: Date(from),
+                until: Date(until - Duration::days(1)),
+                days,
+                rate,
+                principal: Money::new(principal, currency),
+                interest: Money::new(amount, currency),
+            });
+            accrued += amount;
+            from = until;
+        }
+
+        interest += accrued;
+        principal += accrued;
+        year_start = year_end;
+    }
+
+    let collection_costs = if currency == "EUR" {
+        collection_costs(outstanding.amount)
+    } else {
+        Decimal::ZERO
+    };
+
+    Ok(InterestCalculation {
+        date: Date(options.date),
+        due_date: Date(options.date + Duration::days(options.payment_terms_days)),
+        invoice: invoice.clone(),
+        principal: outstanding.clone(),
+        periods,
+        interest: Money::new(interest, currency),
+        collection_costs: Money::new(collection_costs, currency),
+        total: Money::new(interest + collection_costs, currency),
+    })
+}
+
+/// Extrajudicial collection costs over a principal in euro, following the scale of the
+/// Besluit vergoeding voor buitengerechtelijke incassokosten (WIK staffel).
+pub fn collection_costs(principal: Decimal) -> Decimal {
+    // Percentage over each bracket of the principal; the last one over the rest.
+    let brackets: [(Decimal, Decimal); 5] = [
+        (Decimal::from(2_500), Decimal::new(15, 0)),
+        (Decimal::from(2_500), Decimal::new(10, 0)),
+        (Decimal::from(5_000), Decimal::new(5, 0)),
+        (Decimal::from(190_000), Decimal::new(1, 0)),
+        (Decimal::MAX, Decimal::new(5, 1)),
+    ];
+
+    let mut rest = principal;
+    let mut costs = Decimal::ZERO;
+    for (size, percentage) in brackets {
+        let part = rest.min(size);
+        costs += part * percentage / Decimal::ONE_HUNDRED;
+        rest -= part;
+        if rest <= Decimal::ZERO {
+            break;
+        }
+    }
+
+    costs
+        .round_dp(2)
+        .clamp(Decimal::from(40), Decimal::from(6_775))
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::domain::invoice::InvoiceState;
+
+    use super::*;
+
+    #[test]
+    fn test_interest_is_compounded_yearly() {
+        let options = InterestOptions {
+            date: NaiveDate::from_ymd_opt(2024, 1, 31).unwrap(),
+            ..InterestOptions::default()
+        };
+
+        let actual = statutory_interest(
+            &options,
+            &Invoice::issued("2022-001", "2022-11-01", "1000 EUR").with_due_date("2022-12-31"),
+        )
+        .unwrap();
+
+        let periods: Vec<(String, i64, String)> = actual
+            .periods
+            .iter()
+            .map(|p| (p.from.to_string(), p.days, p.interest.to_string()))
+            .collect();
+        // The interest of 2023 is added to the principal from 2024-01-01
+        assert_eq!(
+            vec![
+                ("2023-01-01".to_string(), 181, "52.07 EUR".to_string()),
+                ("2023-07-01".to_string(), 184, "60.49 EUR".to_string()),
+                ("2024-01-01".to_string(), 31, "11.81 EUR".to_string()),
+            ],
+            periods
+        );
+        assert_eq!("1112.56 EUR", actual.periods[2].principal.to_string());
+        assert_eq!("124.37 EUR", actual.interest.to_string());
+        assert_eq!("274.37 EUR", actual.total.to_string());
+    }
+
+    #[test]
+    fn test_invoice_that_is_not_overdue() {
+        let options = InterestOptions {
+            date: NaiveDate::from_ymd_opt(2022, 12, 31).unwrap(),
+            ..InterestOptions::default()
+        };
+
+        let actual = statutory_interest(
+            &options,
+            &Invoice::issued("2022-001", "2022-11-01", "1000 EUR").with_due_date("2022-12-31"),
+        );
+
+        assert!(matches!(actual, Err(InterestError::NotOverdue(_))));
+        assert_eq!(5, TabulaError::from(actual.err().unwrap()).exit_code());
+    }
+
+    #[test]
+    fn test_no_interest_on_drafts() {
+        let options = InterestOptions {
+            date: NaiveDate::from_ymd_opt(2024, 1, 31).unwrap(),
+            ..InterestOptions::default()
+        };
+        let draft = Invoice {
+            state: InvoiceState::Draft,
+            ..Invoice::issued("2022-001", "2022-11-01", "1000 EUR").with_due_date("2022-12-31")
+        };
+
+        let actual = statutory_interest(&options, &draft);
+
+        assert!(matches!(actual, Err(InterestError::Draft(_))));
+        assert_eq!(5, TabulaError::from(actual.err().unwrap()).exit_code());
+    }
+
+    #[test]
+    fn test_collection_costs_scale() {
+        let costs = |principal: i64| collection_costs(Decimal::from(principal));
+
+        assert_eq!(Decimal::from(40), costs(100));
+        assert_eq!(Decimal::from(150), costs(1_000));
+        assert_eq!(Decimal::from(425), costs(3_000));
+        assert_eq!(Decimal::from(3_275), costs(300_000));
+        assert_eq!(Decimal::from(6_775), costs(2_000_000));
+    }
+}
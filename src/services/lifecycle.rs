@@ -0,0 +1,166 @@
+use std::collections::HashSet;
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::domain::invoice::{Date, Invoice, InvoiceNumber};
+
+/// Something that happened to an invoice, as published to webhooks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvoiceEvent {
+    Created,
+    Sent,
+    Paid,
+    Overdue,
+    Credited,
+}
+
+impl InvoiceEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            InvoiceEvent::Created => "invoice.created",
+            InvoiceEvent::Sent => "invoice.sent",
+            InvoiceEvent::Paid => "invoice.paid",
+            InvoiceEvent::Overdue => "invoice.overdue",
+            InvoiceEvent::Credited => "invoice.credited",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+struct InvoiceState {
+    sent_on: Option<Date>,
+    paid: bool,
+    overdue: bool,
+    credited: bool,
+}
+
+/// Where each invoice in a ledger stands on a given day. Comparing two of these tells which
+/// events happened in between.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Lifecycle {
+    states: Vec<(InvoiceNumber, InvoiceState)>,
+}
+
+impl Lifecycle {
+    pub fn new(invoices: &[Invoice], today: NaiveDate, payment_terms_days: i64) -> Self {
+        let credited: HashSet<&str> = invoices
+            .iter()
+            .filter_map(|invoice| invoice.credits_invoice.as_ref())
+            .map(|number| number.0.as_str())
+            .collect();
+
+        let states = invoices
+            .iter()
+            .map(|invoice| {
+                let state = InvoiceState {
+                    sent_on: invoice.sent_on.clone(),
+                    paid: invoice.paid_on().is_some(),
+                    // Drafts are not owed yet, so they cannot be overdue
+                    overdue: !invoice.state.is_draft()
+                        && invoice.is_open()
+                        && invoice.expected_due_date(payment_terms_days) < today,
+                    credited: credited.contains(invoice.number.0.as_str()),
+                };
+                (invoice.number.clone(), state)
+            })
+            .collect();
+
+        Self { states }
+    }
+
+    fn state(&self, number: &InvoiceNumber) -> Option<&InvoiceState> {
+        self.states
+            .iter()
+            .find(|(n, _)| n == number)
+            .map(|(_, state)| state)
+    }
+
+    /// The events that lead from this lifecycle to `later`, in ledger order.
+    pub fn events_until(&self, later: &Lifecycle) -> Vec<(InvoiceEvent, InvoiceNumber)> {
+        let mut events = vec![];
+
+        for (number, after) in &later.states {
+            let before = match self.state(number) {
+                Some(before) => before.clone(),
+                None => {
+                    events.push((InvoiceEvent::Created, number.clone()));
+                    InvoiceState::default()
+                }
+            };
+
+            if after.sent_on.is_some() && after.sent_on != before.sent_on {
+                events.push((InvoiceEvent::Sent, number.clone()));
+            }
+            if after.paid && !before.paid {
+                events.push((InvoiceEvent::Paid, number.clone()));
+            }
+            if after.overdue && !before.overdue {
+                events.push((InvoiceEvent::Overdue, number.clone()));
+            }
+            if after.credited && !before.credited {
+                events.push((InvoiceEvent::Credited, number.clone()));
+            }
+        }
+
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::domain::{invoice, money::Money, payment::Payment};
+
+    use super::*;
+
+    #[test]
+    fn test_events_between_two_readings() {
+        let day = |d| NaiveDate::from_ymd_opt(2023, 7, d).unwrap();
+        let draft = Invoice {
+            state: invoice::InvoiceState::Draft,
+            ..Invoice::issued("TBD", "2023-06-01", "100 EUR").with_due_date("2023-07-01")
+        };
+        let before = vec![
+            Invoice::issued("2023-001", "2023-06-01", "100 EUR").with_due_date("2023-07-01"),
+            Invoice::issued("2023-002", "2023-06-01", "100 EUR").with_due_date("2023-07-01"),
+            draft.clone(),
+        ];
+
+        let mut paid =
+            Invoice::issued("2023-001", "2023-06-01", "100 EUR").with_due_date("2023-07-01");
+        paid.payments.push(Payment {
+            date: "2023-07-01".into(),
+            amount: Money::new(100.into(), "EUR"),
+        });
+        let credit_note = Invoice {
+            total: "-100 EUR".to_string(),
+            credits_invoice: Some(InvoiceNumber("2023-002".to_string())),
+            ..Invoice::issued("2023-003", "2023-06-01", "100 EUR").with_due_date("2023-07-01")
+        };
+        let after = vec![
+            paid,
+            Invoice::issued("2023-002", "2023-06-01", "100 EUR").with_due_date("2023-07-01"),
+            draft,
+            credit_note,
+        ];
+
+        let events =
+            Lifecycle::new(&before, day(1), 30).events_until(&Lifecycle::new(&after, day(2), 30));
+
+        let names: Vec<(&str, &str)> = events
+            .iter()
+            .map(|(event, number)| (event.name(), number.0.as_str()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("invoice.paid", "2023-001"),
+                ("invoice.overdue", "2023-002"),
+                ("invoice.credited", "2023-002"),
+                ("invoice.created", "2023-003"),
+            ],
+            names
+        );
+    }
+}
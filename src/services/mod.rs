@@ -1 +1,8 @@
-
+pub mod bills;
+pub mod dunning;
+pub mod e_invoice;
+pub mod forecast;
+pub mod interest;
+pub mod lifecycle;
+pub mod payment_behaviour;
+pub mod revenue;
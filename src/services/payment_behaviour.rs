@@ -0,0 +1,291 @@
+use std::collections::HashMap;
+
+use chrono::{Duration, NaiveDate};
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::domain::{
+    expression::Expression,
+    invoice::{Date, Invoice},
+    money::Money,
+};
+
+pub struct PaymentBehaviourOptions {
+    pub as_of: NaiveDate,
+    /// The number of days before `as_of` over which sales are counted for the DSO
+    pub window_days: i64,
+    pub currency: String,
+    pub payment_terms_days: i64,
+    /// Only count the invoices for which this holds
+    pub filter: Option<Expression>,
+}
+
+impl Default for PaymentBehaviourOptions {
+    fn default() -> Self {
+        Self {
+            as_of: chrono::Local::now().date_naive(),
+            window_days: 90,
+            currency: "EUR".to_string(),
+            payment_terms_days: 30,
+            filter: None,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct CustomerStats {
+    pub customer: String,
+    pub invoices: usize,
+    pub paid: usize,
+    pub average_days_to_pay: Option<Decimal>,
+    pub average_days_late: Option<Decimal>,
+    /// Percentage of the paid invoices that were paid on or before the due date
+    pub paid_on_time: Option<Decimal>,
+}
+
+#[derive(Serialize)]
+pub struct PaymentBehaviour {
+    pub as_of: Date,
+    pub window_days: i64,
+    /// Days Sales Outstanding: open receivables relative to the sales in the window
+    pub days_sales_outstanding: Option<Decimal>,
+    pub receivables: Money,
+    pub sales: Money,
+    pub customers: Vec<CustomerStats>,
+}
+
+fn days_to_pay(invoice: &Invoice) -> Option<i64> {
+    invoice
+        .paid_on()
+        .map(|paid_on| (paid_on - invoice.date.0).num_days())
+}
+
+fn days_late(invoice: &Invoice, payment_terms_days: i64) -> Option<i64> {
+    invoice
+        .paid_on()
+        .map(|paid_on| (paid_on - invoice.expected_due_date(payment_terms_days)).num_days())
+}
+
+fn average(values: &[i64]) -> Option<Decimal> {
+    if values.is_empty() {
+        return None;
+    }
+    let sum: i64 = values.iter().sum();
+    Some((Decimal::from(sum) / Decimal::from(values.len())).round_dp(1))
+}
+
+/// Average number of days each customer paid after the due date, over all paid invoices.
+/// Negative numbers mean the customer usually pays early.
+pub fn average_days_late(invoices: &[Invoice], payment_terms_days: i64) -> HashMap<String, i64> {
+    let mut days_late_per_customer: HashMap<String, Vec<i64>> = HashMap::new();
+
+    for invoice in invoices {
+        let (Some(customer), Some(late)) =
+            (&invoice.customer, days_late(invoice, payment_terms_days))
+        else {
+            continue;
+        };
+        days_late_per_customer
+            .entry(customer.clone())
+            .or_default()
+            .push(late);
+    }
+
+    days_late_per_customer
+        .into_iter()
+        .map(|(customer, days)| {
+            let average = days.iter().sum::<i64>() / days.len() as i64;
+            (customer, average)
+        })
+        .collect()
+}
+
+/// What was still to be received for an invoice at the end of `date`.
+fn outstanding_on(invoice: &Invoice, date: NaiveDate) -> Decimal {
+    let Some(total) = invoice.total_amount() else {
+        return Decimal::ZERO;
+    };
+    let paid: Decimal = invoice
+        .payments
+        .iter()
+        .filter(|payment| payment.date.0 <= date)
+        .map(|payment| payment.amount.amount)
+        .sum();
+    total.amount - paid
+}
+
+pub fn payment_behaviour(
+    options: &PaymentBehaviourOptions,
+    invoices: &[Invoice],
+) -> PaymentBehaviour {
+    let currency = options.currency.as_str();
+    // Drafts are neither sold nor receivable until they are issued
+    let invoices: Vec<&Invoice> = invoices
+        .iter()
+        .filter(|invoice| !invoice.state.is_draft())
+        .filter(|invoice| invoice.date.0 <= options.as_of)
+        .filter(|invoice| {
+            options.filter.as_ref().is_none_or(|filter| {
+                filter.matches(invoice, options.as_of, options.payment_terms_days)
+            })
+        })
+        .filter(|invoice| {
+            invoice
+                .total_amount()
+                .map(|total| total.currency == currency)
+                .unwrap_or(false)
+        })
+        .collect();
+
+    let mut per_customer: Vec<(String, Vec<&Invoice>)> = vec![];
+    for invoice in &invoices {
+        let customer = invoice.customer.clone().unwrap_or("(none)".to_string());
+        match per_customer.iter_mut().find(|(name, _)| *name == customer) {
+            Some((_, invoices)) => invoices.push(invoice),
+            None => per_customer.push((customer, vec![invoice])),
+        }
+    }
+    per_customer.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let customers = per_customer
+        .into_iter()
+        .map(|(customer, invoices)| {
+            let paid: Vec<&Invoice> = invoices
+                .iter()
+                .copied()
+                .filter(|invoice| matches!(invoice.paid_on(), Some(d) if d <= options.as_of))
+                .collect();
+            let to_pay: Vec<i64> = paid.iter().filter_map(|i| days_to_pay(i)).collect();
+            let late: Vec<i64> = paid
+                .iter()
+                .filter_map(|i| days_late(i, options.payment_terms_days))
+                .collect();
+            let on_time = late.iter().filter(|days| **days <= 0).count();
+
+            CustomerStats {
+                customer,
+                invoices: invoices.len(),
+                paid: paid.len(),
+                average_days_to_pay: average(&to_pay),
+                average_days_late: average(&late),
+                paid_on_time: if late.is_empty() {
+                    None
+                } else {
+                    Some((Decimal::from(on_time * 100) / Decimal::from(late.len())).round_dp(1))
+                },
+            }
+        })
+        .collect();
+
+    let window_start = options.as_of - Duration::days(options.window_days);
+    let receivables: Decimal = invoices
+        .iter()
+        .map(|invoice| outstanding_on(invoice, options.as_of))
+        .sum();
+    let sales: Decimal = invoices
+        .iter()
+        .filter(|invoice| invoice.date.0 > window_start)
+        .filter_map(|invoice| invoice.total_amount())
+        .map(|total| total.amount)
+        .sum();
+    let days_sales_outstanding = if sales.is_zero() {
+        None
+    } else {
+        Some((receivables / sales * Decimal::from(options.window_days)).round_dp(1))
+    };
+
+    PaymentBehaviour {
+        as_of: Date(options.as_of),
+        window_days: options.window_days,
+        days_sales_outstanding,
+        receivables: Money::new(receivables, currency),
+        sales: Money::new(sales, currency),
+        customers,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::domain::invoice::InvoiceState;
+
+    use super::*;
+
+    #[test]
+    fn test_customer_stats() {
+        let invoices = vec![
+            Invoice::issued("2023-001", "2023-01-01", "100 EUR")
+                .with_due_date("2023-01-31")
+                .with_customer("Acme")
+                .with_payment("2023-01-21", "100 EUR"),
+            Invoice::issued("2023-002", "2023-02-01", "100 EUR")
+                .with_due_date("2023-03-03")
+                .with_customer("Acme")
+                .with_payment("2023-03-13", "100 EUR"),
+            Invoice::issued("2023-003", "2023-03-01", "100 EUR")
+                .with_due_date("2023-03-31")
+                .with_customer("Acme"),
+        ];
+        let options = PaymentBehaviourOptions {
+            as_of: NaiveDate::from_ymd_opt(2023, 3, 31).unwrap(),
+            ..PaymentBehaviourOptions::default()
+        };
+
+        let actual = payment_behaviour(&options, &invoices);
+
+        let acme = &actual.customers[0];
+        assert_eq!(3, acme.invoices);
+        assert_eq!(2, acme.paid);
+        assert_eq!(Some(Decimal::new(300, 1)), acme.average_days_to_pay);
+        assert_eq!(Some(Decimal::ZERO), acme.average_days_late);
+        assert_eq!(Some(Decimal::new(500, 1)), acme.paid_on_time);
+    }
+
+    #[test]
+    fn test_days_sales_outstanding() {
+        let invoices = vec![
+            Invoice::issued("2023-001", "2023-01-15", "300 EUR")
+                .with_due_date("2023-02-14")
+                .with_customer("Acme")
+                .with_payment("2023-02-10", "300 EUR"),
+            Invoice::issued("2023-002", "2023-03-01", "100 EUR")
+                .with_due_date("2023-03-31")
+                .with_customer("Acme"),
+        ];
+        let options = PaymentBehaviourOptions {
+            as_of: NaiveDate::from_ymd_opt(2023, 3, 31).unwrap(),
+            ..PaymentBehaviourOptions::default()
+        };
+
+        let actual = payment_behaviour(&options, &invoices);
+
+        // 100 EUR open out of 400 EUR sold in 90 days
+        assert_eq!(Some(Decimal::new(225, 1)), actual.days_sales_outstanding);
+        assert_eq!("100 EUR", actual.receivables.to_string());
+    }
+
+    #[test]
+    fn test_drafts_are_not_sales_nor_receivables() {
+        let invoices = vec![
+            Invoice::issued("2023-001", "2023-03-01", "100 EUR")
+                .with_due_date("2023-03-31")
+                .with_customer("Acme"),
+            Invoice {
+                state: InvoiceState::Draft,
+                ..Invoice::issued("2023-002", "2023-03-15", "500 EUR")
+                    .with_due_date("2023-04-14")
+                    .with_customer("Acme")
+            },
+        ];
+        let options = PaymentBehaviourOptions {
+            as_of: NaiveDate::from_ymd_opt(2023, 3, 31).unwrap(),
+            ..PaymentBehaviourOptions::default()
+        };
+
+        let actual = payment_behaviour(&options, &invoices);
+
+        assert_eq!(1, actual.customers[0].invoices);
+        assert_eq!("100 EUR", actual.receivables.to_string());
+    }
+}
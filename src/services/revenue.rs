@@ -0,0 +1,358 @@
+use std::collections::HashMap;
+
+use chrono::{Datelike, NaiveDate};
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use serde::Serialize;
+
+use crate::domain::{expression::Expression, invoice::Invoice, money::Money};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GroupBy {
+    Customer,
+    Month,
+    Quarter,
+    Account,
+}
+
+pub struct RevenueOptions {
+    pub group_by: GroupBy,
+    pub year: i32,
+    pub currency: String,
+    /// Only show the groups with the most revenue, summing the rest as "Other"
+    pub top: Option<usize>,
+    pub sparkline: bool,
+    pub payment_terms_days: i64,
+    /// Only count the invoices for which this holds
+    pub filter: Option<Expression>,
+}
+
+impl Default for RevenueOptions {
+    fn default() -> Self {
+        Self {
+            group_by: GroupBy::Customer,
+            year: chrono::Local::now().year(),
+            currency: "EUR".to_string(),
+            top: None,
+            sparkline: false,
+            payment_terms_days: 30,
+            filter: None,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct RevenueRow {
+    pub group: String,
+    pub amount: Money,
+    /// Percentage of the total revenue in the year
+    pub share: Decimal,
+    /// Revenue of the same group in the previous year
+    pub previous: Money,
+    /// Percentage change compared to the previous year, if there was revenue then
+    pub change: Option<Decimal>,
+    /// Revenue per month of the year, January first
+    pub monthly: Vec<Money>,
+}
+
+#[derive(Serialize)]
+pub struct RevenueReport {
+    pub year: i32,
+    pub total: Money,
+    pub rows: Vec<RevenueRow>,
+    #[serde(skip)]
+    pub sparkline: bool,
+}
+
+struct Entry {
+    date: NaiveDate,
+    /// How the group is shown, e.g. `2023-04`
+    group: String,
+    /// How the group is matched with the previous year, e.g. `04`
+    key: String,
+    amount: Decimal,
+}
+
+fn entries(invoice: &Invoice, group_by: GroupBy, currency: &str) -> Vec<Entry> {
+    let date = invoice.date.0;
+    invoice
+        .revenue_lines()
+        .into_iter()
+        .filter(|line| line.amount.currency == currency)
+        .map(|line| {
+            let (group, key) = match group_by {
+                GroupBy::Customer => {
+                    let customer = invoice.customer.clone().unwrap_or("(none)".to_string());
+                    (customer.clone(), customer)
+                }
+                GroupBy::Account => (line.account.clone(), line.account.clone()),
+                GroupBy::Month => (
+                    date.format("%Y-%m").to_string(),
+                    date.format("%m").to_string(),
+                ),
+                GroupBy::Quarter => {
+                    let quarter = format!("Q{}", date.month0() / 3 + 1);
+                    (format!("{}-{}", date.year(), quarter), quarter)
+                }
+            };
+            Entry {
+                date,
+                group,
+                key,
+                amount: line.amount.amount,
+            }
+        })
+        .collect()
+}
+
+struct Group {
+    name: String,
+    previous: Decimal,
+    monthly: [Decimal; 12],
+}
+
+impl Group {
+    fn amount(&self) -> Decimal {
+        self.monthly.iter().sum()
+    }
+}
+
+fn percentage(part: Decimal, whole: Decimal) -> Option<Decimal> {
+    if whole.is_zero() {
+        return None;
+    }
+    Some((part * Decimal::ONE_HUNDRED / whole).round_dp(1))
+}
+
+/// Sums invoiced amounts (not payments) in `options.year` per group. Drafts are not invoiced
+/// yet, so they are left out.
+pub fn revenue(options: &RevenueOptions, invoices: &[Invoice]) -> RevenueReport {
+    let currency = options.currency.as_str();
+    let today = chrono::Local::now().date_naive();
+    let all: Vec<Entry> = invoices
+        .iter()
+        .filter(|invoice| !invoice.state.is_draft())
+        .filter(|invoice| {
+            options
+                .filter
+                .as_ref()
+                .is_none_or(|filter| filter.matches(invoice, today, options.payment_terms_days))
+        })
+        .flat_map(|invoice| entries(invoice, options.group_by, currency))
+        .collect();
+
+    let mut previous: HashMap<&str, Decimal> = HashMap::new();
+    for entry in all.iter().filter(|e| e.date.year() == options.year - 1) {
+        *previous.entry(&entry.key).or_default() += entry.amount;
+    }
+
+    let mut groups: Vec<Group> = vec![];
+    for entry in all.iter().filter(|e| e.date.year() == options.year) {
+        let index = match groups.iter().position(|group| group.name == entry.group) {
+            Some(index) => index,
+            None => {
+                groups.push(Group {
+                    name: entry.group.clone(),
+                    previous: previous
+                        .get(entry.key.as_str())
+                        .copied()
+                        .unwrap_or_default(),
+                    monthly: [Decimal::ZERO; 12],
+                });
+                groups.len() - 1
+            }
+        };
+        groups[index].monthly[entry.date.month0() as usize] += entry.amount;
+    }
+
+    let total: Decimal = groups.iter().map(Group::amount).sum();
+
+    groups.sort_by(|a, b| b.amount().cmp(&a.amount()).then(a.name.cmp(&b.name)));
+    if let Some(top) = options.top.filter(|top| groups.len() > *top) {
+        let mut other = Group {
+            name: "Other".to_string(),
+            previous: Decimal::ZERO,
+            monthly: [Decimal::ZERO; 12],
+        };
+        for group in groups.drain(top..) {
+            other.previous += group.previous;
+            for (month, amount) in group.monthly.iter().enumerate() {
+                other.monthly[month] += amount;
+            }
+        }
+        groups.push(other);
+    }
+
+    if matches!(options.group_by, GroupBy::Month | GroupBy::Quarter) {
+        groups.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+
+    let rows = groups
+        .into_iter()
+        .map(|group| {
+            let amount = group.amount();
+            RevenueRow {
+                amount: Money::new(amount, currency),
+                share: percentage(amount, total).unwrap_or_default(),
+                previous: Money::new(group.previous, currency),
+                change: percentage(amount - group.previous, group.previous),
+                monthly: group
+                    .monthly
+                    .iter()
+                    .map(|m| Money::new(*m, currency))
+                    .collect(),
+                group: group.name,
+            }
+        })
+        .collect();
+
+    RevenueReport {
+        year: options.year,
+        total: Money::new(total, currency),
+        rows,
+        sparkline: options.sparkline,
+    }
+}
+
+/// Renders amounts as a row of ASCII characters, from `_` for the lowest to `#` for the highest.
+pub fn sparkline(amounts: &[Decimal]) -> String {
+    const LEVELS: [char; 8] = ['_', '.', ',', '-', '~', '=', '+', '#'];
+
+    let max = amounts.iter().max().copied().unwrap_or_default();
+    let min = amounts.iter().min().copied().unwrap_or_default();
+    let range = max - min;
+
+    amounts
+        .iter()
+        .map(|amount| {
+            if range.is_zero() {
+                return LEVELS[0];
+            }
+            let level = (*amount - min) * Decimal::from(LEVELS.len() - 1) / range;
+            let level = level.round().to_usize().unwrap_or_default();
+            LEVELS[level.min(LEVELS.len() - 1)]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::domain::invoice::InvoiceState;
+
+    use super::*;
+
+    #[test]
+    fn test_revenue_per_customer_with_share_and_change() {
+        let invoices = vec![
+            Invoice::issued("2022-001", "2022-03-01", "100 EUR").with_customer("Acme"),
+            Invoice::issued("2023-001", "2023-03-01", "150 EUR").with_customer("Acme"),
+            Invoice::issued("2023-002", "2023-04-01", "50 EUR").with_customer("Globex"),
+            Invoice::issued("2023-003", "2023-05-01", "100 USD").with_customer("Globex"),
+        ];
+        let options = RevenueOptions {
+            year: 2023,
+            ..RevenueOptions::default()
+        };
+
+        let actual = revenue(&options, &invoices);
+
+        assert_eq!("200 EUR", actual.total.to_string());
+        assert_eq!("Acme", actual.rows[0].group);
+        assert_eq!(Decimal::new(750, 1), actual.rows[0].share);
+        assert_eq!(Some(Decimal::new(500, 1)), actual.rows[0].change);
+        assert_eq!("Globex", actual.rows[1].group);
+        assert_eq!(None, actual.rows[1].change);
+    }
+
+    #[test]
+    fn test_top_sums_the_rest_as_other() {
+        let invoices = vec![
+            Invoice::issued("2023-001", "2023-01-01", "300 EUR").with_customer("Acme"),
+            Invoice::issued("2023-002", "2023-02-01", "200 EUR").with_customer("Globex"),
+            Invoice::issued("2023-003", "2023-03-01", "100 EUR").with_customer("Initech"),
+        ];
+        let options = RevenueOptions {
+            year: 2023,
+            top: Some(1),
+            ..RevenueOptions::default()
+        };
+
+        let actual = revenue(&options, &invoices);
+
+        let groups: Vec<(String, String)> = actual
+            .rows
+            .iter()
+            .map(|row| (row.group.clone(), row.amount.to_string()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("Acme".to_string(), "300 EUR".to_string()),
+                ("Other".to_string(), "300 EUR".to_string())
+            ],
+            groups
+        );
+    }
+
+    #[test]
+    fn test_top_of_more_than_all_groups_still_sorts_by_amount() {
+        let invoices = vec![
+            Invoice::issued("2023-001", "2023-01-01", "100 EUR").with_customer("Acme"),
+            Invoice::issued("2023-002", "2023-02-01", "300 EUR").with_customer("Globex"),
+        ];
+        let options = RevenueOptions {
+            year: 2023,
+            top: Some(5),
+            ..RevenueOptions::default()
+        };
+
+        let actual = revenue(&options, &invoices);
+
+        let groups: Vec<&str> = actual.rows.iter().map(|row| row.group.as_str()).collect();
+        assert_eq!(vec!["Globex", "Acme"], groups);
+    }
+
+    #[test]
+    fn test_drafts_are_not_revenue() {
+        let invoices = vec![
+            Invoice::issued("2023-001", "2023-01-01", "300 EUR").with_customer("Acme"),
+            Invoice {
+                state: InvoiceState::Draft,
+                ..Invoice::issued("2023-002", "2023-02-01", "200 EUR").with_customer("Globex")
+            },
+        ];
+        let options = RevenueOptions {
+            year: 2023,
+            ..RevenueOptions::default()
+        };
+
+        let actual = revenue(&options, &invoices);
+
+        assert_eq!("300 EUR", actual.total.to_string());
+        assert_eq!(1, actual.rows.len());
+    }
+
+    #[test]
+    fn test_filter_limits_the_invoices_counted() {
+        let invoices = vec![
+            Invoice::issued("2023-001", "2023-01-01", "300 EUR").with_customer("Acme"),
+            Invoice::issued("2023-002", "2023-02-01", "200 EUR").with_customer("Globex"),
+        ];
+        let options = RevenueOptions {
+            year: 2023,
+            filter: Some("customer ~ 'glob'".parse().unwrap()),
+            ..RevenueOptions::default()
+        };
+
+        let actual = revenue(&options, &invoices);
+
+        assert_eq!("200 EUR", actual.total.to_string());
+        assert_eq!("Globex", actual.rows[0].group);
+    }
+
+    #[test]
+    fn test_sparkline() {
+        let amounts: Vec<Decimal> = [0, 2, 14].into_iter().map(Decimal::from).collect();
+        assert_eq!("_.#", sparkline(&amounts));
+    }
+}
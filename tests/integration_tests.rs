@@ -87,7 +87,8 @@ fn test_that_invoice_build_generates_template_json() -> Result<(), Box<dyn std::
             "due_date": None::<String>,
             "number": "TBD",
             "total": "1337 USD",
-            "line_items": []
+            "line_items": [],
+            "state": "draft"
         }
     );
 
@@ -192,6 +193,1397 @@ Invoice #2
     Ok(())
 }
 
+#[test]
+fn test_that_reports_forecast_projects_weekly_balance() -> Result<(), Box<dyn std::error::Error>> {
+    let mut file_content = String::new();
+    File::open("./tests/fixtures/forecast.beancount")?.read_to_string(&mut file_content)?;
+
+    let mut cmd = Command::cargo_bin("tabula")?;
+    let out = cmd
+        .args(&["--format", "json"])
+        .arg("reports")
+        .arg("forecast")
+        .args(&[
+            "--start",
+            "2023-07-03",
+            "--weeks",
+            "4",
+            "--threshold",
+            "1000",
+        ])
+        .write_stdin(file_content.clone())
+        .unwrap()
+        .stdout;
+
+    let actual: serde_json::Value = serde_json::from_slice(&out).unwrap();
+    let balances: Vec<&str> = actual["weeks"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|week| week["balance"].as_str().unwrap())
+        .collect();
+
+    assert_eq!("3000 EUR", actual["opening_balance"]);
+    assert_eq!(vec!["3000 EUR", "3500 EUR", "700 EUR", "700 EUR"], balances);
+
+    let mut cmd = Command::cargo_bin("tabula")?;
+    cmd.arg("reports")
+        .arg("forecast")
+        .args(&[
+            "--start",
+            "2023-07-03",
+            "--weeks",
+            "4",
+            "--threshold",
+            "1000",
+        ])
+        .write_stdin(file_content)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Warning: balance of 700 EUR in week 3 (2023-07-17) is below the threshold of 1000 EUR",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn test_that_reports_revenue_groups_by_customer() -> Result<(), Box<dyn std::error::Error>> {
+    let mut file_content = String::new();
+    File::open("./tests/fixtures/revenue.beancount")?.read_to_string(&mut file_content)?;
+
+    let mut cmd = Command::cargo_bin("tabula")?;
+    let out = cmd
+        .args(&["--format", "json"])
+        .arg("reports")
+        .arg("revenue")
+        .args(&["--year", "2023"])
+        .write_stdin(file_content.clone())
+        .unwrap()
+        .stdout;
+
+    let actual: serde_json::Value = serde_json::from_slice(&out).unwrap();
+    assert_eq!("1500 EUR", actual["total"]);
+    assert_eq!("Acme", actual["rows"][0]["group"]);
+    assert_eq!("1000 EUR", actual["rows"][0]["amount"]);
+    assert_eq!("800 EUR", actual["rows"][0]["previous"]);
+
+    let mut cmd = Command::cargo_bin("tabula")?;
+    cmd.args(&["--format", "csv"])
+        .arg("reports")
+        .arg("revenue")
+        .args(&["--year", "2023", "--group-by", "account"])
+        .write_stdin(file_content)
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with(
+            "Group,Currency,Amount,Share,Previous year,Change\n",
+        ))
+        .stdout(predicate::str::contains(
+            "Income:Consulting,EUR,500,33.3,0,\n",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn test_that_customers_stats_shows_payment_behaviour() -> Result<(), Box<dyn std::error::Error>> {
+    let mut file_content = String::new();
+    File::open("./tests/fixtures/forecast.beancount")?.read_to_string(&mut file_content)?;
+
+    let mut cmd = Command::cargo_bin("tabula")?;
+    let out = cmd
+        .args(&["--format", "json"])
+        .arg("customers")
+        .arg("stats")
+        .args(&["--as-of", "2023-07-31"])
+        .write_stdin(file_content)
+        .unwrap()
+        .stdout;
+
+    let actual: serde_json::Value = serde_json::from_slice(&out).unwrap();
+    assert_eq!("30.0", actual["days_sales_outstanding"]);
+    assert_eq!("Acme", actual["customers"][0]["customer"]);
+    assert_eq!("-11", actual["customers"][0]["average_days_late"]);
+
+    Ok(())
+}
+
+#[test]
+fn test_that_invoices_remind_dry_run_lists_due_reminders() -> Result<(), Box<dyn std::error::Error>>
+{
+    let mut file_content = String::new();
+    File::open("./tests/fixtures/reminders.beancount")?.read_to_string(&mut file_content)?;
+
+    let mut cmd = Command::cargo_bin("tabula")?;
+    let out = cmd
+        .args(&["--format", "json"])
+        .arg("invoices")
+        .arg("remind")
+        .args(&["--dry-run", "--date", "2023-08-01"])
+        .write_stdin(file_content)
+        .unwrap()
+        .stdout;
+
+    let actual: serde_json::Value = serde_json::from_slice(&out).unwrap();
+    let letters: Vec<(&str, &str)> = actual["letters"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|letter| {
+            (
+                letter["invoice"]["number"].as_str().unwrap(),
+                letter["level"].as_str().unwrap(),
+            )
+        })
+        .collect();
+
+    assert_eq!(vec![("2023-001", "first"), ("2023-002", "second")], letters);
+
+    Ok(())
+}
+
+#[test]
+fn test_that_invoices_remind_records_reminders_in_ledger() -> Result<(), Box<dyn std::error::Error>>
+{
+    let ledger = assert_fs::NamedTempFile::new("ledger.beancount")?;
+    std::fs::copy("./tests/fixtures/reminders.beancount", ledger.path())?;
+
+    let mut cmd = Command::cargo_bin("tabula")?;
+    cmd.args(&["--ledger", ledger.path().to_str().unwrap()])
+        .arg("invoices")
+        .arg("remind")
+        .args(&["--date", "2023-08-01"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Second reminder"));
+
+    let mut content = String::new();
+    File::open(ledger.path())?.read_to_string(&mut content)?;
+    assert!(content.ends_with(
+        "2023-08-01 custom \"reminder\" \"2023-001\" \"first\"\n\
+         2023-08-01 custom \"reminder\" \"2023-002\" \"second\"\n"
+    ));
+
+    // Reminders that were recorded are not sent again
+    let mut cmd = Command::cargo_bin("tabula")?;
+    cmd.args(&["--ledger", ledger.path().to_str().unwrap()])
+        .args(&["--format", "beancount"])
+        .arg("invoices")
+        .arg("remind")
+        .args(&["--date", "2023-08-01"])
+        .assert()
+        .success()
+        .stdout("\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_that_invoices_remind_is_recorded_in_audit_log() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    let ledger = dir.path().join("ledger.beancount");
+    std::fs::copy("./tests/fixtures/reminders.beancount", &ledger)?;
+
+    let mut cmd = Command::cargo_bin("tabula")?;
+    cmd.args(["--ledger", ledger.to_str().unwrap()])
+        .args(["--log-format", "json"])
+        .arg("invoices")
+        .arg("remind")
+        .args(["--date", "2023-08-01"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("\"level\":\"info\""));
+
+    let audit = std::fs::read_to_string(dir.path().join("audit.jsonl"))?;
+    let entry: serde_json::Value = serde_json::from_str(audit.lines().next().unwrap())?;
+    assert_eq!("invoices remind", entry["command"]);
+    assert_eq!(
+        serde_json::json!(["2023-001", "2023-002"]),
+        entry["invoices"]
+    );
+    assert_ne!(entry["ledger_sha256_before"], entry["ledger_sha256_after"]);
+
+    // A dry run changes nothing, so it is not audited
+    let mut cmd = Command::cargo_bin("tabula")?;
+    cmd.args(["--ledger", ledger.to_str().unwrap()])
+        .arg("-q")
+        .arg("invoices")
+        .arg("remind")
+        .arg("--dry-run")
+        .assert()
+        .success()
+        .stderr("");
+    assert_eq!(
+        1,
+        std::fs::read_to_string(dir.path().join("audit.jsonl"))?
+            .lines()
+            .count()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_that_invoices_interest_renders_interest_invoice() -> Result<(), Box<dyn std::error::Error>>
+{
+    let mut file_content = String::new();
+    File::open("./tests/fixtures/reminders.beancount")?.read_to_string(&mut file_content)?;
+
+    let mut cmd = Command::cargo_bin("tabula")?;
+    let out = cmd
+        .args(&["--format", "json"])
+        .arg("invoices")
+        .arg("interest")
+        .args(&["--invoice-number", "2023-001", "--date", "2023-12-31"])
+        .write_stdin(file_content.clone())
+        .unwrap()
+        .stdout;
+
+    let actual: serde_json::Value = serde_json::from_slice(&out).unwrap();
+    assert_eq!(183, actual["periods"][0]["days"]);
+    assert_eq!("60.16 EUR", actual["interest"]);
+    assert_eq!("150 EUR", actual["collection_costs"]);
+    assert_eq!("210.16 EUR", actual["total"]);
+
+    let mut cmd = Command::cargo_bin("tabula")?;
+    cmd.args(&["--format", "beancount"])
+        .arg("invoices")
+        .arg("interest")
+        .args(&["--invoice-number", "2023-001", "--date", "2023-12-31"])
+        .write_stdin(file_content.clone())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "\tinvoice_number: \"2023-001-interest\"\n",
+        ))
+        .stdout(predicate::str::contains(
+            "\tAssets:AccountsReceivable\t210.16 EUR\n",
+        ));
+
+    // Drafts are not owed yet
+    let mut cmd = Command::cargo_bin("tabula")?;
+    cmd.arg("invoices")
+        .arg("interest")
+        .args(&["--invoice-number", "TBD", "--date", "2023-12-31"])
+        .write_stdin(file_content)
+        .assert()
+        .code(5)
+        .stderr(predicate::str::contains("Invoice TBD is a draft"));
+
+    Ok(())
+}
+
+#[test]
+fn test_that_serve_answers_invoice_requests() -> Result<(), Box<dyn std::error::Error>> {
+    use std::io::Write;
+    use std::net::TcpStream;
+
+    let ledger = assert_fs::NamedTempFile::new("ledger.beancount")?;
+    std::fs::copy("./tests/fixtures/reminders.beancount", ledger.path())?;
+
+    let mut server = std::process::Command::new(assert_cmd::cargo::cargo_bin("tabula"))
+        .args(&["serve", "--bind", "127.0.0.1:0"])
+        .args(&["--ledger", ledger.path().to_str().unwrap()])
+        .stderr(std::process::Stdio::piped())
+        .spawn()?;
+    let mut log = std::io::BufReader::new(server.stderr.take().unwrap());
+    let mut line = String::new();
+    let bind = loop {
+        line.clear();
+        if std::io::BufRead::read_line(&mut log, &mut line)? == 0 {
+            panic!("Server stopped before listening");
+        }
+        if let Some((_, address)) = line.trim().split_once("Listening on http://") {
+            break address.to_string();
+        }
+    };
+
+    let request = |request: &str| -> String {
+        let mut stream = TcpStream::connect(&bind).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    };
+
+    let found = request("GET /invoices/2023-002 HTTP/1.1\r\nConnection: close\r\n\r\n");
+    let body = r#"{"total": "250 EUR", "date": "2023-08-01"}"#;
+    let created = request(&format!(
+        "POST /invoices HTTP/1.1\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    ));
+    let missing = request("GET /invoices/2023-999 HTTP/1.1\r\nConnection: close\r\n\r\n");
+    let body = r#"{"total": "1 EUR", "customer": "Acme\n2023-08-01 * \"Injected\""}"#;
+    let injected = request(&format!(
+        "POST /invoices HTTP/1.1\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    ));
+    let body = r#"{"amount": "1 EUR", "account": "Assets:Bank\tEUR\n"}"#;
+    let bad_account = request(&format!(
+        "POST /invoices/2023-002/payments HTTP/1.1\r\nContent-Length: {}\r\nConnection: \
+         close\r\n\r\n{}",
+        body.len(),
+        body
+    ));
+    server.kill()?;
+
+    assert!(found.starts_with("HTTP/1.1 200"));
+    assert!(found.contains("\"number\": \"2023-002\""));
+    assert!(created.contains("\"number\": \"2023-004\""));
+    assert!(missing.starts_with("HTTP/1.1 404"));
+    assert!(injected.starts_with("HTTP/1.1 400"));
+    assert!(bad_account.starts_with("HTTP/1.1 400"));
+
+    let mut content = String::new();
+    File::open(ledger.path())?.read_to_string(&mut content)?;
+    assert!(content.contains("invoice_number: \"2023-004\""));
+    assert!(!content.contains("Injected"));
+
+    Ok(())
+}
+
+#[test]
+fn test_that_invoices_send_drops_message_and_records_it() -> Result<(), Box<dyn std::error::Error>>
+{
+    let dir = assert_fs::TempDir::new()?;
+    let ledger = dir.path().join("ledger.beancount");
+    std::fs::copy("./tests/fixtures/send.beancount", &ledger)?;
+    let outbox = dir.path().join("outbox");
+    let config = dir.path().join("tabula.toml");
+    std::fs::write(
+        &config,
+        format!(
+            "[notification]\nfrom = \"invoices@example.com\"\ndrop_dir = \"{}\"\n",
+            outbox.display()
+        ),
+    )?;
+
+    let mut cmd = Command::cargo_bin("tabula")?;
+    cmd.args(&["--ledger", ledger.to_str().unwrap()])
+        .args(&["--config", config.to_str().unwrap()])
+        .arg("invoices")
+        .arg("send")
+        .args(&["--invoice-number", "2023-001"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!("Sent on: {}", today())));
+
+    let messages: Vec<_> = std::fs::read_dir(&outbox)?.collect::<Result<_, _>>()?;
+    assert_eq!(1, messages.len());
+    let message = String::from_utf8_lossy(&std::fs::read(messages[0].path())?).into_owned();
+    assert!(message.contains("To: billing@acme.example"));
+    assert!(message.contains("Content-Type: application/pdf"));
+
+    // The PDF that was sent is archived, and its hash recorded with the invoice
+    let archived = std::fs::read(dir.path().join("documents/invoices/2023/2023-001.pdf"))?;
+    assert!(archived.starts_with(b"%PDF"));
+
+    let mut content = String::new();
+    File::open(&ledger)?.read_to_string(&mut content)?;
+    assert!(content.contains("\tdocument: \"documents/invoices/2023/2023-001.pdf\"\n"));
+    assert!(content.contains("\tdocument_sha256: \""));
+    assert!(content.contains("\thtml_document: \"documents/invoices/2023/2023-001.html\"\n"));
+    assert!(content.contains("\thtml_document_sha256: \""));
+    assert!(content.ends_with(&format!(
+        "{} custom \"sent\" \"2023-001\" \"billing@acme.example\"\n",
+        today()
+    )));
+
+    Ok(())
+}
+
+#[test]
+fn test_that_invoices_archive_refuses_to_archive_twice() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    let ledger = dir.path().join("ledger.beancount");
+    std::fs::copy("./tests/fixtures/send.beancount", &ledger)?;
+
+    let archive = || -> Result<assert_cmd::assert::Assert, Box<dyn std::error::Error>> {
+        Ok(Command::cargo_bin("tabula")?
+            .args(&["--ledger", ledger.to_str().unwrap()])
+            .arg("invoices")
+            .arg("archive")
+            .args(&["--invoice-number", "2023-001"])
+            .assert())
+    };
+
+    archive()?.success();
+    assert!(dir
+        .path()
+        .join("documents/invoices/2023/2023-001.pdf")
+        .exists());
+
+    archive()?
+        .failure()
+        .stderr(predicate::str::contains("already archived"));
+
+    Ok(())
+}
+
+#[test]
+fn test_that_invoices_send_checks_the_recorded_documents() -> Result<(), Box<dyn std::error::Error>>
+{
+    let dir = assert_fs::TempDir::new()?;
+    let ledger = dir.path().join("ledger.beancount");
+    std::fs::copy("./tests/fixtures/send.beancount", &ledger)?;
+    let config = dir.path().join("tabula.toml");
+    std::fs::write(
+        &config,
+        format!(
+            "[notification]\nfrom = \"invoices@example.com\"\ndrop_dir = \"{}\"\n",
+            dir.path().join("outbox").display()
+        ),
+    )?;
+    let run = |action: &str| -> Result<assert_cmd::assert::Assert, Box<dyn std::error::Error>> {
+        Ok(Command::cargo_bin("tabula")?
+            .args(&["--ledger", ledger.to_str().unwrap()])
+            .args(&["--config", config.to_str().unwrap()])
+            .arg("invoices")
+            .arg(action)
+            .args(&["--invoice-number", "2023-001"])
+            .assert())
+    };
+    run("archive")?.success();
+
+    // The document is fetched from where the ledger says, not where it would be stored now
+    let archived = dir.path().join("documents/invoices/2023/2023-001.pdf");
+    let moved = dir.path().join("documents/moved.pdf");
+    std::fs::rename(&archived, &moved)?;
+    let content = std::fs::read_to_string(&ledger)?;
+    std::fs::write(
+        &ledger,
+        content.replace("invoices/2023/2023-001.pdf", "moved.pdf"),
+    )?;
+    run("send")?.success();
+
+    std::fs::write(&moved, b"%PDF-1.7 tampered")?;
+    run("send")?.failure().stderr(predicate::str::contains(
+        "does not match its document_sha256",
+    ));
+
+    let content = std::fs::read_to_string(&ledger)?;
+    let without_hash: String = content
+        .lines()
+        .filter(|line| !line.starts_with("\tdocument_sha256:"))
+        .map(|line| format!("{}\n", line))
+        .collect();
+    std::fs::write(&ledger, without_hash)?;
+    run("send")?
+        .failure()
+        .stderr(predicate::str::contains("has no document_sha256"));
+
+    Ok(())
+}
+
+#[test]
+fn test_that_lifecycle_enforces_rules_and_writes_ledger() -> Result<(), Box<dyn std::error::Error>>
+{
+    let dir = assert_fs::TempDir::new()?;
+    let ledger = dir.path().join("ledger.beancount");
+    std::fs::copy("./tests/fixtures/send.beancount", &ledger)?;
+    let lifecycle = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("tabula").unwrap();
+        cmd.args(["--ledger", ledger.to_str().unwrap(), "-q", "lifecycle"])
+            .args(args)
+            .assert()
+    };
+
+    let draft = |number: &str| {
+        lifecycle(&[
+            "draft",
+            "--invoice-number",
+            number,
+            "--date",
+            "2023-07-01",
+            "--total",
+            "500 EUR",
+        ])
+    };
+
+    draft("2023-010").success();
+    let content = std::fs::read_to_string(&ledger)?;
+    assert!(content.contains("2023-07-01 ! \"Invoice #2023-010\"\n"));
+    lifecycle(&[
+        "issue",
+        "--invoice-number",
+        "2023-010",
+        "--date",
+        "2023-07-03",
+    ])
+    .success();
+    lifecycle(&["pay", "--invoice-number", "2023-010", "--amount", "500 EUR"])
+        .success()
+        .stdout(predicate::str::contains("Status: paid"));
+    lifecycle(&["void", "--invoice-number", "2023-010", "--reason", "Typo"])
+        .failure()
+        .stderr(predicate::str::contains("credit them instead"));
+
+    // Voided drafts leave the ledger
+    draft("2023-011").success();
+    lifecycle(&["void", "--invoice-number", "2023-011", "--reason", "Typo"]).success();
+
+    let content = std::fs::read_to_string(&ledger)?;
+    assert!(content.contains(
+        "2023-07-01 * \"Invoice #2023-010\"\n\tinvoice_number: \"2023-010\"\n\
+         \tissued: 2023-07-03\n\tdue: 2023-08-02\n"
+    ));
+    assert!(content.contains("\tpaid_invoice: \"2023-010\"\n"));
+    assert!(!content.contains("2023-011"));
+    let events = std::fs::read_to_string(dir.path().join("invoice-events.jsonl"))?;
+    assert_eq!(5, events.lines().count());
+
+    Ok(())
+}
+
+#[test]
+fn test_that_lifecycle_writes_events_the_ledger_missed() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    let ledger = dir.path().join("ledger.beancount");
+    std::fs::copy("./tests/fixtures/send.beancount", &ledger)?;
+    let lifecycle = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("tabula").unwrap();
+        cmd.args(["--ledger", ledger.to_str().unwrap(), "-q", "lifecycle"])
+            .args(args)
+            .assert()
+    };
+    let issue = [
+        "issue",
+        "--invoice-number",
+        "2023-010",
+        "--date",
+        "2023-07-01",
+    ];
+
+    lifecycle(&[
+        "draft",
+        "--invoice-number",
+        "2023-010",
+        "--date",
+        "2023-07-01",
+        "--total",
+        "500 EUR",
+    ])
+    .success();
+    let drafted = std::fs::read_to_string(&ledger)?;
+    // A ledger that cannot be written to
+    std::fs::remove_file(&ledger)?;
+    std::fs::create_dir(&ledger)?;
+    lifecycle(&issue)
+        .failure()
+        .stderr(predicate::str::contains("could not be written to"));
+
+    std::fs::remove_dir(&ledger)?;
+    std::fs::write(&ledger, &drafted)?;
+    lifecycle(&issue)
+        .failure()
+        .stderr(predicate::str::contains("Only drafts can be issued"));
+    let content = std::fs::read_to_string(&ledger)?;
+    assert!(content.contains("2023-07-01 * \"Invoice #2023-010\"\n"));
+    assert!(!dir.path().join("invoice-events.pending.jsonl").exists());
+
+    lifecycle(&[
+        "credit",
+        "--invoice-number",
+        "2023-010",
+        "--credit-note-number",
+        "2023-001",
+    ])
+    .failure()
+    .stderr(predicate::str::contains(
+        "Invoice 2023-001 is already in the ledger",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_that_accounts_bootstrap_open_and_close() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    let ledger = dir.path().join("books.beancount");
+    let accounts = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("tabula").unwrap();
+        cmd.args(["--ledger", ledger.to_str().unwrap(), "-q"])
+            .args(args)
+            .assert()
+    };
+
+    accounts(&[
+        "accounts",
+        "bootstrap",
+        "--date",
+        "2023-01-01",
+        "--currency",
+        "EUR",
+    ])
+    .success()
+    .stdout(predicate::str::contains("Assets:AccountsReceivable"));
+    accounts(&[
+        "accounts",
+        "open",
+        "--account",
+        "Assets:Bank",
+        "--date",
+        "2023-02-01",
+    ])
+    .failure()
+    .stderr(predicate::str::contains(
+        "Account Assets:Bank is already open",
+    ));
+    accounts(&[
+        "accounts",
+        "open",
+        "--account",
+        "Assets:Brokerage",
+        "--currency",
+        "VWRL",
+        "--booking",
+        "fifo",
+        "--date",
+        "2023-02-01",
+    ])
+    .success();
+    accounts(&[
+        "accounts",
+        "close",
+        "--account",
+        "Assets:Savings",
+        "--date",
+        "2023-12-31",
+    ])
+    .success();
+    accounts(&["accounts", "tree"])
+        .success()
+        .stdout(predicate::str::contains("├── Bank\n"))
+        .stdout(predicate::str::contains("Savings (closed on 2023-12-31)"));
+
+    let content = std::fs::read_to_string(&ledger)?;
+    assert!(content.starts_with("option \"operating_currency\" \"EUR\"\n"));
+    assert!(content.contains("2023-01-01 open Assets:Bank EUR\n\trgs: \"BLim\"\n"));
+    assert!(content.contains("2023-02-01 open Assets:Brokerage VWRL \"FIFO\"\n"));
+    assert!(content.contains("2023-12-31 close Assets:Savings\n"));
+
+    Ok(())
+}
+
+#[test]
+fn test_that_beancount_output_only_posts_to_open_accounts() -> Result<(), Box<dyn std::error::Error>>
+{
+    let dir = assert_fs::TempDir::new()?;
+    let ledger = dir.path().join("ledger.beancount");
+    std::fs::copy("./tests/fixtures/send.beancount", &ledger)?;
+    let tabula = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("tabula").unwrap();
+        cmd.args(["--ledger", ledger.to_str().unwrap(), "-q"])
+            .args(args)
+            .assert()
+    };
+    let convert = [
+        "--format",
+        "beancount",
+        "invoices",
+        "convert",
+        "--invoice-number",
+        "2023-001",
+    ];
+
+    tabula(&[
+        "accounts",
+        "open",
+        "--account",
+        "Assets:AccountsReceivable",
+        "--date",
+        "2023-01-01",
+    ])
+    .success();
+    tabula(&convert)
+        .failure()
+        .stderr(predicate::str::contains("Income:Work is not open"));
+    tabula(&[
+        "accounts",
+        "open",
+        "--account",
+        "Income:Work",
+        "--date",
+        "2023-01-01",
+    ])
+    .success();
+    tabula(&convert).success();
+
+    Ok(())
+}
+
+#[test]
+fn test_that_invoice_list_reads_through_the_cache() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    let ledger = dir.path().join("ledger.beancount");
+    std::fs::write(&ledger, "include \"2023.beancount\"\n")?;
+    std::fs::copy(
+        "./tests/fixtures/invoices.beancount",
+        dir.path().join("2023.beancount"),
+    )?;
+    let config = dir.path().join("tabula.toml");
+    std::fs::write(&config, "[cache]\nenabled = true\n")?;
+    let list = || {
+        let mut cmd = Command::cargo_bin("tabula").unwrap();
+        cmd.args(["--ledger", ledger.to_str().unwrap()])
+            .args(["--config", config.to_str().unwrap()])
+            .args(["--format", "csv", "invoices", "list"])
+            .unwrap()
+            .stdout
+    };
+
+    let cold = list();
+    assert!(dir.path().join(".tabula-cache.sqlite").exists());
+    let warm = list();
+    std::fs::write(&ledger, "")?;
+    let changed = list();
+
+    assert_eq!(String::from_utf8(cold.clone())?, String::from_utf8(warm)?);
+    assert_eq!(4, String::from_utf8(cold)?.trim_end().lines().count());
+    assert_eq!(1, String::from_utf8(changed)?.trim_end().lines().count());
+
+    Ok(())
+}
+
+#[test]
+fn test_that_invoice_convert_writes_valid_ubl() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    let config = dir.path().join("tabula.toml");
+    std::fs::write(
+        &config,
+        r#"
+[seller]
+name = "Tabula B.V."
+vat_id = "NL123456789B01"
+company_id = "12345678"
+endpoint = "0106:12345678"
+city = "Utrecht"
+country = "NL"
+iban = "NL91ABNA0417164300"
+
+[customers.Acme]
+name = "Acme B.V."
+vat_id = "NL987654321B01"
+endpoint = "0106:87654321"
+country = "NL"
+reference = "PO-42"
+"#,
+    )?;
+    let convert = |config: &str, number: &str| {
+        let mut cmd = Command::cargo_bin("tabula").unwrap();
+        cmd.args([
+            "--ledger",
+            "./tests/fixtures/ubl.beancount",
+            "--config",
+            config,
+        ])
+        .args([
+            "--format",
+            "ubl",
+            "invoices",
+            "convert",
+            "--invoice-number",
+            number,
+        ])
+        .assert()
+    };
+
+    convert(config.to_str().unwrap(), "2023-001")
+        .success()
+        .stdout(predicate::str::contains(
+            "<cbc:InvoiceTypeCode>380</cbc:InvoiceTypeCode>",
+        ))
+        .stdout(predicate::str::contains(
+            "<cbc:EndpointID schemeID=\"0106\">12345678</cbc:EndpointID>",
+        ))
+        .stdout(predicate::str::contains(
+            "<cbc:InvoicedQuantity unitCode=\"HUR\">10</cbc:InvoicedQuantity>",
+        ))
+        .stdout(predicate::str::contains(
+            "<cbc:TaxAmount currencyID=\"EUR\">210.00</cbc:TaxAmount>",
+        ))
+        .stdout(predicate::str::contains(
+            "<cbc:PayableAmount currencyID=\"EUR\">1210.00</cbc:PayableAmount>",
+        ));
+    convert(config.to_str().unwrap(), "2023-002")
+        .success()
+        .stdout(predicate::str::contains(
+            "<cbc:CreditNoteTypeCode>381</cbc:CreditNoteTypeCode>",
+        ))
+        .stdout(predicate::str::contains(
+            "<cac:InvoiceDocumentReference>\n      <cbc:ID>2023-001</cbc:ID>",
+        ));
+    // Without the details of the seller and the customer the invoice is refused
+    std::fs::write(&config, "")?;
+    convert(config.to_str().unwrap(), "2023-001")
+        .failure()
+        .stderr(predicate::str::contains("BR-06"))
+        .stderr(predicate::str::contains("PEPPOL-EN16931-R020"));
+
+    Ok(())
+}
+
+#[test]
+fn test_that_invoice_factur_x_embeds_the_cii_xml() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    let config = dir.path().join("tabula.toml");
+    std::fs::write(
+        &config,
+        r#"
+[seller]
+name = "Tabula B.V."
+vat_id = "NL123456789B01"
+country = "NL"
+iban = "NL91ABNA0417164300"
+
+[customers.Acme]
+name = "Acme B.V."
+country = "NL"
+"#,
+    )?;
+    let factur_x = |profile: &str, output: &std::path::Path| {
+        let mut cmd = Command::cargo_bin("tabula").unwrap();
+        cmd.args([
+            "--ledger",
+            "./tests/fixtures/ubl.beancount",
+            "--config",
+            config.to_str().unwrap(),
+        ])
+        .args([
+            "invoices",
+            "factur-x",
+            "--invoice-number",
+            "2023-001",
+            "--profile",
+            profile,
+        ])
+        .arg("--output")
+        .arg(output)
+        .assert()
+    };
+
+    // The relationship of the attached factur-x.xml to the PDF, and the XML itself
+    let attachment = |pdf: &std::path::Path| {
+        let document = lopdf::Document::load(pdf).unwrap();
+        let object = |object| document.dereference(object).unwrap().1;
+        let file_spec = object(
+            &document
+                .catalog()
+                .unwrap()
+                .get(b"AF")
+                .unwrap()
+                .as_array()
+                .unwrap()[0],
+        )
+        .as_dict()
+        .unwrap();
+        let relationship = file_spec
+            .get(b"AFRelationship")
+            .unwrap()
+            .as_name_str()
+            .unwrap();
+        let file = object(
+            file_spec
+                .get(b"EF")
+                .unwrap()
+                .as_dict()
+                .unwrap()
+                .get(b"F")
+                .unwrap(),
+        );
+        let xml = file.as_stream().unwrap().decompressed_content().unwrap();
+        (relationship.to_string(), String::from_utf8(xml).unwrap())
+    };
+
+    let en16931 = dir.path().join("2023-001.pdf");
+    factur_x("en16931", &en16931).success();
+    assert!(std::fs::read(&en16931)?.starts_with(b"%PDF-1.7"));
+    let (relationship, xml) = attachment(&en16931);
+    assert_eq!("Alternative", relationship);
+    assert!(xml.contains("<ram:ID>urn:cen.eu:en16931:2017</ram:ID>"));
+    assert!(xml.contains("<ram:BilledQuantity unitCode=\"HUR\">10</ram:BilledQuantity>"));
+
+    let minimum = dir.path().join("2023-001-minimum.pdf");
+    factur_x("minimum", &minimum).success();
+    let (relationship, xml) = attachment(&minimum);
+    assert_eq!("Data", relationship);
+    assert!(!xml.contains("ram:IncludedSupplyChainTradeLineItem"));
+
+    factur_x("extended", &minimum)
+        .failure()
+        .stderr(predicate::str::contains("Expected a Factur-X profile"));
+
+    Ok(())
+}
+
 fn today() -> String {
     Local::now().format("%F").to_string()
 }
+
+#[test]
+fn test_that_bills_import_books_ubl_and_cii_once() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    let ledger = dir.path().join("ledger.beancount");
+    std::fs::copy("./tests/fixtures/send.beancount", &ledger)?;
+    let config = dir.path().join("tabula.toml");
+    std::fs::write(
+        &config,
+        r#"
+[[bills.accounts]]
+supplier = "Hosting B.V."
+item = "domain"
+account = "Expenses:Telecom"
+
+[[bills.accounts]]
+supplier = "Hosting B.V."
+account = "Expenses:Software"
+
+[[bills.accounts]]
+supplier = "Boekhouders & Partners"
+account = "Expenses:Accounting"
+"#,
+    )?;
+    let import = |file: &str| {
+        Command::cargo_bin("tabula")
+            .unwrap()
+            .args([
+                "--ledger",
+                ledger.to_str().unwrap(),
+                "--config",
+                config.to_str().unwrap(),
+            ])
+            .args(["bills", "import", file])
+            .assert()
+    };
+
+    import("./tests/fixtures/bills/hosting.xml")
+        .success()
+        .stdout(predicate::str::contains("Supplier: Hosting B.V."))
+        .stdout(predicate::str::contains(
+            "Document: documents/bills/2023/HostingBV-INV-2023-0612.xml",
+        ));
+    import("./tests/fixtures/bills/accountant.xml").success();
+
+    let content = std::fs::read_to_string(&ledger)?;
+    assert!(content.contains("2023-06-12 ! \"Bill INV-2023-0612 from Hosting B.V.\"\n\tbill_number: \"INV-2023-0612\"\n\tsupplier: \"Hosting B.V.\"\n\tdue: 2023-06-26\n"));
+    assert!(content.contains("\tExpenses:Software\t120.00 EUR\n\tExpenses:Telecom\t10.00 EUR\n\tAssets:VAT:Input\t27.30 EUR\n\tLiabilities:AccountsPayable:HostingBV\t-157.30 EUR\n"));
+    assert!(content.contains("\tExpenses:Accounting\t450.00 EUR\n\tAssets:VAT:Input\t94.50 EUR\n\tLiabilities:AccountsPayable:BoekhoudersPartners\t-544.50 EUR\n"));
+    let archived = std::fs::read_to_string(
+        dir.path()
+            .join("documents/bills/2023/BoekhoudersPartners-2023-118.xml"),
+    )?;
+    assert!(archived.contains("<ram:ID>2023/118</ram:ID>"));
+
+    import("./tests/fixtures/bills/hosting.xml")
+        .failure()
+        .stderr(predicate::str::contains("INV-2023-0612"))
+        .stderr(predicate::str::contains("Hosting B.V."));
+
+    Ok(())
+}
+
+#[test]
+fn test_that_invoices_import_reads_a_dutch_csv() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    let ledger = dir.path().join("ledger.beancount");
+    std::fs::copy("./tests/fixtures/send.beancount", &ledger)?;
+    let config = dir.path().join("tabula.toml");
+    std::fs::write(
+        &config,
+        r#"
+[csv]
+delimiter = ";"
+decimal_separator = ","
+
+[csv.columns]
+number = "Factuurnummer"
+date = "Datum"
+customer = "Klant"
+narration = "Omschrijving"
+total = "Bedrag"
+"#,
+    )?;
+    let tabula = |args: &[&str]| {
+        Command::cargo_bin("tabula")
+            .unwrap()
+            .args([
+                "--ledger",
+                ledger.to_str().unwrap(),
+                "--config",
+                config.to_str().unwrap(),
+            ])
+            .args(args)
+            .assert()
+    };
+
+    tabula(&[
+        "invoices",
+        "import",
+        "./tests/fixtures/import/invoices.csv",
+        "--dry-run",
+    ])
+    .success();
+    assert_eq!(
+        std::fs::read_to_string("./tests/fixtures/send.beancount")?,
+        std::fs::read_to_string(&ledger)?
+    );
+
+    tabula(&["--format", "beancount", "invoices", "import", "--format", "csv", "./tests/fixtures/import/invoices.csv"])
+        .success()
+        .stdout(predicate::str::contains("2023-06-30 ! \"Hosting; June\"\n\tinvoice_number: \"2023-010\"\n\tcustomer: \"Acme\"\n\tdue: 2023-07-30\n"))
+        .stdout(predicate::str::contains("2023-07-01 ! \"Invoice #2023-011\"\n\tinvoice_number: \"2023-011\"\n"));
+
+    tabula(&["--format", "csv", "invoices", "list", "--expand-lines"])
+        .success()
+        .stdout(predicate::str::starts_with("Number;Date;Due date;Customer;Narration;Description;Quantity;Unit price;Amount\n"))
+        .stdout(predicate::str::contains("2023-010;2023-06-30;2023-07-30;Acme;\"Hosting; June\";\"Hosting; June\";1;1250,50 EUR;1250,50 EUR\n"));
+
+    tabula(&["invoices", "import", "./tests/fixtures/import/invoices.csv"])
+        .failure()
+        .stderr(predicate::str::contains("Invoice 2023-010 already exists"));
+
+    Ok(())
+}
+
+#[test]
+fn test_that_invoices_list_filters_and_sorts() -> Result<(), Box<dyn std::error::Error>> {
+    let ledger = r#"
+2023-01-10 ! "Invoice #1" #hosting
+	invoice_number: "2023-001"
+	customer: "Acme"
+	Assets:AccountsReceivable	100 EUR
+	Income:Work	-100 EUR
+
+2023-02-10 ! "Invoice #2"
+	invoice_number: "2023-002"
+	customer: "Globex"
+	Assets:AccountsReceivable	300 EUR
+	Income:Work	-300 EUR
+
+2023-03-10 ! "Invoice #3" #hosting
+	invoice_number: "2023-003"
+	customer: "Acme"
+	Assets:AccountsReceivable	200 EUR
+	Income:Work	-200 EUR
+"#;
+    let list = |args: &[&str]| {
+        Command::cargo_bin("tabula")
+            .unwrap()
+            .args(["--format", "csv", "invoices", "list"])
+            .args(args)
+            .write_stdin(ledger)
+            .unwrap()
+            .stdout
+    };
+    let numbers = |out: Vec<u8>| {
+        String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .skip(1)
+            .filter(|line| !line.is_empty())
+            .map(|line| line.split(',').next().unwrap().to_string())
+            .collect::<Vec<String>>()
+    };
+
+    assert_eq!(
+        vec!["2023-001", "2023-003"],
+        numbers(list(&["--tag", "hosting", "--customer", "acme"]))
+    );
+    assert_eq!(
+        vec!["2023-002", "2023-003"],
+        numbers(list(&["--from", "2023-02-01", "--min-amount", "150"]))
+    );
+    assert_eq!(
+        vec!["2023-002", "2023-003"],
+        numbers(list(&["--sort", "amount", "--reverse", "--limit", "2"]))
+    );
+    assert_eq!(
+        vec!["2023-002"],
+        numbers(list(&["--search", "#2", "--status", "open"]))
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_that_invoices_list_where_selects_with_an_expression(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let ledger = r#"
+2023-01-10 ! "Invoice #1" #hosting
+	invoice_number: "2023-001"
+	customer: "Acme Corp"
+	project: "alpha"
+	Assets:AccountsReceivable	100 EUR
+	Income:Work	-100 EUR
+
+2023-02-10 ! "Invoice #2"
+	invoice_number: "2023-002"
+	customer: "Globex"
+	Assets:AccountsReceivable	300 EUR
+	Income:Work	-300 EUR
+"#;
+    let list = |expression: &str| {
+        Command::cargo_bin("tabula")
+            .unwrap()
+            .args(["--format", "csv", "invoices", "list", "--where", expression])
+            .write_stdin(ledger)
+            .assert()
+    };
+    let numbers = |out: &[u8]| {
+        String::from_utf8(out.to_vec())
+            .unwrap()
+            .lines()
+            .skip(1)
+            .filter(|line| !line.is_empty())
+            .map(|line| line.split(',').next().unwrap().to_string())
+            .collect::<Vec<String>>()
+    };
+
+    let output = list("status = 'open' and due < 2023-03-31 - 30 and customer ~ 'acme'").success();
+    assert_eq!(vec!["2023-001"], numbers(&output.get_output().stdout));
+    let output = list("meta('project') = 'alpha' or total > 200").success();
+    assert_eq!(
+        vec!["2023-001", "2023-002"],
+        numbers(&output.get_output().stdout)
+    );
+
+    list("total > 'a lot'")
+        .failure()
+        .stderr(predicate::str::contains(
+            "Cannot use > with a number and text at column 1",
+        ))
+        .stderr(predicate::str::contains("^^^^^^^^^^^^^^^"));
+
+    Ok(())
+}
+
+#[test]
+fn test_that_errors_point_at_the_ledger_and_set_the_exit_code(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let ledger = r#"
+2023-06-01 ! "Invoice #1"
+	invoice_number: "2023-001"
+	due: "next week"
+	Assets:AccountsReceivable	1000 EUR
+	Income:Work	-1000 EUR
+"#;
+    Command::cargo_bin("tabula")?
+        .args(["invoices", "list"])
+        .write_stdin(ledger)
+        .assert()
+        .code(3)
+        .stderr(predicate::str::contains(
+            "Error: Expected due to be a date, found \"next week\"",
+        ))
+        .stderr(predicate::str::contains("--> <stdin>:4"))
+        .stderr(predicate::str::contains("4 | \tdue: \"next week\""));
+
+    let mut file_content = String::new();
+    File::open("./tests/fixtures/invoices.beancount")?.read_to_string(&mut file_content)?;
+    Command::cargo_bin("tabula")?
+        .args(["invoices", "convert", "--invoice-number", "2099-001"])
+        .write_stdin(file_content)
+        .assert()
+        .code(4)
+        .stderr(predicate::str::contains("Invoice 2099-001 not found"));
+
+    Command::cargo_bin("tabula")?
+        .args([
+            "--ledger",
+            "./tests/fixtures/missing.beancount",
+            "invoices",
+            "list",
+        ])
+        .assert()
+        .code(6)
+        .stderr(predicate::str::contains(
+            "Could not read ./tests/fixtures/missing.beancount",
+        ));
+
+    Command::cargo_bin("tabula")?
+        .args(["invoices", "list"])
+        .write_stdin(vec![0xff, 0xfe, b'\n'])
+        .assert()
+        .code(6)
+        .stderr(predicate::str::contains("Could not read <stdin>"));
+
+    Command::cargo_bin("tabula")?
+        .args(["invoices", "send", "--invoice-number", "2023-001"])
+        .write_stdin("")
+        .assert()
+        .code(7)
+        .stderr(predicate::str::contains(
+            "Sending needs the ledger as a file, pass it with --ledger",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn test_that_check_reports_broken_conventions_at_their_line(
+) -> Result<(), Box<dyn std::error::Error>> {
+    Command::cargo_bin("tabula")?
+        .args(["--ledger", "./tests/fixtures/check/ledger.beancount", "check"])
+        .assert()
+        .code(5)
+        .stdout(predicate::str::contains(
+            "error[duplicate-number]: Invoice number 2023-001 is already used at \
+             ./tests/fixtures/check/2023.beancount:2\n  --> ./tests/fixtures/check/ledger.beancount:6",
+        ))
+        .stdout(predicate::str::contains(
+            "error[line-item]: Line item \"Hours\" comes to 10 × 90 = 900 EUR, but its posting is 1000 EUR",
+        ))
+        .stdout(predicate::str::contains("2 errors and 2 warnings"));
+
+    let out = Command::cargo_bin("tabula")?
+        .args([
+            "--ledger",
+            "./tests/fixtures/check/ledger.beancount",
+            "--format",
+            "json",
+            "check",
+        ])
+        .assert()
+        .code(5)
+        .get_output()
+        .stdout
+        .clone();
+    let diagnostics: serde_json::Value = serde_json::from_slice(&out)?;
+    assert_eq!(
+        json!({
+            "severity": "warning",
+            "code": "numbering",
+            "message": "Invoice number INV-2 does not follow the numbering of 2023, such as 2023-001",
+            "file": "./tests/fixtures/check/2023.beancount",
+            "line": 10
+        }),
+        diagnostics[3]
+    );
+
+    Command::cargo_bin("tabula")?
+        .args(["--ledger", "./tests/fixtures/invoices.beancount", "check"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "The ledger follows the conventions",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn test_that_invoices_issue_numbers_the_draft_and_freezes_it(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    let ledger = dir.path().join("ledger.beancount");
+    std::fs::copy("./tests/fixtures/invoices.beancount", &ledger)?;
+    let config = dir.path().join("tabula.toml");
+    std::fs::write(
+        &config,
+        format!(
+            "[notification]\nfrom = \"invoices@example.com\"\ndrop_dir = \"{}\"\n",
+            dir.path().join("outbox").display()
+        ),
+    )?;
+
+    let issue = |number: &str| -> Result<assert_cmd::assert::Assert, Box<dyn std::error::Error>> {
+        Ok(Command::cargo_bin("tabula")?
+            .args(&["--ledger", ledger.to_str().unwrap()])
+            .args(&["--format", "json"])
+            .arg("invoices")
+            .arg("issue")
+            .args(&["--invoice-number", number])
+            .args(&["--date", "2023-06-06"])
+            .assert())
+    };
+    let send = |number: &str| -> Result<assert_cmd::assert::Assert, Box<dyn std::error::Error>> {
+        Ok(Command::cargo_bin("tabula")?
+            .args(&["--ledger", ledger.to_str().unwrap()])
+            .args(&["--config", config.to_str().unwrap()])
+            .arg("invoices")
+            .arg("send")
+            .args(&["--invoice-number", number])
+            .args(&["--to", "billing@acme.example"])
+            .assert())
+    };
+
+    send("TBD")?.code(5).stderr(predicate::str::contains(
+        "Invoice TBD is a draft, issue it with `tabula invoices issue` first",
+    ));
+
+    // An earlier attempt stored the documents, but did not get to record them in the ledger
+    let draft = std::fs::read_to_string(&ledger)?;
+    issue("TBD")?.success();
+    let pdf = dir.path().join("documents/invoices/2023/2023-003.pdf");
+    let earlier = std::fs::read(&pdf)?;
+
+    // They are not taken for those of another draft that gets the same number
+    std::fs::write(&ledger, draft.replace("\"Invoice #TBD\"", "\"Design work\""))?;
+    issue("TBD")?.code(5).stderr(predicate::str::contains(
+        "is already archived for another draft of invoice 2023-003",
+    ));
+
+    std::fs::write(&ledger, &draft)?;
+    issue("TBD")?
+        .success()
+        .stdout(predicate::str::contains(r#""number": "2023-003""#))
+        .stdout(predicate::str::contains(r#""state": "issued""#));
+
+    let content = std::fs::read_to_string(&ledger)?;
+    assert!(content.contains(
+        "2023-06-05 * \"Invoice #TBD\"\n\tinvoice_number: \"2023-003\"\n\tissued: 2023-06-06\n"
+    ));
+    assert_eq!(earlier, std::fs::read(&pdf)?);
+    assert!(dir
+        .path()
+        .join("documents/invoices/2023/2023-003.html")
+        .exists());
+
+    issue("2023-003")?.code(5).stderr(predicate::str::contains(
+        "Invoice 2023-003 is already issued",
+    ));
+    send("2023-003")?.success();
+
+    // Which of two drafts numbered `TBD` to issue is not guessed at
+    let draft = "\n2023-06-07 ! \"Invoice #TBD\"\n\tinvoice_number: \"TBD\"\n\
+                 \tAssets:AccountsReceivable\t100 USD\n\tIncome:Work\t-100 USD\n";
+    let content = std::fs::read_to_string(&ledger)?;
+    std::fs::write(&ledger, format!("{}{}{}", content, draft, draft))?;
+    issue("TBD")?.code(5).stderr(predicate::str::contains(
+        "More than one invoice is numbered TBD",
+    ));
+    assert!(!dir
+        .path()
+        .join("documents/invoices/2023/2023-004.pdf")
+        .exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_that_invoices_issue_numbers_from_the_year_it_is_issued(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    let ledger = dir.path().join("ledger.beancount");
+    std::fs::write(
+        &ledger,
+        "2023-12-28 ! \"Invoice #TBD\"\n\tinvoice_number: \"TBD\"\n\
+         \tAssets:AccountsReceivable\t100 EUR\n\tIncome:Work\t-100 EUR\n",
+    )?;
+
+    Command::cargo_bin("tabula")?
+        .args(&["--ledger", ledger.to_str().unwrap()])
+        .args(&["--format", "json"])
+        .arg("invoices")
+        .arg("issue")
+        .args(&["--invoice-number", "TBD"])
+        .args(&["--date", "2024-01-03"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(r#""number": "2024-001""#));
+
+    Ok(())
+}